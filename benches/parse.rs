@@ -0,0 +1,38 @@
+//! Owned and borrowed parsing of a large image, with and without CRC checks. Run with
+//! `cargo bench --bench parse`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pngme::ihdr::{ColorType, Ihdr};
+use pngme::{Chunk, ChunkType, Png, PngRef};
+
+/// 50 IDAT chunks of 1 MiB each, about the size of a large photo
+fn testing_bytes() -> Vec<u8> {
+    let idats = (0..50).map(|i| Chunk::new(ChunkType::IDAT, vec![i as u8; 1 << 20]));
+    let chunks = std::iter::once(Ihdr::new(4096, 4096, 8, ColorType::Rgb).to_chunk())
+        .chain(idats)
+        .chain(std::iter::once(Chunk::new(ChunkType::IEND, vec![])))
+        .collect();
+    Png::new(chunks).as_bytes()
+}
+
+fn parse(c: &mut Criterion) {
+    let bytes = testing_bytes();
+    let mut group = c.benchmark_group("parse 50 x 1 MiB IDAT");
+    group.sample_size(10);
+    group.bench_function("Png::try_from", |b| {
+        b.iter(|| Png::try_from(black_box(bytes.as_slice())).unwrap())
+    });
+    group.bench_function("PngRef::try_from", |b| {
+        b.iter(|| PngRef::try_from(black_box(bytes.as_slice())).unwrap())
+    });
+    group.bench_function("Png::parse_ignoring_crcs", |b| {
+        b.iter(|| Png::parse_ignoring_crcs(black_box(&bytes)).unwrap())
+    });
+    group.bench_function("PngRef::parse_ignoring_crcs", |b| {
+        b.iter(|| PngRef::parse_ignoring_crcs(black_box(&bytes)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::{ChunkRefs, Png, PngRef};
+
+// The strict parsers either reject the input or read it back byte for byte, and whatever they
+// accept can be printed
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        assert_eq!(png.as_bytes(), data);
+        let _ = png.to_string();
+    }
+    let _ = PngRef::try_from(data);
+    if let Ok(png) = Png::parse_ignoring_crcs(data) {
+        let _ = png.to_string();
+    }
+    if let Ok(chunks) = ChunkRefs::ignoring_crcs(data) {
+        for chunk in chunks.flatten() {
+            let _ = chunk.to_chunk().to_string();
+            let _ = chunk.chunk_type().to_string();
+        }
+    }
+});
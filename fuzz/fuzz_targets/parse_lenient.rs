@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::Png;
+
+// Whatever the recovery keeps has to be a well-formed image again, if not one with valid CRCs,
+// and it and the warnings about it can be printed
+fuzz_target!(|data: &[u8]| {
+    if let Ok((png, warnings)) = Png::try_from_lenient(data) {
+        let bytes = png.as_bytes();
+        assert!(bytes.len() <= data.len());
+        let reparsed = Png::parse_ignoring_crcs(&bytes).expect("recovered image parses");
+        assert_eq!(reparsed.chunks().len(), png.chunks().len());
+        for chunk in png.chunks() {
+            let _ = chunk.to_string();
+        }
+        let _ = png.to_string();
+        for warning in warnings {
+            assert!(warning.offset() < data.len());
+            let _ = warning.to_string();
+        }
+    }
+});
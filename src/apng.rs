@@ -0,0 +1,301 @@
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use std::fmt::Display;
+
+/// The chunks that make up an APNG animation. [`crate::png::Png::scrub`] keeps them like the
+/// image chunks.
+pub const ANIMATION_CHUNK_TYPES: [ChunkType; 3] =
+    [ChunkType::acTL, ChunkType::fcTL, ChunkType::fdAT];
+
+/// The animation header, from the acTL chunk
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// Times to play the animation, 0 for forever
+    pub num_plays: u32,
+}
+
+impl TryFrom<ChunkRef<'_>> for AnimationControl {
+    type Error = PngMeError;
+
+    fn try_from(chunk: ChunkRef<'_>) -> Result<Self, Self::Error> {
+        let data = fields(chunk, ChunkType::acTL, 8, "acTL chunk has the wrong length")?;
+        Ok(AnimationControl {
+            num_frames: u32_at(data, 0),
+            num_plays: u32_at(data, 4),
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        AnimationControl::try_from(ChunkRef::from(chunk))
+    }
+}
+
+/// A summary such as `3 frames, looping forever`
+impl Display for AnimationControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.num_frames {
+            1 => write!(f, "1 frame, ")?,
+            frames => write!(f, "{} frames, ", frames)?,
+        }
+        match self.num_plays {
+            0 => write!(f, "looping forever"),
+            1 => write!(f, "played once"),
+            plays => write!(f, "played {} times", plays),
+        }
+    }
+}
+
+/// The region, timing and compositing of one frame, from an fcTL chunk
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    /// 0 means hundredths of a second
+    pub delay_den: u16,
+    pub dispose_op: u8,
+    pub blend_op: u8,
+}
+
+impl TryFrom<ChunkRef<'_>> for FrameControl {
+    type Error = PngMeError;
+
+    fn try_from(chunk: ChunkRef<'_>) -> Result<Self, Self::Error> {
+        let data = fields(
+            chunk,
+            ChunkType::fcTL,
+            26,
+            "fcTL chunk has the wrong length",
+        )?;
+        Ok(FrameControl {
+            sequence_number: u32_at(data, 0),
+            width: u32_at(data, 4),
+            height: u32_at(data, 8),
+            x_offset: u32_at(data, 12),
+            y_offset: u32_at(data, 16),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op: data[24],
+            blend_op: data[25],
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        FrameControl::try_from(ChunkRef::from(chunk))
+    }
+}
+
+/// A summary such as `64x64 at (0, 0) for 0.1s`
+impl Display for FrameControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {}) for {}s",
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+            self.delay()
+        )
+    }
+}
+
+impl FrameControl {
+    /// How long the frame is shown, in seconds
+    pub fn delay(&self) -> f64 {
+        let den = match self.delay_den {
+            0 => 100,
+            den => den,
+        };
+        self.delay_num as f64 / den as f64
+    }
+}
+
+/// The animation of an APNG: its header and the control of each frame, in file order
+#[derive(PartialEq, Debug, Clone)]
+pub struct Animation {
+    pub control: AnimationControl,
+    pub frames: Vec<FrameControl>,
+}
+
+impl Animation {
+    /// Reads the animation out of a chunk list, or returns `None` for a static image
+    pub fn from_chunks<'a>(
+        chunks: impl IntoIterator<Item = ChunkRef<'a>>,
+    ) -> Result<Option<Animation>, PngMeError> {
+        let mut control = None;
+        let mut frames = Vec::new();
+        for chunk in chunks {
+            match chunk.chunk_type() {
+                ChunkType::acTL => control = Some(AnimationControl::try_from(chunk)?),
+                ChunkType::fcTL => frames.push(FrameControl::try_from(chunk)?),
+                _ => (),
+            }
+        }
+        Ok(control.map(|control| Animation { control, frames }))
+    }
+
+    /// The delay of each frame in seconds, joined for display
+    pub fn delays(&self) -> String {
+        let delays: Vec<String> = self
+            .frames
+            .iter()
+            .map(|frame| format!("{}s", frame.delay()))
+            .collect();
+        delays.join(", ")
+    }
+}
+
+/// Checks that the animation chunks still fit together: an acTL whenever there are frames, one
+/// fcTL per frame it announces, and fcTL and fdAT sequence numbers counting up from 0 without
+/// gaps. A file without any animation chunks passes.
+pub fn check_sequence<'a>(
+    chunks: impl IntoIterator<Item = ChunkRef<'a>>,
+) -> Result<(), PngMeError> {
+    let mut control = None;
+    let mut frames = 0;
+    let mut expected = 0;
+    for chunk in chunks {
+        match chunk.chunk_type() {
+            ChunkType::acTL => control = Some(AnimationControl::try_from(chunk)?),
+            ChunkType::fcTL | ChunkType::fdAT => {
+                let sequence_number = chunk
+                    .data()
+                    .get(..4)
+                    .map(|bytes| u32_at(bytes, 0))
+                    .ok_or(PngMeError::Malformed("fcTL or fdAT chunk is too short"))?;
+                if sequence_number != expected {
+                    return Err(PngMeError::Malformed(
+                        "fcTL and fdAT sequence numbers have a gap or are out of order",
+                    ));
+                }
+                expected += 1;
+                if chunk.chunk_type() == ChunkType::fcTL {
+                    frames += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    match control {
+        None if expected > 0 => Err(PngMeError::Malformed("fcTL or fdAT chunk without an acTL")),
+        Some(control) if control.num_frames != frames => Err(PngMeError::Malformed(
+            "acTL frame count doesn't match the fcTL chunks",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn fields<'a>(
+    chunk: ChunkRef<'a>,
+    chunk_type: ChunkType,
+    len: usize,
+    wrong_length: &'static str,
+) -> Result<&'a [u8], PngMeError> {
+    if chunk.chunk_type() != chunk_type {
+        return Err(PngMeError::Invalid("chunk has the wrong type"));
+    }
+    match chunk.data().len() == len {
+        true => Ok(chunk.data()),
+        false => Err(PngMeError::Malformed(wrong_length)),
+    }
+}
+
+fn u32_at(data: &[u8], index: usize) -> u32 {
+    u32::from_be_bytes(data[index..index + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    /// A two-frame animation whose first frame is the IDAT image, so the chunks run acTL, fcTL 0,
+    /// IDAT, fcTL 1, fdAT 2
+    pub(crate) fn testing_apng() -> Png {
+        let frame = |sequence_number: u32| {
+            let mut data = sequence_number.to_be_bytes().to_vec();
+            for field in [1u32, 1, 0, 0] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+            data.extend_from_slice(&[0, 10, 0, 100, 0, 0]);
+            Chunk::new(ChunkType::fcTL, data)
+        };
+        let mut actl = 2u32.to_be_bytes().to_vec();
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend_from_slice(b"pixels");
+
+        Png::new(vec![
+            crate::ihdr::Ihdr::new(1, 1, 8, crate::ihdr::ColorType::Rgb).to_chunk(),
+            Chunk::new(ChunkType::acTL, actl),
+            frame(0),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            frame(1),
+            Chunk::new(ChunkType::fdAT, fdat),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    fn refs(png: &Png) -> impl Iterator<Item = ChunkRef<'_>> {
+        png.chunks().iter().map(ChunkRef::from)
+    }
+
+    #[test]
+    fn test_animation() {
+        let png = testing_apng();
+        let animation = Animation::from_chunks(refs(&png)).unwrap().unwrap();
+        assert_eq!(animation.control.to_string(), "2 frames, looping forever");
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(animation.frames[1].to_string(), "1x1 at (0, 0) for 0.1s");
+        assert_eq!(animation.delays(), "0.1s, 0.1s");
+
+        let still = Png::new(vec![Chunk::new(ChunkType::IDAT, vec![])]);
+        assert_eq!(Animation::from_chunks(refs(&still)).unwrap(), None);
+        let short = Chunk::new(ChunkType::acTL, vec![0; 4]);
+        assert!(AnimationControl::try_from(&short).is_err());
+        assert!(FrameControl::try_from(&short).is_err());
+    }
+
+    #[test]
+    fn test_check_sequence() {
+        let mut png = testing_apng();
+        assert!(check_sequence(refs(&png)).is_ok());
+
+        png.remove_first_chunk("fdAT").unwrap();
+        assert!(check_sequence(refs(&png)).is_ok(), "fdAT 2 was the last");
+        png.remove_first_chunk("fcTL").unwrap();
+        assert!(check_sequence(refs(&png)).is_err(), "numbering starts at 1");
+
+        let mut png = testing_apng();
+        png.remove_first_chunk("acTL").unwrap();
+        assert!(check_sequence(refs(&png)).is_err());
+
+        let mut png = testing_apng();
+        let index = png.indices_of_type("fdAT", crate::selection::Occurrence::First)[0];
+        png.remove_first_chunk("fdAT").unwrap();
+        png.insert_chunk_at(
+            index,
+            Chunk::new(ChunkType::from_str("fdAT").unwrap(), vec![0; 4]),
+        )
+        .unwrap();
+        assert!(check_sequence(refs(&png)).is_err());
+
+        let still = Png::new(vec![Chunk::new(ChunkType::IDAT, vec![])]);
+        assert!(check_sequence(refs(&still)).is_ok());
+    }
+}
@@ -1 +1,835 @@
+use crate::output::{Format, OutputMode};
+use crate::style::ColorChoice;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pngme::compress::Algorithm;
+use pngme::filter::Filter;
+use pngme::ordering::InsertPosition;
+use pngme::selection::{ChunkAddress, Occurrence, Selection};
+use pngme::shamir::Threshold;
+use pngme::text::TextKind;
+use pngme::transform::Pipeline;
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
+/// Hide messages in PNG files
+#[derive(Parser, Debug)]
+#[command(
+    name = "pngme",
+    version,
+    about,
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    /// Print what this build can do as JSON: its version, optional features, the chunk types it
+    /// understands, its encode modes and the payload formats it reads and writes
+    #[arg(long)]
+    pub capabilities: bool,
+    #[command(subcommand)]
+    pub command: Option<PngMeArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PngMeArgs {
+    /// Hide a message in a new chunk
+    Encode(EncodeArgs),
+    /// Print the message stored in a chunk
+    Decode(DecodeArgs),
+    /// Remove the first chunk of a type, or the chunks picked by the selection flags
+    Remove(RemoveArgs),
+    /// Print every chunk in a file
+    Print(PrintArgs),
+    /// List every chunk with its offset, length, CRC and flags
+    List(ListArgs),
+    /// Check a file against the PNG specification, reporting every problem found
+    Verify(VerifyArgs),
+    /// Replace damaged chunk CRCs with ones computed from the chunk contents
+    Repair(RepairArgs),
+    /// Report how large a message each method can hide in a file
+    Capacity(CapacityArgs),
+    /// Search the PNGs in a directory for chunks by type, text keyword or data hash
+    Find(FindArgs),
+    /// Remove every chunk that isn't needed to display the image
+    Scrub(ScrubArgs),
+    /// Remove byte-identical copies of ancillary chunks, keeping the first of each
+    Dedup(DedupArgs),
+    /// Show the chunks added, removed, modified or moved between two files
+    Diff(DiffArgs),
+    /// Show the data of a chunk as hex and ASCII, or write it out as is
+    Dump(DumpArgs),
+    /// Write the data of chunks to files of their own
+    Extract(ExtractArgs),
+    /// Show the text, time, pixel size, gamma and Exif metadata of a file
+    Meta(MetaArgs),
+    /// Attach a note to a chunk, kept in a `.pngme.json` file next to the image
+    Annotate(AnnotateArgs),
+    /// Rename the type of every chunk of a type, recomputing their CRCs
+    Retag(RetagArgs),
+    /// Move a chunk, or sort the ancillary chunks into the positions the spec expects
+    Reorder(ReorderArgs),
+    /// Write every chunk to a file of its own in a directory, with a manifest to put them back
+    Explode(ExplodeArgs),
+    /// Rebuild a PNG from a directory written by `explode`, recomputing the CRCs
+    Assemble(AssembleArgs),
+    /// Record the chunk edits between two files, or replay them on another
+    #[command(subcommand)]
+    Patch(PatchCommand),
+    /// Run the edits listed in a TOML or JSON recipe on each file, keeping none of them unless
+    /// all of them work
+    Apply(ApplyArgs),
+    /// Mark the pixels of an image so any later edit to them can be found
+    #[command(subcommand)]
+    Watermark(WatermarkCommand),
+    /// Check the CRC, hash, signature and encryption code against published test vectors and
+    /// round-trip a generated image through every encode mode, reporting each component
+    Selftest(SelftestArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WatermarkCommand {
+    /// Embed a fragile watermark in the least-significant bit of every pixel sample
+    Embed(WatermarkEmbedArgs),
+    /// Report the regions of the image changed since the watermark was embedded
+    Check(WatermarkCheckArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PatchCommand {
+    /// Write the chunk insertions, removals and replacements that turn one file into another
+    Create(PatchCreateArgs),
+    /// Apply a patch written by `patch create`
+    Apply(PatchApplyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct EncodeArgs {
+    /// PNG file to read, `-` for stdin, or a directory to add the message to each of its PNGs
+    pub file: PathBuf,
+    /// Type of the new chunk, the message, and where to write the result, defaulting to `file`
+    /// itself or stdout when reading stdin. Use `-` as OUTPUT to write to stdout. Leave out the
+    /// chunk type with --text-chunk or --method lsb, and the message with --file.
+    #[arg(
+        value_names = ["CHUNK_TYPE", "MESSAGE", "OUTPUT"],
+        num_args = 1..=3,
+        required = true
+    )]
+    pub values: Vec<String>,
+    /// Where to hide the message
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+    /// Store the message in a standard tEXt, zTXt or iTXt chunk instead
+    #[arg(long, value_name = "TYPE", conflicts_with = "method")]
+    pub text_chunk: Option<TextKind>,
+    /// Keyword of the text chunk
+    #[arg(long, default_value = "Comment")]
+    pub keyword: String,
+    /// Language tag of an iTXt chunk, such as `en` or `pt-BR`
+    #[arg(long, requires = "text_chunk")]
+    pub language: Option<String>,
+    /// Encrypt the message with a passphrase, read from PNGME_PASSPHRASE or prompted for
+    #[arg(long)]
+    pub encrypt: bool,
+    /// File holding the passphrase of another recipient, who can decrypt the message on their
+    /// own. Repeat it for each recipient, up to 15 of them.
+    #[arg(long, value_name = "PATH", requires = "encrypt")]
+    pub recipient: Vec<PathBuf>,
+    /// Encrypt the message with a random key split into shares, such as 2-of-3, so that only
+    /// that many shares together decrypt it. The shares go in key share chunks of the image
+    /// itself, or one in each --carrier.
+    #[arg(long, value_name = "K-of-N", conflicts_with = "encrypt")]
+    pub threshold: Option<Threshold>,
+    /// Another PNG to hide one key share in, changed in place. Give one per share, so no single
+    /// file holds enough of them.
+    #[arg(long, value_name = "PATH", requires = "threshold")]
+    pub carrier: Vec<PathBuf>,
+    /// Compress the message before hiding it: zlib, zstd or none. `decode` notices and
+    /// decompresses on its own. Use --text-chunk zTXt for compressed text instead.
+    #[arg(long, default_value = "none", conflicts_with = "text_chunk")]
+    pub compress: Algorithm,
+    /// Run the message through a chain of steps before hiding it, such as
+    /// `gzip,xor:0x5A,base64`. Steps are gzip, base64 and xor with a `0x` hex or text key. The
+    /// chain is recorded with the message, so `decode` undoes it on its own.
+    #[arg(long, value_name = "CHAIN")]
+    pub transform: Option<Pipeline>,
+    /// Hide the contents of a file, or `-` for stdin, instead of a message, split across as many
+    /// chunks as needed
+    #[arg(long = "file", value_name = "PATH", conflicts_with = "text_chunk")]
+    pub payload_file: Option<PathBuf>,
+    /// Append an HMAC-SHA256 of the message made with --key, so changes to it can be detected
+    #[arg(long, requires = "key")]
+    pub sign: bool,
+    /// Have the signature cover the critical chunks too, so changes to the image are detected
+    #[arg(long, requires = "sign")]
+    pub sign_image: bool,
+    /// Signing key, in hex or as the path of a file holding the raw key
+    #[arg(long, requires = "sign")]
+    pub key: Option<String>,
+    /// Where to put the new chunk: before-idat, after-idat or end. By default it goes wherever the
+    /// spec expects its type, which for most hidden chunks is just before IEND.
+    #[arg(long, conflicts_with_all = ["payload_file", "at_index", "at_offset"])]
+    pub position: Option<InsertPosition>,
+    /// Put the new chunk at this position among all chunks, counting from 0, instead of where
+    /// its type goes
+    #[arg(long, value_name = "N", conflicts_with_all = ["payload_file", "at_offset"])]
+    pub at_index: Option<usize>,
+    /// Put the new chunk just before the chunk holding this byte offset, in decimal or as hex
+    /// with a `0x` prefix
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        value_parser = ChunkAddress::parse_offset,
+        conflicts_with = "payload_file"
+    )]
+    pub at_offset: Option<ChunkAddress>,
+    /// Files to work on at once when `file` is a directory, defaulting to one per CPU
+    #[arg(long, short, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+/// Options shared by the commands that rewrite files
+#[derive(Args, Debug, Clone)]
+pub struct WriteArgs {
+    /// Report the chunks that would change and the change in size, without writing anything
+    #[arg(long, short = 'n')]
+    pub dry_run: bool,
+    /// Change files in place without asking. Without it, changing a file in place asks first
+    /// when stdin is a terminal.
+    #[arg(long, short)]
+    pub yes: bool,
+    /// Keep a copy of each file changed in place, with `.bak` added to its name
+    #[arg(long)]
+    pub backup: bool,
+    /// Set the tIME chunk of each file written to the current time, or to SOURCE_DATE_EPOCH when
+    /// it is set
+    #[arg(long)]
+    pub touch: bool,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+}
+
+/// Options for keeping a record of the files a command reads and writes
+#[derive(Args, Debug, Clone, Default)]
+pub struct AuditArgs {
+    /// Append an entry to this hash-chained log for each file read or written, with the hash of
+    /// its contents
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+    /// Sign each entry added to --audit-log with this Ed25519 secret key, in hex or as the path
+    /// of a file holding the raw 32 bytes
+    #[arg(long, value_name = "KEY", requires = "audit_log")]
+    pub audit_key: Option<String>,
+}
+
+/// Options shared by every command for how results are reported
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct ReportArgs {
+    /// Print nothing, only report the result through the exit code
+    #[arg(long, short)]
+    pub quiet: bool,
+    /// Print tab-separated records that stay stable across releases
+    #[arg(long)]
+    pub porcelain: bool,
+}
+
+impl ReportArgs {
+    /// The output mode asked for, with `--format` on top for the commands that have it
+    pub fn mode(&self, format: Format) -> Result<OutputMode, &'static str> {
+        OutputMode::from_args(self.quiet, self.porcelain, format)
+    }
+}
+
+/// Which of the chunks of a type to use, when a file has several. Without any of these the first
+/// chunk is used.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct OccurrenceArgs {
+    /// Use the chunk at this position among the chunks of the type, counting from 0
+    #[arg(long, value_name = "N")]
+    pub index: Option<usize>,
+    /// Use every chunk of the type
+    #[arg(long)]
+    pub all: bool,
+    /// Use the first chunk of the type
+    #[arg(long)]
+    pub first: bool,
+    /// Use the last chunk of the type
+    #[arg(long)]
+    pub last: bool,
+}
+
+impl OccurrenceArgs {
+    pub fn occurrence(&self) -> Result<Occurrence, &'static str> {
+        Occurrence::from_flags(self.index, self.all, self.first, self.last)
+    }
+
+    /// Whether any of the flags was given, rather than falling back to the first chunk
+    pub fn is_given(&self) -> bool {
+        self.index.is_some() || self.all || self.first || self.last
+    }
+}
+
+/// The chunks a command works on, picked by type, by a `--where` filter or, for files where
+/// duplicated or malformed types make that ambiguous, by where they sit in the file
+#[derive(Args, Debug, Clone, Default)]
+pub struct SelectArgs {
+    /// Type of the chunks to use, left out with --at-index, --at-offset or --where
+    #[arg(required_unless_present_any = ["at_index", "at_offset", "filter"])]
+    pub chunk_type: Option<String>,
+    #[command(flatten)]
+    pub occurrence: OccurrenceArgs,
+    /// Use the chunk at this position among all chunks, counting from 0, or the chunks in a
+    /// range such as `5..9` or `5..=8`
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = ChunkAddress::parse_index,
+        conflicts_with_all = ["chunk_type", "at_offset", "filter", "index", "all", "first", "last"]
+    )]
+    pub at_index: Option<ChunkAddress>,
+    /// Use the chunk holding this byte offset, in decimal or as hex with a `0x` prefix
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        value_parser = ChunkAddress::parse_offset,
+        conflicts_with_all = ["chunk_type", "filter", "index", "all", "first", "last"]
+    )]
+    pub at_offset: Option<ChunkAddress>,
+    /// Use every chunk matching an expression such as `type == "tEXt" && length > 1024`.
+    /// Compares type, index, offset, length and crc, tests the flags critical, ancillary,
+    /// public, private, safe, unsafe and standard, and combines them with !, && and ||.
+    #[arg(
+        long = "where",
+        value_name = "EXPR",
+        conflicts_with_all = ["chunk_type", "index", "all", "first", "last"]
+    )]
+    pub filter: Option<Filter>,
+}
+
+impl SelectArgs {
+    pub fn selection(&self) -> Result<Selection, &'static str> {
+        if let Some(filter) = &self.filter {
+            return Ok(Selection::Where(filter.clone()));
+        }
+        match (
+            &self.chunk_type,
+            self.at_index.as_ref().or(self.at_offset.as_ref()),
+        ) {
+            (None, Some(address)) => Ok(Selection::Address(address.clone())),
+            (Some(chunk_type), None) => Ok(Selection::Type(
+                chunk_type.clone(),
+                self.occurrence.occurrence()?,
+            )),
+            _ => Err("expected a chunk type, --at-index, --at-offset or --where"),
+        }
+    }
+}
+
+/// How a message is hidden in an image
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Method {
+    /// In a chunk of its own, placed where the spec expects its type
+    Chunk,
+    /// In the least-significant bit of every pixel sample, inside IDAT
+    Lsb,
+}
+
+/// The positional values of `encode`, after accounting for the flags that replace them
+#[derive(Debug)]
+pub struct Positionals<'a> {
+    pub chunk_type: Option<&'a str>,
+    pub message: Option<&'a str>,
+    pub output: Option<&'a Path>,
+}
+
+impl EncodeArgs {
+    /// Splits the positional values into chunk type, message and output. clap can't skip an
+    /// optional positional that comes before another, so --text-chunk and --method lsb drop the
+    /// chunk type and --file drops the message.
+    pub fn positionals(&self) -> Result<Positionals<'_>, &'static str> {
+        let mut values = self.values.iter().map(String::as_str);
+        let chunk_type = match (self.text_chunk, self.method) {
+            (Some(_), _) | (None, Method::Lsb) => None,
+            (None, Method::Chunk) => Some(values.next().ok_or("expected a chunk type")?),
+        };
+        let message = match self.payload_file {
+            Some(_) => None,
+            None => Some(values.next().ok_or("expected a message")?),
+        };
+        let output = values.next().map(Path::new);
+        match values.next() {
+            Some(_) => Err("too many arguments, expected [CHUNK_TYPE] [MESSAGE] [OUTPUT]"),
+            None => Ok(Positionals {
+                chunk_type,
+                message,
+                output,
+            }),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DecodeArgs {
+    /// PNG file to read, `-` for stdin, or a directory to decode each of its PNGs
+    pub file: PathBuf,
+    /// Chunk type holding the message, left out with --method lsb
+    #[arg(required_unless_present = "method")]
+    pub chunk_type: Option<String>,
+    /// Where the message was hidden
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+    /// For tEXt, zTXt and iTXt chunks, the keyword to look for instead of the first chunk
+    #[arg(long)]
+    pub keyword: Option<String>,
+    #[command(flatten)]
+    pub occurrence: OccurrenceArgs,
+    /// Decrypt a message written with `encode --encrypt`
+    #[arg(long)]
+    pub decrypt: bool,
+    /// Another PNG holding key shares of a message written with `encode --threshold`. Shares in
+    /// the image itself are always read.
+    #[arg(long, value_name = "PATH", conflicts_with = "decrypt")]
+    pub share_file: Vec<PathBuf>,
+    /// Undo a chain of steps such as `gzip,xor:0x5A,base64`, last step first, for messages
+    /// layered by another tool. Chains recorded by `encode --transform` are undone without it.
+    #[arg(long, value_name = "CHAIN")]
+    pub transform: Option<Pipeline>,
+    /// Check the signature of a message written with `encode --sign`, failing if the message or
+    /// a signed image changed
+    #[arg(long, requires = "key")]
+    pub verify_signature: bool,
+    /// Signing key, in hex or as the path of a file holding the raw key
+    #[arg(long, requires = "verify_signature")]
+    pub key: Option<String>,
+    /// Write the message to a file instead of printing it, for payloads hidden with --file. `-`
+    /// writes the raw bytes to stdout.
+    #[arg(long, short, conflicts_with = "format")]
+    pub output: Option<PathBuf>,
+    /// Files to work on at once when `file` is a directory, defaulting to one per CPU
+    #[arg(long, short, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+    /// Recover what can be read from a damaged file, skipping malformed chunks and warning about
+    /// each problem on stderr
+    #[arg(long)]
+    pub lenient: bool,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RemoveArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub select: SelectArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PrintArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Show the notes attached to each chunk with `annotate`
+    #[arg(long)]
+    pub annotations: bool,
+    /// When to color chunk types: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Only list the chunks matching this expression, as for `remove --where`
+    #[arg(long = "where", value_name = "EXPR")]
+    pub filter: Option<Filter>,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// When to color chunk types: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// When to color the result: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Where to write the repaired file, or `-` for stdout, defaulting to `file` itself
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    /// Recover what can be read from a damaged file, skipping malformed chunks and warning about
+    /// each problem on stderr
+    #[arg(long)]
+    pub lenient: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CapacityArgs {
+    /// PNG file to read, `-` for stdin, or a directory to measure each of its PNGs and the total
+    pub file: PathBuf,
+    /// Only report this method, failing when it can't be used on the file
+    #[arg(long, value_enum)]
+    pub method: Option<Method>,
+    /// Measure the PNGs in subdirectories of `file` too
+    #[arg(long, short)]
+    pub recursive: bool,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
+
+#[derive(Args, Debug)]
+pub struct FindArgs {
+    /// Directory whose PNGs are searched
+    pub dir: PathBuf,
+    /// Search the PNGs in subdirectories too
+    #[arg(long, short)]
+    pub recursive: bool,
+    /// Only match chunks of this type
+    #[arg(long)]
+    pub chunk_type: Option<String>,
+    /// Only match tEXt, zTXt and iTXt chunks with this keyword
+    #[arg(long)]
+    pub keyword: Option<String>,
+    /// Only match chunks whose data has this SHA-256, in hex
+    #[arg(long, value_name = "HEX")]
+    pub payload_sha256: Option<String>,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// When to color chunk types: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
+
+#[derive(Args, Debug)]
+pub struct ScrubArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Where to write the scrubbed file, or `-` for stdout, defaulting to `file` itself
+    pub output: Option<PathBuf>,
+    /// Chunk types to keep as well as IHDR, PLTE, IDAT and IEND, separated by commas
+    #[arg(long, value_name = "TYPES", value_delimiter = ',')]
+    pub keep: Vec<String>,
+    /// Only remove chunks of these types, separated by commas
+    #[arg(
+        long,
+        value_name = "TYPES",
+        value_delimiter = ',',
+        conflicts_with = "keep"
+    )]
+    pub remove_only: Vec<String>,
+    /// Only remove the chunks that also match this expression, as for `remove --where`
+    #[arg(long = "where", value_name = "EXPR")]
+    pub filter: Option<Filter>,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    /// Recover what can be read from a damaged file, skipping malformed chunks and warning about
+    /// each problem on stderr
+    #[arg(long)]
+    pub lenient: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DedupArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Where to write the result, or `-` for stdout, defaulting to `file` itself
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// PNG file to compare from, or `-` for stdin
+    pub old: PathBuf,
+    /// PNG file to compare to, or `-` for stdin
+    pub new: PathBuf,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// When to color added and removed chunks: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
+
+#[derive(Args, Debug)]
+pub struct DumpArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub select: SelectArgs,
+    /// Write the data as is instead of a hex dump
+    #[arg(long)]
+    pub raw: bool,
+    /// Where to write the raw data, defaulting to stdout
+    #[arg(long, short, value_name = "PATH", requires = "raw")]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ExtractArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub select: SelectArgs,
+    /// Directory to write the files to
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub out: PathBuf,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RetagArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Type of the chunks to rename
+    #[arg(long, value_name = "TYPE")]
+    pub from: String,
+    /// Type to give them
+    #[arg(long, value_name = "TYPE")]
+    pub to: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ReorderArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Position of the chunk to move among all chunks, counting from 0
+    #[arg(long = "move", value_name = "N", conflicts_with = "spec_order")]
+    pub moved: Option<usize>,
+    /// Put the chunk just before the first chunk of this type
+    #[arg(
+        long,
+        value_name = "TYPE",
+        requires = "moved",
+        conflicts_with = "after"
+    )]
+    pub before: Option<String>,
+    /// Put the chunk just after the last chunk of this type
+    #[arg(long, value_name = "TYPE", requires = "moved")]
+    pub after: Option<String>,
+    /// Sort the ancillary chunks into the positions the spec expects
+    #[arg(long)]
+    pub spec_order: bool,
+    /// Move the chunk even when that breaks the ordering rules, to see how decoders cope
+    #[arg(long, requires = "moved")]
+    pub force: bool,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplodeArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Directory to write the chunk files and manifest to
+    #[arg(long, value_name = "DIR")]
+    pub out: PathBuf,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AssembleArgs {
+    /// Directory written by `explode`
+    pub dir: PathBuf,
+    /// Where to write the rebuilt file, or `-` for stdout
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PatchCreateArgs {
+    /// File the patch applies to
+    pub from: PathBuf,
+    /// File the patch turns it into
+    pub to: PathBuf,
+    /// Where to write the patch, defaulting to stdout
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PatchApplyArgs {
+    /// PNG file to patch, or `-` for stdin
+    pub file: PathBuf,
+    /// Patch written by `patch create`
+    pub patch: PathBuf,
+    /// Where to write the patched file, or `-` for stdout, defaulting to `file` itself
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    /// Recipe to run, read as TOML when it ends in `.toml` and as JSON otherwise
+    pub recipe: PathBuf,
+    /// PNG files to change in place, or directories to change each of their PNGs
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Files to work on at once, defaulting to one per CPU
+    #[arg(long, short, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct WatermarkEmbedArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Where to write the watermarked file, or `-` for stdout, defaulting to `file` itself
+    pub output: Option<PathBuf>,
+    /// Watermark key, in hex or as the path of a file holding the raw key
+    #[arg(long)]
+    pub key: String,
+    #[command(flatten)]
+    pub write: WriteArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct WatermarkCheckArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Key the watermark was embedded with, in hex or as the path of a file holding the raw key
+    #[arg(long)]
+    pub key: String,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct MetaArgs {
+    /// PNG file to read, or `-` for stdin
+    pub file: PathBuf,
+    /// Write the raw eXIf data to PATH, defaulting to the file name with an `.exif` extension
+    #[arg(long, value_name = "PATH")]
+    pub exif: Option<Option<PathBuf>>,
+    #[command(flatten)]
+    pub audit: AuditArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+    /// Recover what can be read from a damaged file, skipping malformed chunks and warning about
+    /// each problem on stderr
+    #[arg(long)]
+    pub lenient: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AnnotateArgs {
+    /// PNG file the note is about. It is only read, never changed.
+    pub file: PathBuf,
+    /// Index of the chunk the note is about, counting from 0 as `list` does
+    #[arg(long, value_name = "N")]
+    pub chunk: usize,
+    pub note: String,
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SelftestArgs {
+    #[command(flatten)]
+    pub report: ReportArgs,
+    /// When to color the results: auto, always or never
+    #[arg(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Output format: human or json
+    #[arg(long, default_value = "human")]
+    pub format: Format,
+}
@@ -0,0 +1,250 @@
+use crate::clock;
+use crate::sidecar::file_hash;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// `previous` hash of the first entry in a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log. `hash` covers every other field except the signature, and each
+/// entry names the hash of the one before it, so removing, reordering or editing an entry breaks
+/// the chain from that point on.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub file: String,
+    pub operation: String,
+    pub input_sha256: Option<String>,
+    pub output_sha256: Option<String>,
+    pub previous: String,
+    pub hash: String,
+    /// Hex Ed25519 signature over the raw bytes of `hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            self.sequence.to_string().as_str(),
+            self.timestamp.to_string().as_str(),
+            &self.file,
+            &self.operation,
+            self.input_sha256.as_deref().unwrap_or(""),
+            self.output_sha256.as_deref().unwrap_or(""),
+            &self.previous,
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update(b"\n");
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Append-only, hash-chained record of what the tool read and changed
+pub struct AuditLog {
+    path: PathBuf,
+    last_hash: String,
+    next_sequence: u64,
+    signing_key: Option<SigningKey>,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, continuing the chain of any entries already in it
+    pub fn open(path: &Path) -> crate::MyResult<AuditLog> {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => parse_entries(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let (last_hash, next_sequence) = match entries.last() {
+            Some(entry) => (entry.hash.clone(), entry.sequence + 1),
+            None => (String::from(GENESIS_HASH), 0),
+        };
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            last_hash,
+            next_sequence,
+            signing_key: None,
+        })
+    }
+
+    /// Signs every entry recorded from now on with the Ed25519 key whose secret is `secret`
+    pub fn with_signing_key(mut self, secret: [u8; 32]) -> AuditLog {
+        self.signing_key = Some(SigningKey::from_bytes(&secret));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an entry for `operation` on `file`, hashing the bytes read and written if any
+    pub fn record(
+        &mut self,
+        file: &Path,
+        operation: &str,
+        input: Option<&[u8]>,
+        output: Option<&[u8]>,
+    ) -> crate::MyResult<AuditEntry> {
+        let mut entry = AuditEntry {
+            sequence: self.next_sequence,
+            timestamp: clock::now()?,
+            file: file.display().to_string(),
+            operation: String::from(operation),
+            input_sha256: input.map(file_hash),
+            output_sha256: output.map(file_hash),
+            previous: self.last_hash.clone(),
+            hash: String::new(),
+            signature: None,
+        };
+        entry.hash = entry.compute_hash();
+        if let Some(key) = &self.signing_key {
+            let signature = key.sign(&hex::decode(&entry.hash)?);
+            entry.signature = Some(hex::encode(signature.to_bytes()));
+        }
+
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(log, "{}", serde_json::to_string(&entry)?)?;
+
+        self.last_hash = entry.hash.clone();
+        self.next_sequence += 1;
+        Ok(entry)
+    }
+}
+
+/// Checks the whole chain in the log at `path` and returns the number of entries. With a
+/// `verifying_key`, every entry must also carry a valid signature from it.
+pub fn verify(path: &Path, verifying_key: Option<[u8; 32]>) -> crate::MyResult<usize> {
+    let entries = parse_entries(&fs::read_to_string(path)?)?;
+    let verifying_key = verifying_key
+        .map(|key| VerifyingKey::from_bytes(&key))
+        .transpose()?;
+
+    let mut previous = String::from(GENESIS_HASH);
+    for (sequence, entry) in entries.iter().enumerate() {
+        if entry.sequence != sequence as u64 {
+            return Err(format!("entry {} is out of sequence", sequence).into());
+        }
+        if entry.previous != previous {
+            return Err(format!("entry {} does not follow the entry before it", sequence).into());
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(format!("entry {} has been altered", sequence).into());
+        }
+        if let Some(key) = &verifying_key {
+            let signature = entry
+                .signature
+                .as_deref()
+                .ok_or(format!("entry {} is not signed", sequence))?;
+            let signature = Signature::from_slice(&hex::decode(signature)?)?;
+            key.verify(&hex::decode(&entry.hash)?, &signature)
+                .map_err(|_| format!("entry {} has an invalid signature", sequence))?;
+        }
+        previous = entry.hash.clone();
+    }
+
+    Ok(entries.len())
+}
+
+fn parse_entries(contents: &str) -> crate::MyResult<Vec<AuditEntry>> {
+    let mut entries = Vec::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        entries.push(serde_json::from_str(line)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    fn testing_log(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pngme-audit-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn public_key() -> [u8; 32] {
+        SigningKey::from_bytes(&SECRET).verifying_key().to_bytes()
+    }
+
+    #[test]
+    fn test_chain_continues_across_opens() {
+        let path = testing_log("chain");
+        let mut log = AuditLog::open(&path).unwrap();
+        let first = log
+            .record(Path::new("a.png"), "read", Some(b"before"), None)
+            .unwrap();
+        assert_eq!(first.previous, GENESIS_HASH);
+
+        let mut log = AuditLog::open(&path).unwrap();
+        let second = log
+            .record(
+                Path::new("a.png"),
+                "encode",
+                Some(b"before"),
+                Some(b"after"),
+            )
+            .unwrap();
+        assert_eq!(second.sequence, 1);
+        assert_eq!(second.previous, first.hash);
+        assert_eq!(second.output_sha256, Some(file_hash(b"after")));
+
+        assert_eq!(verify(&path, None).unwrap(), 2);
+        assert!(verify(&path, Some(public_key())).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_signed_entries_verify() {
+        let path = testing_log("signed");
+        let mut log = AuditLog::open(&path).unwrap().with_signing_key(SECRET);
+        log.record(Path::new("a.png"), "read", Some(b"contents"), None)
+            .unwrap();
+        log.record(Path::new("a.png"), "remove", Some(b"contents"), Some(b""))
+            .unwrap();
+
+        assert_eq!(verify(&path, Some(public_key())).unwrap(), 2);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes();
+        assert!(verify(&path, Some(other)).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let path = testing_log("tampered");
+        let mut log = AuditLog::open(&path).unwrap();
+        for operation in ["read", "encode", "read"] {
+            log.record(Path::new("a.png"), operation, Some(b"x"), None)
+                .unwrap();
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents.replacen("encode", "decode", 1)).unwrap();
+        assert!(verify(&path, None).is_err());
+
+        let lines: Vec<&str> = contents.lines().collect();
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(verify(&path, None).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
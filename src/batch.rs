@@ -0,0 +1,101 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// What happened to one file of a batch. Errors are flattened to a message so results can cross
+/// threads.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FileOutcome<T> {
+    pub path: PathBuf,
+    pub result: Result<T, String>,
+}
+
+/// Worker threads to use when none are asked for: one per available CPU
+pub fn default_jobs() -> NonZeroUsize {
+    thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Runs `operation` on every path using up to `jobs` threads. Workers take the next unclaimed
+/// path as soon as they finish one, so a few large files don't hold up the rest, and outcomes
+/// come back in the order of `paths` however the work was scheduled.
+pub fn run<T, F>(paths: &[PathBuf], jobs: NonZeroUsize, operation: F) -> Vec<FileOutcome<T>>
+where
+    T: Send,
+    F: Fn(&Path) -> Result<T, String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Result<T, String>>>> =
+        paths.iter().map(|_| Mutex::new(None)).collect();
+
+    let workers = jobs.get().min(paths.len());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                let result = operation(path);
+                *results[index].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    paths
+        .iter()
+        .zip(results)
+        .map(|(path, result)| FileOutcome {
+            path: path.clone(),
+            result: result
+                .into_inner()
+                .unwrap()
+                .expect("every path is claimed by a worker"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_keeps_input_order() {
+        let paths: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(i.to_string())).collect();
+        let outcomes = run(&paths, NonZeroUsize::new(4).unwrap(), |path| {
+            let number: u64 = path.to_str().unwrap().parse().unwrap();
+            // Early paths finish last, so completion order is the reverse of input order
+            thread::sleep(Duration::from_millis(20 - number));
+            match number % 7 {
+                0 => Err(format!("{} failed", number)),
+                _ => Ok(number * 2),
+            }
+        });
+
+        assert_eq!(outcomes.len(), 20);
+        for (i, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(outcome.path, paths[i]);
+            match i % 7 {
+                0 => assert_eq!(outcome.result, Err(format!("{} failed", i))),
+                _ => assert_eq!(outcome.result, Ok(i as u64 * 2)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_more_jobs_than_paths() {
+        assert!(run(&[], default_jobs(), |_| Ok(())).is_empty());
+
+        let paths = vec![PathBuf::from("only")];
+        let outcomes = run(&paths, NonZeroUsize::new(8).unwrap(), |path| {
+            Ok(path.to_path_buf())
+        });
+        assert_eq!(outcomes[0].result, Ok(PathBuf::from("only")));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::output::PORCELAIN_VERSION;
+use pngme::compress::COMPRESSED_VERSION;
+use pngme::crypto::{CRYPTO_VERSION, RECIPIENTS_VERSION};
+use pngme::lsb::LSB_VERSION;
+use pngme::patch::PATCH_VERSION;
+use pngme::png::PIECE_VERSION;
+use pngme::shamir::{SHARE_CHUNK_TYPE, SHARE_VERSION};
+use pngme::signing::SIGNED_VERSION;
+use pngme::transform::TRANSFORM_VERSION;
+use pngme::MyResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What this binary can do, for orchestration tooling to inspect before dispatching work to it.
+/// Only what a command or flag of the binary actually uses is listed.
+#[derive(Serialize, Debug, Clone)]
+pub struct Capabilities {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// Optional components and whether this build includes them
+    pub features: BTreeMap<&'static str, bool>,
+    pub chunk_handlers: Vec<ChunkHandler>,
+    pub encode_modes: Vec<&'static str>,
+    /// Steps `--transform` chains can be built from
+    pub transforms: Vec<&'static str>,
+    pub formats: Vec<FormatVersion>,
+}
+
+/// A chunk type the tool interprets rather than treating as opaque bytes
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct ChunkHandler {
+    pub chunk_type: &'static str,
+    pub handling: &'static str,
+}
+
+/// A versioned payload container or file format the tool reads and writes
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct FormatVersion {
+    pub name: &'static str,
+    pub versions: Vec<u32>,
+}
+
+pub fn capabilities() -> Capabilities {
+    let features = BTreeMap::from([
+        ("crypto", true),
+        ("signing", true),
+        ("mmap", cfg!(feature = "mmap")),
+        ("zstd", cfg!(feature = "zstd")),
+    ]);
+
+    let handler = |chunk_type, handling| ChunkHandler {
+        chunk_type,
+        handling,
+    };
+    let chunk_handlers = vec![
+        handler("IHDR", "image layout"),
+        handler("IDAT", "image data decode and re-encode"),
+        handler("IEND", "ordering"),
+        handler("tEXt", "keyword search"),
+        handler("zTXt", "keyword search"),
+        handler("iTXt", "keyword search"),
+        handler("tIME", "metadata and reproducible timestamps"),
+        handler("pHYs", "metadata"),
+        handler("gAMA", "metadata"),
+        handler("eXIf", "metadata"),
+        handler("acTL", "animation sequence checks"),
+        handler("fcTL", "animation sequence checks"),
+        handler("fdAT", "animation sequence checks"),
+        handler(SHARE_CHUNK_TYPE, "key share recovery"),
+    ];
+
+    let format = |name, versions| FormatVersion { name, versions };
+    let formats = vec![
+        format("porcelain", vec![PORCELAIN_VERSION]),
+        format("encrypted-payload", vec![CRYPTO_VERSION]),
+        format("multi-recipient-payload", vec![RECIPIENTS_VERSION]),
+        format("payload-pieces", vec![PIECE_VERSION]),
+        format("lsb-payload", vec![LSB_VERSION]),
+        format("signed-payload", vec![SIGNED_VERSION]),
+        format("compressed-payload", vec![COMPRESSED_VERSION]),
+        format("chunk-patch", vec![PATCH_VERSION]),
+        format("key-share", vec![SHARE_VERSION.into()]),
+        format("transform-header", vec![TRANSFORM_VERSION]),
+    ];
+
+    Capabilities {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        features,
+        chunk_handlers,
+        encode_modes: vec!["chunk", "lsb", "shamir", "text", "watermark"],
+        transforms: vec!["gzip", "xor", "base64"],
+        formats,
+    }
+}
+
+/// The `--capabilities` output
+pub fn to_json() -> MyResult<String> {
+    Ok(serde_json::to_string_pretty(&capabilities())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_json() {
+        let json: serde_json::Value = serde_json::from_str(&to_json().unwrap()).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["features"]["crypto"], true);
+        assert_eq!(json["chunk_handlers"][0]["chunk_type"], "IHDR");
+        assert!(json["encode_modes"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::Value::from("chunk")));
+        assert_eq!(json["formats"][1]["versions"][0], CRYPTO_VERSION);
+        assert_eq!(
+            json["transforms"],
+            serde_json::json!(["gzip", "xor", "base64"])
+        );
+        assert!(json["formats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|format| format["name"] == "transform-header"
+                && format["versions"][0] == TRANSFORM_VERSION));
+    }
+}
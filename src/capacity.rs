@@ -0,0 +1,152 @@
+use crate::chunk::Chunk;
+use crate::corpus::{self, Skipped};
+use crate::error::PngMeError;
+use crate::pixels::ImageData;
+use crate::png::Png;
+use std::path::{Path, PathBuf};
+
+/// Largest data field the spec allows in a single chunk. Files can carry any number of chunks, so
+/// this is a per-chunk figure rather than a hard limit for the file.
+pub const MAX_CHUNK_LENGTH: u64 = (1 << 31) - 1;
+
+/// How many bytes each hiding strategy can carry in one file
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileCapacity {
+    pub path: PathBuf,
+    /// Bytes per ancillary chunk
+    pub chunk: u64,
+    /// Bytes in the least-significant bit of every sample, or `None` when the image layout has
+    /// no usable bit plane (palette, sub-byte depths or interlacing)
+    pub lsb: Option<u64>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CapacityTotals {
+    pub files: usize,
+    pub chunk: u64,
+    pub lsb: u64,
+    pub lsb_files: usize,
+}
+
+/// Hiding capacity of every PNG under a directory, per file and summed per strategy
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CapacityReport {
+    pub files: Vec<FileCapacity>,
+    pub skipped: Vec<Skipped>,
+    pub totals: CapacityTotals,
+}
+
+/// Bytes that fit in the least-significant bit of every sample of an 8 or 16-bit, non-palette,
+/// non-interlaced image
+pub fn lsb_capacity(png: &Png) -> Result<u64, PngMeError> {
+    let image = ImageData::describe(png)?;
+    if !image.has_lsb_plane() {
+        return Err(PngMeError::Invalid(
+            "LSB embedding needs an 8 or 16-bit image without a palette",
+        ));
+    }
+
+    let samples = image.width as u64 * image.height as u64 * image.channels().unwrap_or(0) as u64;
+    Ok(samples / 8)
+}
+
+/// Message length past which hiding in chunks deserves a warning, because the file would more
+/// than double in size. Chunk mode has no hard limit, since long messages are split across
+/// chunks, but an image much larger than its dimensions explain stands out.
+pub fn chunk_warning_len(png: &Png) -> u64 {
+    let chunks: usize = png.chunks().iter().map(Chunk::total_size).sum();
+    (Png::STANDARD_HEADER.len() + chunks) as u64
+}
+
+pub fn file_capacity(path: &Path, png: &Png) -> FileCapacity {
+    FileCapacity {
+        path: path.to_path_buf(),
+        chunk: MAX_CHUNK_LENGTH,
+        lsb: lsb_capacity(png).ok(),
+    }
+}
+
+/// Measures every PNG in `dir`. Files that can't be read or parsed are listed as skipped rather
+/// than failing the whole report.
+pub fn corpus_capacity(dir: &Path, recursive: bool) -> crate::MyResult<CapacityReport> {
+    let mut report = CapacityReport::default();
+
+    for path in corpus::png_files(dir, recursive)? {
+        match corpus::read_png(&path) {
+            Ok(png) => {
+                let capacity = file_capacity(&path, &png);
+                report.totals.files += 1;
+                report.totals.chunk += capacity.chunk;
+                if let Some(lsb) = capacity.lsb {
+                    report.totals.lsb += lsb;
+                    report.totals.lsb_files += 1;
+                }
+                report.files.push(capacity);
+            }
+            Err(reason) => report.skipped.push(Skipped { path, reason }),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_image;
+    use std::{env, fs};
+
+    #[test]
+    fn test_lsb_capacity() {
+        assert_eq!(lsb_capacity(&testing_image(16, 10, 6, 8)).unwrap(), 80);
+        assert_eq!(lsb_capacity(&testing_image(16, 10, 0, 16)).unwrap(), 20);
+        assert!(lsb_capacity(&testing_image(16, 10, 3, 8)).is_err());
+        assert!(lsb_capacity(&testing_image(16, 10, 0, 4)).is_err());
+    }
+
+    #[test]
+    fn test_chunk_warning_len() {
+        let png = testing_image(16, 10, 6, 8);
+        assert_eq!(chunk_warning_len(&png), png.as_bytes().len() as u64);
+    }
+
+    #[test]
+    fn test_corpus_capacity() {
+        let dir = env::temp_dir().join(format!("pngme-capacity-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("rgb.png"), testing_image(8, 8, 2, 8).as_bytes()).unwrap();
+        fs::write(
+            dir.join("palette.png"),
+            testing_image(8, 8, 3, 8).as_bytes(),
+        )
+        .unwrap();
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        fs::write(
+            dir.join("nested/gray.png"),
+            testing_image(8, 8, 0, 8).as_bytes(),
+        )
+        .unwrap();
+
+        let report = corpus_capacity(&dir, true).unwrap();
+        assert_eq!(report.totals.files, 3);
+        assert_eq!(report.totals.lsb_files, 2);
+        assert_eq!(report.totals.lsb, 24 + 8);
+        assert_eq!(report.totals.chunk, 3 * MAX_CHUNK_LENGTH);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].path.ends_with("broken.png"));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&report).unwrap();
+            assert_eq!(json["totals"]["lsb"], 32);
+        }
+
+        let report = corpus_capacity(&dir, false).unwrap();
+        assert_eq!(report.totals.files, 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,11 +1,10 @@
-#![allow(dead_code)]
-
 use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
 use crc::{Crc, CRC_32_ISO_HDLC};
 use std::{
     fmt::{Display, Formatter},
     mem::size_of,
-    str, u32,
+    str,
 };
 
 const U_32_LEN: usize = size_of::<u32>();
@@ -18,51 +17,194 @@ pub struct Chunk {
     crc: u32,
 }
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = &'static str;
+/// A chunk read in place from a larger buffer, such as a whole file, so its data isn't copied.
+/// Use [`ChunkRef::to_chunk`] to get a [`Chunk`] that can be edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    length: u32,
+    chunk_type: u32,
+    data: &'a [u8],
+    crc: u32,
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngMeError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let chunk = ChunkRef::parse_lenient(value)?;
+
+        // check if crc(which includes chunk_type and chunk_data) is valid
+        match chunk.has_valid_crc() {
+            true => Ok(chunk),
+            false => Err(PngMeError::CrcMismatch {
+                expected: chunk.computed_crc(),
+                actual: chunk.crc(),
+            }),
+        }
+    }
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Reads the chunk at the start of `value` like `try_from`, but keeps the stored CRC even
+    /// when it doesn't match the type and data. The type must still be four ASCII letters.
+    pub fn parse_lenient(value: &'a [u8]) -> Result<ChunkRef<'a>, PngMeError> {
         let mut u32_dst = [0u8; 4];
         let mut start_index = 0;
         let mut end_index = U_32_LEN;
         // NOTE: Using non-inclusive ranges
 
         // length field
-        u32_dst.clone_from_slice(&value[start_index..end_index]);
+        u32_dst.clone_from_slice(field(value, start_index, end_index)?);
         let length = u32::from_be_bytes(u32_dst);
 
         // chunk_type field
         start_index = end_index;
         end_index += U_32_LEN;
-        u32_dst.clone_from_slice(&value[start_index..end_index]);
-        let chunk_type = u32::from_le_bytes(u32_dst);
+        u32_dst.clone_from_slice(field(value, start_index, end_index)?);
+        let chunk_type = u32::from_le_bytes(ChunkType::try_from(u32_dst)?.bytes());
 
         // chunk_data field
         start_index = end_index; // skipping prev two fields
-        let end_index = start_index + (length as usize);
-        let mut chunk_data: Vec<u8> = vec![];
-        chunk_data.extend(&value[start_index..end_index]);
+        let end_index = start_index
+            .checked_add(length as usize)
+            .ok_or(PngMeError::TruncatedChunk)?;
+        let data = field(value, start_index, end_index)?;
 
         // crc field
         let start_index = end_index;
         let end_index = start_index + U_32_LEN;
-        u32_dst.clone_from_slice(&value[start_index..end_index]);
+        u32_dst.clone_from_slice(field(value, start_index, end_index)?);
         let crc = u32::from_be_bytes(u32_dst);
 
-        let chunk_type = ChunkType::new(chunk_type);
+        Ok(ChunkRef {
+            length,
+            chunk_type,
+            data,
+            crc,
+        })
+    }
+    /// Returns the usize of the entire chunk, as [`Chunk::total_size`]
+    pub fn total_size(&self) -> usize {
+        (self.length() as usize) + (2 * U_32_LEN) + (size_of::<ChunkType>())
+    }
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+    pub fn chunk_type(&self) -> ChunkType {
+        ChunkType::new(self.chunk_type)
+    }
+    /// The data field, borrowed from the buffer the chunk was read from
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+    pub fn computed_crc(&self) -> u32 {
+        compute_crc(&self.chunk_type(), self.data)
+    }
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+    /// Copies the data into an owned [`Chunk`], keeping the stored CRC
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            length: self.length,
+            chunk_type: self.chunk_type(),
+            chunk_data: self.data.to_vec(),
+            crc: self.crc,
+        }
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Chunk {
+        chunk.to_chunk()
+    }
+}
 
-        let chunk = Chunk::new(chunk_type, chunk_data);
+impl<'a> From<&'a Chunk> for ChunkRef<'a> {
+    fn from(chunk: &'a Chunk) -> ChunkRef<'a> {
+        ChunkRef {
+            length: chunk.length,
+            chunk_type: u32::from_le_bytes(chunk.chunk_type.bytes()),
+            data: &chunk.chunk_data,
+            crc: chunk.crc,
+        }
+    }
+}
 
-        // check if length and crc(which includes chunk_type and chunk_data) are valid
-        let is_valid_chunk = length == chunk.length() && crc == chunk.crc();
+impl TryFrom<&[u8]> for Chunk {
+    type Error = PngMeError;
 
-        match is_valid_chunk {
-            true => Ok(chunk),
-            false => Err("Given invalid byte array"),
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        ChunkRef::try_from(value).map(Chunk::from)
+    }
+}
+
+impl Chunk {
+    /// Parses a chunk like `try_from` but keeps the stored CRC even when it doesn't match the
+    /// type and data, so a chunk with a damaged CRC can still be read and repaired
+    pub fn parse_lenient(value: &[u8]) -> Result<Chunk, PngMeError> {
+        ChunkRef::parse_lenient(value).map(Chunk::from)
+    }
+}
+
+/// Serializes as the chunk type, the data in base64 and the stored CRC
+#[cfg(feature = "serde")]
+impl serde::Serialize for Chunk {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use serde::ser::SerializeStruct;
+
+        let mut chunk = serializer.serialize_struct("Chunk", 3)?;
+        chunk.serialize_field("chunk_type", &self.chunk_type)?;
+        chunk.serialize_field("data", &STANDARD.encode(&self.chunk_data))?;
+        chunk.serialize_field("crc", &self.crc)?;
+        chunk.end()
+    }
+}
+
+/// The CRC can be left out and is then computed. A CRC that doesn't match is kept, as with
+/// [`Chunk::parse_lenient`], so fixtures can describe damaged files.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Chunk {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            chunk_type: ChunkType,
+            data: String,
+            crc: Option<u32>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let data = STANDARD
+            .decode(&fields.data)
+            .map_err(serde::de::Error::custom)?;
+        let mut chunk = Chunk::new(fields.chunk_type, data);
+        if let Some(crc) = fields.crc {
+            chunk.crc = crc;
         }
+        Ok(chunk)
     }
 }
 
+/// CRC of the type and data fields, as the spec defines it
+fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let crc: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC); // spec mentions using iso-3309 crc method
+    let mut digest = crc.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
+/// Returns `value[start..end]`, or an error when the slice ends before `end`
+fn field(value: &[u8], start: usize, end: usize) -> Result<&[u8], PngMeError> {
+    value.get(start..end).ok_or(PngMeError::TruncatedChunk)
+}
+
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Chunk {{",)?;
@@ -77,18 +219,14 @@ impl Display for Chunk {
 
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let crc: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC); // spec mentions using iso-3309 crc method
-        let type_bytes = chunk_type.bytes();
-        let data_bytes = data.as_slice();
-        let crc_bytes = [&type_bytes, data_bytes].concat();
-        let checksum = crc.checksum(&crc_bytes);
-
-        Chunk {
+        let mut chunk = Chunk {
             length: data.len() as u32,
             chunk_type,
             chunk_data: data,
-            crc: checksum,
-        }
+            crc: 0,
+        };
+        chunk.crc = chunk.computed_crc();
+        chunk
     }
     /// Returns the usize of an entire chunk, including its length, crc, chunktype fields, and the size allocated to the data field
     pub fn total_size(&self) -> usize {
@@ -107,10 +245,25 @@ impl Chunk {
     pub fn crc(&self) -> u32 {
         self.crc
     }
-    pub fn data_as_string(&self) -> crate::MyResult<String> {
+    /// Returns the CRC of the type and data fields, which differs from `crc` only for chunks read
+    /// with [`Chunk::parse_lenient`]
+    pub fn computed_crc(&self) -> u32 {
+        compute_crc(&self.chunk_type, &self.chunk_data)
+    }
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+    /// Replaces the stored CRC with the one computed from the type and data, returning whether it
+    /// changed
+    pub fn recompute_crc(&mut self) -> bool {
+        let stored = self.crc;
+        self.crc = self.computed_crc();
+        stored != self.crc
+    }
+    pub fn data_as_string(&self) -> Result<String, PngMeError> {
         match str::from_utf8(&self.chunk_data) {
             Ok(data_string) => Ok(String::from(data_string)),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(PngMeError::Utf8(e)),
         }
     }
 
@@ -235,6 +388,101 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_ref() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend_from_slice(b"next chunk");
+        let chunk = ChunkRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.total_size(), bytes.len() - b"next chunk".len());
+        assert!(
+            std::ptr::eq(chunk.data().as_ptr(), bytes[8..].as_ptr()),
+            "data must borrow from the input"
+        );
+        assert_eq!(chunk.to_chunk().as_bytes(), testing_chunk().as_bytes());
+
+        bytes[8] ^= 1;
+        assert!(ChunkRef::try_from(bytes.as_slice()).is_err());
+        assert!(!ChunkRef::parse_lenient(&bytes).unwrap().has_valid_crc());
+    }
+
+    #[test]
+    fn test_non_letter_chunk_type() {
+        let chunk = Chunk::new(
+            ChunkType::from_bytes(*b"\xff\xfe\x00\x01"),
+            b"data".to_vec(),
+        );
+        assert_eq!(chunk.chunk_type().to_string(), "\\xff\\xfe\\x00\\x01");
+        assert!(chunk.has_valid_crc());
+        let bytes = chunk.as_bytes();
+        for result in [
+            Chunk::try_from(bytes.as_ref()),
+            Chunk::parse_lenient(bytes.as_ref()),
+        ] {
+            assert!(matches!(result, Err(PngMeError::InvalidChunkType(_))));
+        }
+    }
+
+    #[test]
+    fn test_recompute_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            Chunk::try_from(bytes.as_ref()),
+            Err(PngMeError::CrcMismatch {
+                expected: 2882656334,
+                actual: 2882656335
+            })
+        ));
+
+        let mut chunk = Chunk::parse_lenient(bytes.as_ref()).unwrap();
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.as_bytes(), bytes, "stored CRC must be kept");
+        assert!(chunk.recompute_crc());
+        assert_eq!(chunk.crc(), 2882656334);
+        assert!(!chunk.recompute_crc());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_chunk_serde() {
+        let chunk = testing_chunk();
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "chunk_type": "RuSt",
+                "data": "VGhpcyBpcyB3aGVyZSB5b3VyIHNlY3JldCBtZXNzYWdlIHdpbGwgYmUh",
+                "crc": 2882656334u32
+            })
+        );
+        let parsed: Chunk = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+
+        let parsed: Chunk =
+            serde_json::from_str(r#"{"chunk_type": "ruSt", "data": "YQ==", "crc": 1}"#).unwrap();
+        assert_eq!(parsed.data(), b"a");
+        assert!(!parsed.has_valid_crc());
+        let parsed: Chunk = serde_json::from_str(r#"{"chunk_type": "ruSt", "data": ""}"#).unwrap();
+        assert!(parsed.has_valid_crc());
+        assert!(serde_json::from_str::<Chunk>(r#"{"chunk_type": "ruSt", "data": "!"}"#).is_err());
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let bytes = testing_chunk().as_bytes();
+        for len in [0, 3, 7, 20, bytes.len() - 1] {
+            assert!(matches!(
+                Chunk::try_from(&bytes[..len]),
+                Err(PngMeError::TruncatedChunk)
+            ));
+        }
+
+        let huge_length = [&u32::MAX.to_be_bytes()[..], b"RuSt"].concat();
+        assert!(Chunk::try_from(huge_length.as_slice()).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -1,4 +1,6 @@
-use std::{fmt::Display, mem, str, str::FromStr};
+use crate::error::PngMeError;
+use crate::ordering::Placement;
+use std::{fmt::Display, mem, str::FromStr};
 const TYPE_LEN: usize = mem::size_of::<u32>();
 
 #[derive(PartialEq, Eq, Debug, Default, Clone)]
@@ -7,24 +9,29 @@ pub struct ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = PngMeError;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        Ok(ChunkType::new(u32::from_ne_bytes(value)))
+        match value.iter().all(u8::is_ascii_alphabetic) {
+            true => Ok(ChunkType::from_bytes(value)),
+            false => Err(PngMeError::InvalidChunkType("non-alphabetic character")),
+        }
     }
 }
 
 impl FromStr for ChunkType {
-    type Err = &'static str;
+    type Err = PngMeError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.len() != TYPE_LEN {
-            return Err("incorrect number of bytes in from_str parameter");
+            return Err(PngMeError::InvalidChunkType(
+                "incorrect number of bytes in from_str parameter",
+            ));
         }
 
         let mut result: u32 = 0;
         for (index, byte) in s.as_bytes().iter().enumerate() {
             if !byte.is_ascii_alphabetic() {
-                return Err("non-alphabetic character");
+                return Err(PngMeError::InvalidChunkType("non-alphabetic character"));
             }
 
             let byte = *byte as u32;
@@ -35,19 +42,254 @@ impl FromStr for ChunkType {
     }
 }
 
+/// The four letters, or escapes such as `\xff` for bytes that aren't printable, since a type
+/// made with [`ChunkType::from_bytes`] isn't checked
 impl Display for ChunkType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", str::from_utf8(&self.bytes()).unwrap())
+        write!(f, "{}", self.bytes().escape_ascii())
+    }
+}
+
+/// Serializes as the four-letter name
+#[cfg(feature = "serde")]
+impl serde::Serialize for ChunkType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(&self.bytes()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ChunkType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        ChunkType::from_str(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The chunk types of the PNG specification, its registered extensions and APNG, named as the
+/// types are
+#[allow(non_upper_case_globals)]
+impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType::from_bytes(*b"IHDR");
+    pub const PLTE: ChunkType = ChunkType::from_bytes(*b"PLTE");
+    pub const IDAT: ChunkType = ChunkType::from_bytes(*b"IDAT");
+    pub const IEND: ChunkType = ChunkType::from_bytes(*b"IEND");
+    pub const cHRM: ChunkType = ChunkType::from_bytes(*b"cHRM");
+    pub const gAMA: ChunkType = ChunkType::from_bytes(*b"gAMA");
+    pub const iCCP: ChunkType = ChunkType::from_bytes(*b"iCCP");
+    pub const sBIT: ChunkType = ChunkType::from_bytes(*b"sBIT");
+    pub const sRGB: ChunkType = ChunkType::from_bytes(*b"sRGB");
+    pub const cICP: ChunkType = ChunkType::from_bytes(*b"cICP");
+    pub const mDCV: ChunkType = ChunkType::from_bytes(*b"mDCV");
+    pub const cLLI: ChunkType = ChunkType::from_bytes(*b"cLLI");
+    pub const tRNS: ChunkType = ChunkType::from_bytes(*b"tRNS");
+    pub const bKGD: ChunkType = ChunkType::from_bytes(*b"bKGD");
+    pub const hIST: ChunkType = ChunkType::from_bytes(*b"hIST");
+    pub const pHYs: ChunkType = ChunkType::from_bytes(*b"pHYs");
+    pub const sPLT: ChunkType = ChunkType::from_bytes(*b"sPLT");
+    pub const eXIf: ChunkType = ChunkType::from_bytes(*b"eXIf");
+    pub const tIME: ChunkType = ChunkType::from_bytes(*b"tIME");
+    pub const tEXt: ChunkType = ChunkType::from_bytes(*b"tEXt");
+    pub const zTXt: ChunkType = ChunkType::from_bytes(*b"zTXt");
+    pub const iTXt: ChunkType = ChunkType::from_bytes(*b"iTXt");
+    pub const acTL: ChunkType = ChunkType::from_bytes(*b"acTL");
+    pub const fcTL: ChunkType = ChunkType::from_bytes(*b"fcTL");
+    pub const fdAT: ChunkType = ChunkType::from_bytes(*b"fdAT");
+    pub const oFFs: ChunkType = ChunkType::from_bytes(*b"oFFs");
+    pub const pCAL: ChunkType = ChunkType::from_bytes(*b"pCAL");
+    pub const sCAL: ChunkType = ChunkType::from_bytes(*b"sCAL");
+    pub const sTER: ChunkType = ChunkType::from_bytes(*b"sTER");
+    pub const gIFg: ChunkType = ChunkType::from_bytes(*b"gIFg");
+    pub const gIFx: ChunkType = ChunkType::from_bytes(*b"gIFx");
+}
+
+/// A chunk type defined by the PNG specification, its registered extensions or APNG, with the
+/// rules the spec sets for where and how often it appears
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StandardChunk {
+    Ihdr,
+    Plte,
+    Idat,
+    Iend,
+    Chrm,
+    Gama,
+    Iccp,
+    Sbit,
+    Srgb,
+    Cicp,
+    Mdcv,
+    Clli,
+    Trns,
+    Bkgd,
+    Hist,
+    Phys,
+    Splt,
+    Exif,
+    Time,
+    Text,
+    Ztxt,
+    Itxt,
+    Actl,
+    Fctl,
+    Fdat,
+    Offs,
+    Pcal,
+    Scal,
+    Ster,
+    Gifg,
+    Gifx,
+}
+
+impl StandardChunk {
+    pub const ALL: [StandardChunk; 31] = [
+        StandardChunk::Ihdr,
+        StandardChunk::Plte,
+        StandardChunk::Idat,
+        StandardChunk::Iend,
+        StandardChunk::Chrm,
+        StandardChunk::Gama,
+        StandardChunk::Iccp,
+        StandardChunk::Sbit,
+        StandardChunk::Srgb,
+        StandardChunk::Cicp,
+        StandardChunk::Mdcv,
+        StandardChunk::Clli,
+        StandardChunk::Trns,
+        StandardChunk::Bkgd,
+        StandardChunk::Hist,
+        StandardChunk::Phys,
+        StandardChunk::Splt,
+        StandardChunk::Exif,
+        StandardChunk::Time,
+        StandardChunk::Text,
+        StandardChunk::Ztxt,
+        StandardChunk::Itxt,
+        StandardChunk::Actl,
+        StandardChunk::Fctl,
+        StandardChunk::Fdat,
+        StandardChunk::Offs,
+        StandardChunk::Pcal,
+        StandardChunk::Scal,
+        StandardChunk::Ster,
+        StandardChunk::Gifg,
+        StandardChunk::Gifx,
+    ];
+
+    pub fn chunk_type(self) -> ChunkType {
+        match self {
+            StandardChunk::Ihdr => ChunkType::IHDR,
+            StandardChunk::Plte => ChunkType::PLTE,
+            StandardChunk::Idat => ChunkType::IDAT,
+            StandardChunk::Iend => ChunkType::IEND,
+            StandardChunk::Chrm => ChunkType::cHRM,
+            StandardChunk::Gama => ChunkType::gAMA,
+            StandardChunk::Iccp => ChunkType::iCCP,
+            StandardChunk::Sbit => ChunkType::sBIT,
+            StandardChunk::Srgb => ChunkType::sRGB,
+            StandardChunk::Cicp => ChunkType::cICP,
+            StandardChunk::Mdcv => ChunkType::mDCV,
+            StandardChunk::Clli => ChunkType::cLLI,
+            StandardChunk::Trns => ChunkType::tRNS,
+            StandardChunk::Bkgd => ChunkType::bKGD,
+            StandardChunk::Hist => ChunkType::hIST,
+            StandardChunk::Phys => ChunkType::pHYs,
+            StandardChunk::Splt => ChunkType::sPLT,
+            StandardChunk::Exif => ChunkType::eXIf,
+            StandardChunk::Time => ChunkType::tIME,
+            StandardChunk::Text => ChunkType::tEXt,
+            StandardChunk::Ztxt => ChunkType::zTXt,
+            StandardChunk::Itxt => ChunkType::iTXt,
+            StandardChunk::Actl => ChunkType::acTL,
+            StandardChunk::Fctl => ChunkType::fcTL,
+            StandardChunk::Fdat => ChunkType::fdAT,
+            StandardChunk::Offs => ChunkType::oFFs,
+            StandardChunk::Pcal => ChunkType::pCAL,
+            StandardChunk::Scal => ChunkType::sCAL,
+            StandardChunk::Ster => ChunkType::sTER,
+            StandardChunk::Gifg => ChunkType::gIFg,
+            StandardChunk::Gifx => ChunkType::gIFx,
+        }
+    }
+
+    /// Where the chunk may appear relative to the critical chunks
+    pub fn placement(self) -> Placement {
+        match self {
+            StandardChunk::Ihdr => Placement::First,
+            StandardChunk::Plte => Placement::Plte,
+            StandardChunk::Idat => Placement::Idat,
+            StandardChunk::Iend => Placement::Last,
+            StandardChunk::Chrm
+            | StandardChunk::Gama
+            | StandardChunk::Iccp
+            | StandardChunk::Sbit
+            | StandardChunk::Srgb
+            | StandardChunk::Cicp
+            | StandardChunk::Mdcv
+            | StandardChunk::Clli => Placement::BeforePlte,
+            StandardChunk::Trns | StandardChunk::Bkgd | StandardChunk::Hist => {
+                Placement::AfterPlteBeforeIdat
+            }
+            StandardChunk::Phys
+            | StandardChunk::Splt
+            | StandardChunk::Exif
+            | StandardChunk::Actl
+            | StandardChunk::Offs
+            | StandardChunk::Pcal
+            | StandardChunk::Scal
+            | StandardChunk::Ster => Placement::BeforeIdat,
+            StandardChunk::Time
+            | StandardChunk::Text
+            | StandardChunk::Ztxt
+            | StandardChunk::Itxt
+            | StandardChunk::Fctl
+            | StandardChunk::Fdat
+            | StandardChunk::Gifg
+            | StandardChunk::Gifx => Placement::Anywhere,
+        }
+    }
+
+    /// Whether a file may hold more than one chunk of the type
+    pub fn allows_multiple(self) -> bool {
+        matches!(
+            self,
+            StandardChunk::Idat
+                | StandardChunk::Splt
+                | StandardChunk::Text
+                | StandardChunk::Ztxt
+                | StandardChunk::Itxt
+                | StandardChunk::Fctl
+                | StandardChunk::Fdat
+                | StandardChunk::Gifg
+                | StandardChunk::Gifx
+        )
+    }
+
+    /// Whether every valid file has a chunk of the type
+    pub fn is_required(self) -> bool {
+        matches!(
+            self,
+            StandardChunk::Ihdr | StandardChunk::Idat | StandardChunk::Iend
+        )
+    }
+}
+
+impl Display for StandardChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(&self.chunk_type().to_string())
     }
 }
 
 impl ChunkType {
     const U8_FIRST_BIT_MASK: u8 = 0x1;
 
-    pub fn new(value: u32) -> ChunkType {
+    pub const fn new(value: u32) -> ChunkType {
         ChunkType { chunk_type: value }
     }
 
+    pub const fn from_bytes(bytes: [u8; 4]) -> ChunkType {
+        ChunkType::new(u32::from_ne_bytes(bytes))
+    }
+
     pub fn bytes(&self) -> [u8; 4] {
         self.chunk_type.to_ne_bytes()
     }
@@ -58,21 +300,118 @@ impl ChunkType {
     // "A decoder encountering an unknown chunk in which the ancillary bit
     // is 1 can safely ignore the chunk and proceed to display the image. "
     // Probably good for hiding messages
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         (self.bytes()[0] >> 5) & Self::U8_FIRST_BIT_MASK == 0 // fifth bit of first byte encodes critical/ancillary
     }
 
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         (self.bytes()[1] >> 5) & Self::U8_FIRST_BIT_MASK == 0 // fifth bit of second byte encodes public/private
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         (self.bytes()[2] >> 5) & Self::U8_FIRST_BIT_MASK == 0 // fifth bit of third  byte reserved for future use
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         (self.bytes()[3] >> 5) & Self::U8_FIRST_BIT_MASK == 1 // fifth bit of fourth  byte reserved for safe/unsafe to copy for editors
     }
+
+    /// Whether the type is defined by the PNG specification or its registered extensions, as
+    /// opposed to a private or unregistered type
+    pub fn is_standard(&self) -> bool {
+        self.standard().is_some()
+    }
+
+    /// The standard chunk this type names, if any
+    pub fn standard(&self) -> Option<StandardChunk> {
+        StandardChunk::ALL
+            .into_iter()
+            .find(|standard| standard.chunk_type() == *self)
+    }
+
+    /// Names the critical, public and safe-to-copy bits, using the same words as `--where`
+    pub fn flags(&self) -> [&'static str; 3] {
+        let critical = match self.is_critical() {
+            true => "critical",
+            false => "ancillary",
+        };
+        let public = match self.is_public() {
+            true => "public",
+            false => "private",
+        };
+        let safe = match self.is_safe_to_copy() {
+            true => "safe",
+            false => "unsafe",
+        };
+        [critical, public, safe]
+    }
+
+    /// Lists the property bits that differ when a chunk of this type is renamed to `to`
+    pub fn property_changes(&self, to: &ChunkType) -> Vec<PropertyChange> {
+        let mut changes = Vec::new();
+        if self.is_critical() != to.is_critical() {
+            changes.push(match to.is_critical() {
+                true => PropertyChange::BecomesCritical,
+                false => PropertyChange::BecomesAncillary,
+            });
+        }
+        if self.is_public() != to.is_public() {
+            changes.push(match to.is_public() {
+                true => PropertyChange::BecomesPublic,
+                false => PropertyChange::BecomesPrivate,
+            });
+        }
+        if self.is_safe_to_copy() != to.is_safe_to_copy() {
+            changes.push(match to.is_safe_to_copy() {
+                true => PropertyChange::BecomesSafeToCopy,
+                false => PropertyChange::BecomesUnsafeToCopy,
+            });
+        }
+        if !to.is_reserved_bit_valid() {
+            changes.push(PropertyChange::ReservedBitSet);
+        }
+
+        changes
+    }
+}
+
+/// A change in how decoders and editors treat a chunk after its type is rewritten
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PropertyChange {
+    BecomesCritical,
+    BecomesAncillary,
+    BecomesPublic,
+    BecomesPrivate,
+    BecomesSafeToCopy,
+    BecomesUnsafeToCopy,
+    ReservedBitSet,
+}
+
+impl Display for PropertyChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            PropertyChange::BecomesCritical => {
+                "becomes critical: decoders that don't recognise it will reject the image"
+            }
+            PropertyChange::BecomesAncillary => {
+                "becomes ancillary: decoders that don't recognise it will silently skip it"
+            }
+            PropertyChange::BecomesPublic => {
+                "becomes public: the name is reserved for the PNG spec"
+            }
+            PropertyChange::BecomesPrivate => "becomes private",
+            PropertyChange::BecomesSafeToCopy => {
+                "becomes safe to copy: editors will keep it even after modifying critical chunks"
+            }
+            PropertyChange::BecomesUnsafeToCopy => {
+                "becomes unsafe to copy: editors will drop it after modifying critical chunks"
+            }
+            PropertyChange::ReservedBitSet => {
+                "has the reserved bit set, which the current spec forbids"
+            }
+        };
+        write!(f, "{}", message)
+    }
 }
 
 #[cfg(test)]
@@ -171,4 +510,64 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_property_changes() {
+        let from = ChunkType::from_str("ruSt").unwrap();
+        assert!(from.property_changes(&from).is_empty());
+
+        let changes = from.property_changes(&ChunkType::from_str("RuST").unwrap());
+        assert_eq!(
+            changes,
+            vec![
+                PropertyChange::BecomesCritical,
+                PropertyChange::BecomesUnsafeToCopy
+            ]
+        );
+
+        let changes = from.property_changes(&ChunkType::from_str("rUst").unwrap());
+        assert_eq!(
+            changes,
+            vec![
+                PropertyChange::BecomesPublic,
+                PropertyChange::ReservedBitSet
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_chunk_type_serde() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(serde_json::to_string(&chunk_type).unwrap(), "\"ruSt\"");
+        let parsed: ChunkType = serde_json::from_str("\"ruSt\"").unwrap();
+        assert_eq!(parsed, chunk_type);
+        assert!(serde_json::from_str::<ChunkType>("\"ru5t\"").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_flags() {
+        let ihdr = ChunkType::from_str("IHDR").unwrap();
+        assert_eq!(ihdr.flags(), ["critical", "public", "unsafe"]);
+        let private = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(private.flags(), ["ancillary", "private", "safe"]);
+    }
+
+    #[test]
+    pub fn test_standard_chunks() {
+        assert_eq!(ChunkType::IHDR, ChunkType::from_str("IHDR").unwrap());
+        assert_eq!(ChunkType::tEXt.to_string(), "tEXt");
+        for standard in StandardChunk::ALL {
+            assert_eq!(standard.chunk_type().standard(), Some(standard));
+            assert!(standard.chunk_type().is_valid(), "{}", standard);
+        }
+        assert_eq!(ChunkType::from_str("ruSt").unwrap().standard(), None);
+
+        let gama = ChunkType::gAMA.standard().unwrap();
+        assert_eq!(gama.placement(), Placement::BeforePlte);
+        assert!(!gama.allows_multiple());
+        assert!(StandardChunk::Idat.allows_multiple() && StandardChunk::Idat.is_required());
+        assert_eq!(StandardChunk::Ihdr.placement(), Placement::First);
+        assert_eq!(StandardChunk::Ster.placement(), Placement::BeforeIdat);
+    }
 }
@@ -0,0 +1,122 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::selection::Occurrence;
+use std::{
+    env,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Environment variable that pins every timestamp the tool writes, see
+/// <https://reproducible-builds.org/specs/source-date-epoch/>
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Seconds since the Unix epoch that every writer should stamp its output with: the value of
+/// `SOURCE_DATE_EPOCH` when it is set, otherwise the system time
+pub fn now() -> Result<u64, PngMeError> {
+    from_source_date_epoch(env::var(SOURCE_DATE_EPOCH).ok().as_deref())
+}
+
+fn from_source_date_epoch(value: Option<&str>) -> Result<u64, PngMeError> {
+    match value {
+        Some(value) if !value.is_empty() => value.trim().parse().map_err(|_| {
+            PngMeError::Invalid("SOURCE_DATE_EPOCH must be a non-negative whole number of seconds")
+        }),
+        _ => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .map_err(|_| PngMeError::Invalid("system clock is set before the Unix epoch")),
+    }
+}
+
+/// Replaces any tIME chunks of `png` with one holding [`now`], placed where the spec expects it
+pub fn stamp(png: &mut Png) -> Result<(), PngMeError> {
+    let chunk = time_chunk(now()?);
+    if !png.indices_of_type("tIME", Occurrence::All).is_empty() {
+        png.remove_chunks("tIME", Occurrence::All)?;
+    }
+    png.insert_chunk_ordered(chunk);
+    Ok(())
+}
+
+/// Builds a tIME chunk holding `timestamp` as a UTC calendar date and time
+pub fn time_chunk(timestamp: u64) -> Chunk {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+
+    let mut data = Vec::with_capacity(7);
+    data.extend_from_slice(&(year as u16).to_be_bytes());
+    data.extend_from_slice(&[
+        month,
+        day,
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+    ]);
+
+    Chunk::new(ChunkType::from_str("tIME").unwrap(), data)
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_date_epoch() {
+        assert_eq!(
+            from_source_date_epoch(Some("1700000000")).unwrap(),
+            1_700_000_000
+        );
+        assert!(from_source_date_epoch(Some("yesterday")).is_err());
+        assert!(from_source_date_epoch(Some("-5")).is_err());
+        assert!(from_source_date_epoch(None).unwrap() > 1_700_000_000);
+        assert!(from_source_date_epoch(Some("")).unwrap() > 1_700_000_000);
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_675), (2023, 11, 14));
+    }
+
+    #[test]
+    fn test_stamp() {
+        let mut png = Png::new(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        stamp(&mut png).unwrap();
+        stamp(&mut png).unwrap();
+        let times = png.chunks_by_type("tIME", Occurrence::All);
+        assert_eq!(times.len(), 1);
+        assert_eq!(times[0].data().len(), 7);
+    }
+
+    #[test]
+    fn test_time_chunk() {
+        let chunk = time_chunk(1_700_000_000);
+        assert_eq!(&chunk.chunk_type().to_string(), "tIME");
+        assert_eq!(chunk.data(), &[0x07, 0xe7, 11, 14, 22, 13, 20]);
+    }
+}
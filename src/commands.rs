@@ -1 +1,4030 @@
+use crate::args::{
+    AnnotateArgs, ApplyArgs, AssembleArgs, AuditArgs, CapacityArgs, DecodeArgs, DedupArgs,
+    DiffArgs, DumpArgs, EncodeArgs, ExplodeArgs, ExtractArgs, FindArgs, ListArgs, MetaArgs, Method,
+    PatchApplyArgs, PatchCommand, PatchCreateArgs, PngMeArgs, Positionals, PrintArgs, RemoveArgs,
+    ReorderArgs, RepairArgs, RetagArgs, ScrubArgs, SelftestArgs, VerifyArgs, WatermarkCheckArgs,
+    WatermarkCommand, WatermarkEmbedArgs, WriteArgs,
+};
+use crate::batch::{self, FileOutcome};
+use crate::capabilities;
+use crate::hexdump;
+use crate::output::{ChunkListing, Format, Output, OutputMode};
+use crate::style::Theme;
+use pngme::apng::{self, Animation, AnimationControl, FrameControl, ANIMATION_CHUNK_TYPES};
+use pngme::audit::AuditLog;
+use pngme::capacity::{self, CapacityReport, MAX_CHUNK_LENGTH};
+use pngme::clock;
+use pngme::corpus::{self, FindReport};
+use pngme::crypto;
+use pngme::diff::{self, ChangeKind, ChunkSide, Diff};
+use pngme::explode;
+use pngme::filter::Filter;
+use pngme::ihdr::Ihdr;
+use pngme::lsb;
+use pngme::metadata::Metadata;
+use pngme::ordering::MoveTarget;
+use pngme::patch::Patch;
+use pngme::payload;
+use pngme::png::{CrcRepair, DedupReport, ScrubReport, DEFAULT_PIECE_LEN, IMAGE_CHUNK_TYPES};
+use pngme::reader::ChunkReader;
+use pngme::recipe::Recipe;
+use pngme::selection::{Occurrence, Selection};
+use pngme::selftest::{self, Check, Status};
+use pngme::shamir;
+use pngme::sidecar::{self, Annotation, AnnotationStore};
+use pngme::text::{self, TextChunk, TextKind};
+use pngme::verify::{self, Report};
+use pngme::watermark;
+use pngme::{
+    Chunk, ChunkRef, ChunkType, MyError, MyResult, OpenOptions, Png, PngMeError, PngRef,
+    SealOptions,
+};
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    env,
+    fs::{self, File},
+    io::{self, BufReader, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
 
+/// Path argument that stands for stdin when read and stdout when written
+const STDIO: &str = "-";
+
+pub fn run(command: PngMeArgs) -> MyResult<()> {
+    match command {
+        PngMeArgs::Encode(args) => encode(args),
+        PngMeArgs::Decode(args) => decode(args),
+        PngMeArgs::Remove(args) => remove(args),
+        PngMeArgs::Print(args) => print(args),
+        PngMeArgs::List(args) => list(args),
+        PngMeArgs::Verify(args) => verify(args),
+        PngMeArgs::Repair(args) => repair(args),
+        PngMeArgs::Capacity(args) => capacity(args),
+        PngMeArgs::Find(args) => find(args),
+        PngMeArgs::Scrub(args) => scrub(args),
+        PngMeArgs::Dedup(args) => dedup(args),
+        PngMeArgs::Diff(args) => diff(args),
+        PngMeArgs::Dump(args) => dump(args),
+        PngMeArgs::Extract(args) => extract(args),
+        PngMeArgs::Meta(args) => meta(args),
+        PngMeArgs::Annotate(args) => annotate(args),
+        PngMeArgs::Retag(args) => retag(args),
+        PngMeArgs::Reorder(args) => reorder(args),
+        PngMeArgs::Explode(args) => explode(args),
+        PngMeArgs::Assemble(args) => assemble(args),
+        PngMeArgs::Patch(PatchCommand::Create(args)) => create_patch(args),
+        PngMeArgs::Patch(PatchCommand::Apply(args)) => apply_patch(args),
+        PngMeArgs::Apply(args) => apply(args),
+        PngMeArgs::Watermark(WatermarkCommand::Embed(args)) => embed_watermark(args),
+        PngMeArgs::Watermark(WatermarkCommand::Check(args)) => check_watermark(args),
+        PngMeArgs::Selftest(args) => selftest(args),
+    }
+}
+
+/// Prints the JSON description of what this build can do, for tooling to check before handing
+/// it work
+pub fn print_capabilities() -> MyResult<()> {
+    writeln!(io::stdout().lock(), "{}", capabilities::to_json()?)?;
+    Ok(())
+}
+
+/// Adds the message as a new chunk, placed where the spec expects its type or where --position,
+/// --at-index or --at-offset put it, and writes the result to `output`, or back to `file` when no
+/// output is given. When `file` is a directory every PNG in it is changed in place, several at a
+/// time.
+pub fn encode(args: EncodeArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let positionals = args.positionals()?;
+    if is_stdio(&args.file) && args.payload_file.as_deref().is_some_and(is_stdio) {
+        return Err("the image and --file can't both be read from stdin".into());
+    }
+    let plaintext = match (&args.payload_file, positionals.message) {
+        (Some(path), _) => with_input(path, &args.write.audit, |bytes| Ok(bytes.to_vec()))?,
+        (None, Some(message)) => message.as_bytes().to_vec(),
+        (None, None) => unreachable!("positionals always has a message without --file"),
+    };
+    if !args.compress.is_available() {
+        return Err(format!("this build doesn't support {} compression", args.compress).into());
+    }
+    let placed = args.position.is_some() || args.at_index.is_some() || args.at_offset.is_some();
+    if placed && args.method == Method::Lsb {
+        return Err(
+            "--position, --at-index and --at-offset only apply to messages hidden in a chunk"
+                .into(),
+        );
+    }
+    if args.sign_image {
+        if args.method == Method::Lsb {
+            return Err("--sign-image can't be used with --method lsb, which changes IDAT".into());
+        }
+        if positionals.chunk_type.is_some_and(|chunk_type| {
+            ChunkType::from_str(chunk_type).is_ok_and(|t| t.is_critical())
+        }) {
+            return Err("--sign-image needs an ancillary chunk type".into());
+        }
+    }
+    if let Some(threshold) = args.threshold {
+        if args.file.is_dir() {
+            return Err("--threshold splits one key, so it can't be used with a directory".into());
+        }
+        if !args.carrier.is_empty() && args.carrier.len() != threshold.shares as usize {
+            return Err(format!(
+                "--threshold {}-of-{} needs one --carrier per share, {} given",
+                threshold.required,
+                threshold.shares,
+                args.carrier.len()
+            )
+            .into());
+        }
+    }
+    let shared_key = args.threshold.map(|_| random_key()).transpose()?;
+    let passphrase = match args.encrypt {
+        true => Some(passphrase(is_stdio(&args.file))?),
+        false => None,
+    };
+    let recipients = args
+        .recipient
+        .iter()
+        .map(|path| recipient(path))
+        .collect::<MyResult<Vec<_>>>()?;
+    let recipients: Vec<&str> = recipients.iter().map(String::as_str).collect();
+    let key = args.key.as_deref().map(signing_key).transpose()?;
+    let options = SealOptions {
+        transform: args.transform.as_ref(),
+        compression: args.compress,
+        passphrase: passphrase.as_deref(),
+        recipients: &recipients,
+        shared_key: shared_key.as_ref().map(|key| key.as_slice()),
+        signing_key: key.as_deref(),
+        sign_image: args.sign_image,
+    };
+
+    if args.file.is_dir() {
+        if positionals.output.is_some() {
+            return Err(
+                "OUTPUT can't be used with a directory, its files are changed in place".into(),
+            );
+        }
+        let files = corpus::png_files(&args.file, false)?;
+        confirm(
+            &format!(
+                "Change {} files in {} in place?",
+                files.len(),
+                args.file.display()
+            ),
+            &args.write,
+        )?;
+        let outcomes = batch::run(
+            &files,
+            args.jobs.unwrap_or_else(batch::default_jobs),
+            |path| {
+                let original = read_png(path, &args.write.audit).map_err(|e| e.to_string())?;
+                let mut png = original.clone();
+                embed(&mut png, &args, &positionals, &plaintext, &options)
+                    .map_err(|e| e.to_string())?;
+                if args.write.touch {
+                    clock::stamp(&mut png).map_err(|e| e.to_string())?;
+                }
+                match args.write.dry_run {
+                    true => Ok(PlannedWrite::new(&original, &png, path).to_string()),
+                    false => write_png(&png, path, args.write.backup, true)
+                        .and_then(|()| {
+                            record_audit(
+                                &args.write.audit,
+                                "encode",
+                                path,
+                                None,
+                                Some(&png.as_bytes()),
+                            )
+                        })
+                        .map(|()| String::new())
+                        .map_err(|e| e.to_string()),
+                }
+            },
+        );
+        // Plans are printed once the batch is done so they come out in file order
+        let mut output = Output::new(mode, io::stdout().lock());
+        for outcome in &outcomes {
+            match &outcome.result {
+                Ok(plan) if args.write.dry_run => eprint!("{}", plan),
+                Ok(_) => output.record("encoded", &[&outcome.path.display()])?,
+                Err(_) => (),
+            }
+        }
+        return match args.write.dry_run {
+            true => summarize("checked", &outcomes, &mut output),
+            false => summarize("encoded", &outcomes, &mut output),
+        };
+    }
+
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    embed(&mut png, &args, &positionals, &plaintext, &options)?;
+    let shares = match (args.threshold, &shared_key) {
+        (Some(threshold), Some(shared_key)) => shamir::split(shared_key, threshold)?,
+        _ => Vec::new(),
+    };
+    if args.carrier.is_empty() {
+        for share in &shares {
+            png.insert_chunk_ordered(share.to_chunk());
+        }
+    }
+    let destination = positionals.output.unwrap_or(&args.file);
+    // Every image is built and checked before any is written, so a carrier that can't take its
+    // share doesn't leave the image and the other carriers changed
+    let mut carriers = Vec::new();
+    for (share, carrier) in shares.iter().zip(&args.carrier) {
+        let original = read_png(carrier, &args.write.audit)?;
+        let mut png = original.clone();
+        png.insert_chunk_ordered(share.to_chunk());
+        check_writable(&png, carrier)?;
+        carriers.push((share.index, carrier, original, png));
+    }
+    check_writable(&png, destination)?;
+    if !carriers.is_empty() {
+        confirm(
+            &format!("Add key shares to {} files in place?", carriers.len()),
+            &args.write,
+        )?;
+    }
+
+    write_changes(
+        "encode",
+        &original,
+        &png,
+        &args.file,
+        destination,
+        &args.write,
+    )?;
+    let mut output = Output::new(mode, report_stream(destination));
+    if !args.write.dry_run {
+        output.record("encoded", &[&destination.display()])?;
+    }
+    let confirmed = WriteArgs {
+        yes: true,
+        ..args.write.clone()
+    };
+    for (index, carrier, original, png) in &carriers {
+        write_changes("encode", original, png, carrier, carrier, &confirmed)?;
+        if !args.write.dry_run {
+            output.record("share", &[index, &carrier.display()])?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks `png` the way writing it to `path` would, so several files can be refused before any
+/// of them is written
+fn check_writable(png: &Png, path: &Path) -> MyResult<()> {
+    let report = verify::verify(&png.as_bytes());
+    match report.is_valid() {
+        true => Ok(()),
+        false => Err(format!(
+            "{}: {}",
+            path.display(),
+            PngMeError::UnverifiedWrite(report.problems)
+        )
+        .into()),
+    }
+}
+
+/// A random key for `--threshold`, which is split into shares and never written whole
+fn random_key() -> MyResult<[u8; 32]> {
+    let mut key = [0u8; 32];
+    getrandom::fill(&mut key).map_err(|_| "no randomness available")?;
+    Ok(key)
+}
+
+/// Recovers the key of a message written with `encode --threshold` from the key share chunks in
+/// `png` and in the `--share-file` files. Gives `None` when there are no shares to combine.
+fn shared_key(png: &Png, share_files: &[PathBuf], audit: &AuditArgs) -> MyResult<Option<Vec<u8>>> {
+    let mut shares = shamir::shares_in(png)?;
+    for path in share_files {
+        shares.extend(shamir::shares_in(&read_png(path, audit)?)?);
+    }
+    if shares.is_empty() {
+        return match share_files.is_empty() {
+            true => Ok(None),
+            false => Err("no key shares found in the image or the --share-file files".into()),
+        };
+    }
+    let required = shares[0].required;
+    let key = shamir::combine(&shares).map_err(|e| -> MyError {
+        match shares.len() < required as usize {
+            true => format!("{} of {} key shares found", shares.len(), required).into(),
+            false => e.into(),
+        }
+    })?;
+    Ok(Some(key))
+}
+
+/// Adds the message to one image, sealed by [`payload::seal`] with `options`
+fn embed(
+    png: &mut Png,
+    args: &EncodeArgs,
+    positionals: &Positionals,
+    plaintext: &[u8],
+    options: &SealOptions,
+) -> MyResult<()> {
+    // The hidden chunk goes in after signing, but it's ancillary so the image tag still holds
+    let payload = payload::seal(plaintext, png, options)?;
+
+    match (args.text_chunk, positionals.chunk_type) {
+        (Some(kind), _) => {
+            let text = payload::to_text(payload, options)?;
+            let mut text_chunk = TextChunk::new(kind, &args.keyword, &text)?;
+            if let Some(language) = &args.language {
+                text_chunk = text_chunk.with_language(language, "")?;
+            }
+            insert(png, text_chunk.to_chunk()?, args)?;
+        }
+        (None, Some(chunk_type)) if args.payload_file.is_some() => {
+            png.insert_payload(
+                &ChunkType::from_str(chunk_type)?,
+                &payload,
+                DEFAULT_PIECE_LEN,
+            )?;
+        }
+        (None, Some(chunk_type)) => {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type)?, payload);
+            insert(png, chunk, args)?;
+        }
+        // Only --method lsb leaves out the chunk type without --text-chunk
+        (None, None) => lsb::embed(png, &payload)?,
+    }
+    Ok(())
+}
+
+/// Inserts a chunk at --position, --at-index or --at-offset, or where the spec expects its type
+/// when none is given
+fn insert(png: &mut Png, chunk: Chunk, args: &EncodeArgs) -> MyResult<()> {
+    match (args.position, args.at_index, &args.at_offset) {
+        (Some(position), _, _) => {
+            png.insert_chunk_at_position(chunk, position)?;
+        }
+        (None, Some(index), _) => png.insert_chunk_at(index, chunk)?,
+        (None, None, Some(address)) => {
+            let index = png.indices_at(address)?[0];
+            png.insert_chunk_at(index, chunk)?;
+        }
+        (None, None, None) => {
+            png.insert_chunk_ordered(chunk);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the message in the first chunk of the type, or reassembles it when it was split across
+/// several. `--index`, `--first`, `--last` and `--all` read the message of the chunks they pick
+/// instead, one per chunk. Text chunks print only their text, taken from the chunk with
+/// `--keyword` when one is given. When `file` is a directory the message of every PNG in it is
+/// printed after its path.
+pub fn decode(args: DecodeArgs) -> MyResult<()> {
+    match (args.method, &args.chunk_type) {
+        (Method::Chunk, None) => return Err("expected a chunk type".into()),
+        (Method::Lsb, Some(_)) => return Err("--method lsb doesn't take a chunk type".into()),
+        _ => (),
+    }
+    let is_text = args
+        .chunk_type
+        .as_deref()
+        .is_some_and(|chunk_type| TextKind::from_str(chunk_type).is_ok());
+    if args.keyword.is_some() && !is_text {
+        return Err("--keyword only applies to tEXt, zTXt and iTXt".into());
+    }
+    if args.occurrence.is_given() && (is_text || args.method == Method::Lsb) {
+        return Err("--index, --first, --last and --all don't apply to text chunks or LSB".into());
+    }
+    args.occurrence.occurrence()?;
+    let mode = args.report.mode(args.format)?;
+    let passphrase = match args.decrypt {
+        true => Some(passphrase(is_stdio(&args.file))?),
+        false => None,
+    };
+    let key = args.key.as_deref().map(signing_key).transpose()?;
+
+    if args.file.is_dir() {
+        if args.output.is_some() {
+            return Err("--output can't be used with a directory".into());
+        }
+        if args.occurrence.all {
+            return Err("--all can't be used with a directory".into());
+        }
+        let files = corpus::png_files(&args.file, false)?;
+        let outcomes = batch::run(
+            &files,
+            args.jobs.unwrap_or_else(batch::default_jobs),
+            |path| {
+                // Warnings aren't reported for a whole directory, only whether each file decoded
+                let png = match args.lenient {
+                    true => with_input(path, &args.audit, |bytes| {
+                        Ok(Png::try_from_lenient(bytes)?.0)
+                    }),
+                    false => read_png(path, &args.audit),
+                }
+                .map_err(|e| e.to_string())?;
+                let shared_key = match passphrase {
+                    Some(_) => None,
+                    None => shared_key(&png, &args.share_file, &args.audit)
+                        .map_err(|e| e.to_string())?,
+                };
+                let options = OpenOptions {
+                    transform: args.transform.as_ref(),
+                    passphrase: passphrase.as_deref(),
+                    shared_key: shared_key.as_deref(),
+                    signing_key: key.as_deref(),
+                };
+                // Without --all exactly one chunk is read
+                let message = read_messages(&png, &args, &options)
+                    .map_err(|e| e.to_string())?
+                    .remove(0);
+                String::from_utf8(message).map_err(|e| e.to_string())
+            },
+        );
+        let mut output = Output::new(mode, io::stdout().lock());
+        let mut decoded = Vec::new();
+        for outcome in &outcomes {
+            if let Ok(message) = &outcome.result {
+                output.human(format!("{}: {}", outcome.path.display(), message))?;
+                output.record("message", &[&outcome.path.display(), message])?;
+            }
+            decoded.push(Decoded {
+                path: Some(&outcome.path),
+                chunk_type: args.chunk_type.as_deref(),
+                message: outcome.result.as_deref().ok(),
+                error: outcome.result.as_ref().err().map(String::as_str),
+            });
+        }
+        output.json(&decoded)?;
+        return summarize("decoded", &outcomes, &mut output);
+    }
+
+    let png = read_png_with(&args.file, args.lenient, &args.audit)?;
+    let shared_key = match passphrase {
+        Some(_) => None,
+        None => shared_key(&png, &args.share_file, &args.audit)?,
+    };
+    let options = OpenOptions {
+        transform: args.transform.as_ref(),
+        passphrase: passphrase.as_deref(),
+        shared_key: shared_key.as_deref(),
+        signing_key: key.as_deref(),
+    };
+    let messages = read_messages(&png, &args, &options)?;
+    match &args.output {
+        Some(path) => {
+            let message = messages.concat();
+            match is_stdio(path) {
+                true => io::stdout().lock().write_all(&message)?,
+                false => fs::write(path, &message)?,
+            }
+            record_audit(&args.audit, "decode", path, None, Some(&message))?;
+        }
+        None => {
+            let messages = messages
+                .into_iter()
+                .map(String::from_utf8)
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut output = Output::new(mode, io::stdout().lock());
+            let mut decoded = Vec::new();
+            for message in &messages {
+                output.human(message)?;
+                output.record("message", &[&args.file.display(), message])?;
+                decoded.push(Decoded {
+                    path: None,
+                    chunk_type: args.chunk_type.as_deref(),
+                    message: Some(message),
+                    error: None,
+                });
+            }
+            // --all gives a list even when the file has a single chunk of the type
+            match args.occurrence.all {
+                true => output.json(&decoded)?,
+                false => output.json(&decoded[0])?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A message as written by `decode --format json`. Directories give one per file, with the path
+/// and either the message or why it couldn't be decoded.
+#[derive(Serialize)]
+struct Decoded<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Reads the messages out of one image, one per chunk picked by the occurrence flags or a single
+/// one otherwise, and opens each with [`payload::open`], naming the flag to use when it's
+/// encrypted or signed and the passphrase or key is missing
+fn read_messages(png: &Png, args: &DecodeArgs, options: &OpenOptions) -> MyResult<Vec<Vec<u8>>> {
+    let payloads = match args.chunk_type.as_deref() {
+        None => vec![lsb::extract(png)?],
+        Some(chunk_type) => match TextKind::from_str(chunk_type) {
+            Ok(kind) => vec![payload::from_text(
+                text::find(png, kind, args.keyword.as_deref())?.text(),
+            )],
+            Err(_) if args.occurrence.is_given() => {
+                let selection = Selection::Type(chunk_type.into(), args.occurrence.occurrence()?);
+                png.indices_selected(&selection)?
+                    .into_iter()
+                    .map(|index| png.chunks()[index].data().to_vec())
+                    .collect()
+            }
+            Err(_) => vec![png.extract_payload(chunk_type)?],
+        },
+    };
+    payloads
+        .iter()
+        .map(|payload| {
+            payload::open(payload, png, options).map_err(|e| match e {
+                PngMeError::PassphraseRequired => {
+                    "the message is encrypted, decode it with --decrypt".into()
+                }
+                PngMeError::KeyRequired => {
+                    "the message is signed, decode it with --verify-signature --key KEY".into()
+                }
+                e => e.into(),
+            })
+        })
+        .collect()
+}
+
+/// Reports the files of a batch that failed on stderr, in file order, then how many succeeded,
+/// on stderr too unless it's a `summary` record. Fails when any file did.
+fn summarize<T, W: Write>(
+    verb: &str,
+    outcomes: &[FileOutcome<T>],
+    output: &mut Output<W>,
+) -> MyResult<()> {
+    let mut failed = 0;
+    for outcome in outcomes {
+        if let Err(e) = &outcome.result {
+            eprintln!("{}: {}", outcome.path.display(), e);
+            failed += 1;
+        }
+    }
+    match output.mode() {
+        OutputMode::Quiet => (),
+        OutputMode::Porcelain => {
+            output.record("summary", &[&(outcomes.len() - failed), &failed])?
+        }
+        OutputMode::Human | OutputMode::Json => eprintln!(
+            "{} {} of {} files",
+            verb,
+            outcomes.len() - failed,
+            outcomes.len()
+        ),
+    }
+
+    match failed {
+        0 => Ok(()),
+        1 => Err("1 file failed".into()),
+        failed => Err(format!("{} files failed", failed).into()),
+    }
+}
+
+/// Removes the selected chunks from `file` in place, or copies stdin to stdout without them.
+/// Each removed chunk is reported on stderr, or as a `removed` record.
+pub fn remove(args: RemoveArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let selection = args.select.selection()?;
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    let animated = check_animation(&png).is_ok();
+    let removed = png.remove_selected(&selection)?;
+    if animated {
+        check_animation(&png).map_err(|e| {
+            let types: Vec<String> = removed
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect();
+            format!(
+                "removing {} would break the APNG animation: {}",
+                types.join(", "),
+                e
+            )
+        })?;
+    }
+    write_changes(
+        "remove",
+        &original,
+        &png,
+        &args.file,
+        &args.file,
+        &args.write,
+    )?;
+
+    if !args.write.dry_run {
+        let mut output = Output::new(mode, report_stream(&args.file));
+        for chunk in &removed {
+            if mode == OutputMode::Human {
+                eprint!("Removed {}", chunk);
+            }
+            output.record(
+                "removed",
+                &[
+                    chunk.chunk_type(),
+                    &chunk.length(),
+                    &format!("{:08x}", chunk.crc()),
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Renames the chunks of type --from to --to in `file` in place, or copies stdin to stdout with
+/// them renamed. Each property bit the new type flips, such as an ancillary chunk becoming
+/// critical, is warned about on stderr.
+pub fn retag(args: RetagArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let from = ChunkType::from_str(&args.from)?;
+    let to = ChunkType::from_str(&args.to)?;
+    if mode != OutputMode::Quiet {
+        for change in from.property_changes(&to) {
+            eprintln!("warning: {} {}", to, change);
+        }
+    }
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    let retagged = png.retag(&args.from, &to)?;
+    write_changes(
+        "retag",
+        &original,
+        &png,
+        &args.file,
+        &args.file,
+        &args.write,
+    )?;
+
+    if !args.write.dry_run {
+        let mut output = Output::new(mode, report_stream(&args.file));
+        for index in &retagged {
+            output.human(format!("retagged chunk {} from {} to {}", index, from, to))?;
+            output.record("retagged", &[index, &from, &to])?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the chunk at --move next to a chunk of another type, or with --spec-order sorts the
+/// ancillary chunks into the positions the spec expects, in `file` in place or from stdin to
+/// stdout. A move that breaks the ordering rules is refused unless forced, and a forced file is
+/// written without checking the order.
+pub fn reorder(args: ReorderArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    let moved = match (args.moved, args.spec_order) {
+        (Some(index), false) => Some(move_chunk(&mut png, index, &args)?),
+        (None, true) => None,
+        _ => return Err("expected --move N with --before or --after, or --spec-order".into()),
+    };
+    let sorted = match args.spec_order {
+        true => png.reorder_spec(),
+        false => 0,
+    };
+    write_changes_with(
+        "reorder",
+        &original,
+        &png,
+        &args.file,
+        &args.file,
+        &args.write,
+        !args.force,
+    )?;
+
+    if !args.write.dry_run {
+        let mut output = Output::new(mode, report_stream(&args.file));
+        match moved {
+            Some((index, chunk_type, destination)) => {
+                output.human(format!(
+                    "moved chunk {} {} to position {}",
+                    index, chunk_type, destination
+                ))?;
+                output.record("moved", &[&index, &chunk_type, &destination])?;
+            }
+            None => {
+                output.human(format!("moved {} chunks into spec order", sorted))?;
+                output.record("reordered", &[&sorted])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves the chunk at `index` before the first chunk of the --before type or after the last
+/// chunk of the --after type, returning its type and new position
+fn move_chunk(
+    png: &mut Png,
+    index: usize,
+    args: &ReorderArgs,
+) -> MyResult<(usize, ChunkType, usize)> {
+    let anchor = |chunk_type: &str, occurrence| {
+        png.indices_of_type(chunk_type, occurrence)
+            .first()
+            .copied()
+            .ok_or_else(|| PngMeError::ChunkNotFound(String::from(chunk_type)))
+    };
+    let target = match (&args.before, &args.after) {
+        (Some(chunk_type), _) => MoveTarget::Before(anchor(chunk_type, Occurrence::First)?),
+        (None, Some(chunk_type)) => MoveTarget::After(anchor(chunk_type, Occurrence::Last)?),
+        (None, None) => return Err("--move needs --before or --after".into()),
+    };
+    let chunk_type = png
+        .chunks()
+        .get(index)
+        .ok_or(PngMeError::IndexOutOfRange)?
+        .chunk_type()
+        .clone();
+    let destination = png
+        .move_chunk(index, target, args.force)
+        .map_err(|e| -> MyError {
+            match e {
+                PngMeError::Invalid(rule @ "move would break chunk ordering rules") => {
+                    format!("{}, --force moves it anyway", rule).into()
+                }
+                e => e.into(),
+            }
+        })?;
+    Ok((index, chunk_type, destination))
+}
+
+/// Checks that the APNG animation chunks, if any, still fit together
+fn check_animation(png: &Png) -> Result<(), PngMeError> {
+    apng::check_sequence(png.chunks().iter().map(ChunkRef::from))
+}
+
+/// Prints the chunks as they are read, so a large file is never held in memory at once. JSON
+/// output keeps only the metadata of each chunk until the end.
+pub fn print(args: PrintArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    let mut output = Output::new(mode, io::stdout().lock());
+    // Notes are keyed by the hash of the whole file, and the audit log records the hash of what
+    // was read, so either one has the file read up front
+    let audited = args.audit.audit_log.is_some();
+    let (input, annotations): (Box<dyn Read>, Vec<Annotation>) = match args.annotations {
+        true if is_stdio(&args.file) => {
+            return Err("--annotations needs a file, its notes are kept next to it".into())
+        }
+        false if !audited => (open(&args.file)?, Vec::new()),
+        annotated => {
+            let bytes = read_all(&args.file)?;
+            record_audit(&args.audit, "read", &args.file, Some(&bytes), None)?;
+            let annotations = match annotated {
+                true => AnnotationStore::open(&args.file)?
+                    .annotations(&bytes)
+                    .to_vec(),
+                false => Vec::new(),
+            };
+            (Box::new(io::Cursor::new(bytes)), annotations)
+        }
+    };
+    let theme = Theme::new(args.color);
+    let mut reader = ChunkReader::new(input);
+    let mut listings = Vec::new();
+    let mut index = 0;
+    while let Some(chunk) = reader.read_chunk()? {
+        output.human(painted_chunk(&chunk, &theme))?;
+        if let Ok(ihdr) = Ihdr::try_from(&chunk) {
+            output.human(format!("Image: {}", ihdr))?;
+        }
+        if let Ok(control) = AnimationControl::try_from(&chunk) {
+            output.human(format!("Animation: {}", control))?;
+        }
+        if let Ok(frame) = FrameControl::try_from(&chunk) {
+            output.human(format!("Frame: {}", frame))?;
+        }
+        let notes: Vec<&str> = annotations
+            .iter()
+            .filter(|annotation| annotation.chunk == index)
+            .map(|annotation| annotation.note.as_str())
+            .collect();
+        for note in &notes {
+            output.human(format!("Note: {}", note))?;
+        }
+        let offset = reader.offset() as usize - chunk.total_size();
+        output.chunk_record(index, offset, &(&chunk).into())?;
+        for note in &notes {
+            output.record("annotation", &[&index, note])?;
+        }
+        if mode == OutputMode::Json {
+            let mut listing = ChunkListing::new(index, offset, &(&chunk).into());
+            listing.annotations = notes.iter().map(|note| note.to_string()).collect();
+            listings.push(listing);
+        }
+        index += 1;
+    }
+    output.json(&listings)?;
+    Ok(())
+}
+
+/// The chunk as [`Chunk`] displays it, with its type colored by category
+fn painted_chunk(chunk: &Chunk, theme: &Theme) -> String {
+    let chunk_type = chunk.chunk_type();
+    chunk.to_string().trim_end().replacen(
+        &format!("Type: {}", chunk_type),
+        &format!("Type: {}", theme.chunk_type(chunk_type)),
+        1,
+    )
+}
+
+/// Stores the note in the sidecar next to the file. The file itself is only read, to check the
+/// chunk exists and to key the note by its hash.
+pub fn annotate(args: AnnotateArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    if is_stdio(&args.file) {
+        return Err("annotate needs a file, its notes are kept next to it".into());
+    }
+    sidecar::annotate_file(&args.file, args.chunk, &args.note)?;
+
+    let store = AnnotationStore::sidecar_path(&args.file);
+    let mut output = Output::new(mode, io::stdout().lock());
+    output.human(format!("noted chunk {} in {}", args.chunk, store.display()))?;
+    output.record("annotated", &[&args.chunk, &store.display()])?;
+    Ok(())
+}
+
+/// Reads a signing key given in hex, or from the file at `value` when there is one
+fn signing_key(value: &str) -> MyResult<Vec<u8>> {
+    let path = Path::new(value);
+    match path.is_file() {
+        true => Ok(fs::read(path)?),
+        false => {
+            hex::decode(value).map_err(|_| "--key must be hex or the path of a key file".into())
+        }
+    }
+}
+
+/// Reads the passphrase of another `--recipient` from the first line of a file
+fn recipient(path: &Path) -> MyResult<String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("can't read --recipient {}: {}", path.display(), e))?;
+    match contents.lines().next().filter(|line| !line.is_empty()) {
+        Some(line) => Ok(String::from(line)),
+        None => Err(format!("--recipient {} holds no passphrase", path.display()).into()),
+    }
+}
+
+/// Reads the passphrase from `PNGME_PASSPHRASE`, or asks for it and reads a line of stdin unless
+/// the image is already coming from there
+fn passphrase(stdin_in_use: bool) -> MyResult<String> {
+    if let Some(passphrase) = env::var_os(crypto::PASSPHRASE_ENV).filter(|value| !value.is_empty())
+    {
+        return passphrase
+            .into_string()
+            .map_err(|_| "passphrase is not valid UTF-8".into());
+    }
+    if stdin_in_use {
+        return Err(format!(
+            "the image is read from stdin, so the passphrase has to come from {}",
+            crypto::PASSPHRASE_ENV
+        )
+        .into());
+    }
+    eprint!("Passphrase: ");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(String::from(line.trim_end_matches(['\r', '\n'])))
+}
+
+/// Reads only the length, type and CRC of each chunk and skips over its data, so listing a large
+/// mapped file doesn't page it all in. CRCs are shown as stored; `verify` checks them.
+pub fn list(args: ListArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    with_input(&args.file, &args.audit, |bytes| {
+        let png = PngRef::parse_ignoring_crcs(bytes)?;
+        let mut output = Output::new(mode, io::stdout().lock());
+        let theme = Theme::new(args.color);
+        write_list(&png, args.filter.as_ref(), &mut output, &theme)?;
+        Ok(())
+    })
+}
+
+/// Writes one line or `chunk` record per chunk matching `filter`, in file order
+fn write_list<W: Write>(
+    png: &PngRef,
+    filter: Option<&Filter>,
+    output: &mut Output<W>,
+    theme: &Theme,
+) -> io::Result<()> {
+    output.human(format!(
+        "{:>5}  {:>8}  type  {:>8}  crc       flags",
+        "index", "offset", "length"
+    ))?;
+    let offsets = png.chunk_offsets();
+    let listed: Vec<_> = offsets
+        .iter()
+        .zip(png.chunks())
+        .enumerate()
+        .filter(|(index, (offset, chunk))| {
+            filter.is_none_or(|filter| filter.matches(*index, **offset, **chunk))
+        })
+        .collect();
+    for &(index, (&offset, chunk)) in &listed {
+        output.human(format!(
+            "{:>5}  {:>8}  {}  {:>8}  {:08x}  {}",
+            index,
+            offset,
+            theme.chunk_type(&chunk.chunk_type()),
+            chunk.length(),
+            chunk.crc(),
+            chunk.chunk_type().flags().join(", ")
+        ))?;
+        output.chunk_record(index, offset, chunk)?;
+    }
+    if let Ok(Some(animation)) = Animation::from_chunks(png.chunks().iter().copied()) {
+        output.human(format!(
+            "Animation: {}, delays {}",
+            animation.control,
+            animation.delays()
+        ))?;
+        output.record(
+            "animation",
+            &[&animation.control.num_frames, &animation.control.num_plays],
+        )?;
+    }
+    let listings: Vec<ChunkListing> = listed
+        .into_iter()
+        .map(|(index, (offset, chunk))| ChunkListing::new(index, *offset, chunk))
+        .collect();
+    output.json(&listings)
+}
+
+/// Fails when the file has any problem, so CI can gate on the exit code
+pub fn verify(args: VerifyArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    let report = with_input(&args.file, &args.audit, |bytes| Ok(verify::verify(bytes)))?;
+    let mut output = Output::new(mode, io::stdout().lock());
+    write_report(&report, &mut output, &Theme::new(args.color))?;
+
+    match report.problems.len() {
+        0 => Ok(()),
+        1 => Err("1 problem found".into()),
+        count => Err(format!("{} problems found", count).into()),
+    }
+}
+
+/// Writes one line or `problem` record per problem, then a summary
+fn write_report<W: Write>(
+    report: &Report,
+    output: &mut Output<W>,
+    theme: &Theme,
+) -> io::Result<()> {
+    for problem in &report.problems {
+        output.human(theme.error(problem))?;
+        let index = problem
+            .index
+            .map_or(String::from("-"), |index| index.to_string());
+        output.record("problem", &[&problem.offset, &index, &problem.message])?;
+    }
+    if report.is_valid() {
+        output.human(theme.success(format!("valid, {} chunks", report.chunks)))?;
+    }
+    output.record("summary", &[&report.chunks, &report.problems.len()])?;
+    output.json(report)
+}
+
+/// Reads the file without checking CRCs and writes it back with every CRC recomputed. The file
+/// is left untouched when nothing needed repairing, unless --lenient recovered it. When the image
+/// goes to stdout the report goes to stderr instead.
+pub fn repair(args: RepairArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let original = match args.lenient {
+        true => read_png_with(&args.file, true, &args.write.audit)?,
+        false => with_input(&args.file, &args.write.audit, |bytes| {
+            Ok(Png::parse_ignoring_crcs(bytes)?)
+        })?,
+    };
+    let mut png = original.clone();
+    let repairs = png.repair();
+    let destination = args.output.as_deref().unwrap_or(&args.file);
+    if !repairs.is_empty() || args.output.is_some() || is_stdio(destination) || args.lenient {
+        write_changes(
+            "repair",
+            &original,
+            &png,
+            &args.file,
+            destination,
+            &args.write,
+        )?;
+    }
+
+    write_repairs(&repairs, &mut Output::new(mode, report_stream(destination)))?;
+    Ok(())
+}
+
+/// Writes one line or `repaired` record per fixed chunk, then a summary
+fn write_repairs<W: Write>(repairs: &[CrcRepair], output: &mut Output<W>) -> io::Result<()> {
+    for repair in repairs {
+        output.human(format!(
+            "chunk {} {}: CRC {:08x} replaced with {:08x}",
+            repair.index, repair.chunk_type, repair.stored, repair.computed
+        ))?;
+        output.record(
+            "repaired",
+            &[
+                &repair.index,
+                &repair.chunk_type,
+                &format!("{:08x}", repair.stored),
+                &format!("{:08x}", repair.computed),
+            ],
+        )?;
+    }
+    output.record("summary", &[&repairs.len()])?;
+    match repairs.len() {
+        0 => output.human("every CRC is correct"),
+        1 => output.human("repaired 1 chunk"),
+        count => output.human(format!("repaired {} chunks", count)),
+    }
+}
+
+/// Removes the chunks that could hide a message, writing the result to `output` or back to
+/// `file`. When the image goes to stdout the report goes to stderr instead.
+pub fn scrub(args: ScrubArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    if let Some(image) = args
+        .remove_only
+        .iter()
+        .find(|chunk_type| IMAGE_CHUNK_TYPES.contains(&chunk_type.as_str()))
+    {
+        return Err(format!(
+            "{} is needed to display the image and is always kept",
+            image
+        )
+        .into());
+    }
+    if let Some(animation) = args.remove_only.iter().find(|chunk_type| {
+        ANIMATION_CHUNK_TYPES
+            .iter()
+            .any(|animation| animation.to_string() == **chunk_type)
+    }) {
+        return Err(format!(
+            "{} is part of the APNG animation and is always kept",
+            animation
+        )
+        .into());
+    }
+    let chunk_types = |types: &[String]| -> Result<Vec<ChunkType>, PngMeError> {
+        types
+            .iter()
+            .map(|chunk_type| ChunkType::from_str(chunk_type))
+            .collect()
+    };
+    let keep = chunk_types(&args.keep)?;
+    let only = chunk_types(&args.remove_only)?;
+
+    let original = read_png_with(&args.file, args.lenient, &args.write.audit)?;
+    let mut png = original.clone();
+    let only = (!only.is_empty()).then_some(only.as_slice());
+    let report = match &args.filter {
+        Some(filter) => png.scrub_where(&keep, only, filter),
+        None => png.scrub(&keep, only),
+    };
+    if args.lenient {
+        // Recovered chunks keep a wrong CRC as read, which the written file mustn't have
+        png.repair();
+    }
+    let destination = args.output.as_deref().unwrap_or(&args.file);
+    if !report.removed.is_empty() || args.output.is_some() || is_stdio(destination) || args.lenient
+    {
+        write_changes(
+            "scrub",
+            &original,
+            &png,
+            &args.file,
+            destination,
+            &args.write,
+        )?;
+    }
+
+    write_scrubbed(&report, &mut Output::new(mode, report_stream(destination)))?;
+    Ok(())
+}
+
+/// Writes one line or `removed` record per removed chunk, then a summary
+fn write_scrubbed<W: Write>(report: &ScrubReport, output: &mut Output<W>) -> io::Result<()> {
+    for (index, chunk) in &report.removed {
+        output.human(format!(
+            "removed chunk {} {}, {} bytes",
+            index,
+            chunk.chunk_type(),
+            chunk.total_size()
+        ))?;
+        output.record("removed", &[index, chunk.chunk_type(), &chunk.total_size()])?;
+    }
+    output.record("summary", &[&report.removed.len(), &report.bytes_saved])?;
+    match report.removed.len() {
+        0 => output.human("nothing to remove"),
+        1 => output.human(format!(
+            "removed 1 chunk, saving {} bytes",
+            report.bytes_saved
+        )),
+        count => output.human(format!(
+            "removed {} chunks, saving {} bytes",
+            count, report.bytes_saved
+        )),
+    }
+}
+
+/// Removes byte-identical copies of ancillary chunks, a common leftover of repeated editing,
+/// writing the result to `output` or back to `file`
+pub fn dedup(args: DedupArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    let report = png.dedup_ancillary();
+    let destination = args.output.as_deref().unwrap_or(&args.file);
+    if !report.removed.is_empty() || args.output.is_some() || is_stdio(destination) {
+        write_changes(
+            "dedup",
+            &original,
+            &png,
+            &args.file,
+            destination,
+            &args.write,
+        )?;
+    }
+
+    write_deduped(&report, &mut Output::new(mode, report_stream(destination)))?;
+    Ok(())
+}
+
+/// Writes one line or `removed` record per dropped copy, then a summary
+fn write_deduped<W: Write>(report: &DedupReport, output: &mut Output<W>) -> io::Result<()> {
+    for (index, chunk_type) in &report.removed {
+        output.human(format!("removed duplicate chunk {} {}", index, chunk_type))?;
+        output.record("removed", &[index, chunk_type])?;
+    }
+    output.record("summary", &[&report.removed.len(), &report.bytes_saved])?;
+    match report.removed.len() {
+        0 => output.human("no duplicate chunks"),
+        1 => output.human(format!(
+            "removed 1 duplicate, saving {} bytes",
+            report.bytes_saved
+        )),
+        count => output.human(format!(
+            "removed {} duplicates, saving {} bytes",
+            count, report.bytes_saved
+        )),
+    }
+}
+
+/// Compares the chunk lists of two files
+pub fn diff(args: DiffArgs) -> MyResult<()> {
+    if is_stdio(&args.old) && is_stdio(&args.new) {
+        return Err("only one of the files can be read from stdin".into());
+    }
+    let mode = args.report.mode(args.format)?;
+    let diff = diff::diff(
+        &read_png(&args.old, &args.audit)?,
+        &read_png(&args.new, &args.audit)?,
+    );
+    write_diff(
+        &diff,
+        &mut Output::new(mode, io::stdout().lock()),
+        &Theme::new(args.color),
+    )?;
+    // With --quiet only the exit code is left to tell whether the files differ
+    match mode == OutputMode::Quiet && !diff.changes.is_empty() {
+        true => Err("the files differ".into()),
+        false => Ok(()),
+    }
+}
+
+/// Writes one line or `change` record per changed chunk, then a summary
+fn write_diff<W: Write>(diff: &Diff, output: &mut Output<W>, theme: &Theme) -> io::Result<()> {
+    let field = |side: &Option<ChunkSide>, value: fn(&ChunkSide) -> String| {
+        side.as_ref().map_or(String::from("-"), value)
+    };
+    for change in &diff.changes {
+        let details = match (&change.old, &change.new) {
+            (Some(old), Some(new)) if old.crc == new.crc => {
+                format!("at {} -> {}", old.index, new.index)
+            }
+            (Some(old), Some(new)) => format!(
+                "at {} -> {}  length {} -> {}  crc {} -> {}",
+                old.index, new.index, old.length, new.length, old.crc, new.crc
+            ),
+            (Some(side), None) | (None, Some(side)) => {
+                format!(
+                    "at {}  length {}  crc {}",
+                    side.index, side.length, side.crc
+                )
+            }
+            (None, None) => unreachable!("a change has at least one side"),
+        };
+        let kind = format!("{:<8}", change.kind);
+        let kind = match change.kind {
+            ChangeKind::Added => theme.success(kind).to_string(),
+            ChangeKind::Removed => theme.error(kind).to_string(),
+            ChangeKind::Modified | ChangeKind::Moved => kind,
+        };
+        let chunk_type = match ChunkType::from_str(&change.chunk_type) {
+            Ok(chunk_type) => theme.chunk_type(&chunk_type).to_string(),
+            Err(_) => change.chunk_type.clone(),
+        };
+        output.human(format!("{}  {}  {}", kind, chunk_type, details))?;
+        output.record(
+            "change",
+            &[
+                &change.kind,
+                &change.chunk_type,
+                &field(&change.old, |side| side.index.to_string()),
+                &field(&change.new, |side| side.index.to_string()),
+                &field(&change.old, |side| side.length.to_string()),
+                &field(&change.new, |side| side.length.to_string()),
+                &field(&change.old, |side| side.crc.clone()),
+                &field(&change.new, |side| side.crc.clone()),
+                &field(&change.old, |side| side.sha256.clone()),
+                &field(&change.new, |side| side.sha256.clone()),
+            ],
+        )?;
+    }
+    output.record("summary", &[&diff.unchanged, &diff.changes.len()])?;
+    match diff.changes.len() {
+        0 => output.human(format!("identical, {} chunks", diff.unchanged))?,
+        1 => output.human(format!("1 change, {} chunks unchanged", diff.unchanged))?,
+        count => output.human(format!(
+            "{} changes, {} chunks unchanged",
+            count, diff.unchanged
+        ))?,
+    }
+    output.json(diff)
+}
+
+/// Prints the data of the selected chunks as a hex dump, or writes the bytes unchanged with
+/// --raw, which works for binary payloads that can't be printed as text. Several chunks are
+/// dumped one after the other, the raw data with nothing in between.
+pub fn dump(args: DumpArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    if args.raw && mode == OutputMode::Porcelain {
+        return Err("--raw writes the data as is, so it can't be combined with --porcelain".into());
+    }
+    // Only a few chunks are shown, so the file is read in place rather than copied chunk by chunk
+    with_input(&args.file, &args.audit, |bytes| {
+        dump_chunks(&PngRef::try_from(bytes)?, &args, mode)
+    })
+}
+
+fn dump_chunks(png: &PngRef, args: &DumpArgs, mode: OutputMode) -> MyResult<()> {
+    let indices = png.indices_selected(&args.select.selection()?)?;
+    let chunks: Vec<(usize, ChunkRef)> = indices
+        .into_iter()
+        .map(|index| (index, png.chunks()[index]))
+        .collect();
+
+    let mut output = Output::new(mode, io::stdout().lock());
+    for (_, chunk) in &chunks {
+        output.record(
+            "data",
+            &[
+                &chunk.chunk_type(),
+                &chunk.length(),
+                &hex::encode(chunk.data()),
+            ],
+        )?;
+    }
+    match (args.raw, &args.output) {
+        (true, Some(path)) if !is_stdio(path) => {
+            let data: Vec<u8> = chunks
+                .iter()
+                .flat_map(|(_, chunk)| chunk.data())
+                .copied()
+                .collect();
+            fs::write(path, &data)?;
+            record_audit(&args.audit, "dump", path, None, Some(&data))?;
+        }
+        _ if mode != OutputMode::Human => (),
+        (true, _) => {
+            for (_, chunk) in &chunks {
+                io::stdout().lock().write_all(chunk.data())?;
+            }
+        }
+        (false, _) => {
+            for (index, chunk) in &chunks {
+                if chunks.len() > 1 {
+                    println!(
+                        "chunk {} {}, {} bytes",
+                        index,
+                        chunk.chunk_type(),
+                        chunk.length()
+                    );
+                }
+                hexdump::write_hexdump(chunk.data(), io::stdout().lock())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the data of each selected chunk to a file of its own in `--out`, named after the image,
+/// the position of the chunk and its type, as in `photo.3.ruSt`
+pub fn extract(args: ExtractArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let selection = args.select.selection()?;
+    let stem = match is_stdio(&args.file) {
+        true => String::from("stdin"),
+        false => args
+            .file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+    };
+    with_input(&args.file, &args.audit, |bytes| {
+        let png = PngRef::try_from(bytes)?;
+        let indices = png.indices_selected(&selection)?;
+        fs::create_dir_all(&args.out)?;
+
+        let mut output = Output::new(mode, io::stdout().lock());
+        for index in indices {
+            let chunk = png.chunks()[index];
+            let path = args
+                .out
+                .join(format!("{}.{}.{}", stem, index, chunk.chunk_type()));
+            fs::write(&path, chunk.data())?;
+            record_audit(&args.audit, "extract", &path, None, Some(chunk.data()))?;
+            output.human(format!(
+                "Wrote chunk {} ({}, {} bytes) to {}",
+                index,
+                chunk.chunk_type(),
+                chunk.length(),
+                path.display()
+            ))?;
+            output.record(
+                "extracted",
+                &[
+                    &index,
+                    &chunk.chunk_type(),
+                    &chunk.length(),
+                    &path.display(),
+                ],
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes every chunk of `file` to `--out` as `000_IHDR.bin`, `001_tEXt.bin`, ... with a manifest,
+/// so chunks can be edited with other tools and put back with `assemble`
+pub fn explode(args: ExplodeArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let png = read_png(&args.file, &args.audit)?;
+    let manifest = explode::explode(&png, &args.out)?;
+    for (entry, chunk) in manifest.chunks.iter().zip(png.chunks()) {
+        let path = args.out.join(&entry.file);
+        record_audit(&args.audit, "explode", &path, None, Some(chunk.data()))?;
+    }
+    if args.audit.audit_log.is_some() {
+        let path = args.out.join(explode::MANIFEST_FILE_NAME);
+        record_audit(&args.audit, "explode", &path, None, Some(&fs::read(&path)?))?;
+    }
+
+    let mut output = Output::new(mode, io::stdout().lock());
+    for (index, entry) in manifest.chunks.iter().enumerate() {
+        output.record("chunk", &[&index, &entry.chunk_type, &entry.file])?;
+    }
+    output.human(format!(
+        "wrote {} chunks to {}",
+        manifest.chunks.len(),
+        args.out.display()
+    ))?;
+    Ok(())
+}
+
+/// Rebuilds a file from a directory written by `explode`, in the order its manifest lists the
+/// chunks, and reports the chunks edited since
+pub fn assemble(args: AssembleArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let assembled = explode::assemble(&args.dir)?;
+    write_png(&assembled.png, &args.output, false, true)?;
+    record_audit(
+        &args.audit,
+        "assemble",
+        &args.output,
+        None,
+        Some(&assembled.png.as_bytes()),
+    )?;
+
+    let mut output = Output::new(mode, report_stream(&args.output));
+    for index in &assembled.edited {
+        let chunk_type = assembled.png.chunks()[*index].chunk_type();
+        output.human(format!("edited chunk {} {}", index, chunk_type))?;
+        output.record("edited", &[index, chunk_type])?;
+    }
+    output.human(format!(
+        "assembled {} chunks into {}",
+        assembled.png.chunks().len(),
+        args.output.display()
+    ))?;
+    output.record(
+        "assembled",
+        &[&assembled.png.chunks().len(), &args.output.display()],
+    )?;
+    Ok(())
+}
+
+/// Writes the chunk-level edits that turn `from` into `to` as a JSON patch, to stdout unless
+/// --output is given
+pub fn create_patch(args: PatchCreateArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let patch = Patch::create(
+        &read_png(&args.from, &args.audit)?,
+        &read_png(&args.to, &args.audit)?,
+    );
+    let json = patch.to_json()?;
+    let destination = args.output.as_deref().unwrap_or(Path::new(STDIO));
+    match is_stdio(destination) {
+        true => writeln!(io::stdout().lock(), "{}", json)?,
+        false => fs::write(destination, &json)?,
+    }
+    record_audit(
+        &args.audit,
+        "patch",
+        destination,
+        None,
+        Some(json.as_bytes()),
+    )?;
+
+    let mut output = Output::new(mode, report_stream(destination));
+    output.human(format!("{} chunk edits", patch.ops.len()))?;
+    output.record("patch", &[&patch.ops.len()])?;
+    Ok(())
+}
+
+/// Applies a patch written by `patch create` to `file`, writing the result to `output` or back
+/// to `file`. A patch made for a different file is refused without writing anything.
+pub fn apply_patch(args: PatchApplyArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let patch = with_input(&args.patch, &args.write.audit, |bytes| {
+        Patch::from_json(str::from_utf8(bytes)?)
+    })?;
+    let original = read_png(&args.file, &args.write.audit)?;
+    let png = patch.apply(&original)?;
+    let destination = args.output.as_deref().unwrap_or(&args.file);
+    write_changes(
+        "patch",
+        &original,
+        &png,
+        &args.file,
+        destination,
+        &args.write,
+    )?;
+
+    if !args.write.dry_run {
+        let mut output = Output::new(mode, report_stream(destination));
+        output.human(format!("applied {} chunk edits", patch.ops.len()))?;
+        output.record("applied", &[&patch.ops.len()])?;
+    }
+    Ok(())
+}
+
+/// Runs a recipe on every file given, and every PNG in the directories given, several at a time.
+/// Each file is changed in place only if the whole recipe applies to it.
+pub fn apply(args: ApplyArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let recipe = Recipe::from_file(&args.recipe)?;
+    let mut files = Vec::new();
+    for path in &args.files {
+        match path.is_dir() {
+            true => files.extend(corpus::png_files(path, false)?),
+            false => files.push(path.clone()),
+        }
+    }
+    confirm(
+        &format!("Change {} files in place?", files.len()),
+        &args.write,
+    )?;
+
+    let outcomes = batch::run(
+        &files,
+        args.jobs.unwrap_or_else(batch::default_jobs),
+        |path| {
+            // A recipe that recomputes CRCs has to be able to read the files that need it
+            let original = match recipe.fixes_crcs() {
+                true => with_input(path, &args.write.audit, |bytes| {
+                    Ok(Png::parse_ignoring_crcs(bytes)?)
+                }),
+                false => read_png(path, &args.write.audit),
+            }
+            .map_err(|e| e.to_string())?;
+            let mut png = original.clone();
+            recipe.apply(&mut png).map_err(|e| e.to_string())?;
+            if args.write.touch {
+                clock::stamp(&mut png).map_err(|e| e.to_string())?;
+            }
+            match args.write.dry_run {
+                true => Ok(PlannedWrite::new(&original, &png, path).to_string()),
+                false => write_png(&png, path, args.write.backup, true)
+                    .and_then(|()| {
+                        record_audit(
+                            &args.write.audit,
+                            "apply",
+                            path,
+                            None,
+                            Some(&png.as_bytes()),
+                        )
+                    })
+                    .map(|()| String::new())
+                    .map_err(|e| e.to_string()),
+            }
+        },
+    );
+    // Plans are printed once the batch is done so they come out in file order
+    let mut output = Output::new(mode, io::stdout().lock());
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(plan) if args.write.dry_run => eprint!("{}", plan),
+            Ok(_) => output.record("applied", &[&outcome.path.display()])?,
+            Err(_) => (),
+        }
+    }
+    match args.write.dry_run {
+        true => summarize("checked", &outcomes, &mut output),
+        false => summarize("applied the recipe to", &outcomes, &mut output),
+    }
+}
+
+/// Embeds a fragile watermark in the pixels of `file`, writing the result to `output` or back to
+/// `file`
+pub fn embed_watermark(args: WatermarkEmbedArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let key = signing_key(&args.key)?;
+    let original = read_png(&args.file, &args.write.audit)?;
+    let mut png = original.clone();
+    watermark::embed(&mut png, &key)?;
+    let destination = args.output.as_deref().unwrap_or(&args.file);
+    write_changes(
+        "watermark",
+        &original,
+        &png,
+        &args.file,
+        destination,
+        &args.write,
+    )?;
+
+    if !args.write.dry_run {
+        Output::new(mode, report_stream(destination))
+            .record("watermarked", &[&destination.display()])?;
+    }
+    Ok(())
+}
+
+/// Checks the watermark of every block of pixels, printing each region changed since it was
+/// embedded. Fails when any region was.
+pub fn check_watermark(args: WatermarkCheckArgs) -> MyResult<()> {
+    let mode = args.report.mode(Format::Human)?;
+    let key = signing_key(&args.key)?;
+    let report = watermark::check(&read_png(&args.file, &args.audit)?, &key)?;
+
+    let mut output = Output::new(mode, io::stdout().lock());
+    for region in &report.altered {
+        output.human(format!(
+            "altered: {}x{} pixels at {},{}",
+            region.width, region.height, region.x, region.y
+        ))?;
+        output.record(
+            "altered",
+            &[&region.x, &region.y, &region.width, &region.height],
+        )?;
+    }
+    output.record("summary", &[&report.blocks, &report.altered.len()])?;
+    match report.altered.len() {
+        0 => {
+            output.human(format!("intact, {} blocks checked", report.blocks))?;
+            Ok(())
+        }
+        altered => Err(format!("{} of {} blocks altered", altered, report.blocks).into()),
+    }
+}
+
+/// Runs the known-answer and round-trip checks of [`selftest::run`], failing when any of them
+/// does, so a build can be vetted before it's trusted with real files
+pub fn selftest(args: SelftestArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    let checks = selftest::run();
+    let mut output = Output::new(mode, io::stdout().lock());
+    write_checks(&checks, &mut output, &Theme::new(args.color))?;
+
+    match checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count()
+    {
+        0 => Ok(()),
+        1 => Err("1 check failed".into()),
+        failed => Err(format!("{} checks failed", failed).into()),
+    }
+}
+
+/// Writes one line or `check` record per component, then a summary
+fn write_checks<W: Write>(
+    checks: &[Check],
+    output: &mut Output<W>,
+    theme: &Theme,
+) -> io::Result<()> {
+    for check in checks {
+        let status = match check.status {
+            Status::Pass => {
+                output.human(theme.success(check))?;
+                "pass"
+            }
+            Status::Fail => {
+                output.human(theme.error(check))?;
+                "fail"
+            }
+            Status::Skipped => {
+                output.human(check)?;
+                "skipped"
+            }
+        };
+        output.record("check", &[&check.component, &status, &check.detail])?;
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    output.record("summary", &[&checks.len(), &failed])?;
+    output.json(&checks)
+}
+
+/// Prints the standard metadata chunks of a file. With --exif the eXIf data is written out as
+/// well, next to the file unless a path is given.
+pub fn meta(args: MetaArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    let metadata = Metadata::of(&read_png_with(&args.file, args.lenient, &args.audit)?);
+    if let Some(path) = &args.exif {
+        let exif = metadata
+            .exif
+            .as_ref()
+            .ok_or_else(|| PngMeError::ChunkNotFound(String::from("eXIf")))?;
+        let path = match path {
+            Some(path) => path.clone(),
+            None if is_stdio(&args.file) => {
+                return Err("--exif needs a path when reading stdin".into())
+            }
+            None => args.file.with_extension("exif"),
+        };
+        fs::write(&path, &exif.data)?;
+        record_audit(&args.audit, "meta", &path, None, Some(&exif.data))?;
+    }
+    write_meta(&metadata, &mut Output::new(mode, io::stdout().lock()))?;
+    Ok(())
+}
+
+/// Writes one line or record per piece of metadata, with unreadable chunks as warnings
+fn write_meta<W: Write>(metadata: &Metadata, output: &mut Output<W>) -> io::Result<()> {
+    for entry in &metadata.text {
+        match &entry.language {
+            Some(language) => output.human(format!(
+                "{} {} [{}]: {}",
+                entry.chunk_type, entry.keyword, language, entry.text
+            ))?,
+            None => output.human(format!(
+                "{} {}: {}",
+                entry.chunk_type, entry.keyword, entry.text
+            ))?,
+        }
+        output.record(
+            "text",
+            &[
+                &entry.chunk_type,
+                &entry.keyword,
+                &entry.language.as_deref().unwrap_or("-"),
+                &entry.text,
+            ],
+        )?;
+    }
+    if let Some(modified) = &metadata.modified {
+        output.human(format!("Modified: {}", modified))?;
+        output.record("time", &[modified])?;
+    }
+    if let Some(physical) = &metadata.physical {
+        output.human(format!("Pixel size: {}", physical))?;
+        let unit = match physical.in_metres {
+            true => "metre",
+            false => "unknown",
+        };
+        output.record(
+            "phys",
+            &[
+                &physical.pixels_per_unit_x,
+                &physical.pixels_per_unit_y,
+                &unit,
+            ],
+        )?;
+    }
+    if let Some(gamma) = metadata.gamma {
+        output.human(format!("Gamma: {}", gamma))?;
+        output.record("gamma", &[&gamma])?;
+    }
+    if let Some(exif) = &metadata.exif {
+        output.human(format!("eXIf: {} bytes, {}", exif.length, exif.byte_order))?;
+        output.record("exif", &[&exif.length, &exif.byte_order])?;
+    }
+    if metadata.is_empty() {
+        output.human("no metadata")?;
+    }
+    for unreadable in &metadata.unreadable {
+        output.human(format!("warning: {}", unreadable))?;
+        output.record("unreadable", &[unreadable])?;
+    }
+    output.json(metadata)
+}
+
+/// How much one method can hide, as written by `capacity`
+#[derive(Serialize, PartialEq, Eq, Debug)]
+struct MethodCapacity {
+    method: &'static str,
+    /// Most bytes of message that fit, or `None` when there is no limit
+    bytes: Option<u64>,
+    /// Message length past which the method makes the file stand out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warn_over: Option<u64>,
+    /// Why the method can't be used on the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Reports the largest message each method can hide. Without --method, a method that can't be
+/// used on the file is reported as such rather than failing the command.
+pub fn capacity(args: CapacityArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    if args.file.is_dir() {
+        if args.method.is_some() {
+            return Err("--method only applies to a single file".into());
+        }
+        let report = capacity::corpus_capacity(&args.file, args.recursive)?;
+        write_corpus_capacity(&report, &mut Output::new(mode, io::stdout().lock()))?;
+        return Ok(());
+    }
+    if args.recursive {
+        return Err("--recursive only applies to a directory".into());
+    }
+    let png = read_png(&args.file, &args.audit)?;
+    let mut capacities = Vec::new();
+    if args.method.is_none_or(|method| method == Method::Chunk) {
+        capacities.push(MethodCapacity {
+            method: "chunk",
+            bytes: None,
+            warn_over: Some(capacity::chunk_warning_len(&png)),
+            error: None,
+        });
+    }
+    if args.method.is_none_or(|method| method == Method::Lsb) {
+        let (bytes, error) = match lsb::capacity(&png) {
+            Ok(bytes) => (bytes, None),
+            Err(e) if args.method.is_none() => (0, Some(e.to_string())),
+            Err(e) => return Err(e.into()),
+        };
+        capacities.push(MethodCapacity {
+            method: "lsb",
+            bytes: Some(bytes),
+            warn_over: None,
+            error,
+        });
+    }
+
+    write_capacities(&capacities, &mut Output::new(mode, io::stdout().lock()))?;
+    Ok(())
+}
+
+/// Writes one line or `capacity` record per method
+fn write_capacities<W: Write>(
+    capacities: &[MethodCapacity],
+    output: &mut Output<W>,
+) -> io::Result<()> {
+    let optional = |value: Option<u64>| value.map_or(String::from("-"), |value| value.to_string());
+    for capacity in capacities {
+        match (capacity.bytes, &capacity.error) {
+            (_, Some(error)) => {
+                output.human(format!("{}: unavailable, {}", capacity.method, error))?
+            }
+            (Some(bytes), None) => output.human(format!("{}: {} bytes", capacity.method, bytes))?,
+            (None, None) => output.human(format!(
+                "{}: unlimited, split into chunks of up to {} bytes",
+                capacity.method, MAX_CHUNK_LENGTH
+            ))?,
+        }
+        if let Some(warn_over) = capacity.warn_over {
+            output.human(format!(
+                "  warning: messages over {} bytes more than double the file size",
+                warn_over
+            ))?;
+        }
+        output.record(
+            "capacity",
+            &[
+                &capacity.method,
+                &optional(capacity.bytes),
+                &optional(capacity.warn_over),
+            ],
+        )?;
+    }
+    output.json(&capacities)
+}
+
+/// Writes one line or `file` record per PNG, a warning or `skipped` record per file that
+/// couldn't be read, then the totals
+fn write_corpus_capacity<W: Write>(
+    report: &CapacityReport,
+    output: &mut Output<W>,
+) -> io::Result<()> {
+    for file in &report.files {
+        match file.lsb {
+            Some(lsb) => output.human(format!("{}: lsb {} bytes", file.path.display(), lsb))?,
+            None => output.human(format!("{}: lsb unavailable", file.path.display()))?,
+        }
+        let lsb = file.lsb.map_or(String::from("-"), |lsb| lsb.to_string());
+        output.record("file", &[&file.path.display(), &lsb])?;
+    }
+    for skipped in &report.skipped {
+        output.human(format!(
+            "warning: skipped {}: {}",
+            skipped.path.display(),
+            skipped.reason
+        ))?;
+        output.record("skipped", &[&skipped.path.display(), &skipped.reason])?;
+    }
+    let totals = &report.totals;
+    output.human(format!(
+        "{} files: lsb {} bytes across {} of them, chunks unlimited",
+        totals.files, totals.lsb, totals.lsb_files
+    ))?;
+    output.record("summary", &[&totals.files, &totals.lsb, &totals.lsb_files])?;
+    output.json(report)
+}
+
+/// Lists every chunk in the PNGs under `dir` that matches all the criteria given
+pub fn find(args: FindArgs) -> MyResult<()> {
+    let mode = args.report.mode(args.format)?;
+    if args.chunk_type.is_none() && args.keyword.is_none() && args.payload_sha256.is_none() {
+        return Err("give at least one of --chunk-type, --keyword or --payload-sha256".into());
+    }
+    if let Some(chunk_type) = &args.chunk_type {
+        ChunkType::from_str(chunk_type)?;
+    }
+    if let Some(hash) = &args.payload_sha256 {
+        if hash.len() != 64 || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err("--payload-sha256 must be 64 hex digits".into());
+        }
+    }
+    let query = corpus::Query {
+        chunk_type: args.chunk_type,
+        keyword: args.keyword,
+        payload_sha256: args.payload_sha256,
+    };
+    let report = corpus::find(&args.dir, args.recursive, &query)?;
+    write_found(
+        &report,
+        &mut Output::new(mode, io::stdout().lock()),
+        &Theme::new(args.color),
+    )?;
+    Ok(())
+}
+
+/// Writes one line or `found` record per matching chunk, a warning or `skipped` record per file
+/// that couldn't be read, then a summary
+fn write_found<W: Write>(
+    report: &FindReport,
+    output: &mut Output<W>,
+    theme: &Theme,
+) -> io::Result<()> {
+    for found in &report.matches {
+        let chunk_type = match ChunkType::from_str(&found.chunk_type) {
+            Ok(chunk_type) => theme.chunk_type(&chunk_type).to_string(),
+            Err(_) => found.chunk_type.clone(),
+        };
+        output.human(format!(
+            "{}: chunk {} {}, {} bytes",
+            found.path.display(),
+            found.index,
+            chunk_type,
+            found.length
+        ))?;
+        output.record(
+            "found",
+            &[
+                &found.path.display(),
+                &found.index,
+                &found.chunk_type,
+                &found.length,
+            ],
+        )?;
+    }
+    for skipped in &report.skipped {
+        output.human(format!(
+            "warning: skipped {}: {}",
+            skipped.path.display(),
+            skipped.reason
+        ))?;
+        output.record("skipped", &[&skipped.path.display(), &skipped.reason])?;
+    }
+    if report.matches.is_empty() {
+        output.human("no matching chunks")?;
+    }
+    output.record("summary", &[&report.matches.len(), &report.skipped.len()])?;
+    output.json(report)
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO
+}
+
+/// Where a command that writes an image to `destination` reports on it: stdout, unless the image
+/// itself goes there
+fn report_stream(destination: &Path) -> Box<dyn Write> {
+    match is_stdio(destination) {
+        true => Box::new(io::stderr().lock()),
+        false => Box::new(io::stdout().lock()),
+    }
+}
+
+/// Opens `path` for reading, or stdin when it is `-`
+fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    match is_stdio(path) {
+        true => Ok(Box::new(io::stdin().lock())),
+        false => Ok(Box::new(BufReader::new(File::open(path)?))),
+    }
+}
+
+fn read_all(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Calls `f` with the contents of the file, or of stdin for `-`, and records the read in the
+/// --audit-log with the hash of the bytes as read. With the `mmap` feature files are mapped
+/// rather than read, so `f` only loads the parts it looks at unless the read is logged.
+fn with_input<T>(
+    path: &Path,
+    audit: &AuditArgs,
+    f: impl FnOnce(&[u8]) -> MyResult<T>,
+) -> MyResult<T> {
+    let f = |bytes: &[u8]| {
+        record_audit(audit, "read", path, Some(bytes), None)?;
+        f(bytes)
+    };
+    #[cfg(feature = "mmap")]
+    if !is_stdio(path) {
+        return f(Png::open_mmap(path)?.as_bytes());
+    }
+    f(&read_all(path)?)
+}
+
+fn read_png(path: &Path, audit: &AuditArgs) -> MyResult<Png> {
+    with_input(path, audit, |bytes| Ok(Png::try_from(bytes)?))
+}
+
+/// Reads the image like [`read_png`], or with --lenient recovers what it can from a damaged file
+/// with [`Png::try_from_lenient`], warning on stderr about each problem worked around
+fn read_png_with(path: &Path, lenient: bool, audit: &AuditArgs) -> MyResult<Png> {
+    if !lenient {
+        return read_png(path, audit);
+    }
+    with_input(path, audit, |bytes| {
+        let (png, warnings) = Png::try_from_lenient(bytes)?;
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+        Ok(png)
+    })
+}
+
+/// Writes the image to stdout when `path` is `-`, and otherwise replaces the file with
+/// [`Png::write_file`], keeping a copy of the old file as `path.bak` with `backup`. Without
+/// `check_order` the chunks may be out of the order the spec requires.
+fn write_png(png: &Png, path: &Path, backup: bool, check_order: bool) -> MyResult<()> {
+    let backup = backup.then(|| with_suffix(path, ".bak"));
+    match (is_stdio(path), &backup, check_order) {
+        (true, _, _) => png.write_to(io::stdout().lock())?,
+        (false, backup, false) => png.write_file_ignoring_order(path, backup.as_deref())?,
+        (false, Some(backup), true) => png.write_file_with_backup(path, backup)?,
+        (false, None, true) => png.write_file(path)?,
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` added to the end of its file name, as in `image.png.bak`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Writes `png`, an edited copy of `original` read from `source`, to `destination` the way the
+/// shared --dry-run and --yes options ask. Every command that rewrites a file goes through here.
+/// A dry run reports on stderr how the result would differ from `original` and writes nothing.
+/// Replacing `source` itself asks first, and keeps a copy of it with --backup.
+fn write_changes(
+    operation: &str,
+    original: &Png,
+    png: &Png,
+    source: &Path,
+    destination: &Path,
+    write: &WriteArgs,
+) -> MyResult<()> {
+    write_changes_with(operation, original, png, source, destination, write, true)
+}
+
+/// Like [`write_changes`], only checking the chunk order of the written file with `check_order`
+fn write_changes_with(
+    operation: &str,
+    original: &Png,
+    png: &Png,
+    source: &Path,
+    destination: &Path,
+    write: &WriteArgs,
+    check_order: bool,
+) -> MyResult<()> {
+    let mut png = Cow::Borrowed(png);
+    if write.touch {
+        clock::stamp(png.to_mut())?;
+    }
+    let in_place = is_same_file(source, destination);
+    if write.dry_run {
+        eprint!("{}", PlannedWrite::new(original, &png, destination));
+        if in_place && write.backup {
+            eprintln!(
+                "would back up {} to {}",
+                source.display(),
+                with_suffix(source, ".bak").display()
+            );
+        }
+        return Ok(());
+    }
+    if in_place {
+        confirm(&format!("Overwrite {}?", destination.display()), write)?;
+    }
+    write_png(&png, destination, in_place && write.backup, check_order)?;
+    record_audit(
+        &write.audit,
+        operation,
+        destination,
+        None,
+        Some(&png.as_bytes()),
+    )
+}
+
+/// Appends an entry for `operation` on `path` to the --audit-log, if one was given, with the
+/// hashes of the bytes read from the file or written to it, signed with --audit-key if given.
+/// Entries from one process go in one at a time so files handled in parallel don't break the
+/// chain.
+fn record_audit(
+    audit: &AuditArgs,
+    operation: &str,
+    path: &Path,
+    input: Option<&[u8]>,
+    output: Option<&[u8]>,
+) -> MyResult<()> {
+    static LOG: Mutex<()> = Mutex::new(());
+    let Some(log) = &audit.audit_log else {
+        return Ok(());
+    };
+    let key = audit.audit_key.as_deref().map(audit_key).transpose()?;
+    let _guard = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut log = AuditLog::open(log)?;
+    if let Some(key) = key {
+        log = log.with_signing_key(key);
+    }
+    log.record(path, operation, input, output)?;
+    Ok(())
+}
+
+/// Reads the Ed25519 secret key for --audit-key, given the same ways as a signing key
+fn audit_key(value: &str) -> MyResult<[u8; 32]> {
+    let key =
+        signing_key(value).map_err(|_| "--audit-key must be hex or the path of a key file")?;
+    key.try_into()
+        .map_err(|_| "--audit-key must be a 32-byte Ed25519 secret key".into())
+}
+
+/// Asks `question` on stderr and fails unless the answer is yes. Nothing is asked with --yes or
+/// --dry-run, or when stdin isn't a terminal, so scripts and pipes carry on as before.
+fn confirm(question: &str, write: &WriteArgs) -> MyResult<()> {
+    if write.yes || write.dry_run || !io::stdin().is_terminal() {
+        return Ok(());
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err("cancelled, nothing was written".into()),
+    }
+}
+
+/// Whether both paths name the same existing file. stdin and stdout never do.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    if is_stdio(a) || is_stdio(b) {
+        return false;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// What writing an edited image would change, as reported by --dry-run
+struct PlannedWrite<'a> {
+    destination: &'a Path,
+    diff: Diff,
+    /// Size of the written file minus the size of the file it was made from
+    byte_delta: i64,
+}
+
+impl PlannedWrite<'_> {
+    fn new<'a>(original: &Png, png: &Png, destination: &'a Path) -> PlannedWrite<'a> {
+        PlannedWrite {
+            destination,
+            diff: diff::diff(original, png),
+            byte_delta: png.as_bytes().len() as i64 - original.as_bytes().len() as i64,
+        }
+    }
+
+    fn count(&self, kind: ChangeKind) -> usize {
+        self.diff
+            .changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .count()
+    }
+}
+
+/// A summary line such as `would write a.png: 1 added, 0 removed, 0 modified, 0 moved, +25
+/// bytes`, then one indented line per changed chunk
+impl std::fmt::Display for PlannedWrite<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let destination = match is_stdio(self.destination) {
+            true => String::from("stdout"),
+            false => self.destination.display().to_string(),
+        };
+        writeln!(
+            f,
+            "would write {}: {} added, {} removed, {} modified, {} moved, {:+} bytes",
+            destination,
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.count(ChangeKind::Modified),
+            self.count(ChangeKind::Moved),
+            self.byte_delta
+        )?;
+        for change in &self.diff.changes {
+            match (&change.old, &change.new) {
+                (Some(old), Some(new)) if old.index != new.index => writeln!(
+                    f,
+                    "  {} {}, chunk {} to {}",
+                    change.kind, change.chunk_type, old.index, new.index
+                )?,
+                (Some(side), _) | (None, Some(side)) => writeln!(
+                    f,
+                    "  {} {}, chunk {}",
+                    change.kind, change.chunk_type, side.index
+                )?,
+                (None, None) => unreachable!("a change has at least one side"),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{OccurrenceArgs, ReportArgs, SelectArgs};
+    use crate::style::ColorChoice;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use pngme::compress::{self, Algorithm};
+    use pngme::ihdr::ColorType;
+    use pngme::ordering::InsertPosition;
+    use pngme::pixels::ImageData;
+    use pngme::selection::ChunkAddress;
+    use pngme::shamir::Threshold;
+    use pngme::signing;
+    use pngme::transform;
+    use pngme::PngMeError;
+    use std::{
+        env, fs,
+        num::NonZeroUsize,
+        path::{Path, PathBuf},
+        str::FromStr,
+    };
+
+    fn decode_message(file: &Path, chunk_type: &str) -> Result<String, PngMeError> {
+        pngme::decode_message(&Png::from_file(file)?, chunk_type)
+    }
+
+    /// Tests change their files in place without being asked, even when run from a terminal
+    const QUIET: ReportArgs = ReportArgs {
+        quiet: true,
+        porcelain: false,
+    };
+
+    const IN_PLACE: WriteArgs = WriteArgs {
+        dry_run: false,
+        yes: true,
+        backup: false,
+        touch: false,
+        audit: UNAUDITED,
+    };
+
+    const UNAUDITED: AuditArgs = AuditArgs {
+        audit_log: None,
+        audit_key: None,
+    };
+
+    /// Arguments for hiding `values` in `file` in place, with every other option at its default
+    fn encode_args(file: &Path, values: &[&str]) -> EncodeArgs {
+        EncodeArgs {
+            report: ReportArgs::default(),
+            write: IN_PLACE,
+            file: file.to_path_buf(),
+            values: values.iter().map(|value| String::from(*value)).collect(),
+            method: Method::Chunk,
+            text_chunk: None,
+            keyword: String::from("Comment"),
+            language: None,
+            encrypt: false,
+            recipient: vec![],
+            threshold: None,
+            carrier: vec![],
+            compress: Algorithm::None,
+            transform: None,
+            payload_file: None,
+            sign: false,
+            sign_image: false,
+            key: None,
+            position: None,
+            at_index: None,
+            at_offset: None,
+            jobs: None,
+        }
+    }
+
+    /// Arguments for printing the message in the first `chunk_type` chunk of `file`, with every
+    /// other option at its default
+    fn decode_args(file: &Path, chunk_type: &str) -> DecodeArgs {
+        DecodeArgs {
+            audit: UNAUDITED,
+            report: ReportArgs::default(),
+            lenient: false,
+            file: file.to_path_buf(),
+            chunk_type: Some(String::from(chunk_type)),
+            method: Method::Chunk,
+            keyword: None,
+            occurrence: OccurrenceArgs::default(),
+            decrypt: false,
+            share_file: vec![],
+            transform: None,
+            verify_signature: false,
+            key: None,
+            output: None,
+            jobs: None,
+            format: Format::Human,
+        }
+    }
+
+    /// The first `chunk_type` chunk
+    fn select(chunk_type: &str) -> SelectArgs {
+        SelectArgs {
+            chunk_type: Some(String::from(chunk_type)),
+            ..SelectArgs::default()
+        }
+    }
+
+    fn testing_file(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pngme-commands-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let png = Png::new(vec![
+            Ihdr::new(1, 1, 8, ColorType::Rgb).to_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let file = dir.join(name);
+        fs::write(&file, png.as_bytes()).unwrap();
+        file
+    }
+
+    fn types(file: &Path) -> Vec<String> {
+        Png::from_file(file)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_decode_remove() {
+        let file = testing_file("in-place.png");
+        encode(encode_args(&file, &["ruSt", "hidden message"])).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(decode_message(&file, "ruSt").unwrap(), "hidden message");
+
+        remove(RemoveArgs {
+            report: ReportArgs::default(),
+            write: IN_PLACE,
+            file: file.clone(),
+            select: select("ruSt"),
+        })
+        .unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "IEND"]);
+        assert!(decode_message(&file, "ruSt").is_err());
+        assert!(Png::from_file(&file)
+            .unwrap()
+            .chunks_by_type("ruSt", Occurrence::All)
+            .is_empty());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_encode_to_output() {
+        let file = testing_file("original.png");
+        let output = file.with_file_name("copy.png");
+        encode(encode_args(
+            &file,
+            &["ruSt", "hidden message", &output.to_string_lossy()],
+        ))
+        .unwrap();
+
+        assert_eq!(types(&file).len(), 3);
+        assert_eq!(decode_message(&output, "ruSt").unwrap(), "hidden message");
+        assert!(encode(encode_args(&file, &["ru5t", ""])).is_err());
+
+        fs::remove_file(file).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_encode_position() {
+        let file = testing_file("position.png");
+        let encode_args = |chunk_type: &str, position| EncodeArgs {
+            position,
+            ..encode_args(&file, &[chunk_type, "placed"])
+        };
+
+        encode(encode_args("pHYs", None)).unwrap();
+        encode(encode_args("ruSt", Some(InsertPosition::BeforeIdat))).unwrap();
+        encode(encode_args("prVt", Some(InsertPosition::AfterIdat))).unwrap();
+        assert_eq!(
+            types(&file),
+            vec!["IHDR", "pHYs", "ruSt", "IDAT", "prVt", "IEND"]
+        );
+        assert!(encode(encode_args("pHYs", Some(InsertPosition::End))).is_err());
+
+        encode(EncodeArgs {
+            at_index: Some(1),
+            ..encode_args("abCd", None)
+        })
+        .unwrap();
+        let idat_offset = Png::from_file(&file).unwrap().chunk_offsets()[4];
+        encode(EncodeArgs {
+            at_offset: Some(ChunkAddress::Offset(idat_offset + 5)),
+            ..encode_args("efGh", None)
+        })
+        .unwrap();
+        assert_eq!(
+            types(&file),
+            vec!["IHDR", "abCd", "pHYs", "ruSt", "efGh", "IDAT", "prVt", "IEND"]
+        );
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_encode_text_chunk() {
+        let file = testing_file("text.png");
+        encode(EncodeArgs {
+            text_chunk: Some(TextKind::Compressed),
+            ..encode_args(&file, &["hidden in plain sight"])
+        })
+        .unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "zTXt", "IEND"]);
+
+        let png = Png::from_file(&file).unwrap();
+        let text_chunk = text::find(&png, TextKind::Compressed, Some("Comment")).unwrap();
+        assert_eq!(text_chunk.text(), "hidden in plain sight");
+        assert!(encode(EncodeArgs {
+            text_chunk: Some(TextKind::Text),
+            ..encode_args(&file, &["tEXt", "message", "out.png"])
+        })
+        .is_err());
+        assert!(decode(DecodeArgs {
+            keyword: Some(String::from("Comment")),
+            ..decode_args(&file, "ruSt")
+        })
+        .is_err());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_encode_decode_file() {
+        let file = testing_file("carrier.png");
+        let secret = file.with_file_name("secret.bin");
+        let recovered = file.with_file_name("recovered.bin");
+        let contents: Vec<u8> = (0..=255).collect();
+        fs::write(&secret, &contents).unwrap();
+
+        encode(EncodeArgs {
+            payload_file: Some(secret.clone()),
+            ..encode_args(&file, &["ruSt"])
+        })
+        .unwrap();
+        decode(DecodeArgs {
+            output: Some(recovered.clone()),
+            ..decode_args(&file, "ruSt")
+        })
+        .unwrap();
+        assert_eq!(fs::read(&recovered).unwrap(), contents);
+
+        for path in [file, secret, recovered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_signed() {
+        let file = testing_file("signed.png");
+        let recovered = file.with_file_name("signed.txt");
+        let encode_args = |chunk_type: &str| EncodeArgs {
+            sign: true,
+            sign_image: true,
+            key: Some(String::from("00112233445566778899aabbccddeeff")),
+            ..encode_args(&file, &[chunk_type, "tamper evident"])
+        };
+        let decode_args = |key: Option<&str>| DecodeArgs {
+            verify_signature: key.is_some(),
+            key: key.map(String::from),
+            output: Some(recovered.clone()),
+            ..decode_args(&file, "ruSt")
+        };
+        assert!(encode(encode_args("RUST")).is_err());
+        encode(encode_args("ruSt")).unwrap();
+
+        decode(decode_args(Some("00112233445566778899aabbccddeeff"))).unwrap();
+        assert_eq!(fs::read(&recovered).unwrap(), b"tamper evident");
+        let wrong = decode(decode_args(Some("ff"))).unwrap_err();
+        assert!(matches!(
+            wrong.downcast_ref::<PngMeError>(),
+            Some(PngMeError::SignatureMismatch)
+        ));
+
+        let mut png = Png::from_file(&file).unwrap();
+        png.remove_first_chunk("IDAT").unwrap();
+        let idat = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0]);
+        png.insert_chunk_at(1, idat).unwrap();
+        png.write_file(&file).unwrap();
+        assert!(decode(decode_args(Some("00112233445566778899aabbccddeeff"))).is_err());
+        let unverified = decode(decode_args(None)).unwrap_err();
+        assert!(unverified.to_string().contains("--verify-signature"));
+
+        for path in [file, recovered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_decode_framed_text_chunk() {
+        let file = testing_file("framed-text.png");
+        let recovered = file.with_file_name("framed-text.txt");
+        let key = [7u8; 16];
+        let decode_args = |keyword: &str, key: Option<String>| DecodeArgs {
+            keyword: Some(String::from(keyword)),
+            verify_signature: key.is_some(),
+            key,
+            output: Some(recovered.clone()),
+            ..decode_args(&file, "tEXt")
+        };
+        let mut png = Png::from_file(&file).unwrap();
+        let payloads = [
+            (
+                "Encrypted",
+                crypto::encrypt("passphrase", b"secret").unwrap(),
+            ),
+            ("Signed", signing::sign(&key, b"signed", None).unwrap()),
+        ];
+        for (keyword, payload) in payloads {
+            let text = STANDARD.encode(payload);
+            let chunk = TextChunk::new(TextKind::Text, keyword, &text).unwrap();
+            png.insert_chunk_ordered(chunk.to_chunk().unwrap());
+        }
+        png.write_file(&file).unwrap();
+
+        let encrypted = decode(decode_args("Encrypted", None)).unwrap_err();
+        assert!(encrypted.to_string().contains("--decrypt"));
+        let signed = decode(decode_args("Signed", None)).unwrap_err();
+        assert!(signed.to_string().contains("--verify-signature"));
+        assert!(!recovered.exists(), "no base64 was written out");
+        decode(decode_args("Signed", Some(hex::encode(key)))).unwrap();
+        assert_eq!(fs::read(&recovered).unwrap(), b"signed");
+
+        for path in [file, recovered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_compressed() {
+        let file = testing_file("compressed.png");
+        let recovered = file.with_file_name("compressed.txt");
+        let message = "all work and no play ".repeat(50);
+        encode(EncodeArgs {
+            compress: Algorithm::Zlib,
+            ..encode_args(&file, &["ruSt", &message])
+        })
+        .unwrap();
+        let png = Png::from_file(&file).unwrap();
+        let data = png.chunk_by_type("ruSt").unwrap().data();
+        assert!(compress::is_compressed(data));
+        assert!(data.len() < message.len() / 4);
+
+        decode(DecodeArgs {
+            output: Some(recovered.clone()),
+            ..decode_args(&file, "ruSt")
+        })
+        .unwrap();
+        assert_eq!(fs::read(&recovered).unwrap(), message.as_bytes());
+
+        for path in [file, recovered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_transformed() {
+        let file = testing_file("transformed.png");
+        let recovered = file.with_file_name("transformed.txt");
+        let decoded = |chunk_type: &str| {
+            decode(DecodeArgs {
+                output: Some(recovered.clone()),
+                ..decode_args(&file, chunk_type)
+            })
+            .unwrap();
+            fs::read_to_string(&recovered).unwrap()
+        };
+        let transform = Some("gzip,xor:0x5A,base64".parse().unwrap());
+        encode(EncodeArgs {
+            transform: transform.clone(),
+            ..encode_args(&file, &["ruSt", "layered"])
+        })
+        .unwrap();
+        let png = Png::from_file(&file).unwrap();
+        assert!(transform::is_transformed(
+            png.chunk_by_type("ruSt").unwrap().data()
+        ));
+        assert_eq!(decoded("ruSt"), "layered");
+
+        encode(EncodeArgs {
+            transform: transform.clone(),
+            text_chunk: Some(TextKind::Text),
+            ..encode_args(&file, &["layered text"])
+        })
+        .unwrap();
+        assert_eq!(decoded("tEXt"), "layered text");
+
+        // A chain without a header is only undone when named
+        let layered = STANDARD.encode(b"from elsewhere");
+        encode(encode_args(&file, &["prVt", &layered])).unwrap();
+        assert_eq!(decoded("prVt"), layered);
+        decode(DecodeArgs {
+            output: Some(recovered.clone()),
+            transform: Some("base64".parse().unwrap()),
+            ..decode_args(&file, "prVt")
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&recovered).unwrap(), "from elsewhere");
+
+        for path in [file, recovered] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_animation_kept() {
+        let file = testing_file("animated.png");
+        let mut png = Png::from_file(&file).unwrap();
+        let mut frame = 0u32.to_be_bytes().to_vec();
+        frame.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1]);
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&[0, 1, 0, 2, 0, 0]);
+        png.insert_chunk_at(1, Chunk::new(ChunkType::acTL, vec![0, 0, 0, 1, 0, 0, 0, 0]))
+            .unwrap();
+        png.insert_chunk_at(2, Chunk::new(ChunkType::fcTL, frame))
+            .unwrap();
+        png.write_file(&file).unwrap();
+
+        let remove_args = |chunk_type: &str| RemoveArgs {
+            report: ReportArgs::default(),
+            write: IN_PLACE,
+            file: file.clone(),
+            select: select(chunk_type),
+        };
+        let refused = remove(remove_args("fcTL")).unwrap_err();
+        assert!(refused.to_string().contains("break the APNG animation"));
+        assert_eq!(types(&file), ["IHDR", "acTL", "fcTL", "IDAT", "IEND"]);
+
+        let bytes = fs::read(&file).unwrap();
+        let mut output = Output::new(OutputMode::Human, Vec::new());
+        write_list(
+            &PngRef::try_from(bytes.as_slice()).unwrap(),
+            None,
+            &mut output,
+            &Theme::plain(),
+        )
+        .unwrap();
+        let listed = String::from_utf8(output.into_inner()).unwrap();
+        assert_eq!(
+            listed.lines().last().unwrap(),
+            "Animation: 1 frame, looping forever, delays 0.5s"
+        );
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_scrub() {
+        let file = testing_file("scrub.png");
+        let scrubbed = file.with_file_name("scrubbed.png");
+        let mut png = Png::from_file(&file).unwrap();
+        for (chunk_type, data) in [("tEXt", "kept"), ("ruSt", "hidden"), ("prVt", "hidden")] {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into());
+            png.insert_before_iend(chunk).unwrap();
+        }
+        png.write_file(&file).unwrap();
+        let args = |keep: &[&str], remove_only: &[&str]| ScrubArgs {
+            filter: None,
+            lenient: false,
+            write: IN_PLACE,
+            file: file.clone(),
+            output: Some(scrubbed.clone()),
+            keep: keep
+                .iter()
+                .map(|chunk_type| String::from(*chunk_type))
+                .collect(),
+            remove_only: remove_only
+                .iter()
+                .map(|chunk_type| String::from(*chunk_type))
+                .collect(),
+            report: QUIET,
+        };
+
+        scrub(args(&["tEXt"], &[])).unwrap();
+        assert_eq!(types(&scrubbed), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        scrub(args(&[], &["prVt"])).unwrap();
+        assert_eq!(
+            types(&scrubbed),
+            vec!["IHDR", "IDAT", "tEXt", "ruSt", "IEND"]
+        );
+        assert!(scrub(args(&[], &["IDAT"])).is_err());
+        scrub(ScrubArgs {
+            filter: Some("!public && length > 4".parse().unwrap()),
+            ..args(&[], &[])
+        })
+        .unwrap();
+        assert_eq!(types(&scrubbed), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+
+        let mut png = Png::from_file(&file).unwrap();
+        let report = png.scrub(&[], None);
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_scrubbed(&report, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            "version\t1\nremoved\t2\ttEXt\t16\nremoved\t3\truSt\t18\n\
+             removed\t4\tprVt\t18\nsummary\t3\t52\n"
+        );
+
+        for path in [file, scrubbed] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let file = testing_file("diff.png");
+        let changed = file.with_file_name("diff-changed.png");
+        let mut png = Png::from_file(&file).unwrap();
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+        png.insert_before_iend(chunk).unwrap();
+        png.write_file(&changed).unwrap();
+
+        let changes = diff::diff(&Png::from_file(&file).unwrap(), &png);
+        let mut output = Output::new(OutputMode::Human, Vec::new());
+        write_diff(&changes, &mut output, &Theme::plain()).unwrap();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            format!(
+                "added     ruSt  at 2  length 6  crc {:08x}\n1 change, 3 chunks unchanged\n",
+                png.chunks()[2].crc()
+            )
+        );
+        let mut output = Output::new(OutputMode::Human, Vec::new());
+        write_diff(&changes, &mut output, &Theme::new(ColorChoice::Always)).unwrap();
+        assert!(String::from_utf8(output.into_inner())
+            .unwrap()
+            .starts_with("\x1b[32madded   \x1b[0m  \x1b[1;7;33mruSt\x1b[0m  at 2"));
+
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_diff(&changes, &mut output, &Theme::plain()).unwrap();
+        let written = String::from_utf8(output.into_inner()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert!(lines[1].starts_with("change\tadded\truSt\t-\t2\t-\t6\t"));
+        assert_eq!(lines[2], "summary\t3\t1");
+
+        let args = |new: &Path| DiffArgs {
+            audit: UNAUDITED,
+            old: file.clone(),
+            new: new.to_path_buf(),
+            report: ReportArgs::default(),
+            color: ColorChoice::Never,
+            format: Format::Json,
+        };
+        assert!(diff(args(&changed)).is_ok());
+        let quiet = |new: &Path| DiffArgs {
+            report: QUIET,
+            format: Format::Human,
+            ..args(new)
+        };
+        assert!(diff(quiet(&file)).is_ok());
+        assert_eq!(
+            diff(quiet(&changed)).unwrap_err().to_string(),
+            "the files differ"
+        );
+        assert!(diff(DiffArgs {
+            old: PathBuf::from("-"),
+            ..args(Path::new("-"))
+        })
+        .is_err());
+
+        for path in [file, changed] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let file = testing_file("dump.png");
+        let raw = file.with_file_name("dump.bin");
+        let mut png = Png::from_file(&file).unwrap();
+        for data in [vec![0, 159, 146, 150], vec![255]] {
+            png.insert_before_iend(Chunk::new(ChunkType::from_str("ruSt").unwrap(), data))
+                .unwrap();
+        }
+        png.write_file(&file).unwrap();
+        let args = |index, raw_output: bool| DumpArgs {
+            audit: UNAUDITED,
+            report: ReportArgs::default(),
+            file: file.clone(),
+            select: SelectArgs {
+                occurrence: OccurrenceArgs {
+                    index,
+                    ..OccurrenceArgs::default()
+                },
+                ..select("ruSt")
+            },
+            raw: raw_output,
+            output: raw_output.then(|| raw.clone()),
+        };
+
+        dump(args(None, true)).unwrap();
+        assert_eq!(fs::read(&raw).unwrap(), [0, 159, 146, 150]);
+        dump(args(Some(1), true)).unwrap();
+        assert_eq!(fs::read(&raw).unwrap(), [255]);
+        assert!(dump(args(None, false)).is_ok());
+        fs::remove_file(&raw).unwrap();
+        dump(DumpArgs {
+            report: QUIET,
+            ..args(None, true)
+        })
+        .unwrap();
+        assert_eq!(
+            fs::read(&raw).unwrap(),
+            [0, 159, 146, 150],
+            "--quiet still writes --output"
+        );
+        let porcelain = ReportArgs {
+            porcelain: true,
+            ..ReportArgs::default()
+        };
+        assert!(dump(DumpArgs {
+            report: porcelain,
+            ..args(None, true)
+        })
+        .is_err());
+
+        let error = dump(args(Some(2), true)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PngMeError>(),
+            Some(PngMeError::IndexOutOfRange)
+        ));
+        let error = dump(DumpArgs {
+            select: select("prVt"),
+            ..args(None, true)
+        })
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PngMeError>(),
+            Some(PngMeError::ChunkNotFound(_))
+        ));
+
+        for path in [file, raw] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_select_occurrence() {
+        let file = testing_file("occurrence.png");
+        let out = file.with_file_name("occurrence");
+        let message = file.with_file_name("occurrence.txt");
+        let mut png = Png::from_file(&file).unwrap();
+        for data in ["first", "second", "third"] {
+            png.insert_before_iend(Chunk::new(
+                ChunkType::from_str("ruSt").unwrap(),
+                data.as_bytes().to_vec(),
+            ))
+            .unwrap();
+        }
+        png.write_file(&file).unwrap();
+        let occurrence = |index, all, last| OccurrenceArgs {
+            index,
+            all,
+            last,
+            ..OccurrenceArgs::default()
+        };
+
+        let decode_to_file = |occurrence| {
+            decode(DecodeArgs {
+                occurrence,
+                output: Some(message.clone()),
+                ..decode_args(&file, "ruSt")
+            })
+            .unwrap();
+            fs::read_to_string(&message).unwrap()
+        };
+        assert_eq!(decode_to_file(occurrence(None, false, true)), "third");
+        assert_eq!(decode_to_file(occurrence(Some(1), false, false)), "second");
+        assert_eq!(
+            decode_to_file(occurrence(None, true, false)),
+            "firstsecondthird"
+        );
+        assert!(decode(DecodeArgs {
+            occurrence: occurrence(Some(0), false, true),
+            ..decode_args(&file, "ruSt")
+        })
+        .is_err());
+
+        extract(ExtractArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            select: SelectArgs {
+                occurrence: occurrence(None, true, false),
+                ..select("ruSt")
+            },
+            out: out.clone(),
+            report: QUIET,
+        })
+        .unwrap();
+        assert_eq!(fs::read(out.join("occurrence.2.ruSt")).unwrap(), b"first");
+        assert_eq!(fs::read(out.join("occurrence.4.ruSt")).unwrap(), b"third");
+        let third_offset = Png::from_file(&file).unwrap().chunk_offsets()[4];
+        extract(ExtractArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            select: SelectArgs {
+                at_offset: Some(ChunkAddress::Offset(third_offset + 8)),
+                ..SelectArgs::default()
+            },
+            out: out.clone(),
+            report: QUIET,
+        })
+        .unwrap();
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 3);
+
+        remove(RemoveArgs {
+            report: QUIET,
+            write: IN_PLACE,
+            file: file.clone(),
+            select: SelectArgs {
+                occurrence: occurrence(None, false, true),
+                ..select("ruSt")
+            },
+        })
+        .unwrap();
+        assert_eq!(decode_to_file(occurrence(None, false, true)), "second");
+        let by_index = |address: &str| RemoveArgs {
+            report: QUIET,
+            write: IN_PLACE,
+            file: file.clone(),
+            select: SelectArgs {
+                at_index: Some(ChunkAddress::parse_index(address).unwrap()),
+                ..SelectArgs::default()
+            },
+        };
+        let error = remove(by_index("2..6")).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PngMeError>(),
+            Some(PngMeError::IndexOutOfRange)
+        ));
+        remove(RemoveArgs {
+            select: SelectArgs {
+                filter: Some("type == \"ruSt\" && length == 5".parse().unwrap()),
+                ..SelectArgs::default()
+            },
+            ..by_index("0")
+        })
+        .unwrap();
+        assert_eq!(decode_to_file(occurrence(None, false, true)), "second");
+        remove(by_index("2..3")).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "IEND"]);
+        let error = remove(RemoveArgs {
+            select: SelectArgs {
+                filter: Some("!critical".parse().unwrap()),
+                ..SelectArgs::default()
+            },
+            ..by_index("0")
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "no matching chunk found");
+
+        fs::remove_dir_all(out).unwrap();
+        for path in [file, message] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_retag() {
+        let file = testing_file("retag.png");
+        encode(encode_args(&file, &["ruSt", "renamed"])).unwrap();
+        let args = |from: &str| RetagArgs {
+            file: file.clone(),
+            from: String::from(from),
+            to: String::from("prVt"),
+            write: IN_PLACE,
+            report: QUIET,
+        };
+
+        retag(args("ruSt")).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "prVt", "IEND"]);
+        assert_eq!(decode_message(&file, "prVt").unwrap(), "renamed");
+        // --touch stamps the written file with a tIME chunk
+        retag(RetagArgs {
+            from: String::from("prVt"),
+            to: String::from("ruSt"),
+            write: WriteArgs {
+                touch: true,
+                ..IN_PLACE
+            },
+            ..args("prVt")
+        })
+        .unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "ruSt", "tIME", "IEND"]);
+        retag(args("ruSt")).unwrap();
+        let error = retag(args("ruSt")).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PngMeError>(),
+            Some(PngMeError::ChunkNotFound(_))
+        ));
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_reorder() {
+        let file = testing_file("reorder.png");
+        encode(encode_args(&file, &["ruSt", "moved"])).unwrap();
+        encode(encode_args(&file, &["pHYs", "placed"])).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "pHYs", "IDAT", "ruSt", "IEND"]);
+        let args = |moved, after: Option<&str>, force| ReorderArgs {
+            file: file.clone(),
+            moved,
+            before: after.is_none().then(|| String::from("IDAT")),
+            after: after.map(String::from),
+            spec_order: false,
+            force,
+            write: IN_PLACE,
+            report: QUIET,
+        };
+
+        reorder(args(Some(3), None, false)).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "pHYs", "ruSt", "IDAT", "IEND"]);
+        let refused = reorder(args(Some(1), Some("IDAT"), false)).unwrap_err();
+        assert!(refused.to_string().contains("--force"));
+        reorder(args(Some(1), Some("IDAT"), true)).unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "ruSt", "IDAT", "pHYs", "IEND"]);
+
+        reorder(ReorderArgs {
+            spec_order: true,
+            ..args(None, None, false)
+        })
+        .unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "pHYs", "ruSt", "IDAT", "IEND"]);
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_dedup() {
+        let file = testing_file("dedup.png");
+        let deduped = file.with_file_name("deduped.png");
+        for _ in 0..3 {
+            encode(encode_args(&file, &["ruSt", "copy"])).unwrap();
+        }
+        let args = |output: Option<PathBuf>| DedupArgs {
+            file: file.clone(),
+            output,
+            report: QUIET,
+            write: IN_PLACE,
+        };
+
+        dedup(args(Some(deduped.clone()))).unwrap();
+        assert_eq!(types(&deduped), vec!["IHDR", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(types(&file).len(), 6, "only OUTPUT is written");
+
+        let mut report = Vec::new();
+        let mut output = Output::new(OutputMode::Human, &mut report);
+        write_deduped(
+            &Png::from_file(&file).unwrap().dedup_ancillary(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "removed duplicate chunk 3 ruSt\n\
+             removed duplicate chunk 4 ruSt\n\
+             removed 2 duplicates, saving 32 bytes\n"
+        );
+
+        for path in [file, deduped] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_explode_assemble() {
+        let file = testing_file("explode.png");
+        let dir = file.with_file_name("exploded");
+        let assembled = file.with_file_name("assembled.png");
+        encode(encode_args(&file, &["ruSt", "before"])).unwrap();
+
+        explode(ExplodeArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            out: dir.clone(),
+            report: QUIET,
+        })
+        .unwrap();
+        assert_eq!(fs::read(dir.join("002_ruSt.bin")).unwrap(), b"before");
+        fs::write(dir.join("002_ruSt.bin"), b"after").unwrap();
+        assemble(AssembleArgs {
+            audit: UNAUDITED,
+            dir: dir.clone(),
+            output: assembled.clone(),
+            report: QUIET,
+        })
+        .unwrap();
+        assert_eq!(types(&assembled), types(&file));
+        assert_eq!(decode_message(&assembled, "ruSt").unwrap(), "after");
+
+        fs::remove_dir_all(dir).unwrap();
+        for path in [file, assembled] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_patch() {
+        let from = testing_file("patch-from.png");
+        let to = testing_file("patch-to.png");
+        let other = testing_file("patch-other.png");
+        let patch = from.with_file_name("changes.pmp");
+        encode(encode_args(&to, &["ruSt", "patched"])).unwrap();
+        encode(encode_args(&other, &["abCd", "unrelated"])).unwrap();
+
+        let create_args = |from: &Path, to: &Path| PatchCreateArgs {
+            audit: UNAUDITED,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            output: Some(patch.clone()),
+            report: QUIET,
+        };
+        let apply_args = |file: &Path| PatchApplyArgs {
+            file: file.to_path_buf(),
+            patch: patch.clone(),
+            output: None,
+            write: IN_PLACE,
+            report: QUIET,
+        };
+        create_patch(create_args(&from, &to)).unwrap();
+        apply_patch(apply_args(&from)).unwrap();
+        assert_eq!(fs::read(&from).unwrap(), fs::read(&to).unwrap());
+
+        // Removing ruSt expects its CRC, which a file with another chunk there doesn't have
+        create_patch(create_args(&to, &other)).unwrap();
+        let before = fs::read(&other).unwrap();
+        assert!(apply_patch(apply_args(&other)).is_err());
+        assert_eq!(fs::read(&other).unwrap(), before);
+
+        for path in [from, to, other, patch] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let first = testing_file("apply-first.png");
+        let second = testing_file("apply-second.png");
+        let recipe = first.with_file_name("recipe.toml");
+        fs::write(
+            &recipe,
+            "[[operations]]\n\
+             op = \"set-text\"\n\
+             keyword = \"Author\"\n\
+             text = \"someone\"\n\
+             [[operations]]\n\
+             op = \"strip-types\"\n\
+             types = [\"ruSt\"]\n",
+        )
+        .unwrap();
+        encode(encode_args(&second, &["ruSt", "stripped"])).unwrap();
+        let args = |files: Vec<PathBuf>| ApplyArgs {
+            recipe: recipe.clone(),
+            files,
+            jobs: None,
+            write: IN_PLACE,
+            report: QUIET,
+        };
+
+        apply(args(vec![first.clone(), second.clone()])).unwrap();
+        for file in [&first, &second] {
+            assert_eq!(types(file), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        }
+        assert!(apply(args(vec![first.with_file_name("missing.png")])).is_err());
+
+        // A damaged CRC can only be read by a recipe that recomputes it
+        let mut bytes = fs::read(&first).unwrap();
+        bytes[29] ^= 0xff;
+        fs::write(&first, &bytes).unwrap();
+        assert!(apply(args(vec![first.clone()])).is_err());
+        fs::write(&recipe, "[[operations]]\nop = \"fix-crcs\"\n").unwrap();
+        apply(args(vec![first.clone()])).unwrap();
+        assert!(Png::from_file(&first).is_ok());
+
+        for path in [first, second, recipe] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_watermark() {
+        let file = testing_file("watermark.png");
+        let image = ImageData {
+            width: 16,
+            height: 16,
+            bit_depth: 8,
+            color_type: 2,
+            data: vec![128; 16 * 16 * 3],
+        };
+        fs::write(&file, image.to_png().unwrap().as_bytes()).unwrap();
+        let check_args = |key: &str| WatermarkCheckArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            key: String::from(key),
+            report: QUIET,
+        };
+
+        embed_watermark(WatermarkEmbedArgs {
+            file: file.clone(),
+            output: None,
+            key: String::from("0102"),
+            write: IN_PLACE,
+            report: QUIET,
+        })
+        .unwrap();
+        check_watermark(check_args("0102")).unwrap();
+
+        let mut png = Png::from_file(&file).unwrap();
+        let mut image = ImageData::decode(&png).unwrap();
+        image.data[0] ^= 0x80;
+        image.encode_into(&mut png).unwrap();
+        png.write_file(&file).unwrap();
+        let error = check_watermark(check_args("0102")).unwrap_err();
+        assert_eq!(error.to_string(), "1 of 4 blocks altered");
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_threshold() {
+        let file = testing_file("threshold.png");
+        let carriers: Vec<PathBuf> = (0..3)
+            .map(|i| testing_file(&format!("threshold-carrier-{}.png", i)))
+            .collect();
+        let threshold = Threshold::from_str("2-of-3").unwrap();
+        let args = EncodeArgs {
+            threshold: Some(threshold),
+            carrier: carriers[..2].to_vec(),
+            ..encode_args(&file, &["ruSt", "dead drop"])
+        };
+        assert!(encode(args).is_err(), "one carrier per share");
+        // A carrier that can't be read stops the encode before anything is written
+        let before = fs::read(&file).unwrap();
+        let first_carrier = fs::read(&carriers[0]).unwrap();
+        assert!(encode(EncodeArgs {
+            threshold: Some(threshold),
+            carrier: vec![
+                carriers[0].clone(),
+                carriers[1].clone(),
+                file.with_file_name("missing-carrier.png"),
+            ],
+            ..encode_args(&file, &["ruSt", "dead drop"])
+        })
+        .is_err());
+        assert_eq!(fs::read(&file).unwrap(), before);
+        assert_eq!(fs::read(&carriers[0]).unwrap(), first_carrier);
+        encode(EncodeArgs {
+            threshold: Some(threshold),
+            carrier: carriers.clone(),
+            ..encode_args(&file, &["ruSt", "dead drop"])
+        })
+        .unwrap();
+        assert!(!types(&file).contains(&String::from(shamir::SHARE_CHUNK_TYPE)));
+        for carrier in &carriers {
+            assert!(types(carrier).contains(&String::from(shamir::SHARE_CHUNK_TYPE)));
+        }
+
+        // The image alone is still encrypted, and one share isn't enough
+        let error = decode(decode_args(&file, "ruSt")).unwrap_err();
+        assert!(error.to_string().contains("encrypted"));
+        let decode_with = |share_file: &[PathBuf]| DecodeArgs {
+            share_file: share_file.to_vec(),
+            output: Some(file.with_file_name("threshold.txt")),
+            ..decode_args(&file, "ruSt")
+        };
+        let error = decode(decode_with(&carriers[..1])).unwrap_err();
+        assert_eq!(error.to_string(), "1 of 2 key shares found");
+        decode(decode_with(&[carriers[2].clone(), carriers[0].clone()])).unwrap();
+        assert_eq!(
+            fs::read(file.with_file_name("threshold.txt")).unwrap(),
+            b"dead drop"
+        );
+
+        for path in carriers
+            .into_iter()
+            .chain([file.clone(), file.with_file_name("threshold.txt")])
+        {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = testing_file("find.png").with_file_name("find-corpus");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::rename(testing_file("find.png"), dir.join("a.png")).unwrap();
+        encode(encode_args(&dir.join("a.png"), &["ruSt", "hidden"])).unwrap();
+        let mut png = Png::from_file(&testing_file("find.png")).unwrap();
+        let comment = TextChunk::new(TextKind::Text, "Comment", "hidden").unwrap();
+        png.insert_chunk_ordered(comment.to_chunk().unwrap());
+        png.write_file(&dir.join("nested/b.png")).unwrap();
+        fs::remove_file(testing_file("find.png")).unwrap();
+
+        let found = |query: &corpus::Query, recursive| {
+            let report = corpus::find(&dir, recursive, query).unwrap();
+            let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+            write_found(&report, &mut output, &Theme::plain()).unwrap();
+            String::from_utf8(output.into_inner()).unwrap()
+        };
+        let ru_st = corpus::Query {
+            chunk_type: Some(String::from("ruSt")),
+            ..corpus::Query::default()
+        };
+        assert_eq!(
+            found(&ru_st, true),
+            format!(
+                "version\t1\nfound\t{}\t2\truSt\t6\nsummary\t1\t0\n",
+                dir.join("a.png").display()
+            )
+        );
+        let comment = corpus::Query {
+            keyword: Some(String::from("Comment")),
+            ..corpus::Query::default()
+        };
+        assert!(found(&comment, true).contains("nested/b.png\t2\ttEXt"));
+        assert_eq!(found(&comment, false), "version\t1\nsummary\t0\t0\n");
+
+        let args = |chunk_type: Option<&str>, payload_sha256: Option<&str>| FindArgs {
+            dir: dir.clone(),
+            recursive: true,
+            chunk_type: chunk_type.map(String::from),
+            keyword: None,
+            payload_sha256: payload_sha256.map(String::from),
+            report: ReportArgs::default(),
+            color: ColorChoice::Never,
+            format: Format::Human,
+        };
+        assert!(find(args(None, None)).is_err());
+        assert!(find(args(Some("ru5t"), None)).is_err());
+        assert!(find(args(None, Some("abc"))).is_err());
+        assert!(find(args(Some("ruSt"), None)).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let file = testing_file("audited.png");
+        let log = file.with_file_name("audited.log");
+        let out = file.with_file_name("audited-chunks");
+        let _ = fs::remove_file(&log);
+        let secret = [7u8; 32];
+        let audit = AuditArgs {
+            audit_log: Some(log.clone()),
+            audit_key: Some(hex::encode(secret)),
+        };
+        let audited = WriteArgs {
+            audit: audit.clone(),
+            ..IN_PLACE
+        };
+        let before = fs::read(&file).unwrap();
+        encode(EncodeArgs {
+            write: audited.clone(),
+            ..encode_args(&file, &["ruSt", "logged"])
+        })
+        .unwrap();
+        let encoded = fs::read(&file).unwrap();
+        retag(RetagArgs {
+            file: file.clone(),
+            from: String::from("ruSt"),
+            to: String::from("prVt"),
+            write: audited.clone(),
+            report: QUIET,
+        })
+        .unwrap();
+        // A dry run writes nothing, so only the read is logged
+        remove(RemoveArgs {
+            file: file.clone(),
+            select: select("prVt"),
+            write: WriteArgs {
+                dry_run: true,
+                ..audited
+            },
+            report: QUIET,
+        })
+        .unwrap();
+        extract(ExtractArgs {
+            file: file.clone(),
+            select: select("prVt"),
+            out: out.clone(),
+            audit,
+            report: QUIET,
+        })
+        .unwrap();
+
+        let public_key = ed25519_dalek::SigningKey::from_bytes(&secret)
+            .verifying_key()
+            .to_bytes();
+        assert_eq!(pngme::audit::verify(&log, Some(public_key)).unwrap(), 7);
+        let entries: Vec<serde_json::Value> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let operations: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry["operation"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            operations,
+            ["read", "encode", "read", "retag", "read", "read", "extract"]
+        );
+        assert_eq!(entries[0]["input_sha256"], sidecar::file_hash(&before));
+        assert_eq!(entries[1]["output_sha256"], sidecar::file_hash(&encoded));
+        assert_eq!(entries[2]["input_sha256"], entries[1]["output_sha256"]);
+        assert_eq!(
+            entries[6]["file"],
+            out.join("audited.2.prVt").display().to_string()
+        );
+        assert_eq!(entries[6]["output_sha256"], sidecar::file_hash(b"logged"));
+
+        fs::remove_dir_all(out).unwrap();
+        for path in [file, log] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_audit_log_hashes_input_as_read() {
+        let file = testing_file("audited-lenient.png");
+        let log = file.with_file_name("audited-lenient.log");
+        let _ = fs::remove_file(&log);
+        // Bytes after IEND are dropped by --lenient, so the image as parsed isn't what was read
+        let mut bytes = fs::read(&file).unwrap();
+        bytes.extend_from_slice(b"trailing");
+        fs::write(&file, &bytes).unwrap();
+
+        scrub(ScrubArgs {
+            lenient: true,
+            write: WriteArgs {
+                audit: AuditArgs {
+                    audit_log: Some(log.clone()),
+                    audit_key: None,
+                },
+                ..IN_PLACE
+            },
+            filter: None,
+            file: file.clone(),
+            output: None,
+            keep: vec![],
+            remove_only: vec![],
+            report: QUIET,
+        })
+        .unwrap();
+        let entry: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&log).unwrap().lines().next().unwrap())
+                .unwrap();
+        assert_eq!(entry["input_sha256"], sidecar::file_hash(&bytes));
+
+        for path in [file, log] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_painted_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+        assert_eq!(
+            painted_chunk(&chunk, &Theme::plain()),
+            chunk.to_string().trim_end()
+        );
+        assert!(painted_chunk(&chunk, &Theme::new(ColorChoice::Always))
+            .contains("  Type: \x1b[1;7;33mruSt\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_write_checks() {
+        let checks = [
+            Check {
+                component: "crc32",
+                status: Status::Pass,
+                detail: String::from("check value 0xcbf43926"),
+            },
+            Check {
+                component: "zstd",
+                status: Status::Skipped,
+                detail: String::from("not in this build"),
+            },
+        ];
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_checks(&checks, &mut output, &Theme::plain()).unwrap();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            "version\t1\ncheck\tcrc32\tpass\tcheck value 0xcbf43926\n\
+             check\tzstd\tskipped\tnot in this build\nsummary\t2\t0\n"
+        );
+
+        let mut output = Output::new(OutputMode::Json, Vec::new());
+        write_checks(&checks, &mut output, &Theme::plain()).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&output.into_inner()).unwrap();
+        assert_eq!(written[1]["status"], "skipped");
+
+        selftest(SelftestArgs {
+            report: QUIET,
+            color: ColorChoice::Never,
+            format: Format::Human,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_meta() {
+        let file = testing_file("meta.png");
+        let args = |exif| MetaArgs {
+            audit: UNAUDITED,
+            lenient: false,
+            file: file.clone(),
+            exif,
+            report: ReportArgs::default(),
+            format: Format::Human,
+        };
+        let error = meta(args(Some(None))).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PngMeError>(),
+            Some(PngMeError::ChunkNotFound(_))
+        ));
+
+        let mut png = Png::from_file(&file).unwrap();
+        png.insert_chunk_ordered(
+            TextChunk::new(TextKind::Text, "Comment", "tab\there")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+        );
+        png.insert_chunk_ordered(Chunk::new(ChunkType::eXIf, b"MM\x00\x2a\x00".to_vec()));
+        png.insert_chunk_ordered(Chunk::new(ChunkType::gAMA, vec![0; 4]));
+        png.write_file(&file).unwrap();
+        meta(args(Some(None))).unwrap();
+        let sidecar = file.with_extension("exif");
+        assert_eq!(fs::read(&sidecar).unwrap(), b"MM\x00\x2a\x00");
+
+        let metadata = Metadata::of(&png);
+        let written = |mode| {
+            let mut output = Output::new(mode, Vec::new());
+            write_meta(&metadata, &mut output).unwrap();
+            String::from_utf8(output.into_inner()).unwrap()
+        };
+        assert_eq!(
+            written(OutputMode::Human),
+            "tEXt Comment: tab\there\n\
+             eXIf: 5 bytes, big-endian\n\
+             warning: chunk 2 (gAMA): gAMA can't be 0\n"
+        );
+        assert_eq!(
+            written(OutputMode::Porcelain),
+            "version\t1\n\
+             text\ttEXt\tComment\t-\ttab\\there\n\
+             exif\t5\tbig-endian\n\
+             unreadable\tchunk 2 (gAMA): gAMA can't be 0\n"
+        );
+        assert!(written(OutputMode::Json).contains("\"byte_order\": \"big-endian\""));
+
+        for path in [file, sidecar] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let file = testing_file("dry_run.png");
+        let copy = file.with_file_name("dry_run_copy.png");
+        let dry_run = WriteArgs {
+            dry_run: true,
+            ..IN_PLACE
+        };
+        let mut png = Png::from_file(&file).unwrap();
+        png.insert_before_iend(Chunk::new(ChunkType::tEXt, b"a\0b".to_vec()))
+            .unwrap();
+        png.write_file(&file).unwrap();
+        let before = fs::read(&file).unwrap();
+
+        remove(RemoveArgs {
+            report: ReportArgs::default(),
+            file: file.clone(),
+            select: select("tEXt"),
+            write: dry_run.clone(),
+        })
+        .unwrap();
+        scrub(ScrubArgs {
+            filter: None,
+            lenient: false,
+            file: file.clone(),
+            output: Some(copy.clone()),
+            keep: vec![],
+            remove_only: vec![],
+            report: QUIET,
+            write: dry_run,
+        })
+        .unwrap();
+        assert_eq!(fs::read(&file).unwrap(), before);
+        assert!(!copy.exists());
+
+        let mut scrubbed = png.clone();
+        scrubbed.remove_first_chunk("tEXt").unwrap();
+        scrubbed.insert_chunk_ordered(Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![1]));
+        assert_eq!(
+            PlannedWrite::new(&png, &scrubbed, &copy).to_string(),
+            format!(
+                "would write {}: 1 added, 1 removed, 0 modified, 0 moved, -2 bytes\n  \
+                 removed tEXt, chunk 2\n  \
+                 added ruSt, chunk 2\n",
+                copy.display()
+            )
+        );
+
+        assert!(is_same_file(&file, &file.with_file_name("./dry_run.png")));
+        assert!(!is_same_file(&file, &copy), "copy doesn't exist");
+        assert!(!is_same_file(Path::new(STDIO), Path::new(STDIO)));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_backup() {
+        let file = testing_file("backup.png");
+        let backup = with_suffix(&file, ".bak");
+        encode(encode_args(&file, &["ruSt", "hidden message"])).unwrap();
+        let before = fs::read(&file).unwrap();
+        let args = |chunk_type: &str, write| RemoveArgs {
+            report: ReportArgs::default(),
+            file: file.clone(),
+            select: select(chunk_type),
+            write,
+        };
+        let with_backup = WriteArgs {
+            backup: true,
+            ..IN_PLACE
+        };
+
+        // The result is verified before anything is replaced, and a file without IEND isn't valid
+        assert!(remove(args("IEND", with_backup.clone())).is_err());
+        assert_eq!(fs::read(&file).unwrap(), before);
+        assert!(!backup.exists());
+
+        remove(args("ruSt", with_backup)).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), before);
+        assert_eq!(types(&file), ["IHDR", "IDAT", "IEND"]);
+        let dir = fs::read_dir(file.parent().unwrap()).unwrap();
+        assert!(!dir
+            .map(|entry| entry.unwrap().file_name())
+            .any(|name| name.to_string_lossy().ends_with(".pngme-tmp")));
+
+        for path in [file, backup] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_lenient() {
+        let file = testing_file("lenient.png");
+        let mut bytes = fs::read(&file).unwrap();
+        bytes.extend_from_slice(b"junk after IEND");
+        fs::write(&file, &bytes).unwrap();
+        let args = |lenient| ScrubArgs {
+            filter: None,
+            file: file.clone(),
+            output: None,
+            keep: vec![],
+            remove_only: vec![],
+            report: QUIET,
+            lenient,
+            write: IN_PLACE,
+        };
+
+        assert!(scrub(args(false)).is_err());
+        scrub(args(true)).unwrap();
+        assert_eq!(types(&file), ["IHDR", "IDAT", "IEND"]);
+
+        let crc_at = bytes.len() - b"junk after IEND".len() - 13;
+        bytes[crc_at] ^= 0xff; // last CRC byte of IDAT
+        fs::write(&file, &bytes).unwrap();
+        assert!(scrub(args(false)).is_err());
+        scrub(args(true)).unwrap();
+        assert_eq!(types(&file), ["IHDR", "IDAT", "IEND"], "CRC was recomputed");
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_write_report() {
+        let file = testing_file("verify.png");
+        let mut bytes = fs::read(&file).unwrap();
+        let written = |report: &Report, mode| {
+            let mut output = Output::new(mode, Vec::new());
+            write_report(report, &mut output, &Theme::plain()).unwrap();
+            String::from_utf8(output.into_inner()).unwrap()
+        };
+
+        let report = verify::verify(&bytes);
+        assert_eq!(written(&report, OutputMode::Human), "valid, 3 chunks\n");
+
+        bytes.truncate(bytes.len() - 12);
+        let report = verify::verify(&bytes);
+        assert_eq!(
+            written(&report, OutputMode::Human),
+            "byte 45: no IEND chunk\n"
+        );
+        assert_eq!(
+            written(&report, OutputMode::Porcelain),
+            "version\t1\nproblem\t45\t-\tno IEND chunk\nsummary\t2\t1\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&written(&report, OutputMode::Json)).unwrap();
+        assert_eq!(json["chunks"], 2);
+        assert_eq!(
+            json["problems"],
+            serde_json::json!([{ "offset": 45, "index": null, "message": "no IEND chunk" }])
+        );
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_repair() {
+        let file = testing_file("repair.png");
+        let mut bytes = fs::read(&file).unwrap();
+        let crc_at = bytes.len() - 13;
+        bytes[crc_at] ^= 0xff; // last CRC byte of IDAT
+        fs::write(&file, &bytes).unwrap();
+        assert!(Png::from_file(&file).is_err());
+
+        let args = |output| RepairArgs {
+            lenient: false,
+            write: IN_PLACE,
+            file: file.clone(),
+            output,
+            report: QUIET,
+        };
+        let repaired = file.with_extension("fixed.png");
+        repair(args(Some(repaired.clone()))).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), bytes, "input must be left alone");
+        repair(args(None)).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), fs::read(&repaired).unwrap());
+        assert_eq!(Png::from_file(&file).unwrap().chunks().len(), 3);
+
+        let mut output = Output::new(OutputMode::Human, Vec::new());
+        let repairs = Png::parse_ignoring_crcs(&bytes).unwrap().repair();
+        write_repairs(&repairs, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            format!(
+                "chunk 1 IDAT: CRC {:08x} replaced with {:08x}\nrepaired 1 chunk\n",
+                repairs[0].stored, repairs[0].computed
+            )
+        );
+
+        fs::remove_file(file).unwrap();
+        fs::remove_file(repaired).unwrap();
+    }
+
+    #[test]
+    fn test_encode_decode_lsb() {
+        let file = testing_file("lsb.png");
+        let image = ImageData {
+            width: 16,
+            height: 16,
+            bit_depth: 8,
+            color_type: 2,
+            data: vec![128; 16 * 16 * 3],
+        };
+        fs::write(&file, image.to_png().unwrap().as_bytes()).unwrap();
+
+        encode(EncodeArgs {
+            method: Method::Lsb,
+            ..encode_args(&file, &["no chunk needed"])
+        })
+        .unwrap();
+        assert_eq!(types(&file), vec!["IHDR", "IDAT", "IEND"]);
+        let png = Png::from_file(&file).unwrap();
+        assert_eq!(lsb::extract(&png).unwrap(), b"no chunk needed");
+
+        let decode_args = |chunk_type: Option<&str>, method| DecodeArgs {
+            chunk_type: chunk_type.map(String::from),
+            method,
+            ..decode_args(&file, "")
+        };
+        decode(decode_args(None, Method::Lsb)).unwrap();
+        assert!(decode(decode_args(Some("ruSt"), Method::Lsb)).is_err());
+        assert!(decode(decode_args(None, Method::Chunk)).is_err());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_batch_encode_decode() {
+        let file = testing_file("batch.png");
+        let dir = file.with_file_name("batch");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            fs::copy(&file, dir.join(name)).unwrap();
+        }
+        fs::remove_file(file).unwrap();
+        let encode_args = |values: &[&str]| EncodeArgs {
+            jobs: NonZeroUsize::new(2),
+            ..encode_args(&dir, values)
+        };
+        encode(encode_args(&["ruSt", "hidden message"])).unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            assert_eq!(
+                decode_message(&dir.join(name), "ruSt").unwrap(),
+                "hidden message"
+            );
+        }
+        assert!(encode(encode_args(&["ruSt", "hidden message", "out.png"])).is_err());
+
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        let decode_args = decode_args(&dir, "ruSt");
+        assert_eq!(
+            decode(decode_args).unwrap_err().to_string(),
+            "1 file failed"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stdio_path() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+        assert!(!is_stdio(Path::new("-.png")));
+    }
+
+    #[test]
+    fn test_annotate() {
+        let file = testing_file("annotate.png");
+        let before = fs::read(&file).unwrap();
+        let args = |chunk, note: &str| AnnotateArgs {
+            file: file.clone(),
+            chunk,
+            note: String::from(note),
+            report: QUIET,
+        };
+
+        annotate(args(1, "looks like attacker tool output")).unwrap();
+        assert!(annotate(args(3, "no such chunk")).is_err());
+        assert_eq!(fs::read(&file).unwrap(), before);
+        let store = AnnotationStore::open(&file).unwrap();
+        let notes = store.chunk_annotations(&before, 1);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note, "looks like attacker tool output");
+
+        let print_args = |file: &Path| PrintArgs {
+            audit: UNAUDITED,
+            file: file.to_path_buf(),
+            annotations: true,
+            color: ColorChoice::Never,
+            format: Format::Human,
+            report: QUIET,
+        };
+        print(print_args(&file)).unwrap();
+        assert!(print(print_args(Path::new(STDIO))).is_err());
+
+        fs::remove_file(store.path()).unwrap();
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_write_list() {
+        let file = testing_file("list.png");
+        let bytes = fs::read(&file).unwrap();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        let mut output = Output::new(OutputMode::Human, Vec::new());
+        write_list(&png, None, &mut output, &Theme::plain()).unwrap();
+        let listed = String::from_utf8(output.into_inner()).unwrap();
+        let lines: Vec<&str> = listed.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            format!(
+                "    1        33  IDAT         0  {:08x}  critical, public, unsafe",
+                png.chunks()[1].crc()
+            )
+        );
+
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_list(&png, None, &mut output, &Theme::plain()).unwrap();
+        let listed = String::from_utf8(output.into_inner()).unwrap();
+        assert!(listed.starts_with("version\t1\nchunk\t0\t8\tIHDR\t13\t"));
+        assert_eq!(listed.lines().count(), 4);
+
+        let filter: Filter = "critical && offset > 8".parse().unwrap();
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_list(&png, Some(&filter), &mut output, &Theme::plain()).unwrap();
+        let listed = String::from_utf8(output.into_inner()).unwrap();
+        let indexes: Vec<&str> = listed
+            .lines()
+            .skip(1)
+            .map(|line| line.split('\t').nth(1).unwrap())
+            .collect();
+        assert_eq!(indexes, ["1", "2"]);
+
+        let mut output = Output::new(OutputMode::Json, Vec::new());
+        write_list(&png, None, &mut output, &Theme::plain()).unwrap();
+        let listed: serde_json::Value = serde_json::from_slice(&output.into_inner()).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 3);
+        assert_eq!(listed[1]["chunk_type"], "IDAT");
+        assert_eq!(listed[1]["offset"], 33);
+        assert_eq!(listed[2]["flags"][0], "critical");
+
+        // Listing reads chunk headers only, so a bad CRC is shown rather than failing the list
+        let mut bytes = bytes;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&file, &bytes).unwrap();
+        list(ListArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            filter: None,
+            report: QUIET,
+            color: ColorChoice::Never,
+            format: Format::Human,
+        })
+        .unwrap();
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_capacity() {
+        let file = testing_file("capacity.png");
+        let written = |capacities: &[MethodCapacity], mode| {
+            let mut output = Output::new(mode, Vec::new());
+            write_capacities(capacities, &mut output).unwrap();
+            String::from_utf8(output.into_inner()).unwrap()
+        };
+        let capacities = [
+            MethodCapacity {
+                method: "chunk",
+                bytes: None,
+                warn_over: Some(57),
+                error: None,
+            },
+            MethodCapacity {
+                method: "lsb",
+                bytes: Some(1224),
+                warn_over: None,
+                error: None,
+            },
+        ];
+        assert_eq!(
+            written(&capacities, OutputMode::Human),
+            "chunk: unlimited, split into chunks of up to 2147483647 bytes\n  \
+             warning: messages over 57 bytes more than double the file size\n\
+             lsb: 1224 bytes\n"
+        );
+        assert_eq!(
+            written(&capacities, OutputMode::Porcelain),
+            "version\t1\ncapacity\tchunk\t-\t57\ncapacity\tlsb\t1224\t-\n"
+        );
+
+        let image = ImageData {
+            width: 8,
+            height: 8,
+            bit_depth: 8,
+            color_type: 3,
+            data: vec![0; 64],
+        };
+        fs::write(&file, image.to_png().unwrap().as_bytes()).unwrap();
+        let args = |method| CapacityArgs {
+            audit: UNAUDITED,
+            file: file.clone(),
+            method,
+            report: ReportArgs {
+                porcelain: true,
+                ..ReportArgs::default()
+            },
+            format: Format::Human,
+            recursive: false,
+        };
+        assert!(capacity(args(None)).is_ok());
+        assert!(capacity(args(Some(Method::Chunk))).is_ok());
+        assert!(capacity(args(Some(Method::Lsb))).is_err());
+        assert!(capacity(CapacityArgs {
+            recursive: true,
+            ..args(None)
+        })
+        .is_err());
+
+        let dir = file.with_file_name("capacity-corpus");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let image = ImageData {
+            width: 16,
+            height: 16,
+            bit_depth: 8,
+            color_type: 2,
+            data: vec![128; 768],
+        };
+        fs::write(dir.join("a.png"), image.to_png().unwrap().as_bytes()).unwrap();
+        fs::rename(&file, dir.join("nested/b.png")).unwrap();
+        fs::write(dir.join("nested/c.png"), b"not a png").unwrap();
+        let report = capacity::corpus_capacity(&dir, true).unwrap();
+        let mut output = Output::new(OutputMode::Porcelain, Vec::new());
+        write_corpus_capacity(&report, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            format!(
+                "version\t1\nfile\t{}\t96\nfile\t{}\t-\nskipped\t{}\t{}\nsummary\t2\t96\t1\n",
+                dir.join("a.png").display(),
+                dir.join("nested/b.png").display(),
+                dir.join("nested/c.png").display(),
+                report.skipped[0].reason
+            )
+        );
+        let args = |recursive| CapacityArgs {
+            file: dir.clone(),
+            recursive,
+            ..args(None)
+        };
+        assert!(capacity(args(true)).is_ok());
+        assert!(capacity(CapacityArgs {
+            method: Some(Method::Lsb),
+            ..args(false)
+        })
+        .is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
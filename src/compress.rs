@@ -0,0 +1,206 @@
+use crate::error::PngMeError;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+/// Marks a compressed payload. The algorithm byte follows, then the length of the message as a
+/// big-endian u64, then the compressed message.
+pub const COMPRESSED_MAGIC: &[u8; 4] = b"pmZ1";
+/// Version of the compressed payload format, the digit at the end of [`COMPRESSED_MAGIC`]
+pub const COMPRESSED_VERSION: u32 = 1;
+const HEADER_LEN: usize = COMPRESSED_MAGIC.len() + 1 + 8;
+
+/// How a payload is compressed before it's hidden. zstd is only built with the `zstd` feature.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Algorithm {
+    #[default]
+    None,
+    Zlib,
+    Zstd,
+}
+
+impl Algorithm {
+    /// Whether this build can compress and decompress with the algorithm
+    pub fn is_available(self) -> bool {
+        match self {
+            Algorithm::None | Algorithm::Zlib => true,
+            Algorithm::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Algorithm::None => 0,
+            Algorithm::Zlib => 1,
+            Algorithm::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Algorithm, PngMeError> {
+        match id {
+            1 => Ok(Algorithm::Zlib),
+            2 => Ok(Algorithm::Zstd),
+            _ => Err(PngMeError::Malformed("payload uses an unknown compression")),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Algorithm::None),
+            "zlib" => Ok(Algorithm::Zlib),
+            "zstd" => Ok(Algorithm::Zstd),
+            _ => Err("compression must be one of zlib, zstd or none"),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Algorithm::None => "none",
+            Algorithm::Zlib => "zlib",
+            Algorithm::Zstd => "zstd",
+        };
+        f.pad(name)
+    }
+}
+
+/// Compresses `message` and frames it with a header naming the algorithm. With
+/// [`Algorithm::None`] the message is returned as is, without a header.
+pub fn compress(algorithm: Algorithm, message: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    if algorithm == Algorithm::None {
+        return Ok(message.to_vec());
+    }
+    let mut framed = COMPRESSED_MAGIC.to_vec();
+    framed.push(algorithm.id());
+    framed.extend_from_slice(&(message.len() as u64).to_be_bytes());
+    match algorithm {
+        Algorithm::None => unreachable!("returned above"),
+        Algorithm::Zlib => {
+            let mut encoder = ZlibEncoder::new(framed, Compression::best());
+            encoder.write_all(message)?;
+            Ok(encoder.finish()?)
+        }
+        Algorithm::Zstd => zstd_compress(framed, message),
+    }
+}
+
+/// Undoes [`compress`]. A payload without the header wasn't compressed and is returned as is.
+pub fn decompress(payload: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    if !is_compressed(payload) {
+        return Ok(payload.to_vec());
+    }
+    if payload.len() < HEADER_LEN {
+        return Err(PngMeError::TruncatedChunk);
+    }
+    let algorithm = Algorithm::from_id(payload[COMPRESSED_MAGIC.len()])?;
+    let length = u64::from_be_bytes(payload[HEADER_LEN - 8..HEADER_LEN].try_into().unwrap());
+    let compressed = &payload[HEADER_LEN..];
+
+    // Reading one byte past the recorded length is enough to tell that it's wrong, without
+    // inflating a payload that claims to be small without limit
+    let mut message = Vec::new();
+    match algorithm {
+        Algorithm::None => unreachable!("from_id never returns None"),
+        Algorithm::Zlib => ZlibDecoder::new(compressed)
+            .take(length.saturating_add(1))
+            .read_to_end(&mut message)
+            .map_err(|_| PngMeError::Malformed("payload is not valid zlib data"))?,
+        Algorithm::Zstd => zstd_decompress(compressed, length.saturating_add(1), &mut message)?,
+    };
+    match message.len() as u64 == length {
+        true => Ok(message),
+        false => Err(PngMeError::Malformed(
+            "decompressed payload doesn't match its recorded length",
+        )),
+    }
+}
+
+/// Whether `payload` starts like the output of [`compress`]
+pub fn is_compressed(payload: &[u8]) -> bool {
+    payload.starts_with(COMPRESSED_MAGIC)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_compress(framed: Vec<u8>, message: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    let mut encoder = zstd::Encoder::new(framed, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    encoder.write_all(message)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_compress(_framed: Vec<u8>, _message: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    Err(PngMeError::Invalid("this build doesn't support zstd"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decompress(compressed: &[u8], limit: u64, out: &mut Vec<u8>) -> Result<usize, PngMeError> {
+    zstd::Decoder::new(compressed)?
+        .take(limit)
+        .read_to_end(out)
+        .map_err(|_| PngMeError::Malformed("payload is not valid zstd data"))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decompress(_compressed: &[u8], _limit: u64, _out: &mut [u8]) -> Result<usize, PngMeError> {
+    Err(PngMeError::Invalid(
+        "payload is compressed with zstd, which this build doesn't support",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let message = "meet at dawn ".repeat(100);
+        for algorithm in [Algorithm::Zlib, Algorithm::Zstd] {
+            if !algorithm.is_available() {
+                assert!(compress(algorithm, message.as_bytes()).is_err());
+                continue;
+            }
+            let compressed = compress(algorithm, message.as_bytes()).unwrap();
+            assert!(is_compressed(&compressed));
+            assert!(compressed.len() < message.len() / 4, "{}", algorithm);
+            assert_eq!(decompress(&compressed).unwrap(), message.as_bytes());
+        }
+
+        let stored = compress(Algorithm::None, b"meet at dawn").unwrap();
+        assert_eq!(stored, b"meet at dawn");
+        assert_eq!(decompress(&stored).unwrap(), b"meet at dawn");
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        let mut compressed = compress(Algorithm::Zlib, b"meet at dawn").unwrap();
+        assert!(matches!(
+            decompress(&compressed[..HEADER_LEN - 1]),
+            Err(PngMeError::TruncatedChunk)
+        ));
+
+        compressed[HEADER_LEN - 1] += 1;
+        assert!(decompress(&compressed).is_err(), "wrong length");
+        compressed[HEADER_LEN - 1] -= 2;
+        assert!(decompress(&compressed).is_err(), "wrong length");
+
+        compressed[COMPRESSED_MAGIC.len()] = 9;
+        assert!(decompress(&compressed).is_err());
+        assert!(decompress(b"pmZ1\x01\x00\x00\x00\x00\x00\x00\x00\x01garbage").is_err());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Algorithm::from_str("zstd"), Ok(Algorithm::Zstd));
+        assert_eq!(Algorithm::from_str("none"), Ok(Algorithm::None));
+        assert!(Algorithm::from_str("gzip").is_err());
+        assert_eq!(Algorithm::Zlib.to_string(), "zlib");
+    }
+}
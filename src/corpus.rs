@@ -0,0 +1,211 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Lists the `.png` files in `dir`, descending into subdirectories when `recursive` is set.
+/// Symlinked directories are not followed. Paths are sorted so reports come out in a stable order.
+pub fn png_files(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+
+            if file_type.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+            } else if is_png_path(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// A file that was left out of a directory-wide report, and why
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// What [`find`] looks for. Every criterion that is set must hold for a chunk to match.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Query {
+    pub chunk_type: Option<String>,
+    /// Keyword of a `tEXt`, `zTXt` or `iTXt` chunk
+    pub keyword: Option<String>,
+    /// Hex sha256 of the chunk data
+    pub payload_sha256: Option<String>,
+}
+
+impl Query {
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type().bytes();
+        let type_matches = self
+            .chunk_type
+            .as_ref()
+            .is_none_or(|wanted| wanted.as_bytes() == chunk_type);
+        let keyword_matches = self.keyword.as_ref().is_none_or(|wanted| {
+            TEXT_CHUNK_TYPES.contains(&&chunk_type)
+                && chunk.data().split(|byte| *byte == 0).next() == Some(wanted.as_bytes())
+        });
+        let hash_matches = self.payload_sha256.as_ref().is_none_or(|wanted| {
+            wanted.eq_ignore_ascii_case(&hex::encode(Sha256::digest(chunk.data())))
+        });
+
+        type_matches && keyword_matches && hash_matches
+    }
+}
+
+const TEXT_CHUNK_TYPES: [&[u8; 4]; 3] = [b"tEXt", b"zTXt", b"iTXt"];
+
+/// A chunk that satisfied a [`Query`]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Found {
+    pub path: PathBuf,
+    pub index: usize,
+    pub chunk_type: String,
+    pub length: u32,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FindReport {
+    pub matches: Vec<Found>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Searches every PNG in `dir` for chunks matching `query`
+pub fn find(dir: &Path, recursive: bool, query: &Query) -> crate::MyResult<FindReport> {
+    let mut report = FindReport::default();
+
+    for path in png_files(dir, recursive)? {
+        let png = match read_png(&path) {
+            Ok(png) => png,
+            Err(reason) => {
+                report.skipped.push(Skipped { path, reason });
+                continue;
+            }
+        };
+
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            if query.matches(chunk) {
+                report.matches.push(Found {
+                    path: path.clone(),
+                    index,
+                    chunk_type: chunk.chunk_type().to_string(),
+                    length: chunk.length(),
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Reads and parses one file, flattening the failure into a reason suitable for a report
+pub fn read_png(path: &Path) -> Result<Png, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    Png::try_from(bytes.as_slice()).map_err(|e| e.to_string())
+}
+
+fn is_png_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_png_files() {
+        let dir = env::temp_dir().join(format!("pngme-corpus-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for file in ["b.png", "a.PNG", "notes.txt", "nested/c.png"] {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|file| file.strip_prefix(&dir).unwrap().display().to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(png_files(&dir, false).unwrap()),
+            vec!["a.PNG", "b.png"]
+        );
+        assert_eq!(
+            names(png_files(&dir, true).unwrap()),
+            vec!["a.PNG", "b.png", "nested/c.png"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let text = Chunk::new("tEXt".parse().unwrap(), b"Comment\0hello".to_vec());
+        let payload = Chunk::new("ruSt".parse().unwrap(), b"abc".to_vec());
+
+        let query = Query {
+            keyword: Some(String::from("Comment")),
+            ..Query::default()
+        };
+        assert!(query.matches(&text));
+        assert!(!query.matches(&payload));
+
+        let query = Query {
+            chunk_type: Some(String::from("ruSt")),
+            payload_sha256: Some(String::from(
+                "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD",
+            )),
+            ..Query::default()
+        };
+        assert!(query.matches(&payload));
+        assert!(!query.matches(&text));
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = env::temp_dir().join(format!("pngme-find-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let carrier = Png::new(vec![
+            Chunk::new("IHDR".parse().unwrap(), vec![0; 13]),
+            Chunk::new("ruSt".parse().unwrap(), b"artifact".to_vec()),
+        ]);
+        let clean = Png::new(vec![Chunk::new("IHDR".parse().unwrap(), vec![0; 13])]);
+        fs::write(dir.join("clean.png"), clean.as_bytes()).unwrap();
+        fs::write(dir.join("nested/carrier.png"), carrier.as_bytes()).unwrap();
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+
+        let query = Query {
+            chunk_type: Some(String::from("ruSt")),
+            ..Query::default()
+        };
+        let report = find(&dir, true, &query).unwrap();
+        assert_eq!(report.matches.len(), 1);
+        assert!(report.matches[0].path.ends_with("nested/carrier.png"));
+        assert_eq!(report.matches[0].index, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(find(&dir, false, &query).unwrap().matches.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
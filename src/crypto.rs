@@ -0,0 +1,332 @@
+use crate::error::PngMeError;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use sha2::Sha256;
+
+/// Marks an encrypted payload. The key derivation rounds, salt and nonce follow, then the
+/// ciphertext with its authentication tag.
+pub const CRYPTO_MAGIC: &[u8; 4] = b"pmE1";
+/// Version of the encrypted payload format, the digit at the end of [`CRYPTO_MAGIC`]
+pub const CRYPTO_VERSION: u32 = 1;
+/// Marks a payload encrypted for several passphrases. The key derivation rounds and the number of
+/// key slots follow, then the key slots, then the nonce and the ciphertext with its tag.
+pub const RECIPIENTS_MAGIC: &[u8; 4] = b"pmR1";
+/// Version of the multi-recipient payload format, the digit at the end of [`RECIPIENTS_MAGIC`]
+pub const RECIPIENTS_VERSION: u32 = 1;
+/// Environment variable the CLI reads the passphrase from before falling back to a prompt
+pub const PASSPHRASE_ENV: &str = "PNGME_PASSPHRASE";
+
+/// PBKDF2-HMAC-SHA256 rounds for new payloads, the OWASP recommendation
+pub const KDF_ROUNDS: u32 = 600_000;
+/// Refuses payloads asking for more rounds than this, counting every key slot a passphrase may
+/// have to be tried against, so a crafted file can't stall decryption
+const MAX_KDF_ROUNDS: u32 = 10_000_000;
+/// Most passphrases one payload can be encrypted for. Each needs its own key derivation when
+/// decrypting, so this many at [`KDF_ROUNDS`] stays under [`MAX_KDF_ROUNDS`].
+pub const MAX_RECIPIENTS: usize = 16;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = CRYPTO_MAGIC.len() + 4 + SALT_LEN + NONCE_LEN;
+const TAG_LEN: usize = 16;
+/// A salt and nonce, then the message key encrypted under the key derived from one passphrase
+const SLOT_LEN: usize = SALT_LEN + NONCE_LEN + 32 + TAG_LEN;
+const RECIPIENTS_HEADER_LEN: usize = RECIPIENTS_MAGIC.len() + 4 + 1;
+
+/// Encrypts `plaintext` with ChaCha20-Poly1305 under a key derived from `passphrase`, using a
+/// fresh random salt and nonce
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    seal(passphrase, plaintext, KDF_ROUNDS)
+}
+
+/// Encrypts `plaintext` under a key that is random already, such as one split into key shares.
+/// Stretching it gains nothing, so the key is derived with a single round.
+pub fn encrypt_with_key(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    seal(&hex::encode(key), plaintext, 1)
+}
+
+/// Decrypts a payload made by [`encrypt_with_key`]
+pub fn decrypt_with_key(key: &[u8], payload: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    decrypt(&hex::encode(key), payload)
+}
+
+/// Encrypts `plaintext` so that any one of `passphrases` decrypts it on its own. The message is
+/// encrypted once under a random key, and each passphrase gets a key slot holding that key
+/// encrypted under a key derived from the passphrase.
+pub fn encrypt_for(passphrases: &[&str], plaintext: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    seal_for(passphrases, plaintext, KDF_ROUNDS)
+}
+
+/// Decrypts a payload made by [`encrypt`], or by [`encrypt_for`] with any of its passphrases. A
+/// wrong passphrase and a modified payload both fail authentication and can't be told apart.
+pub fn decrypt(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    if payload.starts_with(RECIPIENTS_MAGIC) {
+        return open_slots(passphrase, payload);
+    }
+    if payload.len() < HEADER_LEN {
+        return Err(match payload.starts_with(CRYPTO_MAGIC) {
+            true => PngMeError::TruncatedChunk,
+            false => PngMeError::Malformed("message is not encrypted"),
+        });
+    }
+    let (header, ciphertext) = payload.split_at(HEADER_LEN);
+    let rest = header
+        .strip_prefix(CRYPTO_MAGIC)
+        .ok_or(PngMeError::Malformed("message is not encrypted"))?;
+    let (rounds, rest) = rest.split_at(4);
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+
+    let rounds = check_rounds(rounds, 1)?;
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt, rounds));
+    let nonce = Nonce::try_from(nonce).unwrap();
+    cipher
+        .decrypt(&nonce, ciphertext)
+        .map_err(|_| PngMeError::DecryptionFailed)
+}
+
+/// Whether `payload` starts like the output of [`encrypt`] or [`encrypt_for`]
+pub fn is_encrypted(payload: &[u8]) -> bool {
+    payload.starts_with(CRYPTO_MAGIC) || payload.starts_with(RECIPIENTS_MAGIC)
+}
+
+/// Tries each key slot in turn until one opens with `passphrase`, then decrypts the message with
+/// the key it holds
+fn open_slots(passphrase: &str, payload: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    let header = payload
+        .get(..RECIPIENTS_HEADER_LEN)
+        .ok_or(PngMeError::TruncatedChunk)?;
+    let slot_count = header[RECIPIENTS_HEADER_LEN - 1] as usize;
+    if slot_count == 0 || slot_count > MAX_RECIPIENTS {
+        return Err(PngMeError::Malformed(
+            "encrypted message has an unsupported number of key slots",
+        ));
+    }
+    let rounds = check_rounds(
+        &header[RECIPIENTS_MAGIC.len()..RECIPIENTS_MAGIC.len() + 4],
+        slot_count,
+    )?;
+    let slots_len = slot_count * SLOT_LEN;
+    let rest = &payload[RECIPIENTS_HEADER_LEN..];
+    if rest.len() < slots_len + NONCE_LEN {
+        return Err(PngMeError::TruncatedChunk);
+    }
+    let (slots, rest) = rest.split_at(slots_len);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    for slot in slots.chunks(SLOT_LEN) {
+        let (salt, rest) = slot.split_at(SALT_LEN);
+        let (slot_nonce, sealed_key) = rest.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt, rounds));
+        let Ok(key) = cipher.decrypt(&Nonce::try_from(slot_nonce).unwrap(), sealed_key) else {
+            continue;
+        };
+        return ChaCha20Poly1305::new(&Key::try_from(key.as_slice()).unwrap())
+            .decrypt(&Nonce::try_from(nonce).unwrap(), ciphertext)
+            .map_err(|_| PngMeError::DecryptionFailed);
+    }
+    Err(PngMeError::DecryptionFailed)
+}
+
+/// Reads the key derivation rounds of a payload with `slots` key slots, refusing a count of zero
+/// or one that adds up to more than [`MAX_KDF_ROUNDS`] over every slot
+fn check_rounds(rounds: &[u8], slots: usize) -> Result<u32, PngMeError> {
+    let rounds = u32::from_be_bytes(rounds.try_into().unwrap());
+    match rounds > 0 && rounds as u64 * slots as u64 <= MAX_KDF_ROUNDS as u64 {
+        true => Ok(rounds),
+        false => Err(PngMeError::Malformed(
+            "encrypted message has an unsupported round count",
+        )),
+    }
+}
+
+/// [`encrypt`] with a chosen number of key derivation rounds, so tests don't pay for the full
+/// count
+pub(crate) fn seal(passphrase: &str, plaintext: &[u8], rounds: u32) -> Result<Vec<u8>, PngMeError> {
+    if passphrase.is_empty() {
+        return Err(PngMeError::Invalid("passphrase can't be empty"));
+    }
+    let salt: [u8; SALT_LEN] = random()?;
+    let nonce: [u8; NONCE_LEN] = random()?;
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, rounds));
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), plaintext)
+        .map_err(|_| PngMeError::Invalid("message is too long to encrypt"))?;
+
+    let mut payload = CRYPTO_MAGIC.to_vec();
+    payload.extend_from_slice(&rounds.to_be_bytes());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&nonce);
+    payload.extend(ciphertext);
+    Ok(payload)
+}
+
+/// [`encrypt_for`] with a chosen number of key derivation rounds
+pub(crate) fn seal_for(
+    passphrases: &[&str],
+    plaintext: &[u8],
+    rounds: u32,
+) -> Result<Vec<u8>, PngMeError> {
+    if passphrases.is_empty() || passphrases.len() > MAX_RECIPIENTS {
+        return Err(PngMeError::Invalid(
+            "between 1 and 16 passphrases are needed",
+        ));
+    }
+    if passphrases.iter().any(|passphrase| passphrase.is_empty()) {
+        return Err(PngMeError::Invalid("passphrase can't be empty"));
+    }
+    let key = Key::from(random::<32>()?);
+
+    let mut payload = RECIPIENTS_MAGIC.to_vec();
+    payload.extend_from_slice(&rounds.to_be_bytes());
+    payload.push(passphrases.len() as u8);
+    for passphrase in passphrases {
+        let salt: [u8; SALT_LEN] = random()?;
+        let nonce: [u8; NONCE_LEN] = random()?;
+        let sealed_key = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, rounds))
+            .encrypt(&Nonce::from(nonce), key.as_slice())
+            .map_err(|_| PngMeError::Invalid("message key can't be encrypted"))?;
+        payload.extend_from_slice(&salt);
+        payload.extend_from_slice(&nonce);
+        payload.extend(sealed_key);
+    }
+
+    let nonce: [u8; NONCE_LEN] = random()?;
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&Nonce::from(nonce), plaintext)
+        .map_err(|_| PngMeError::Invalid("message is too long to encrypt"))?;
+    payload.extend_from_slice(&nonce);
+    payload.extend(ciphertext);
+    Ok(payload)
+}
+
+fn random<const N: usize>() -> Result<[u8; N], PngMeError> {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).map_err(|_| PngMeError::Invalid("no randomness available"))?;
+    Ok(bytes)
+}
+
+pub(crate) fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Full-strength key derivation is slow in debug builds, and the format records the rounds
+    const TEST_ROUNDS: u32 = 1_000;
+
+    #[test]
+    fn test_round_trip() {
+        let payload = seal("correct horse", b"meet at dawn", TEST_ROUNDS).unwrap();
+        assert!(is_encrypted(&payload));
+        assert_eq!(payload.len(), HEADER_LEN + b"meet at dawn".len() + 16);
+        assert_eq!(decrypt("correct horse", &payload).unwrap(), b"meet at dawn");
+
+        let again = seal("correct horse", b"meet at dawn", TEST_ROUNDS).unwrap();
+        assert_ne!(payload, again, "salt and nonce must be fresh");
+    }
+
+    #[test]
+    fn test_authentication() {
+        let mut payload = seal("correct horse", b"meet at dawn", TEST_ROUNDS).unwrap();
+        assert!(matches!(
+            decrypt("wrong horse", &payload),
+            Err(PngMeError::DecryptionFailed)
+        ));
+
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+        assert!(matches!(
+            decrypt("correct horse", &payload),
+            Err(PngMeError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        assert!(decrypt("passphrase", b"plain text message, not encrypted").is_err());
+        assert!(matches!(
+            decrypt("passphrase", b"pmE1\x00"),
+            Err(PngMeError::TruncatedChunk)
+        ));
+        assert!(seal("", b"message", TEST_ROUNDS).is_err());
+
+        let mut payload = seal("passphrase", b"message", TEST_ROUNDS).unwrap();
+        payload[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decrypt("passphrase", &payload),
+            Err(PngMeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_random_key() {
+        let payload = encrypt_with_key(&[7; 32], b"meet at dawn").unwrap();
+        assert_eq!(
+            payload[CRYPTO_MAGIC.len()..CRYPTO_MAGIC.len() + 4],
+            [0, 0, 0, 1]
+        );
+        assert_eq!(
+            decrypt_with_key(&[7; 32], &payload).unwrap(),
+            b"meet at dawn"
+        );
+        assert!(decrypt_with_key(&[8; 32], &payload).is_err());
+    }
+
+    #[test]
+    fn test_recipients() {
+        let payload = seal_for(&["first", "second"], b"meet at dawn", TEST_ROUNDS).unwrap();
+        assert!(is_encrypted(&payload));
+        assert_eq!(
+            payload.len(),
+            RECIPIENTS_HEADER_LEN + 2 * SLOT_LEN + NONCE_LEN + b"meet at dawn".len() + TAG_LEN
+        );
+        assert_eq!(decrypt("first", &payload).unwrap(), b"meet at dawn");
+        assert_eq!(decrypt("second", &payload).unwrap(), b"meet at dawn");
+        assert!(matches!(
+            decrypt("third", &payload),
+            Err(PngMeError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            decrypt("first", &payload[..RECIPIENTS_HEADER_LEN + SLOT_LEN]),
+            Err(PngMeError::TruncatedChunk)
+        ));
+        assert!(seal_for(&[], b"message", TEST_ROUNDS).is_err());
+        assert!(seal_for(&["first"; MAX_RECIPIENTS + 1], b"message", TEST_ROUNDS).is_err());
+        assert!(seal_for(&["first", ""], b"message", TEST_ROUNDS).is_err());
+    }
+
+    #[test]
+    fn test_slot_rounds_are_capped() {
+        let mut payload = seal_for(&["first", "second"], b"message", TEST_ROUNDS).unwrap();
+        let rounds = RECIPIENTS_MAGIC.len()..RECIPIENTS_MAGIC.len() + 4;
+        // Within the cap for one slot, but not for both
+        payload[rounds].copy_from_slice(&(MAX_KDF_ROUNDS / 2 + 1).to_be_bytes());
+        assert!(matches!(
+            decrypt("first", &payload),
+            Err(PngMeError::Malformed(_))
+        ));
+
+        let mut payload = seal_for(&["first"], b"message", TEST_ROUNDS).unwrap();
+        payload[RECIPIENTS_HEADER_LEN - 1] = u8::MAX;
+        assert!(matches!(
+            decrypt("first", &payload),
+            Err(PngMeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_derive_key() {
+        // RFC 7914 section 11, truncated to the 32 bytes of a key
+        let key = derive_key("passwd", b"salt", 1);
+        assert_eq!(
+            hex::encode(key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::chunk::Chunk;
+use crate::patch::{self, Step};
+use crate::png::Png;
+use sha2::{Digest, Sha256};
+use std::fmt::Display;
+
+/// How a chunk differs between two files
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ChangeKind {
+    /// Only in the second file
+    Added,
+    /// Only in the first file
+    Removed,
+    /// In both files with the same type but different contents
+    Modified,
+    /// Identical in both files, but in a different order relative to the other chunks
+    Moved,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Moved => "moved",
+        };
+        f.pad(name)
+    }
+}
+
+/// A chunk as it is in one of the two files
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChunkSide {
+    pub index: usize,
+    pub length: u32,
+    /// CRC in hex, as in the `list` output
+    pub crc: String,
+    /// SHA-256 of the chunk data in hex
+    pub sha256: String,
+}
+
+impl ChunkSide {
+    fn new(index: usize, chunk: &Chunk) -> ChunkSide {
+        ChunkSide {
+            index,
+            length: chunk.length(),
+            crc: format!("{:08x}", chunk.crc()),
+            sha256: hex::encode(Sha256::digest(chunk.data())),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChunkChange {
+    pub kind: ChangeKind,
+    pub chunk_type: String,
+    /// The chunk in the first file, unless it was added
+    pub old: Option<ChunkSide>,
+    /// The chunk in the second file, unless it was removed
+    pub new: Option<ChunkSide>,
+}
+
+/// Everything [`diff`] found between two files
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diff {
+    /// Chunks that are identical and in the same order in both files
+    pub unchanged: usize,
+    /// Changes in the order of the first file, with added chunks placed by their new index
+    pub changes: Vec<ChunkChange>,
+}
+
+impl Diff {
+    pub fn is_identical(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compares the chunk lists of two files. Chunks are aligned along their longest common
+/// subsequence, as in [`patch::Patch::create`]. Of the rest, an identical chunk in both files
+/// is reported as moved, which is how reordered IDAT chunks show up, and chunks of the same type
+/// are paired up in order as modified. Whatever is left was added or removed.
+pub fn diff(old: &Png, new: &Png) -> Diff {
+    let old_bytes: Vec<Vec<u8>> = old.chunks().iter().map(Chunk::as_bytes).collect();
+    let new_bytes: Vec<Vec<u8>> = new.chunks().iter().map(Chunk::as_bytes).collect();
+
+    let mut report = Diff::default();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for step in patch::diff(&old_bytes, &new_bytes) {
+        match step {
+            Step::Keep => report.unchanged += 1,
+            Step::Remove(i) => removed.push(i),
+            Step::Insert(j) => added.push(j),
+        }
+    }
+
+    let change = |kind, i: Option<usize>, j: Option<usize>| {
+        let chunk = i.map_or_else(|| &new.chunks()[j.unwrap()], |i| &old.chunks()[i]);
+        ChunkChange {
+            kind,
+            chunk_type: String::from_utf8_lossy(&chunk.chunk_type().bytes()).into_owned(),
+            old: i.map(|i| ChunkSide::new(i, &old.chunks()[i])),
+            new: j.map(|j| ChunkSide::new(j, &new.chunks()[j])),
+        }
+    };
+    let mut pair = |kind, matches: &dyn Fn(usize, usize) -> bool| {
+        removed.retain(|&i| match added.iter().position(|&j| matches(i, j)) {
+            Some(position) => {
+                let j = added.remove(position);
+                report.changes.push(change(kind, Some(i), Some(j)));
+                false
+            }
+            None => true,
+        });
+    };
+    pair(ChangeKind::Moved, &|i, j| old_bytes[i] == new_bytes[j]);
+    pair(ChangeKind::Modified, &|i, j| {
+        old.chunks()[i].chunk_type() == new.chunks()[j].chunk_type()
+    });
+    for i in removed {
+        report
+            .changes
+            .push(change(ChangeKind::Removed, Some(i), None));
+    }
+    for j in added {
+        report
+            .changes
+            .push(change(ChangeKind::Added, None, Some(j)));
+    }
+
+    report.changes.sort_by_key(|change| {
+        change
+            .old
+            .as_ref()
+            .or(change.new.as_ref())
+            .map(|side| side.index)
+    });
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png(chunks: &[(&str, &str)]) -> Png {
+        Png::new(
+            chunks
+                .iter()
+                .map(|(chunk_type, data)| {
+                    Chunk::new(
+                        ChunkType::from_str(chunk_type).unwrap(),
+                        data.as_bytes().to_vec(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn summary(diff: &Diff) -> Vec<String> {
+        diff.changes
+            .iter()
+            .map(|change| {
+                let index = |side: &Option<ChunkSide>| {
+                    side.as_ref()
+                        .map_or(String::from("-"), |side| side.index.to_string())
+                };
+                format!(
+                    "{} {} {} {}",
+                    change.kind,
+                    change.chunk_type,
+                    index(&change.old),
+                    index(&change.new)
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let png = testing_png(&[("IHDR", "h"), ("IDAT", "a"), ("IEND", "")]);
+        let diff = diff(&png, &png.clone());
+        assert!(diff.is_identical());
+        assert_eq!(diff.unchanged, 3);
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = testing_png(&[
+            ("IHDR", "h"),
+            ("tEXt", "old"),
+            ("IDAT", "a"),
+            ("IDAT", "b"),
+            ("IDAT", "c"),
+            ("zTXt", "gone"),
+            ("IEND", ""),
+        ]);
+        let new = testing_png(&[
+            ("IHDR", "h"),
+            ("tEXt", "new"),
+            ("IDAT", "b"),
+            ("IDAT", "a"),
+            ("IDAT", "c"),
+            ("ruSt", "hidden"),
+            ("IEND", ""),
+        ]);
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            summary(&diff),
+            [
+                "modified tEXt 1 1",
+                "moved IDAT 2 3",
+                "removed zTXt 5 -",
+                "added ruSt - 5",
+            ]
+        );
+        assert_eq!(diff.unchanged, 4);
+
+        let modified = &diff.changes[0];
+        let (before, after) = (
+            modified.old.as_ref().unwrap(),
+            modified.new.as_ref().unwrap(),
+        );
+        assert_eq!(before.length, after.length);
+        assert_ne!(before.crc, after.crc);
+        assert_eq!(
+            after.sha256,
+            "11507a0e2f5e69d5dfa40a62a1bd7b6ee57e6bcd85c67c9b8431b36fff21c437"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::verify::Problem;
+use std::{fmt, io, str::Utf8Error};
+
+/// Everything that can go wrong while parsing or editing a PNG
+#[derive(Debug)]
+pub enum PngMeError {
+    /// The file doesn't start with the 8-byte PNG signature
+    InvalidSignature,
+    /// The data ends partway through a chunk
+    TruncatedChunk,
+    /// A chunk declares more data than the reader accepts
+    ChunkTooLong {
+        length: u32,
+        max: u32,
+    },
+    /// A chunk type isn't four ASCII letters
+    InvalidChunkType(&'static str),
+    /// A chunk's stored CRC doesn't match its type and data
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// No chunk of the requested type exists
+    ChunkNotFound(String),
+    /// A chunk index or range points past the last chunk
+    IndexOutOfRange,
+    /// An encrypted message failed authentication: the passphrase is wrong or the data changed
+    DecryptionFailed,
+    /// A signed message failed its HMAC check: the key is wrong or the message or image changed
+    SignatureMismatch,
+    /// A message is encrypted and no passphrase was given to decrypt it
+    PassphraseRequired,
+    /// A message is signed and no key was given to check it
+    KeyRequired,
+    /// A message is larger than the image can hide
+    CapacityExceeded {
+        needed: u64,
+        available: u64,
+    },
+    /// Data that doesn't have the format it should, such as image data that isn't valid zlib or
+    /// a payload with pieces missing
+    Malformed(&'static str),
+    /// An edit was refused, with the reason
+    Invalid(&'static str),
+    /// A file written to replace another didn't pass [`verify`](crate::verify::verify) once read
+    /// back, so the original was kept
+    UnverifiedWrite(Vec<Problem>),
+    Io(io::Error),
+    Utf8(Utf8Error),
+}
+
+impl PngMeError {
+    /// Process exit code for the CLI, following the BSD `sysexits.h` conventions
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PngMeError::InvalidSignature
+            | PngMeError::TruncatedChunk
+            | PngMeError::ChunkTooLong { .. }
+            | PngMeError::CrcMismatch { .. }
+            | PngMeError::DecryptionFailed
+            | PngMeError::SignatureMismatch
+            | PngMeError::CapacityExceeded { .. }
+            | PngMeError::Malformed(_)
+            | PngMeError::UnverifiedWrite(_)
+            | PngMeError::Utf8(_) => 65,
+            PngMeError::ChunkNotFound(_) => 66,
+            PngMeError::InvalidChunkType(_)
+            | PngMeError::IndexOutOfRange
+            | PngMeError::PassphraseRequired
+            | PngMeError::KeyRequired
+            | PngMeError::Invalid(_) => 64,
+            PngMeError::Io(_) => 74,
+        }
+    }
+}
+
+impl fmt::Display for PngMeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngMeError::InvalidSignature => write!(f, "not a PNG file: the signature is missing"),
+            PngMeError::TruncatedChunk => write!(f, "the file ends in the middle of a chunk"),
+            PngMeError::ChunkTooLong { length, max } => write!(
+                f,
+                "chunk declares {} bytes of data, more than the limit of {}",
+                length, max
+            ),
+            PngMeError::InvalidChunkType(reason) => write!(f, "invalid chunk type: {}", reason),
+            PngMeError::CrcMismatch { expected, actual } => write!(
+                f,
+                "chunk CRC is {:08x} but its contents give {:08x}",
+                actual, expected
+            ),
+            PngMeError::ChunkNotFound(chunk_type) => write!(f, "no {} chunk found", chunk_type),
+            PngMeError::IndexOutOfRange => write!(f, "chunk index is out of range"),
+            PngMeError::DecryptionFailed => write!(
+                f,
+                "decryption failed: wrong passphrase or the message was modified"
+            ),
+            PngMeError::SignatureMismatch => write!(
+                f,
+                "signature check failed: wrong key or the message or image was modified"
+            ),
+            PngMeError::PassphraseRequired => {
+                write!(
+                    f,
+                    "the message is encrypted, a passphrase is needed to read it"
+                )
+            }
+            PngMeError::KeyRequired => {
+                write!(f, "the message is signed, a key is needed to check it")
+            }
+            PngMeError::CapacityExceeded { needed, available } => write!(
+                f,
+                "message is {} bytes but the image can hide only {}",
+                needed, available
+            ),
+            PngMeError::Malformed(reason) | PngMeError::Invalid(reason) => write!(f, "{}", reason),
+            PngMeError::UnverifiedWrite(problems) => {
+                write!(f, "the written image doesn't verify, nothing was replaced")?;
+                match problems.as_slice() {
+                    [] => Ok(()),
+                    [first, rest @ ..] => {
+                        write!(f, ": {}", first)?;
+                        match rest.len() {
+                            0 => Ok(()),
+                            more => write!(f, " and {} more", more),
+                        }
+                    }
+                }
+            }
+            PngMeError::Io(e) => write!(f, "{}", e),
+            PngMeError::Utf8(e) => write!(f, "chunk data is not valid UTF-8: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PngMeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngMeError::Io(e) => Some(e),
+            PngMeError::Utf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PngMeError {
+    fn from(e: io::Error) -> Self {
+        PngMeError::Io(e)
+    }
+}
+
+impl From<Utf8Error> for PngMeError {
+    fn from(e: Utf8Error) -> Self {
+        PngMeError::Utf8(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = PngMeError::CrcMismatch {
+            expected: 0xae426082,
+            actual: 1,
+        };
+        assert_eq!(
+            error.to_string(),
+            "chunk CRC is 00000001 but its contents give ae426082"
+        );
+        assert_eq!(
+            PngMeError::ChunkNotFound(String::from("ruSt")).to_string(),
+            "no ruSt chunk found"
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(PngMeError::InvalidSignature.exit_code(), 65);
+        assert_eq!(PngMeError::Invalid("refused").exit_code(), 64);
+        assert_eq!(PngMeError::Malformed("truncated").exit_code(), 65);
+        let io = io::Error::new(io::ErrorKind::NotFound, "missing");
+        assert_eq!(PngMeError::from(io).exit_code(), 74);
+    }
+}
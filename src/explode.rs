@@ -0,0 +1,137 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, str::FromStr};
+
+/// Lists the chunk files in an exploded directory in the order they are reassembled. Editing the
+/// order of entries here reorders the chunks in the assembled file.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub chunk_type: String,
+    /// CRC at the time of exploding, used to tell which chunks were edited since
+    pub crc: u32,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Manifest {
+    pub chunks: Vec<ManifestEntry>,
+}
+
+/// Result of reassembling a directory, with the positions of chunks whose data or type changed
+#[derive(Debug)]
+pub struct Assembled {
+    pub png: Png,
+    pub edited: Vec<usize>,
+}
+
+/// Writes the data of every chunk to `dir` as `000_IHDR.bin`, `001_tEXt.bin`, ... along with a
+/// manifest describing how to put them back together
+pub fn explode(png: &Png, dir: &Path) -> crate::MyResult<Manifest> {
+    fs::create_dir_all(dir)?;
+
+    let mut manifest = Manifest::default();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let file = format!("{:03}_{}.bin", index, chunk.chunk_type());
+        fs::write(dir.join(&file), chunk.data())?;
+        manifest.chunks.push(ManifestEntry {
+            file,
+            chunk_type: chunk.chunk_type().to_string(),
+            crc: chunk.crc(),
+        });
+    }
+    fs::write(
+        dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    Ok(manifest)
+}
+
+/// Rebuilds a PNG from a directory written by [`explode`], recomputing the CRC of every chunk
+pub fn assemble(dir: &Path) -> crate::MyResult<Assembled> {
+    let manifest: Manifest =
+        serde_json::from_str(&fs::read_to_string(dir.join(MANIFEST_FILE_NAME))?)?;
+
+    let mut chunks = Vec::with_capacity(manifest.chunks.len());
+    let mut edited = Vec::new();
+    for (index, entry) in manifest.chunks.iter().enumerate() {
+        let chunk_type = ChunkType::from_str(&entry.chunk_type)?;
+        let chunk = Chunk::new(chunk_type, fs::read(dir.join(&entry.file))?);
+        if chunk.crc() != entry.crc {
+            edited.push(index);
+        }
+        chunks.push(chunk);
+    }
+
+    Ok(Assembled {
+        png: Png::new(chunks),
+        edited,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn testing_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("pngme-explode-{}-{}", name, std::process::id()))
+    }
+
+    fn testing_png() -> Png {
+        Png::new(vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"first".to_vec()),
+            Chunk::new(
+                ChunkType::from_str("tEXt").unwrap(),
+                b"Comment\0hi".to_vec(),
+            ),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_explode_writes_chunk_files() {
+        let dir = testing_dir("files");
+        let manifest = explode(&testing_png(), &dir).unwrap();
+
+        let files: Vec<&str> = manifest.chunks.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, vec!["000_FrSt.bin", "001_tEXt.bin", "002_LASt.bin"]);
+        assert_eq!(fs::read(dir.join("001_tEXt.bin")).unwrap(), b"Comment\0hi");
+        assert!(dir.join(MANIFEST_FILE_NAME).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = testing_dir("round-trip");
+        let png = testing_png();
+        explode(&png, &dir).unwrap();
+
+        let assembled = assemble(&dir).unwrap();
+        assert_eq!(assembled.png.as_bytes(), png.as_bytes());
+        assert!(assembled.edited.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_assemble_recomputes_crc_of_edited_chunks() {
+        let dir = testing_dir("edited");
+        explode(&testing_png(), &dir).unwrap();
+        fs::write(dir.join("001_tEXt.bin"), b"Comment\0edited").unwrap();
+
+        let assembled = assemble(&dir).unwrap();
+        assert_eq!(assembled.edited, vec![1]);
+        let bytes = assembled.png.as_bytes();
+        let reparsed = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(reparsed.chunks()[1].data(), b"Comment\0edited");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
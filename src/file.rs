@@ -0,0 +1,221 @@
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::verify;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Reading and writing image files. Everything else in [`Png`] works on bytes in memory, so the
+/// library also runs where there is no filesystem, such as `wasm32-unknown-unknown`.
+impl Png {
+    pub fn from_file(path: &Path) -> Result<Png, PngMeError> {
+        Png::try_from(fs::read(path)?.as_slice())
+    }
+
+    /// Replaces `path` with the image without ever leaving a damaged file behind. The bytes go
+    /// to a temporary file next to it, which is synced to disk, read back and checked with
+    /// [`verify::verify`]. Only then is it renamed over `path`, keeping the permissions `path`
+    /// had. On failure the temporary file is removed and `path` is left as it was.
+    pub fn write_file(&self, path: &Path) -> Result<(), PngMeError> {
+        self.replace_file(path, None, true)
+    }
+
+    /// Like [`Png::write_file`], first copying the file being replaced to `backup`, once the new
+    /// image has been checked
+    pub fn write_file_with_backup(&self, path: &Path, backup: &Path) -> Result<(), PngMeError> {
+        self.replace_file(path, Some(backup), true)
+    }
+
+    /// Like [`Png::write_file`], checking the result with [`verify::verify_ignoring_order`] so
+    /// chunks can be put out of the order the spec requires on purpose
+    pub fn write_file_ignoring_order(
+        &self,
+        path: &Path,
+        backup: Option<&Path>,
+    ) -> Result<(), PngMeError> {
+        self.replace_file(path, backup, false)
+    }
+
+    fn replace_file(
+        &self,
+        path: &Path,
+        backup: Option<&Path>,
+        check_order: bool,
+    ) -> Result<(), PngMeError> {
+        let (temp, file) = create_temp_file(path)?;
+        let result = self
+            .write_checked(file, &temp, path, check_order)
+            .and_then(|()| {
+                if let Some(backup) = backup.filter(|_| path.exists()) {
+                    fs::copy(path, backup)?;
+                }
+                fs::rename(&temp, path)?;
+                sync_parent(path)
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    fn write_checked(
+        &self,
+        mut file: File,
+        temp: &Path,
+        path: &Path,
+        check_order: bool,
+    ) -> Result<(), PngMeError> {
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let bytes = self.as_bytes();
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        let written = fs::read(temp)?;
+        if written != bytes {
+            return Err(PngMeError::UnverifiedWrite(vec![verify::Problem {
+                offset: 0,
+                index: None,
+                message: String::from("the file reads back different from what was written"),
+            }]));
+        }
+        let report = match check_order {
+            true => verify::verify(&written),
+            false => verify::verify_ignoring_order(&written),
+        };
+        match report.is_valid() {
+            true => Ok(()),
+            false => Err(PngMeError::UnverifiedWrite(report.problems)),
+        }
+    }
+}
+
+/// Creates a temporary file next to `path` under a name no other write uses, even from another
+/// process writing the same file at the same time
+fn create_temp_file(path: &Path) -> Result<(PathBuf, File), PngMeError> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let temp = path.with_file_name(format!(
+            ".{}.{}-{}.pngme-tmp",
+            name,
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(PngMeError::Io(e)),
+        }
+    }
+}
+
+/// Syncs the directory holding `path`, so the rename survives a crash. Directories can only be
+/// opened for this on Unix.
+fn sync_parent(path: &Path) -> Result<(), PngMeError> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::{ColorType, Ihdr};
+    use crate::ordering::MoveTarget;
+    use crate::selection::Occurrence;
+    use std::env;
+
+    fn testing_png() -> Png {
+        Png::new(vec![
+            Ihdr::new(1, 1, 8, ColorType::Rgb).to_chunk(),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    fn leftovers(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".pngme-tmp"))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dir = env::temp_dir().join(format!("pngme-file-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("image.png");
+        let backup = dir.join("image.png.bak");
+        fs::write(&file, b"old").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        testing_png()
+            .write_file_with_backup(&file, &backup)
+            .unwrap();
+        assert_eq!(fs::read(&file).unwrap(), testing_png().as_bytes());
+        assert_eq!(fs::read(&backup).unwrap(), b"old");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        // An image the spec doesn't allow never replaces the file
+        let mut broken = testing_png();
+        broken.remove_chunks("IEND", Occurrence::All).unwrap();
+        assert!(matches!(
+            broken.write_file(&file),
+            Err(PngMeError::UnverifiedWrite(_))
+        ));
+        assert_eq!(fs::read(&file).unwrap(), testing_png().as_bytes());
+        assert!(leftovers(&dir).is_empty());
+
+        // Out of order is only written when asked for
+        let mut misordered = testing_png();
+        misordered
+            .move_chunk(1, MoveTarget::Before(0), true)
+            .unwrap();
+        assert!(misordered.write_file(&file).is_err());
+        misordered.write_file_ignoring_order(&file, None).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), misordered.as_bytes());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_temp_file_names_are_unique() {
+        let dir = env::temp_dir().join(format!("pngme-file-temp-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("image.png");
+        let (first, _) = create_temp_file(&file).unwrap();
+        let (second, _) = create_temp_file(&file).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(leftovers(&dir).len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
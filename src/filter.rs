@@ -0,0 +1,385 @@
+use crate::chunk::ChunkRef;
+use std::str::FromStr;
+
+/// A compiled `--where` expression such as `type == "tEXt" && length > 1024 && !standard`.
+///
+/// Comparisons take a field on the left and a literal on the right:
+/// - `type` compares against a quoted chunk type with `==` or `!=`
+/// - `index`, `offset`, `length` and `crc` compare against a decimal or `0x` hex number with
+///   `==`, `!=`, `<`, `<=`, `>` or `>=`
+///
+/// The flags `critical`, `ancillary`, `public`, `private`, `safe`, `unsafe` and `standard` stand
+/// alone. Terms combine with `!`, `&&`, `||` and parentheses, with the usual precedence.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Expr {
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Flag(Flag),
+    Type(Comparison, [u8; 4]),
+    Number(Field, Comparison, u64),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Flag {
+    Critical,
+    Ancillary,
+    Public,
+    Private,
+    Safe,
+    Unsafe,
+    Standard,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Field {
+    Index,
+    Offset,
+    Length,
+    Crc,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(u64),
+    Compare(Comparison),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Filter {
+    /// Whether the chunk at `index`, starting `offset` bytes into the file, matches
+    pub fn matches<'a>(&self, index: usize, offset: usize, chunk: impl Into<ChunkRef<'a>>) -> bool {
+        self.expr.eval(index, offset, &chunk.into())
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {:?} in filter", token));
+        }
+
+        Ok(Filter { expr })
+    }
+}
+
+impl Expr {
+    fn eval(&self, index: usize, offset: usize, chunk: &ChunkRef) -> bool {
+        let chunk_type = chunk.chunk_type();
+        match self {
+            Expr::Not(inner) => !inner.eval(index, offset, chunk),
+            Expr::And(left, right) => {
+                left.eval(index, offset, chunk) && right.eval(index, offset, chunk)
+            }
+            Expr::Or(left, right) => {
+                left.eval(index, offset, chunk) || right.eval(index, offset, chunk)
+            }
+            Expr::Flag(flag) => match flag {
+                Flag::Critical => chunk_type.is_critical(),
+                Flag::Ancillary => !chunk_type.is_critical(),
+                Flag::Public => chunk_type.is_public(),
+                Flag::Private => !chunk_type.is_public(),
+                Flag::Safe => chunk_type.is_safe_to_copy(),
+                Flag::Unsafe => !chunk_type.is_safe_to_copy(),
+                Flag::Standard => chunk_type.is_standard(),
+            },
+            Expr::Type(comparison, bytes) => match comparison {
+                Comparison::Eq => chunk_type.bytes() == *bytes,
+                _ => chunk_type.bytes() != *bytes,
+            },
+            Expr::Number(field, comparison, value) => {
+                let actual = match field {
+                    Field::Index => index as u64,
+                    Field::Offset => offset as u64,
+                    Field::Length => chunk.length() as u64,
+                    Field::Crc => chunk.crc() as u64,
+                };
+                match comparison {
+                    Comparison::Eq => actual == *value,
+                    Comparison::Ne => actual != *value,
+                    Comparison::Lt => actual < *value,
+                    Comparison::Le => actual <= *value,
+                    Comparison::Gt => actual > *value,
+                    Comparison::Ge => actual >= *value,
+                }
+            }
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, width) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Compare(Comparison::Eq), 2),
+            ('!', Some('=')) => (Token::Compare(Comparison::Ne), 2),
+            ('<', Some('=')) => (Token::Compare(Comparison::Le), 2),
+            ('>', Some('=')) => (Token::Compare(Comparison::Ge), 2),
+            ('<', _) => (Token::Compare(Comparison::Lt), 1),
+            ('>', _) => (Token::Compare(Comparison::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('"', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or("unterminated string in filter")?;
+                let text = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::Str(text), end + 2)
+            }
+            (c, _) if c.is_ascii_alphanumeric() || c == '_' => {
+                let width = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                let word: String = chars[i..i + width].iter().collect();
+                let token = if c.is_ascii_digit() {
+                    Token::Number(parse_number(&word)?)
+                } else {
+                    Token::Ident(word)
+                };
+                (token, width)
+            }
+            (c, _) => return Err(format!("unexpected character '{}' in filter", c)),
+        };
+        tokens.push(token);
+        i += width;
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Result<u64, String> {
+    let parsed = match word.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}' in filter", word))
+}
+
+/// Recursive descent over `or := and ("||" and)*`, `and := unary ("&&" unary)*` and
+/// `unary := "!" unary | "(" or ")" | term`
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("filter ends unexpectedly")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let expr = self.or()?;
+                match self.next()? {
+                    Token::Close => Ok(expr),
+                    token => Err(format!("expected ')' but found {:?} in filter", token)),
+                }
+            }
+            Token::Ident(name) => self.term(&name),
+            token => Err(format!("unexpected {:?} in filter", token)),
+        }
+    }
+
+    fn term(&mut self, name: &str) -> Result<Expr, String> {
+        let flag = match name {
+            "critical" => Some(Flag::Critical),
+            "ancillary" => Some(Flag::Ancillary),
+            "public" => Some(Flag::Public),
+            "private" => Some(Flag::Private),
+            "safe" => Some(Flag::Safe),
+            "unsafe" => Some(Flag::Unsafe),
+            "standard" => Some(Flag::Standard),
+            _ => None,
+        };
+        if let Some(flag) = flag {
+            return Ok(Expr::Flag(flag));
+        }
+
+        let field = match name {
+            "type" => None,
+            "index" => Some(Field::Index),
+            "offset" => Some(Field::Offset),
+            "length" => Some(Field::Length),
+            "crc" => Some(Field::Crc),
+            _ => return Err(format!("unknown field '{}' in filter", name)),
+        };
+        let comparison = match self.next()? {
+            Token::Compare(comparison) => comparison,
+            token => {
+                return Err(format!(
+                    "expected a comparison after '{}', found {:?}",
+                    name, token
+                ))
+            }
+        };
+
+        match (field, self.next()?) {
+            (None, Token::Str(text)) => {
+                if !matches!(comparison, Comparison::Eq | Comparison::Ne) {
+                    return Err(String::from(
+                        "chunk types can only be compared with == or !=",
+                    ));
+                }
+                let bytes: [u8; 4] = text
+                    .as_bytes()
+                    .try_into()
+                    .map_err(|_| format!("'{}' is not a four letter chunk type", text))?;
+                Ok(Expr::Type(comparison, bytes))
+            }
+            (Some(field), Token::Number(value)) => Ok(Expr::Number(field, comparison, value)),
+            (None, token) => Err(format!("expected a quoted chunk type, found {:?}", token)),
+            (Some(_), token) => Err(format!(
+                "expected a number after '{}', found {:?}",
+                name, token
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, length: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; length])
+    }
+
+    fn matches(filter: &str, chunk: &Chunk) -> bool {
+        Filter::from_str(filter).unwrap().matches(3, 100, chunk)
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let text = chunk("tEXt", 2048);
+        assert!(matches(r#"type == "tEXt""#, &text));
+        assert!(!matches(r#"type != "tEXt""#, &text));
+        assert!(matches("length > 1024", &text));
+        assert!(matches("length >= 2048 && length <= 0x800", &text));
+        assert!(!matches("length < 2048", &text));
+        assert!(matches("index == 3 && offset == 100", &text));
+        assert!(matches(&format!("crc == {}", text.crc()), &text));
+    }
+
+    #[test]
+    fn test_flags() {
+        let hidden = chunk("ruSt", 4);
+        assert!(matches(
+            "ancillary && private && safe && !standard",
+            &hidden
+        ));
+        assert!(!matches(
+            "critical || public || unsafe || standard",
+            &hidden
+        ));
+        assert!(matches("critical && standard", &chunk("IHDR", 13)));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let text = chunk("tEXt", 10);
+        assert!(matches(r#"critical && public || type == "tEXt""#, &text));
+        assert!(!matches(r#"critical && (public || type == "tEXt")"#, &text));
+        assert!(matches(r#"!critical && !!standard"#, &text));
+    }
+
+    #[test]
+    fn test_request_example() {
+        let filter = r#"type == "tEXt" && length > 1024 && !standard"#;
+        assert!(!matches(filter, &chunk("tEXt", 2048)));
+        assert!(Filter::from_str(filter).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for filter in [
+            "",
+            "length >",
+            "length > \"tEXt\"",
+            "type > \"tEXt\"",
+            "type == \"text chunk\"",
+            "size > 3",
+            "(critical",
+            "critical)",
+            "critical &",
+            "type == \"tEXt",
+            "length > 0xzz",
+        ] {
+            assert!(Filter::from_str(filter).is_err(), "{}", filter);
+        }
+    }
+}
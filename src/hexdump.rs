@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+
+/// Bytes shown on each line of a dump
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Writes `data` in the layout of `xxd`: the offset, the bytes in hex in groups of two, then the
+/// bytes as ASCII with anything unprintable shown as `.`
+pub fn write_hexdump<W: Write>(data: &[u8], mut out: W) -> io::Result<()> {
+    for (line, bytes) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let mut hex = String::with_capacity(BYTES_PER_LINE * 5 / 2);
+        for (i, byte) in bytes.iter().enumerate() {
+            if i > 0 && i % 2 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", byte));
+        }
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                true => byte as char,
+                false => '.',
+            })
+            .collect();
+
+        // Pad short last lines so the ASCII column stays aligned
+        let width = BYTES_PER_LINE * 2 + BYTES_PER_LINE / 2 - 1;
+        writeln!(
+            out,
+            "{:08x}: {:<width$}  {}",
+            line * BYTES_PER_LINE,
+            hex,
+            ascii,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hexdump(data: &[u8]) -> String {
+        let mut dump = Vec::new();
+        write_hexdump(data, &mut dump).unwrap();
+        String::from_utf8(dump).unwrap()
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR hidden";
+        assert_eq!(
+            hexdump(data),
+            "00000000: 8950 4e47 0d0a 1a0a 0000 000d 4948 4452  .PNG........IHDR\n\
+             00000010: 2068 6964 6465 6e                         hidden\n"
+        );
+        assert_eq!(hexdump(&[]), "");
+    }
+}
@@ -0,0 +1,236 @@
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use std::fmt::Display;
+
+/// Bytes in the data of an IHDR chunk
+pub const IHDR_LEN: usize = 13;
+
+/// How pixels are stored, from byte 9 of IHDR
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngMeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(PngMeError::Malformed("unknown color type in IHDR")),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorType {
+    /// Samples per pixel. An indexed pixel is a single palette index.
+    pub fn channels(self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths the spec allows for this color type
+    pub fn bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+/// The image header: dimensions and pixel layout, from the IHDR chunk
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    /// Always 0, zlib deflate, in the current spec
+    pub compression: u8,
+    /// Always 0, adaptive filtering with five filter types, in the current spec
+    pub filter: u8,
+    /// 0 for none, 1 for Adam7
+    pub interlace: u8,
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        Ihdr::try_from(ChunkRef::from(chunk))
+    }
+}
+
+impl TryFrom<ChunkRef<'_>> for Ihdr {
+    type Error = PngMeError;
+
+    /// Parses an IHDR chunk, rejecting any field value the spec doesn't define
+    fn try_from(chunk: ChunkRef<'_>) -> Result<Self, Self::Error> {
+        if chunk.chunk_type() != ChunkType::IHDR {
+            return Err(PngMeError::Invalid("not an IHDR chunk"));
+        }
+        let data = chunk.data();
+        if data.len() != IHDR_LEN {
+            return Err(PngMeError::Malformed("IHDR chunk has the wrong length"));
+        }
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression: data[10],
+            filter: data[11],
+            interlace: data[12],
+        };
+        let max_dimension = i32::MAX as u32;
+        if ihdr.width == 0 || ihdr.height == 0 {
+            return Err(PngMeError::Malformed(
+                "image width and height must be at least 1",
+            ));
+        }
+        if ihdr.width > max_dimension || ihdr.height > max_dimension {
+            return Err(PngMeError::Malformed(
+                "image width and height must be below 2^31",
+            ));
+        }
+        if !ihdr.color_type.bit_depths().contains(&ihdr.bit_depth) {
+            return Err(PngMeError::Malformed(
+                "bit depth isn't allowed for the color type in IHDR",
+            ));
+        }
+        if ihdr.compression != 0 {
+            return Err(PngMeError::Malformed("unknown compression method in IHDR"));
+        }
+        if ihdr.filter != 0 {
+            return Err(PngMeError::Malformed("unknown filter method in IHDR"));
+        }
+        if ihdr.interlace > 1 {
+            return Err(PngMeError::Malformed("unknown interlace method in IHDR"));
+        }
+
+        Ok(ihdr)
+    }
+}
+
+/// A one-line summary such as `640x480, 8-bit RGBA, non-interlaced`
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            match self.is_interlaced() {
+                true => "Adam7 interlaced",
+                false => "non-interlaced",
+            }
+        )
+    }
+}
+
+impl Ihdr {
+    /// A non-interlaced header with the standard compression and filter methods
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression: 0,
+            filter: 0,
+            interlace: 0,
+        }
+    }
+
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace == 1
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(IHDR_LEN);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type as u8,
+            self.compression,
+            self.filter,
+            self.interlace,
+        ]);
+        Chunk::new(ChunkType::IHDR, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ihdr = Ihdr::new(640, 480, 8, ColorType::Rgba);
+        let chunk = ihdr.to_chunk();
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(
+            chunk.data(),
+            [0, 0, 2, 128, 0, 0, 1, 224, 8, 6, 0, 0, 0].as_slice()
+        );
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+        assert_eq!(ihdr.to_string(), "640x480, 8-bit RGBA, non-interlaced");
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let with = |index: usize, value: u8| {
+            let mut data = Ihdr::new(1, 1, 8, ColorType::Rgb)
+                .to_chunk()
+                .data()
+                .to_vec();
+            data[index] = value;
+            Ihdr::try_from(&Chunk::new(ChunkType::IHDR, data))
+        };
+        assert!(with(0, 0).is_ok());
+        assert!(with(3, 0).is_err(), "zero width");
+        assert!(with(0, 128).is_err(), "width of 2^31");
+        assert!(with(8, 4).is_err(), "4-bit RGB");
+        assert!(with(9, 5).is_err(), "color type 5");
+        assert!(with(10, 1).is_err());
+        assert!(with(11, 1).is_err());
+        assert!(with(12, 2).is_err());
+        assert!(with(12, 1).unwrap().is_interlaced());
+
+        let short = Chunk::new(ChunkType::IHDR, vec![0; 12]);
+        assert!(Ihdr::try_from(&short).is_err());
+        let other = Chunk::new(ChunkType::IDAT, vec![0; 13]);
+        assert!(Ihdr::try_from(&other).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Hide, find and manage messages in PNG chunks.
+//!
+//! [`encode_message`] and [`decode_message`] cover the common case of storing text in a chunk,
+//! and [`encode_message_with`] and [`decode_message_with`] compress, encrypt and sign it the way
+//! `png-me encode` does. [`Png`], [`Chunk`] and [`ChunkType`] give full control over the chunk
+//! list.
+
+pub mod apng;
+#[cfg(feature = "serde")]
+pub mod audit;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_type;
+pub mod clock;
+pub mod compress;
+pub mod corpus;
+pub mod crypto;
+pub mod diff;
+pub mod error;
+#[cfg(feature = "serde")]
+pub mod explode;
+pub mod file;
+pub mod filter;
+pub mod ihdr;
+pub mod lsb;
+pub mod metadata;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod ordering;
+pub mod patch;
+pub mod payload;
+pub mod pixels;
+pub mod png;
+pub mod reader;
+#[cfg(feature = "serde")]
+pub mod recipe;
+pub mod recovery;
+pub mod selection;
+pub mod selftest;
+pub mod shamir;
+#[cfg(feature = "serde")]
+pub mod sidecar;
+pub mod signing;
+pub mod text;
+pub mod transaction;
+pub mod transform;
+pub mod verify;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+pub mod watermark;
+
+pub use chunk::{Chunk, ChunkRef};
+pub use chunk_type::ChunkType;
+pub use error::PngMeError;
+pub use payload::{OpenOptions, SealOptions};
+pub use png::{ChunkRefs, Png, PngRef};
+
+use std::str::FromStr;
+
+pub type MyError = Box<dyn std::error::Error>;
+pub type MyResult<T> = std::result::Result<T, MyError>;
+
+/// Stores `message` in a new chunk of type `chunk_type`, placed where the spec expects the type
+pub fn encode_message(png: &mut Png, chunk_type: &str, message: &str) -> Result<(), PngMeError> {
+    encode_message_with(png, chunk_type, message.as_bytes(), &SealOptions::default())
+}
+
+/// Like [`encode_message`], compressing, encrypting and signing the message first as `options`
+/// ask, the way `png-me encode` does
+pub fn encode_message_with(
+    png: &mut Png,
+    chunk_type: &str,
+    message: &[u8],
+    options: &SealOptions,
+) -> Result<(), PngMeError> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let payload = payload::seal(message, png, options)?;
+    png.insert_chunk_ordered(Chunk::new(chunk_type, payload));
+    Ok(())
+}
+
+/// Reads the message stored in the first chunk of type `chunk_type`, or reassembled from the
+/// pieces of a payload split across several
+pub fn decode_message(png: &Png, chunk_type: &str) -> Result<String, PngMeError> {
+    let message = decode_message_with(png, chunk_type, &OpenOptions::default())?;
+    Ok(String::from_utf8(message).map_err(|e| e.utf8_error())?)
+}
+
+/// Like [`decode_message`] for a message written with [`encode_message_with`] or
+/// `png-me encode`, checking its signature, decrypting and decompressing it as needed
+pub fn decode_message_with(
+    png: &Png,
+    chunk_type: &str,
+    options: &OpenOptions,
+) -> Result<Vec<u8>, PngMeError> {
+    payload::open(&png.extract_payload(chunk_type)?, png, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_message() {
+        let mut png = Png::new(
+            ["IHDR", "IDAT", "IEND"]
+                .iter()
+                .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![]))
+                .collect(),
+        );
+        encode_message(&mut png, "ruSt", "hidden message").unwrap();
+        assert_eq!(&png.chunks()[2].chunk_type().to_string(), "ruSt");
+        assert_eq!(decode_message(&png, "ruSt").unwrap(), "hidden message");
+        assert!(decode_message(&png, "prVt").is_err());
+        assert!(encode_message(&mut png, "ru5t", "").is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_sealed_message() {
+        let mut png = Png::new(
+            ["IHDR", "IDAT", "IEND"]
+                .iter()
+                .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![]))
+                .collect(),
+        );
+        let key = [7u8; 16];
+        let seal_options = SealOptions {
+            compression: compress::Algorithm::Zlib,
+            signing_key: Some(&key),
+            sign_image: true,
+            ..SealOptions::default()
+        };
+        encode_message_with(&mut png, "ruSt", b"hidden message", &seal_options).unwrap();
+        assert!(matches!(
+            decode_message(&png, "ruSt"),
+            Err(PngMeError::KeyRequired)
+        ));
+        let open_options = OpenOptions {
+            signing_key: Some(&key),
+            ..OpenOptions::default()
+        };
+        assert_eq!(
+            decode_message_with(&png, "ruSt", &open_options).unwrap(),
+            b"hidden message"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::capacity;
+use crate::error::PngMeError;
+use crate::pixels::ImageData;
+use crate::png::Png;
+
+/// Marks a message hidden in the least-significant bits of the pixels. The message length follows
+/// as a big-endian u32, then the message itself.
+pub const LSB_MAGIC: &[u8; 4] = b"pmL1";
+/// Version of the LSB layout, the digit at the end of [`LSB_MAGIC`]
+pub const LSB_VERSION: u32 = 1;
+/// Magic and message length
+pub const LSB_HEADER_LEN: u64 = LSB_MAGIC.len() as u64 + 4;
+
+/// Bytes of message that fit in the image, after the header
+pub fn capacity(png: &Png) -> Result<u64, PngMeError> {
+    Ok(capacity::lsb_capacity(png)?.saturating_sub(LSB_HEADER_LEN))
+}
+
+/// Hides `message` in the least-significant bit of each sample, in scanline order, and rewrites
+/// IDAT with the result. Nothing outside the pixel data changes, so there is no extra chunk to
+/// give the message away.
+pub fn embed(png: &mut Png, message: &[u8]) -> Result<(), PngMeError> {
+    let mut image = decode(png)?;
+    let positions = lsb_positions(&image);
+    let available = (positions.len() as u64 / 8).saturating_sub(LSB_HEADER_LEN);
+    let length = u32::try_from(message.len())
+        .ok()
+        .filter(|length| *length as u64 <= available)
+        .ok_or(PngMeError::CapacityExceeded {
+            needed: message.len() as u64,
+            available,
+        })?;
+
+    let mut payload = LSB_MAGIC.to_vec();
+    payload.extend_from_slice(&length.to_be_bytes());
+    payload.extend_from_slice(message);
+    let bits = payload
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    for (position, bit) in positions.into_iter().zip(bits) {
+        image.data[position] = (image.data[position] & !1) | bit;
+    }
+
+    image.encode_into(png)
+}
+
+/// Reads back a message hidden by [`embed`]
+pub fn extract(png: &Png) -> Result<Vec<u8>, PngMeError> {
+    let image = decode(png)?;
+    let positions = lsb_positions(&image);
+    let mut bytes = positions.chunks_exact(8).map(|byte| {
+        byte.iter()
+            .fold(0, |acc, position| acc << 1 | image.data[*position] & 1)
+    });
+
+    let header: Vec<u8> = bytes.by_ref().take(LSB_HEADER_LEN as usize).collect();
+    if !header.starts_with(LSB_MAGIC) {
+        return Err(PngMeError::Malformed("no message is hidden in the pixels"));
+    }
+    let length = u32::from_be_bytes(header[LSB_MAGIC.len()..].try_into().unwrap());
+    let message: Vec<u8> = bytes.take(length as usize).collect();
+    match message.len() == length as usize {
+        true => Ok(message),
+        false => Err(PngMeError::Malformed(
+            "hidden message is longer than the image can hold",
+        )),
+    }
+}
+
+fn decode(png: &Png) -> Result<ImageData, PngMeError> {
+    let image = ImageData::decode(png)?;
+    if !image.has_lsb_plane() {
+        return Err(PngMeError::Invalid(
+            "LSB embedding needs an 8 or 16-bit image without a palette",
+        ));
+    }
+    Ok(image)
+}
+
+/// Returns the index of the byte holding the least-significant bit of each sample. Scanlines of
+/// 8 and 16-bit images have no padding, so that is every byte, or every second one.
+fn lsb_positions(image: &ImageData) -> Vec<usize> {
+    let bytes_per_sample = image.bytes_per_sample();
+    (bytes_per_sample - 1..image.data.len())
+        .step_by(bytes_per_sample)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::{resize_header, testing_image};
+    use crate::selection::Occurrence;
+
+    #[test]
+    fn test_round_trip() {
+        for (color_type, bit_depth) in [(2, 8), (6, 16), (0, 8)] {
+            let mut png = testing_image(16, 16, color_type, bit_depth);
+            let original = ImageData::decode(&png).unwrap();
+            embed(&mut png, b"meet at dawn").unwrap();
+            assert_eq!(extract(&png).unwrap(), b"meet at dawn");
+
+            let changed = ImageData::decode(&png).unwrap();
+            assert!(original
+                .data
+                .iter()
+                .zip(&changed.data)
+                .all(|(before, after)| before & !1 == after & !1));
+            assert_eq!(png.chunks_by_type("IDAT", Occurrence::All).len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 4x4 RGB at 8 bits: 48 samples, 6 bytes, none left after the header
+        let mut png = testing_image(4, 4, 2, 8);
+        assert_eq!(capacity(&png).unwrap(), 0);
+        assert!(matches!(
+            embed(&mut png, b"x"),
+            Err(PngMeError::CapacityExceeded {
+                needed: 1,
+                available: 0
+            })
+        ));
+
+        let mut png = testing_image(8, 8, 2, 8);
+        assert_eq!(capacity(&png).unwrap(), 16);
+        embed(&mut png, &[7; 16]).unwrap();
+        assert!(embed(&mut png, &[7; 17]).is_err());
+
+        assert!(capacity(&testing_image(8, 8, 3, 8)).is_err());
+        assert!(embed(&mut testing_image(8, 8, 0, 4), b"x").is_err());
+    }
+
+    #[test]
+    fn test_no_message() {
+        assert!(extract(&testing_image(8, 8, 2, 8)).is_err());
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        let mut png = testing_image(2, 2, 6, 16);
+        resize_header(&mut png, 2147483647, 2147483647);
+        assert!(matches!(extract(&png), Err(PngMeError::Malformed(_))));
+    }
+}
@@ -1,12 +1,27 @@
 mod args;
-mod chunk;
-mod chunk_type;
+mod batch;
+mod capabilities;
 mod commands;
-mod png;
+mod hexdump;
+mod output;
+mod style;
 
-pub type MyError = Box<dyn std::error::Error>;
-pub type MyResult<T> = std::result::Result<T, MyError>;
+use clap::Parser;
+use pngme::PngMeError;
+use std::process;
 
-fn main() -> MyResult<()> {
-    todo!()
+fn main() {
+    let cli = args::Cli::parse();
+    // Without a subcommand clap only lets --capabilities through
+    let result = match cli.command {
+        Some(command) => commands::run(command),
+        None => commands::print_capabilities(),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        let code = e
+            .downcast_ref::<PngMeError>()
+            .map_or(1, PngMeError::exit_code);
+        process::exit(code);
+    }
 }
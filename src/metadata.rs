@@ -0,0 +1,338 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::text::TextChunk;
+use std::fmt::Display;
+
+/// When the image was last changed, from the tIME chunk, in UTC
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ModificationTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, for leap seconds
+    pub second: u8,
+}
+
+impl TryFrom<&Chunk> for ModificationTime {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = fields(chunk, ChunkType::tIME, 7)?;
+        let time = ModificationTime {
+            year: u16::from_be_bytes([data[0], data[1]]),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            second: data[6],
+        };
+        let in_range = (1..=12).contains(&time.month)
+            && (1..=31).contains(&time.day)
+            && time.hour < 24
+            && time.minute < 60
+            && time.second <= 60;
+        match in_range {
+            true => Ok(time),
+            false => Err(PngMeError::Malformed(
+                "tIME holds an impossible date or time",
+            )),
+        }
+    }
+}
+
+/// RFC 3339, such as `2023-11-14T22:13:20Z`
+impl Display for ModificationTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Pixel size or aspect ratio, from the pHYs chunk
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PhysicalDimensions {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    /// Whether the unit is the metre. Otherwise only the aspect ratio is known.
+    pub in_metres: bool,
+}
+
+impl TryFrom<&Chunk> for PhysicalDimensions {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = fields(chunk, ChunkType::pHYs, 9)?;
+        let in_metres = match data[8] {
+            0 => false,
+            1 => true,
+            _ => return Err(PngMeError::Malformed("unknown unit in pHYs")),
+        };
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            pixels_per_unit_y: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            in_metres,
+        })
+    }
+}
+
+/// Such as `2835x2835 pixels per metre (72x72 dpi)`, or `1x2 aspect ratio`
+impl Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.dpi() {
+            Some((x, y)) => write!(
+                f,
+                "{}x{} pixels per metre ({}x{} dpi)",
+                self.pixels_per_unit_x,
+                self.pixels_per_unit_y,
+                x.round(),
+                y.round()
+            ),
+            None => write!(
+                f,
+                "{}x{} aspect ratio",
+                self.pixels_per_unit_x, self.pixels_per_unit_y
+            ),
+        }
+    }
+}
+
+impl PhysicalDimensions {
+    /// Horizontal and vertical dots per inch, when the unit is known
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        let per_inch = |per_metre: u32| per_metre as f64 * 0.0254;
+        self.in_metres.then(|| {
+            (
+                per_inch(self.pixels_per_unit_x),
+                per_inch(self.pixels_per_unit_y),
+            )
+        })
+    }
+}
+
+/// Reads the gamma of the image from a gAMA chunk, which stores it times 100000
+pub fn gamma(chunk: &Chunk) -> Result<f64, PngMeError> {
+    let data = fields(chunk, ChunkType::gAMA, 4)?;
+    match u32::from_be_bytes(data.try_into().unwrap()) {
+        0 => Err(PngMeError::Malformed("gAMA can't be 0")),
+        gamma => Ok(gamma as f64 / 100_000.0),
+    }
+}
+
+/// The Exif data of an eXIf chunk. It's reported as is rather than decoded.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Exif {
+    /// `big-endian` or `little-endian`, from the TIFF header the data starts with
+    pub byte_order: &'static str,
+    pub length: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<&Chunk> for Exif {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != ChunkType::eXIf {
+            return Err(PngMeError::Invalid("chunk has the wrong type"));
+        }
+        let byte_order = match chunk.data().get(..4) {
+            Some(b"MM\x00\x2a") => "big-endian",
+            Some(b"II\x2a\x00") => "little-endian",
+            _ => {
+                return Err(PngMeError::Malformed(
+                    "eXIf doesn't start with a TIFF header",
+                ))
+            }
+        };
+        Ok(Exif {
+            byte_order,
+            length: chunk.data().len(),
+            data: chunk.data().to_vec(),
+        })
+    }
+}
+
+/// One keyword and value from a tEXt, zTXt or iTXt chunk
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TextEntry {
+    pub chunk_type: String,
+    pub keyword: String,
+    pub text: String,
+    /// Language tag of an iTXt chunk, when it has one
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub language: Option<String>,
+}
+
+impl From<&TextChunk> for TextEntry {
+    fn from(text_chunk: &TextChunk) -> TextEntry {
+        TextEntry {
+            chunk_type: text_chunk.kind().to_string(),
+            keyword: String::from(text_chunk.keyword()),
+            text: String::from(text_chunk.text()),
+            language: Some(String::from(text_chunk.language_tag()))
+                .filter(|language| !language.is_empty()),
+        }
+    }
+}
+
+/// The standard metadata chunks of an image, read by [`Metadata::of`]
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Metadata {
+    /// Text chunks in file order
+    pub text: Vec<TextEntry>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub modified: Option<ModificationTime>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub physical: Option<PhysicalDimensions>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub gamma: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub exif: Option<Exif>,
+    /// Metadata chunks that couldn't be read, such as `chunk 3 (tIME): ...`
+    pub unreadable: Vec<String>,
+}
+
+impl Metadata {
+    /// Reads every metadata chunk it knows. A chunk that fails to parse is noted in
+    /// `unreadable` instead of failing the whole read. Of the types the spec allows once, the
+    /// first chunk wins.
+    pub fn of(png: &Png) -> Metadata {
+        let mut metadata = Metadata::default();
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            let read = match *chunk.chunk_type() {
+                ChunkType::tEXt | ChunkType::zTXt | ChunkType::iTXt => TextChunk::try_from(chunk)
+                    .map(|text_chunk| metadata.text.push(TextEntry::from(&text_chunk))),
+                ChunkType::tIME if metadata.modified.is_none() => {
+                    ModificationTime::try_from(chunk).map(|time| metadata.modified = Some(time))
+                }
+                ChunkType::pHYs if metadata.physical.is_none() => {
+                    PhysicalDimensions::try_from(chunk)
+                        .map(|physical| metadata.physical = Some(physical))
+                }
+                ChunkType::gAMA if metadata.gamma.is_none() => {
+                    gamma(chunk).map(|gamma| metadata.gamma = Some(gamma))
+                }
+                ChunkType::eXIf if metadata.exif.is_none() => {
+                    Exif::try_from(chunk).map(|exif| metadata.exif = Some(exif))
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = read {
+                metadata.unreadable.push(format!(
+                    "chunk {} ({}): {}",
+                    index,
+                    chunk.chunk_type(),
+                    e
+                ));
+            }
+        }
+        metadata
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+            && self.modified.is_none()
+            && self.physical.is_none()
+            && self.gamma.is_none()
+            && self.exif.is_none()
+    }
+}
+
+fn fields(chunk: &Chunk, chunk_type: ChunkType, len: usize) -> Result<&[u8], PngMeError> {
+    if *chunk.chunk_type() != chunk_type {
+        return Err(PngMeError::Invalid("chunk has the wrong type"));
+    }
+    match chunk.data().len() == len {
+        true => Ok(chunk.data()),
+        false => Err(PngMeError::Malformed("chunk has the wrong length")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock;
+    use crate::text::TextKind;
+
+    #[test]
+    fn test_metadata() {
+        let mut png = Png::new(vec![Chunk::new(ChunkType::IDAT, vec![])]);
+        let mut phys = 2835u32.to_be_bytes().repeat(2);
+        phys.push(1);
+        let mut exif = b"II\x2a\x00".to_vec();
+        exif.extend_from_slice(&[8, 0, 0, 0]);
+        let chunks = [
+            Chunk::new(ChunkType::gAMA, 45_455u32.to_be_bytes().to_vec()),
+            Chunk::new(ChunkType::pHYs, phys),
+            TextChunk::new(TextKind::Text, "Title", "Sunset")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+            TextChunk::new(TextKind::International, "Author", "Zoë")
+                .unwrap()
+                .with_language("fr", "Auteur")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+            clock::time_chunk(1_700_000_000),
+            Chunk::new(ChunkType::eXIf, exif),
+            Chunk::new(ChunkType::tIME, vec![0; 7]),
+        ];
+        for chunk in chunks {
+            png.append_chunk(chunk);
+        }
+
+        let metadata = Metadata::of(&png);
+        assert_eq!(metadata.gamma, Some(0.45455));
+        assert_eq!(
+            metadata.physical.unwrap().to_string(),
+            "2835x2835 pixels per metre (72x72 dpi)"
+        );
+        assert_eq!(metadata.text.len(), 2);
+        assert_eq!(metadata.text[1].text, "Zoë");
+        assert_eq!(metadata.text[1].language.as_deref(), Some("fr"));
+        assert_eq!(metadata.text[0].language, None);
+        assert_eq!(
+            metadata.modified.unwrap().to_string(),
+            "2023-11-14T22:13:20Z"
+        );
+        let exif = metadata.exif.as_ref().unwrap();
+        assert_eq!((exif.byte_order, exif.length), ("little-endian", 8));
+        assert!(metadata.unreadable.is_empty(), "second tIME is ignored");
+        assert!(!metadata.is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_chunks() {
+        let png = Png::new(vec![
+            Chunk::new(ChunkType::tIME, vec![0; 7]),
+            Chunk::new(ChunkType::pHYs, vec![0; 8]),
+            Chunk::new(ChunkType::gAMA, vec![0; 4]),
+            Chunk::new(ChunkType::eXIf, b"not exif".to_vec()),
+        ]);
+        let metadata = Metadata::of(&png);
+        assert!(metadata.is_empty());
+        assert_eq!(metadata.unreadable.len(), 4);
+        assert!(metadata.unreadable[0].starts_with("chunk 0 (tIME): "));
+
+        let aspect = PhysicalDimensions {
+            pixels_per_unit_x: 1,
+            pixels_per_unit_y: 2,
+            in_metres: false,
+        };
+        assert_eq!(aspect.to_string(), "1x2 aspect ratio");
+        assert_eq!(aspect.dpi(), None);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::error::PngMeError;
+use crate::png::{ChunkRefs, Png, PngRef};
+use memmap2::Mmap;
+use std::{fs::File, path::Path};
+
+/// An image file mapped into memory rather than read. Chunks are parsed straight from the mapping
+/// as they're iterated, so only the parts of the file that are actually looked at get loaded.
+///
+/// As with any mapping, the contents change if another process writes the file while it's open,
+/// and reading past a point where the file was truncated kills the process with `SIGBUS`.
+#[derive(Debug)]
+pub struct MappedPng {
+    map: Mmap,
+}
+
+impl MappedPng {
+    pub fn open(path: &Path) -> Result<MappedPng, PngMeError> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only ever read, and the risks of the file changing underneath
+        // are documented on the type
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedPng { map })
+    }
+    /// The whole file
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+    /// Iterates over the chunks, checking the CRC of each, which reads its data
+    pub fn chunks(&self) -> Result<ChunkRefs<'_>, PngMeError> {
+        ChunkRefs::new(&self.map)
+    }
+    /// Iterates over the chunks without checking CRCs, reading only the length and type of each
+    pub fn chunks_ignoring_crcs(&self) -> Result<ChunkRefs<'_>, PngMeError> {
+        ChunkRefs::ignoring_crcs(&self.map)
+    }
+    /// Parses every chunk, for the same lookups as on a [`Png`]
+    pub fn parse(&self) -> Result<PngRef<'_>, PngMeError> {
+        PngRef::try_from(self.as_bytes())
+    }
+}
+
+impl Png {
+    /// Maps the file at `path` instead of reading it, see [`MappedPng`]
+    pub fn open_mmap(path: &Path) -> Result<MappedPng, PngMeError> {
+        MappedPng::open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::testing_image;
+    use std::{env, fs};
+
+    #[test]
+    fn test_open_mmap() {
+        let path = env::temp_dir().join(format!("pngme-mmap-{}.png", std::process::id()));
+        let png = testing_image(8, 8, 2, 8);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let mapped = Png::open_mmap(&path).unwrap();
+        assert_eq!(mapped.as_bytes(), png.as_bytes());
+        let types: Vec<String> = mapped
+            .chunks_ignoring_crcs()
+            .unwrap()
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(mapped.chunks().unwrap().count(), 3);
+        assert_eq!(mapped.parse().unwrap().to_png().as_bytes(), png.as_bytes());
+
+        fs::write(&path, b"not a png").unwrap();
+        assert!(Png::open_mmap(&path).unwrap().chunks().is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(Png::open_mmap(&path).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::{ChunkType, StandardChunk};
+use std::{fmt::Display, str::FromStr};
+
+/// Where the PNG spec allows a chunk type to appear relative to the critical chunks
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Placement {
+    First,
+    BeforePlte,
+    Plte,
+    AfterPlteBeforeIdat,
+    BeforeIdat,
+    Idat,
+    Last,
+    /// Anywhere between IHDR and IEND, which is also assumed for unknown types
+    Anywhere,
+}
+
+impl Placement {
+    /// Looks up the placement of a standard type, see [`StandardChunk::placement`]
+    pub fn of(chunk_type: &ChunkType) -> Placement {
+        chunk_type
+            .standard()
+            .map_or(Placement::Anywhere, StandardChunk::placement)
+    }
+}
+
+/// A chunk that sits somewhere the spec doesn't allow
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Violation {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub rule: &'static str,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chunk {} ({}): {}",
+            self.index, self.chunk_type, self.rule
+        )
+    }
+}
+
+/// Destination of a chunk move, relative to the position of another chunk before the move
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MoveTarget {
+    Before(usize),
+    After(usize),
+}
+
+/// Where to insert a chunk when its type's usual place isn't wanted
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum InsertPosition {
+    /// Just before the first IDAT
+    BeforeIdat,
+    /// Just after the last IDAT
+    AfterIdat,
+    /// Just before IEND
+    End,
+}
+
+impl FromStr for InsertPosition {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "before-idat" => Ok(InsertPosition::BeforeIdat),
+            "after-idat" => Ok(InsertPosition::AfterIdat),
+            "end" => Ok(InsertPosition::End),
+            _ => Err("position must be one of before-idat, after-idat or end"),
+        }
+    }
+}
+
+/// Checks every chunk against the ordering rules and reports all violations, not just the first.
+/// Only the chunk types are read, so chunks borrowed from a file can be checked without copying
+/// their data.
+pub fn violations<'a>(chunks: impl IntoIterator<Item = ChunkRef<'a>>) -> Vec<Violation> {
+    let chunk_types: Vec<ChunkType> = chunks.into_iter().map(|chunk| chunk.chunk_type()).collect();
+    let position = |placement| {
+        chunk_types
+            .iter()
+            .position(|chunk_type| Placement::of(chunk_type) == placement)
+    };
+    let plte = position(Placement::Plte);
+    let first_idat = position(Placement::Idat);
+
+    let mut violations = Vec::new();
+    for (index, chunk_type) in chunk_types.iter().enumerate() {
+        let before = |other: Option<usize>| other.is_none_or(|other| index < other);
+        let after = |other: Option<usize>| other.is_none_or(|other| index > other);
+
+        let rule = match Placement::of(chunk_type) {
+            Placement::First if index != 0 => Some("IHDR must be the first chunk"),
+            Placement::Last if index != chunk_types.len() - 1 => {
+                Some("IEND must be the last chunk")
+            }
+            Placement::Plte | Placement::BeforeIdat if !before(first_idat) => {
+                Some("must appear before the first IDAT")
+            }
+            Placement::BeforePlte if !before(plte) || !before(first_idat) => {
+                Some("must appear before PLTE and IDAT")
+            }
+            Placement::AfterPlteBeforeIdat if !after(plte) || !before(first_idat) => {
+                Some("must appear after PLTE and before the first IDAT")
+            }
+            Placement::Idat if index > 0 && is_idat_gap(&chunk_types, index) => {
+                Some("IDAT chunks must be consecutive")
+            }
+            _ => None,
+        };
+
+        if let Some(rule) = rule {
+            violations.push(Violation {
+                index,
+                chunk_type: chunk_type.clone(),
+                rule,
+            });
+        }
+    }
+
+    violations
+}
+
+/// Flags the IDAT that follows a run broken up by other chunks
+fn is_idat_gap(chunk_types: &[ChunkType], index: usize) -> bool {
+    let previous_is_idat = Placement::of(&chunk_types[index - 1]) == Placement::Idat;
+    let earlier_idat = chunk_types[..index]
+        .iter()
+        .any(|chunk_type| Placement::of(chunk_type) == Placement::Idat);
+
+    !previous_is_idat && earlier_idat
+}
+
+/// Stably sorts ancillary chunks into the positions the spec expects. Chunks that may appear
+/// anywhere stay on the side of the image data they started on.
+pub fn spec_order(chunks: Vec<Chunk>) -> Vec<Chunk> {
+    let first_idat = chunks
+        .iter()
+        .position(|chunk| Placement::of(chunk.chunk_type()) == Placement::Idat);
+
+    let mut ranked: Vec<(u8, Chunk)> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let rank = match Placement::of(chunk.chunk_type()) {
+                Placement::First => 0,
+                Placement::BeforePlte => 1,
+                Placement::Plte => 2,
+                Placement::AfterPlteBeforeIdat => 3,
+                Placement::BeforeIdat => 4,
+                Placement::Anywhere if first_idat.is_none_or(|idat| index < idat) => 5,
+                Placement::Idat => 6,
+                Placement::Anywhere => 7,
+                Placement::Last => 8,
+            };
+            (rank, chunk)
+        })
+        .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+
+    ranked.into_iter().map(|(_, chunk)| chunk).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunks(types: &[&str]) -> Vec<Chunk> {
+        types
+            .iter()
+            .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![]))
+            .collect()
+    }
+
+    fn types(chunks: &[Chunk]) -> Vec<String> {
+        chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_order() {
+        let chunks = chunks(&[
+            "IHDR", "gAMA", "PLTE", "tRNS", "pHYs", "tEXt", "IDAT", "IDAT", "ruSt", "IEND",
+        ]);
+        assert!(violations(chunks.iter().map(ChunkRef::from)).is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_violation() {
+        let chunks = chunks(&[
+            "gAMA", "IHDR", "IDAT", "PLTE", "tEXt", "IDAT", "IEND", "ruSt",
+        ]);
+        let found: Vec<(usize, &str)> = violations(chunks.iter().map(ChunkRef::from))
+            .iter()
+            .map(|violation| (violation.index, violation.rule))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (1, "IHDR must be the first chunk"),
+                (3, "must appear before the first IDAT"),
+                (5, "IDAT chunks must be consecutive"),
+                (6, "IEND must be the last chunk"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_before_plte_violation() {
+        let chunks = chunks(&["IHDR", "PLTE", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(
+            violations(chunks.iter().map(ChunkRef::from))[0].rule,
+            "must appear before PLTE and IDAT"
+        );
+    }
+
+    #[test]
+    fn test_spec_order() {
+        let chunks = chunks(&[
+            "IHDR", "tEXt", "PLTE", "gAMA", "IDAT", "pHYs", "ruSt", "IDAT", "IEND",
+        ]);
+        let ordered = spec_order(chunks);
+        assert_eq!(
+            types(&ordered),
+            vec!["IHDR", "gAMA", "PLTE", "pHYs", "tEXt", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+        assert!(violations(ordered.iter().map(ChunkRef::from)).is_empty());
+    }
+
+    #[test]
+    fn test_insert_position_from_str() {
+        assert_eq!(
+            InsertPosition::from_str("after-idat"),
+            Ok(InsertPosition::AfterIdat)
+        );
+        assert_eq!(InsertPosition::from_str("end"), Ok(InsertPosition::End));
+        assert!(InsertPosition::from_str("start").is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use pngme::ChunkRef;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// Version written at the top of porcelain output. Bump it only when an existing record changes
+/// shape; adding a new record kind or a trailing field is not a format change.
+pub const PORCELAIN_VERSION: u32 = 1;
+
+/// How a command reports its results
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum OutputMode {
+    /// Formatted for people, free to change between releases
+    #[default]
+    Human,
+    /// Nothing on stdout, the exit code carries the result
+    Quiet,
+    /// Tab-separated records for scripts, stable across releases
+    Porcelain,
+    /// A single JSON document for scripts
+    Json,
+}
+
+/// The `--format` option
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Format {
+    #[default]
+    Human,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err("format must be human or json"),
+        }
+    }
+}
+
+impl OutputMode {
+    /// Maps the `--quiet` and `--porcelain` flags, which can't be combined
+    pub fn from_flags(quiet: bool, porcelain: bool) -> Result<OutputMode, &'static str> {
+        match (quiet, porcelain) {
+            (false, false) => Ok(OutputMode::Human),
+            (true, false) => Ok(OutputMode::Quiet),
+            (false, true) => Ok(OutputMode::Porcelain),
+            (true, true) => Err("--quiet and --porcelain can't be used together"),
+        }
+    }
+
+    /// Like [`OutputMode::from_flags`], with `--format json` on top
+    pub fn from_args(
+        quiet: bool,
+        porcelain: bool,
+        format: Format,
+    ) -> Result<OutputMode, &'static str> {
+        match (OutputMode::from_flags(quiet, porcelain)?, format) {
+            (mode, Format::Human) => Ok(mode),
+            (OutputMode::Human, Format::Json) => Ok(OutputMode::Json),
+            _ => Err("--format json can't be combined with --quiet or --porcelain"),
+        }
+    }
+}
+
+/// Metadata of one chunk as written by `--format json`
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct ChunkListing {
+    pub index: usize,
+    pub offset: usize,
+    pub chunk_type: String,
+    pub length: u32,
+    /// CRC in hex, as in the human and porcelain output
+    pub crc: String,
+    pub flags: [&'static str; 3],
+    /// Notes attached with `annotate`, shown by `print --annotations`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+}
+
+impl ChunkListing {
+    pub fn new(index: usize, offset: usize, chunk: &ChunkRef) -> ChunkListing {
+        ChunkListing {
+            index,
+            offset,
+            chunk_type: String::from_utf8_lossy(&chunk.chunk_type().bytes()).into_owned(),
+            length: chunk.length(),
+            crc: format!("{:08x}", chunk.crc()),
+            flags: chunk.chunk_type().flags(),
+            annotations: Vec::new(),
+        }
+    }
+}
+
+/// Writes command output in the selected mode. Commands report everything twice, once as a human
+/// line and once as a porcelain record, and only the one matching the mode is written.
+pub struct Output<W: Write> {
+    mode: OutputMode,
+    out: W,
+    started: bool,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(mode: OutputMode, out: W) -> Output<W> {
+        Output {
+            mode,
+            out,
+            started: false,
+        }
+    }
+
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    pub fn human(&mut self, line: impl fmt::Display) -> io::Result<()> {
+        match self.mode {
+            OutputMode::Human => writeln!(self.out, "{}", line),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes one record: the record kind followed by its fields, tab-separated. The first record
+    /// is preceded by a `version` record.
+    pub fn record(&mut self, kind: &str, fields: &[&dyn fmt::Display]) -> io::Result<()> {
+        if self.mode != OutputMode::Porcelain {
+            return Ok(());
+        }
+        if !self.started {
+            writeln!(self.out, "version\t{}", PORCELAIN_VERSION)?;
+            self.started = true;
+        }
+
+        let mut line = escape(kind);
+        for field in fields {
+            line.push('\t');
+            line.push_str(&escape(&field.to_string()));
+        }
+        writeln!(self.out, "{}", line)
+    }
+
+    /// Writes the `chunk` record: index, byte offset, type, data length, CRC in hex and the
+    /// comma-separated type flags
+    pub fn chunk_record(
+        &mut self,
+        index: usize,
+        offset: usize,
+        chunk: &ChunkRef,
+    ) -> io::Result<()> {
+        self.record(
+            "chunk",
+            &[
+                &index,
+                &offset,
+                &chunk.chunk_type(),
+                &chunk.length(),
+                &format!("{:08x}", chunk.crc()),
+                &chunk.chunk_type().flags().join(","),
+            ],
+        )
+    }
+
+    /// Writes `value` as pretty-printed JSON. Commands call it once, with their whole result.
+    pub fn json(&mut self, value: &impl Serialize) -> io::Result<()> {
+        if self.mode != OutputMode::Json {
+            return Ok(());
+        }
+        serde_json::to_writer_pretty(&mut self.out, value)?;
+        writeln!(self.out)
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Escapes backslashes, tabs and line breaks so every record stays on one line with a fixed
+/// number of fields
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngme::{Chunk, ChunkType};
+    use std::str::FromStr;
+
+    fn written(mode: OutputMode) -> String {
+        let mut output = Output::new(mode, Vec::new());
+        output.human("1 chunk found").unwrap();
+        output
+            .chunk_record(
+                0,
+                8,
+                &ChunkRef::from(&Chunk::new(
+                    ChunkType::from_str("tEXt").unwrap(),
+                    b"a".to_vec(),
+                )),
+            )
+            .unwrap();
+        output.record("note", &[&"tab\there"]).unwrap();
+        output.json(&[1, 2]).unwrap();
+        String::from_utf8(output.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(OutputMode::from_flags(false, false), Ok(OutputMode::Human));
+        assert_eq!(OutputMode::from_flags(true, false), Ok(OutputMode::Quiet));
+        assert_eq!(
+            OutputMode::from_flags(false, true),
+            Ok(OutputMode::Porcelain)
+        );
+        assert!(OutputMode::from_flags(true, true).is_err());
+
+        assert_eq!(
+            OutputMode::from_args(false, false, Format::Json),
+            Ok(OutputMode::Json)
+        );
+        assert_eq!(
+            OutputMode::from_args(true, false, Format::Human),
+            Ok(OutputMode::Quiet)
+        );
+        assert!(OutputMode::from_args(false, true, Format::Json).is_err());
+    }
+
+    #[test]
+    fn test_modes() {
+        assert_eq!(written(OutputMode::Human), "1 chunk found\n");
+        assert_eq!(written(OutputMode::Quiet), "");
+
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a".to_vec());
+        assert_eq!(
+            written(OutputMode::Porcelain),
+            format!(
+                "version\t1\nchunk\t0\t8\ttEXt\t1\t{:08x}\tancillary,public,safe\nnote\ttab\\there\n",
+                chunk.crc()
+            )
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(written(OutputMode::Json), "[\n  1,\n  2\n]\n");
+
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a".to_vec());
+        let listing = serde_json::to_value(ChunkListing::new(0, 8, &(&chunk).into())).unwrap();
+        assert_eq!(listing["chunk_type"], "tEXt");
+        assert_eq!(listing["crc"], format!("{:08x}", chunk.crc()));
+        assert_eq!(
+            listing["flags"],
+            serde_json::json!(["ancillary", "public", "safe"])
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\\b\nc\td"), "a\\\\b\\nc\\td");
+    }
+}
@@ -0,0 +1,313 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use std::str::FromStr;
+
+pub const PATCH_VERSION: u32 = 1;
+
+/// One chunk-level edit. Indexes refer to the chunk list as it stands when the operation is
+/// applied, after every earlier operation in the patch. Removals and replacements carry the type
+/// and CRC of the chunk they expect to find so a patch never applies to the wrong file.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", rename_all = "lowercase"))]
+pub enum PatchOp {
+    Remove {
+        index: usize,
+        chunk_type: String,
+        crc: u32,
+    },
+    Insert {
+        index: usize,
+        chunk_type: String,
+        data: String,
+    },
+    Replace {
+        index: usize,
+        chunk_type: String,
+        crc: u32,
+        new_chunk_type: String,
+        data: String,
+    },
+}
+
+/// A replayable list of chunk insertions, removals and replacements, stored as JSON
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Patch {
+    pub version: u32,
+    pub ops: Vec<PatchOp>,
+}
+
+/// One step of [`diff`]: a chunk common to both lists, or one only in the old or new list
+pub(crate) enum Step {
+    Keep,
+    Remove(usize),
+    Insert(usize),
+}
+
+impl Patch {
+    /// Builds the smallest patch, by chunk count, that turns `from` into `to`
+    pub fn create(from: &Png, to: &Png) -> Patch {
+        let old: Vec<Vec<u8>> = from.chunks().iter().map(Chunk::as_bytes).collect();
+        let new: Vec<Vec<u8>> = to.chunks().iter().map(Chunk::as_bytes).collect();
+
+        let mut ops = Vec::new();
+        let mut index = 0;
+        let mut steps = diff(&old, &new).into_iter().peekable();
+        while let Some(step) = steps.next() {
+            match step {
+                Step::Keep => index += 1,
+                Step::Remove(i) => {
+                    let removed = &from.chunks()[i];
+                    if let Some(Step::Insert(j)) = steps.peek() {
+                        let inserted = &to.chunks()[*j];
+                        ops.push(PatchOp::Replace {
+                            index,
+                            chunk_type: removed.chunk_type().to_string(),
+                            crc: removed.crc(),
+                            new_chunk_type: inserted.chunk_type().to_string(),
+                            data: to_hex(inserted.data()),
+                        });
+                        steps.next();
+                        index += 1;
+                    } else {
+                        ops.push(PatchOp::Remove {
+                            index,
+                            chunk_type: removed.chunk_type().to_string(),
+                            crc: removed.crc(),
+                        });
+                    }
+                }
+                Step::Insert(j) => {
+                    let inserted = &to.chunks()[j];
+                    ops.push(PatchOp::Insert {
+                        index,
+                        chunk_type: inserted.chunk_type().to_string(),
+                        data: to_hex(inserted.data()),
+                    });
+                    index += 1;
+                }
+            }
+        }
+
+        Patch {
+            version: PATCH_VERSION,
+            ops,
+        }
+    }
+
+    /// Applies every operation to a copy of `png`. Nothing is returned unless the whole patch
+    /// applies cleanly.
+    pub fn apply(&self, png: &Png) -> Result<Png, PngMeError> {
+        if self.version != PATCH_VERSION {
+            return Err(PngMeError::Malformed("unsupported patch version"));
+        }
+
+        let mut chunks = png.chunks().to_vec();
+        for op in self.ops.iter() {
+            match op {
+                PatchOp::Remove {
+                    index,
+                    chunk_type,
+                    crc,
+                } => {
+                    expect_chunk(&chunks, *index, chunk_type, *crc)?;
+                    chunks.remove(*index);
+                }
+                PatchOp::Insert {
+                    index,
+                    chunk_type,
+                    data,
+                } => {
+                    if *index > chunks.len() {
+                        return Err(PngMeError::Invalid(
+                            "patch does not apply: insert position is out of range",
+                        ));
+                    }
+                    chunks.insert(*index, new_chunk(chunk_type, data)?);
+                }
+                PatchOp::Replace {
+                    index,
+                    chunk_type,
+                    crc,
+                    new_chunk_type,
+                    data,
+                } => {
+                    expect_chunk(&chunks, *index, chunk_type, *crc)?;
+                    chunks[*index] = new_chunk(new_chunk_type, data)?;
+                }
+            }
+        }
+
+        Ok(Png::new(chunks))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> crate::MyResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> crate::MyResult<Patch> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+fn expect_chunk(
+    chunks: &[Chunk],
+    index: usize,
+    chunk_type: &str,
+    crc: u32,
+) -> Result<(), PngMeError> {
+    match chunks.get(index) {
+        Some(chunk) if chunk.chunk_type().to_string() == chunk_type && chunk.crc() == crc => Ok(()),
+        Some(_) => Err(PngMeError::Invalid(
+            "patch does not apply: chunk differs from the one the patch expects",
+        )),
+        None => Err(PngMeError::Invalid(
+            "patch does not apply: chunk index is out of range",
+        )),
+    }
+}
+
+fn new_chunk(chunk_type: &str, data: &str) -> Result<Chunk, PngMeError> {
+    Ok(Chunk::new(
+        ChunkType::from_str(chunk_type)?,
+        from_hex(data)?,
+    ))
+}
+
+/// Aligns two chunk lists along their longest common subsequence
+pub(crate) fn diff(old: &[Vec<u8>], new: &[Vec<u8>]) -> Vec<Step> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut steps = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            steps.push(Step::Keep);
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            steps.push(Step::Remove(i));
+            i += 1;
+        } else {
+            steps.push(Step::Insert(j));
+            j += 1;
+        }
+    }
+
+    steps
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, PngMeError> {
+    let invalid = || PngMeError::Malformed("invalid hex data in patch");
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(chunks: &[(&str, &str)]) -> Png {
+        Png::new(
+            chunks
+                .iter()
+                .map(|(chunk_type, data)| {
+                    Chunk::new(
+                        ChunkType::from_str(chunk_type).unwrap(),
+                        data.as_bytes().to_vec(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_identical_files() {
+        let a = png(&[("FrSt", "one"), ("LASt", "two")]);
+        let patch = Patch::create(&a, &a);
+        assert!(patch.ops.is_empty());
+    }
+
+    #[test]
+    fn test_create_and_apply() {
+        let a = png(&[
+            ("FrSt", "one"),
+            ("ruSt", "old"),
+            ("tEXt", "a"),
+            ("LASt", "end"),
+        ]);
+        let b = png(&[
+            ("FrSt", "one"),
+            ("ruSt", "new"),
+            ("LASt", "end"),
+            ("zzZz", "x"),
+        ]);
+
+        let patch = Patch::create(&a, &b);
+        let kinds: Vec<&str> = patch
+            .ops
+            .iter()
+            .map(|op| match op {
+                PatchOp::Remove { .. } => "remove",
+                PatchOp::Insert { .. } => "insert",
+                PatchOp::Replace { .. } => "replace",
+            })
+            .collect();
+        assert_eq!(kinds, vec!["remove", "replace", "insert"]);
+        assert_eq!(patch.apply(&a).unwrap().as_bytes(), b.as_bytes());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let a = png(&[("FrSt", "one")]);
+        let b = png(&[("FrSt", "one"), ("ruSt", "hidden")]);
+        let patch = Patch::create(&a, &b);
+
+        let json = patch.to_json().unwrap();
+        assert!(json.contains("\"op\": \"insert\""));
+        assert_eq!(Patch::from_json(&json).unwrap(), patch);
+    }
+
+    #[test]
+    fn test_apply_to_different_file() {
+        let a = png(&[("FrSt", "one"), ("ruSt", "old")]);
+        let b = png(&[("FrSt", "one")]);
+        let patch = Patch::create(&a, &b);
+
+        let other = png(&[("FrSt", "one"), ("ruSt", "something else")]);
+        assert!(patch.apply(&other).is_err());
+        assert!(patch.apply(&b).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff").unwrap(), vec![0, 15, 255]);
+        assert!(from_hex("0").is_err());
+        assert!(from_hex("zz").is_err());
+        assert!(from_hex("é0").is_err());
+    }
+}
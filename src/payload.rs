@@ -0,0 +1,247 @@
+use crate::compress::{self, Algorithm};
+use crate::crypto;
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::signing;
+use crate::transform::{self, Pipeline};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// How a message is turned into the payload that gets hidden. The default leaves it as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SealOptions<'a> {
+    /// Runs the message through this transform chain first, recording it in a header
+    pub transform: Option<&'a Pipeline>,
+    pub compression: Algorithm,
+    /// Encrypts the message with this passphrase
+    pub passphrase: Option<&'a str>,
+    /// More passphrases that can each decrypt the message on their own, alongside `passphrase`
+    pub recipients: &'a [&'a str],
+    /// Encrypts the message with this random key instead of a passphrase
+    pub shared_key: Option<&'a [u8]>,
+    /// Frames the result with an HMAC-SHA256 tag made with this key
+    pub signing_key: Option<&'a [u8]>,
+    /// Has the tag cover the critical chunks of the image as well, which only holds when the
+    /// payload goes in an ancillary chunk
+    pub sign_image: bool,
+}
+
+impl SealOptions<'_> {
+    /// Whether the payload is binary whatever the message was, so text chunks need it as base64
+    fn is_binary(&self) -> bool {
+        self.transform.is_some()
+            || self.passphrase.is_some()
+            || self.shared_key.is_some()
+            || self.signing_key.is_some()
+    }
+}
+
+/// What reading a payload back takes: the passphrase or random key it was encrypted with and the
+/// key it was signed with, if any
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenOptions<'a> {
+    /// Transform chain to undo when the message has no transform header of its own
+    pub transform: Option<&'a Pipeline>,
+    pub passphrase: Option<&'a str>,
+    pub shared_key: Option<&'a [u8]>,
+    pub signing_key: Option<&'a [u8]>,
+}
+
+/// Transforms and compresses the message, then encrypts it and signs the result, as `options`
+/// ask. Ciphertext doesn't compress, so compression has to come first. `image` is the file the
+/// payload is going into, and is only read for `sign_image`.
+pub fn seal(message: &[u8], image: &Png, options: &SealOptions) -> Result<Vec<u8>, PngMeError> {
+    let transformed = match options.transform {
+        Some(pipeline) => pipeline.encode(message).map_err(PngMeError::Invalid)?,
+        None => message.to_vec(),
+    };
+    let compressed = compress::compress(options.compression, &transformed)?;
+    let encrypted = match (options.passphrase, options.shared_key) {
+        (Some(passphrase), _) if !options.recipients.is_empty() => {
+            let passphrases: Vec<&str> = [passphrase]
+                .into_iter()
+                .chain(options.recipients.iter().copied())
+                .collect();
+            crypto::encrypt_for(&passphrases, &compressed)?
+        }
+        (Some(passphrase), _) => crypto::encrypt(passphrase, &compressed)?,
+        (None, Some(key)) => crypto::encrypt_with_key(key, &compressed)?,
+        (None, None) => compressed,
+    };
+    match options.signing_key {
+        Some(key) => signing::sign(key, &encrypted, options.sign_image.then_some(image)),
+        None => Ok(encrypted),
+    }
+}
+
+/// Undoes [`seal`]: checks the signature, decrypts, decompresses and reverses the transform chain
+/// named in the header, or `options.transform` without one. A signed or encrypted payload
+/// without the key or passphrase for it fails rather than coming back as the message.
+pub fn open(payload: &[u8], image: &Png, options: &OpenOptions) -> Result<Vec<u8>, PngMeError> {
+    let verified = match options.signing_key {
+        Some(key) => signing::verify(key, payload, image)?,
+        None if signing::is_signed(payload) => return Err(PngMeError::KeyRequired),
+        None => payload.to_vec(),
+    };
+    let decrypted = match (options.passphrase, options.shared_key) {
+        (Some(passphrase), _) => crypto::decrypt(passphrase, &verified)?,
+        (None, Some(key)) => crypto::decrypt_with_key(key, &verified)?,
+        (None, None) if crypto::is_encrypted(&verified) => {
+            return Err(PngMeError::PassphraseRequired)
+        }
+        (None, None) => verified,
+    };
+    let decompressed = compress::decompress(&decrypted)?;
+    match (options.transform, transform::is_transformed(&decompressed)) {
+        (Some(pipeline), false) => pipeline.reverse(&decompressed),
+        _ => transform::decode(&decompressed),
+    }
+    .map_err(PngMeError::Malformed)
+}
+
+/// The payload as text for a tEXt, zTXt or iTXt chunk. Encrypted and signed payloads go in as
+/// base64, and anything else has to be UTF-8 already.
+pub fn to_text(payload: Vec<u8>, options: &SealOptions) -> Result<String, PngMeError> {
+    match options.is_binary() {
+        true => Ok(STANDARD.encode(payload)),
+        false => String::from_utf8(payload).map_err(|e| e.utf8_error().into()),
+    }
+}
+
+/// The payload held by a text chunk. Text that decodes from base64 to an encrypted, signed or
+/// transformed payload is taken as that payload, whatever options it's opened with, so [`open`]
+/// can tell when a passphrase or key is missing. Any other text is the message itself.
+pub fn from_text(text: &str) -> Vec<u8> {
+    match STANDARD.decode(text) {
+        Ok(payload)
+            if crypto::is_encrypted(&payload)
+                || signing::is_signed(&payload)
+                || transform::is_transformed(&payload) =>
+        {
+            payload
+        }
+        _ => text.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::transform::Transform;
+
+    fn testing_png() -> Png {
+        Png::new(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![1, 2, 3]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    // Encryption is left to test_open_encrypted, which can use fewer key derivation rounds
+    #[test]
+    fn test_seal_open() {
+        let png = testing_png();
+        let message = b"a message that repeats, a message that repeats".as_slice();
+        let seal_options = SealOptions {
+            compression: Algorithm::Zlib,
+            signing_key: Some(&[7; 16]),
+            sign_image: true,
+            ..SealOptions::default()
+        };
+        let sealed = seal(message, &png, &seal_options).unwrap();
+        assert!(signing::is_signed(&sealed));
+
+        let open_options = OpenOptions {
+            signing_key: Some(&[7; 16]),
+            ..OpenOptions::default()
+        };
+        assert_eq!(open(&sealed, &png, &open_options).unwrap(), message);
+        assert!(matches!(
+            open(&sealed, &png, &OpenOptions::default()),
+            Err(PngMeError::KeyRequired)
+        ));
+        let changed = Png::new(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![4]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        assert!(matches!(
+            open(&sealed, &changed, &open_options),
+            Err(PngMeError::SignatureMismatch)
+        ));
+
+        let plain = SealOptions::default();
+        assert_eq!(seal(message, &png, &plain).unwrap(), message);
+        assert_eq!(
+            open(message, &png, &OpenOptions::default()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_open_encrypted() {
+        let png = testing_png();
+        let compressed = compress::compress(Algorithm::Zlib, b"secret").unwrap();
+        let encrypted = crypto::seal("passphrase", &compressed, 1_000).unwrap();
+        assert!(matches!(
+            open(&encrypted, &png, &OpenOptions::default()),
+            Err(PngMeError::PassphraseRequired)
+        ));
+        let options = OpenOptions {
+            passphrase: Some("passphrase"),
+            ..OpenOptions::default()
+        };
+        assert_eq!(open(&encrypted, &png, &options).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_transform() {
+        let png = testing_png();
+        let pipeline: Pipeline = "gzip,xor:0x5a".parse().unwrap();
+        let options = SealOptions {
+            transform: Some(&pipeline),
+            ..SealOptions::default()
+        };
+        let sealed = seal(b"layered", &png, &options).unwrap();
+        assert_eq!(
+            open(&sealed, &png, &OpenOptions::default()).unwrap(),
+            b"layered"
+        );
+        let text = to_text(sealed.clone(), &options).unwrap();
+        assert_eq!(from_text(&text), sealed);
+
+        // A chain layered by something else has no header, so it has to be named to be undone
+        let named = OpenOptions {
+            transform: Some(&pipeline),
+            ..OpenOptions::default()
+        };
+        let layered = [Transform::Gzip, Transform::Xor(vec![0x5a])]
+            .iter()
+            .try_fold(b"elsewhere".to_vec(), |data, step| step.apply(&data))
+            .unwrap();
+        assert_eq!(open(&layered, &png, &named).unwrap(), b"elsewhere");
+        assert_eq!(
+            open(&layered, &png, &OpenOptions::default()).unwrap(),
+            layered
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let png = testing_png();
+        let options = SealOptions {
+            signing_key: Some(&[7; 16]),
+            ..SealOptions::default()
+        };
+        let sealed = seal(b"signed", &png, &options).unwrap();
+        let text = to_text(sealed.clone(), &options).unwrap();
+        assert_eq!(from_text(&text), sealed);
+
+        // Plain text stays as it is, even when it happens to be valid base64
+        let plain = SealOptions::default();
+        assert_eq!(to_text(b"TWFu".to_vec(), &plain).unwrap(), "TWFu");
+        assert_eq!(from_text("TWFu"), b"TWFu");
+        assert!(to_text(vec![0xff], &plain).is_err());
+    }
+}
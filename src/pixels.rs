@@ -0,0 +1,330 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::selection::Occurrence;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+};
+
+/// Unfiltered scanlines of a non-interlaced image, decoded from its IDAT chunks. `data` holds
+/// `height` rows of `stride` bytes each, without the per-row filter type byte.
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl ImageData {
+    /// Reads the image layout from the IHDR of `png` without touching the image data, leaving
+    /// `data` empty
+    pub fn describe(png: &Png) -> Result<ImageData, PngMeError> {
+        let ihdr = png.header()?;
+        if ihdr.is_interlaced() {
+            return Err(PngMeError::Invalid("interlaced images are not supported"));
+        }
+
+        Ok(ImageData {
+            width: ihdr.width,
+            height: ihdr.height,
+            bit_depth: ihdr.bit_depth,
+            color_type: ihdr.color_type as u8,
+            data: Vec::new(),
+        })
+    }
+
+    /// Inflates and unfilters the image data of `png`
+    pub fn decode(png: &Png) -> Result<ImageData, PngMeError> {
+        let mut image = Self::describe(png)?;
+        let compressed: Vec<u8> = png
+            .chunks_by_type("IDAT", Occurrence::All)
+            .iter()
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        let stride = image
+            .checked_stride()
+            .ok_or(PngMeError::Malformed("IHDR dimensions are too large"))?;
+        let height = image.height as usize;
+        let expected = (stride + 1)
+            .checked_mul(height)
+            .ok_or(PngMeError::Malformed("IHDR dimensions are too large"))?;
+
+        // Inflating more than the dimensions call for would only be thrown away, and an IDAT
+        // that inflates without limit shouldn't exhaust memory first
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take((expected as u64).saturating_add(1))
+            .read_to_end(&mut filtered)
+            .map_err(|_| PngMeError::Malformed("image data is not valid zlib data"))?;
+
+        if filtered.len() < expected {
+            return Err(PngMeError::Malformed(
+                "image data is shorter than the IHDR dimensions require",
+            ));
+        }
+
+        image.data = vec![0u8; height * stride];
+        let bpp = image.filter_bytes_per_pixel();
+        for row in 0..height {
+            let line = &filtered[row * (stride + 1)..(row + 1) * (stride + 1)];
+            let (previous, current) = image.data.split_at_mut(row * stride);
+            let previous = match row {
+                0 => None,
+                _ => Some(&previous[(row - 1) * stride..]),
+            };
+            unfilter(line[0], &line[1..], previous, &mut current[..stride], bpp)?;
+        }
+
+        Ok(image)
+    }
+
+    /// Replaces the IDAT chunks of `png` with a single IDAT holding this image data, placed where
+    /// the first IDAT was
+    pub fn encode_into(&self, png: &mut Png) -> Result<(), PngMeError> {
+        let stride = self.stride();
+        let mut filtered = Vec::with_capacity(self.data.len() + self.height as usize);
+        for row in self.data.chunks(stride) {
+            filtered.push(0);
+            filtered.extend_from_slice(row);
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered)?;
+        let compressed = encoder.finish()?;
+
+        let position = *png
+            .indices_of_type("IDAT", Occurrence::First)
+            .first()
+            .ok_or(PngMeError::ChunkNotFound(String::from("IDAT")))?;
+        png.remove_chunks("IDAT", Occurrence::All)?;
+        png.insert_chunk_at(
+            position,
+            Chunk::new(ChunkType::from_str("IDAT")?, compressed),
+        )
+    }
+
+    /// Builds a minimal non-interlaced PNG holding just this image: IHDR, one IDAT and IEND
+    pub fn to_png(&self) -> Result<Png, PngMeError> {
+        let color_type = ColorType::try_from(self.color_type)?;
+        let ihdr = Ihdr::new(self.width, self.height, self.bit_depth, color_type);
+
+        let mut png = Png::new(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT")?, vec![]),
+            Chunk::new(ChunkType::from_str("IEND")?, vec![]),
+        ]);
+        self.encode_into(&mut png)?;
+        Ok(png)
+    }
+
+    /// Samples per pixel for the color type, or `None` for a color type the spec doesn't define
+    pub fn channels(&self) -> Option<usize> {
+        match self.color_type {
+            0 => Some(1),
+            2 => Some(3),
+            3 => Some(1),
+            4 => Some(2),
+            6 => Some(4),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        match self.bit_depth {
+            16 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether each sample has a least-significant bit that can change without visibly altering
+    /// the pixel, which rules out palette indexes and sub-byte depths
+    pub fn has_lsb_plane(&self) -> bool {
+        self.color_type != 3 && self.bit_depth >= 8
+    }
+
+    /// Bytes in one unfiltered scanline. Saturates for dimensions too large for any image
+    /// [`ImageData::decode`] accepts.
+    pub fn stride(&self) -> usize {
+        self.checked_stride().unwrap_or(usize::MAX)
+    }
+
+    /// Bytes in one unfiltered scanline, or `None` when the width overflows `usize`
+    fn checked_stride(&self) -> Option<usize> {
+        let bits = (self.width as usize)
+            .checked_mul(self.channels().unwrap_or(0))?
+            .checked_mul(self.bit_depth as usize)?;
+        Some(bits.div_ceil(8))
+    }
+
+    /// Distance, in bytes, to the corresponding byte of the previous pixel when filtering
+    fn filter_bytes_per_pixel(&self) -> usize {
+        let bits = self.channels().unwrap_or(0) * self.bit_depth as usize;
+        bits.div_ceil(8).max(1)
+    }
+}
+
+fn unfilter(
+    filter: u8,
+    line: &[u8],
+    previous: Option<&[u8]>,
+    out: &mut [u8],
+    bpp: usize,
+) -> Result<(), PngMeError> {
+    for i in 0..line.len() {
+        let left = if i >= bpp { out[i - bpp] } else { 0 };
+        let up = previous.map_or(0, |previous| previous[i]);
+        let up_left = match previous {
+            Some(previous) if i >= bpp => previous[i - bpp],
+            _ => 0,
+        };
+
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(PngMeError::Malformed("unknown scanline filter type")),
+        };
+        out[i] = line[i].wrapping_add(predictor);
+    }
+
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a minimal non-interlaced image filled with a predictable byte pattern
+    pub(crate) fn testing_image(width: u32, height: u32, color_type: u8, bit_depth: u8) -> Png {
+        let mut image = ImageData {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            data: vec![],
+        };
+        let stride = image.stride();
+        image.data = (0..height as usize * stride)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+
+        image.to_png().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let png = testing_image(5, 4, 2, 8);
+        let image = ImageData::decode(&png).unwrap();
+        assert_eq!(image.stride(), 15);
+        assert_eq!(image.data.len(), 60);
+        assert_eq!(image.data[8], 56);
+
+        let mut copy = png.clone();
+        image.encode_into(&mut copy).unwrap();
+        assert_eq!(ImageData::decode(&copy).unwrap().data, image.data);
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+        let line = [1, 2, 3, 4];
+        let mut out = [0u8; 4];
+
+        unfilter(1, &line, Some(&previous), &mut out, 2).unwrap();
+        assert_eq!(out, [1, 2, 4, 6]);
+        unfilter(2, &line, Some(&previous), &mut out, 2).unwrap();
+        assert_eq!(out, [11, 22, 33, 44]);
+        unfilter(3, &line, Some(&previous), &mut out, 2).unwrap();
+        assert_eq!(out, [6, 12, 21, 30]);
+        unfilter(4, &line, Some(&previous), &mut out, 2).unwrap();
+        assert_eq!(out, [11, 22, 33, 44]);
+        assert!(matches!(
+            unfilter(5, &line, None, &mut out, 2),
+            Err(PngMeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_zlib_data() {
+        let mut png = testing_image(2, 2, 0, 8);
+        png.remove_chunks("IDAT", Occurrence::All).unwrap();
+        png.insert_chunk_at(
+            1,
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), b"nope".to_vec()),
+        )
+        .unwrap();
+        assert!(matches!(
+            ImageData::decode(&png),
+            Err(PngMeError::Malformed("image data is not valid zlib data"))
+        ));
+    }
+
+    /// Replaces the IHDR of `png` with one declaring `width` by `height`, keeping the rest
+    pub(crate) fn resize_header(png: &mut Png, width: u32, height: u32) {
+        let mut ihdr = png.chunk_by_type("IHDR").unwrap().data().to_vec();
+        ihdr[..4].copy_from_slice(&width.to_be_bytes());
+        ihdr[4..8].copy_from_slice(&height.to_be_bytes());
+        png.remove_chunks("IHDR", Occurrence::All).unwrap();
+        png.insert_chunk_at(0, Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_oversized_dimensions() {
+        let mut png = testing_image(2, 2, 6, 16);
+        resize_header(&mut png, 2147483647, 2147483647);
+        assert!(matches!(
+            ImageData::decode(&png),
+            Err(PngMeError::Malformed("IHDR dimensions are too large"))
+        ));
+
+        resize_header(&mut png, 65535, 65535);
+        assert!(matches!(
+            ImageData::decode(&png),
+            Err(PngMeError::Malformed(
+                "image data is shorter than the IHDR dimensions require"
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_sub_byte_stride() {
+        let png = testing_image(9, 2, 0, 1);
+        let image = ImageData::decode(&png).unwrap();
+        assert_eq!(image.stride(), 2);
+    }
+
+    #[test]
+    fn test_interlaced_image_is_rejected() {
+        let mut png = testing_image(2, 2, 0, 8);
+        let mut ihdr = png.chunk_by_type("IHDR").unwrap().data().to_vec();
+        ihdr[12] = 1;
+        png.remove_chunks("IHDR", Occurrence::All).unwrap();
+        png.insert_chunk_at(0, Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr))
+            .unwrap();
+        assert!(ImageData::decode(&png).is_err());
+    }
+}
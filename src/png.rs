@@ -1,51 +1,217 @@
-#![allow(dead_code, unused_variables)]
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    io::{self, Read, Write},
+};
 
-use std::fmt::Display;
+use crate::apng::ANIMATION_CHUNK_TYPES;
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::filter::Filter;
+use crate::ihdr::Ihdr;
+use crate::ordering::{self, InsertPosition, MoveTarget, Placement, Violation};
+use crate::selection::{ChunkAddress, Occurrence, Selection};
+use crate::transaction::Transaction;
+use sha2::{Digest, Sha256};
+
+/// Marks one piece of a payload split across several chunks by [`Png::insert_payload`]
+pub const PIECE_MAGIC: &[u8; 4] = b"pmP1";
+/// Version of the piece header, the digit at the end of [`PIECE_MAGIC`]
+pub const PIECE_VERSION: u32 = 1;
+/// Magic, sequence number, piece count and the SHA-256 of the whole payload
+pub const PIECE_HEADER_LEN: usize = PIECE_MAGIC.len() + 4 + 4 + 32;
+/// Payload bytes per piece. libpng rejects ancillary chunks over 8 MB by default, so pieces stay
+/// well below that.
+pub const DEFAULT_PIECE_LEN: usize = 1 << 20;
+/// The chunks needed to display an image, which [`Png::scrub`] never removes
+pub const IMAGE_CHUNK_TYPES: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
 
-use crate::chunk::Chunk;
 #[derive(Debug, Clone, Default)]
 pub struct Png {
-    header: [u8; 8],
+    signature: [u8; 8],
     chunks: Vec<Chunk>,
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
+/// A chunk whose stored CRC [`Png::repair`] replaced
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CrcRepair {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub computed: u32,
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut header = [0u8; 8];
-        header.copy_from_slice(&value[0..8]);
+/// Chunks dropped by [`Png::dedup_ancillary`], by their position before removal
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct DedupReport {
+    pub removed: Vec<(usize, ChunkType)>,
+    pub bytes_saved: usize,
+}
+
+/// Chunks dropped by [`Png::scrub`], with their position before removal
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub removed: Vec<(usize, Chunk)>,
+    pub bytes_saved: usize,
+}
+
+/// Iterates over the chunks of a buffer holding a whole image, parsing each one only when it's
+/// reached. Iteration stops at the end of the buffer or after the first error.
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+    rest: &'a [u8],
+    offset: usize,
+    parse_chunk: fn(&'a [u8]) -> Result<ChunkRef<'a>, PngMeError>,
+}
 
-        match header {
-            Self::STANDARD_HEADER => (),
-            _ => return Err("Header bytes don't match PNG standard header"),
+impl<'a> ChunkRefs<'a> {
+    /// Checks the signature, then yields the chunks after it, checking the CRC of each
+    pub fn new(value: &'a [u8]) -> Result<ChunkRefs<'a>, PngMeError> {
+        ChunkRefs::with(value, ChunkRef::try_from)
+    }
+    /// Like `new` but keeps chunks whose CRC doesn't match, so only the length and type of each
+    /// chunk are read
+    pub fn ignoring_crcs(value: &'a [u8]) -> Result<ChunkRefs<'a>, PngMeError> {
+        ChunkRefs::with(value, ChunkRef::parse_lenient)
+    }
+    fn with(
+        value: &'a [u8],
+        parse_chunk: fn(&'a [u8]) -> Result<ChunkRef<'a>, PngMeError>,
+    ) -> Result<ChunkRefs<'a>, PngMeError> {
+        match value.get(0..8) {
+            Some(signature) if signature == Png::STANDARD_HEADER => Ok(ChunkRefs {
+                rest: &value[8..],
+                offset: 8,
+                parse_chunk,
+            }),
+            _ => Err(PngMeError::InvalidSignature),
         }
+    }
+    /// Byte offset in the buffer where the next chunk starts
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
 
-        let mut chunk_index = 8;
-        let mut chunks: Vec<Chunk> = Vec::new();
-        let buf_size = value.len();
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>, PngMeError>;
 
-        while chunk_index < buf_size {
-            let chunk = Chunk::try_from(&value[chunk_index..]);
-            match chunk {
-                Ok(chunk) => {
-                    chunk_index += chunk.total_size();
-                    chunks.push(chunk);
-                }
-                Err(_) => return Err("Error parsing bytes"),
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let chunk = (self.parse_chunk)(self.rest);
+        match &chunk {
+            Ok(chunk) => {
+                self.rest = &self.rest[chunk.total_size()..];
+                self.offset += chunk.total_size();
             }
+            Err(_) => self.rest = &[],
         }
+        Some(chunk)
+    }
+}
+
+/// An image parsed in place from a buffer, such as a whole file, whose chunks borrow their data
+/// from it. Reading a large file this way copies nothing. Use [`PngRef::to_png`] to get a [`Png`]
+/// that can be edited.
+#[derive(Debug, Clone)]
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PngMeError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let chunks = ChunkRefs::new(value)?.collect::<Result<_, _>>()?;
+        Ok(PngRef { chunks })
+    }
+}
+
+impl<'a> PngRef<'a> {
+    /// Parses like `try_from` but accepts chunks whose CRC doesn't match, as
+    /// [`Png::parse_ignoring_crcs`]
+    pub fn parse_ignoring_crcs(value: &'a [u8]) -> Result<PngRef<'a>, PngMeError> {
+        let chunks = ChunkRefs::ignoring_crcs(value)?.collect::<Result<_, _>>()?;
+        Ok(PngRef { chunks })
+    }
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks_by_type(chunk_type, Occurrence::First).into_iter().next()
+    }
+    pub fn chunks_by_type(&self, chunk_type: &str, occurrence: Occurrence) -> Vec<&ChunkRef<'a>> {
+        let matches = self
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == chunk_type.as_bytes())
+            .collect();
+        occurrence.select(matches)
+    }
+    /// Returns the positions of the selected chunks, as [`Png::indices_selected`]
+    pub fn indices_selected(&self, selection: &Selection) -> Result<Vec<usize>, PngMeError> {
+        select(selection, self.chunks().iter().copied())
+    }
+    /// Returns the byte offset in the file where each chunk starts, as [`Png::chunk_offsets`]
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        self.chunks()
+            .iter()
+            .scan(Png::STANDARD_HEADER.len(), |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.total_size();
+                Some(start)
+            })
+            .collect()
+    }
+    /// Copies every chunk into an owned [`Png`]
+    pub fn to_png(&self) -> Png {
+        Png::new(self.chunks.iter().map(ChunkRef::to_chunk).collect())
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = PngMeError;
+
+    /// Parses through [`PngRef`], so each chunk's data is copied once, straight from `value`
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        PngRef::try_from(value).map(|png| png.to_png())
+    }
+}
 
-        let png = Png { header, chunks };
-        dbg!(&png);
+/// Serializes as the list of chunks. Every image starts with the standard signature, so it isn't
+/// stored.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Png {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
 
-        Ok(png)
+        let mut png = serializer.serialize_struct("Png", 1)?;
+        png.serialize_field("chunks", &self.chunks)?;
+        png.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Png {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            chunks: Vec<Chunk>,
+        }
+
+        Ok(Png::new(Fields::deserialize(deserializer)?.chunks))
     }
 }
 
 impl Display for Png {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", std::str::from_utf8(self.header()).unwrap())
+        for chunk in self.chunks() {
+            write!(f, "{}", chunk)?;
+        }
+        Ok(())
     }
 }
 
@@ -54,57 +220,538 @@ impl Png {
 
     pub fn new(chunks: Vec<Chunk>) -> Png {
         Png {
-            header: Self::STANDARD_HEADER,
+            signature: Self::STANDARD_HEADER,
             chunks,
         }
     }
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    /// Reads a whole image from `reader`, such as stdin
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Png, PngMeError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Png::try_from(bytes.as_slice())
+    }
+    /// Parses like `try_from` but accepts chunks whose CRC doesn't match, keeping the stored
+    /// value. Signature and length problems are still errors, unlike with
+    /// [`Png::try_from_lenient`], which works around them.
+    pub fn parse_ignoring_crcs(value: &[u8]) -> Result<Png, PngMeError> {
+        PngRef::parse_ignoring_crcs(value).map(|png| png.to_png())
+    }
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png::new(chunks)
     }
-    fn append_chunk(&mut self, chunk: Chunk) {
+    pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk)
     }
-    fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, &'static str> {
-        let mut del_index = None;
-        for (i, chunk) in self.chunks().iter().enumerate() {
-            if chunk.chunk_type().bytes() == chunk_type.as_bytes() {
-                del_index = Some(i);
+    /// Starts staging edits that are only applied if the result follows the ordering rules
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+    pub fn insert_chunk_at(&mut self, index: usize, chunk: Chunk) -> Result<(), PngMeError> {
+        if index > self.chunks.len() {
+            return Err(PngMeError::IndexOutOfRange);
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+    /// Inserts `chunk` just before IEND, or at the end when the image has no IEND
+    pub fn insert_before_iend(&mut self, chunk: Chunk) -> Result<(), PngMeError> {
+        let index = match self.indices_of_type("IEND", Occurrence::Last).first() {
+            Some(iend) => *iend,
+            None => self.chunks.len(),
+        };
+        self.insert_chunk_at(index, chunk)
+    }
+    /// Inserts `chunk` where the spec expects its type, as late as the rules allow: types that
+    /// must come before the image data go just before PLTE or the first IDAT, IDAT goes after the
+    /// last IDAT, and everything else just before IEND. Returns the index of the new chunk.
+    pub fn insert_chunk_ordered(&mut self, chunk: Chunk) -> usize {
+        let find = |chunk_type, occurrence| {
+            self.indices_of_type(chunk_type, occurrence).first().copied()
+        };
+        let first = |chunk_type| find(chunk_type, Occurrence::First);
+        let last = |chunk_type| find(chunk_type, Occurrence::Last);
+        let end = last("IEND").unwrap_or(self.chunks.len());
+        let index = match Placement::of(chunk.chunk_type()) {
+            Placement::First => 0,
+            Placement::BeforePlte => first("PLTE").or(first("IDAT")).unwrap_or(end),
+            Placement::Plte | Placement::AfterPlteBeforeIdat | Placement::BeforeIdat => {
+                first("IDAT").unwrap_or(end)
+            }
+            Placement::Idat => last("IDAT").map_or(end, |idat| idat + 1),
+            Placement::Last => self.chunks.len(),
+            Placement::Anywhere => end,
+        };
+
+        self.chunks.insert(index, chunk);
+        index
+    }
+    /// Inserts `chunk` at `position` rather than where its type usually goes, refusing a position
+    /// that breaks the ordering rules. Returns the index of the new chunk.
+    pub fn insert_chunk_at_position(
+        &mut self,
+        chunk: Chunk,
+        position: InsertPosition,
+    ) -> Result<usize, PngMeError> {
+        let idat = |occurrence| {
+            self.indices_of_type("IDAT", occurrence)
+                .first()
+                .copied()
+                .ok_or_else(|| PngMeError::ChunkNotFound(String::from("IDAT")))
+        };
+        let index = match position {
+            InsertPosition::BeforeIdat => idat(Occurrence::First)?,
+            InsertPosition::AfterIdat => idat(Occurrence::Last)? + 1,
+            InsertPosition::End => match self.indices_of_type("IEND", Occurrence::Last).first() {
+                Some(iend) => *iend,
+                None => self.chunks.len(),
+            },
+        };
+
+        let mut chunks = self.chunks.clone();
+        chunks.insert(index, chunk);
+        if ordering::violations(chunks.iter().map(ChunkRef::from)).len() > self.violations().len() {
+            return Err(PngMeError::Invalid(
+                "position would break chunk ordering rules",
+            ));
+        }
+        self.chunks = chunks;
+        Ok(index)
+    }
+    /// Splits `payload` into pieces of at most `piece_len` bytes, each stored in a chunk of
+    /// `chunk_type` with a header for reassembly and placed as by [`Png::insert_chunk_ordered`].
+    /// Returns the number of chunks written.
+    pub fn insert_payload(
+        &mut self,
+        chunk_type: &ChunkType,
+        payload: &[u8],
+        piece_len: usize,
+    ) -> Result<usize, PngMeError> {
+        if piece_len == 0 {
+            return Err(PngMeError::Invalid(
+                "piece length must be at least one byte",
+            ));
+        }
+        let digest = Sha256::digest(payload);
+        let pieces: Vec<&[u8]> = match payload.is_empty() {
+            true => vec![payload],
+            false => payload.chunks(piece_len).collect(),
+        };
+        let total = u32::try_from(pieces.len())
+            .map_err(|_| PngMeError::Invalid("payload needs too many chunks"))?;
+
+        for (sequence, piece) in pieces.iter().enumerate() {
+            let mut data = PIECE_MAGIC.to_vec();
+            data.extend_from_slice(&(sequence as u32).to_be_bytes());
+            data.extend_from_slice(&total.to_be_bytes());
+            data.extend_from_slice(&digest);
+            data.extend_from_slice(piece);
+            self.insert_chunk_ordered(Chunk::new(chunk_type.clone(), data));
+        }
+        Ok(pieces.len())
+    }
+    /// Reassembles the payload stored by [`Png::insert_payload`] in chunks of `chunk_type`,
+    /// whatever order they ended up in, and checks it against its hash. When the first chunk of
+    /// the type isn't a piece its data is returned as is, so single-chunk messages read the same.
+    pub fn extract_payload(&self, chunk_type: &str) -> Result<Vec<u8>, PngMeError> {
+        let chunks = self.chunks_by_type(chunk_type, Occurrence::All);
+        let first = chunks
+            .first()
+            .ok_or_else(|| PngMeError::ChunkNotFound(String::from(chunk_type)))?;
+        let Some((_, total, digest)) = piece_header(first.data()) else {
+            return Ok(first.data().to_vec());
+        };
+        if total as usize > chunks.len() {
+            return Err(PngMeError::Malformed(
+                "some pieces of the payload are missing",
+            ));
+        }
+
+        // Pieces of another payload stored under the same type are skipped
+        let mut pieces: Vec<Option<&[u8]>> = vec![None; total as usize];
+        for chunk in &chunks {
+            match piece_header(chunk.data()) {
+                Some((sequence, piece_total, piece_digest))
+                    if piece_total == total && piece_digest == digest =>
+                {
+                    if let Some(slot @ None) = pieces.get_mut(sequence as usize) {
+                        *slot = Some(&chunk.data()[PIECE_HEADER_LEN..]);
+                    }
+                }
+                _ => (),
+            }
+        }
+        let payload = pieces
+            .into_iter()
+            .collect::<Option<Vec<&[u8]>>>()
+            .ok_or(PngMeError::Malformed(
+                "some pieces of the payload are missing",
+            ))?
+            .concat();
+
+        match Sha256::digest(&payload)[..] == digest {
+            true => Ok(payload),
+            false => Err(PngMeError::Malformed(
+                "reassembled payload doesn't match its hash",
+            )),
+        }
+    }
+    /// Removes the first chunk of the given type
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngMeError> {
+        let mut removed = self.remove_chunks(chunk_type, Occurrence::First)?;
+        Ok(removed.remove(0))
+    }
+    /// Removes every chunk of the given type, returning them in file order
+    pub fn remove_all_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>, PngMeError> {
+        self.remove_chunks(chunk_type, Occurrence::All)
+    }
+    /// Removes the selected chunks of the given type, returning them in file order
+    pub fn remove_chunks(
+        &mut self,
+        chunk_type: &str,
+        occurrence: Occurrence,
+    ) -> Result<Vec<Chunk>, PngMeError> {
+        let indices = self.indices_of_type(chunk_type, occurrence);
+        if indices.is_empty() {
+            return Err(PngMeError::ChunkNotFound(String::from(chunk_type)));
+        }
+
+        Ok(self.remove_indices(&indices))
+    }
+    /// Removes the chunks at the given address, returning them in file order
+    pub fn remove_at(&mut self, address: &ChunkAddress) -> Result<Vec<Chunk>, PngMeError> {
+        let indices = self.indices_at(address)?;
+        Ok(self.remove_indices(&indices))
+    }
+    /// Removes the selected chunks, returning them in file order
+    pub fn remove_selected(&mut self, selection: &Selection) -> Result<Vec<Chunk>, PngMeError> {
+        let indices = self.indices_selected(selection)?;
+        Ok(self.remove_indices(&indices))
+    }
+    /// Removes every chunk matching `filter`, returning them in file order
+    pub fn remove_where(&mut self, filter: &Filter) -> Vec<Chunk> {
+        let indices = self.indices_where(filter);
+        self.remove_indices(&indices)
+    }
+    fn remove_indices(&mut self, indices: &[usize]) -> Vec<Chunk> {
+        let mut removed: Vec<Chunk> = indices
+            .iter()
+            .rev()
+            .map(|index| self.chunks.remove(*index))
+            .collect();
+        removed.reverse();
+
+        removed
+    }
+    /// Renames every chunk of type `from` to `to`, recomputing their CRCs. Returns the positions of
+    /// the renamed chunks.
+    pub fn retag(&mut self, from: &str, to: &ChunkType) -> Result<Vec<usize>, PngMeError> {
+        let indices = self.indices_of_type(from, Occurrence::All);
+        if indices.is_empty() {
+            return Err(PngMeError::ChunkNotFound(String::from(from)));
+        }
+
+        for index in indices.iter() {
+            let data = self.chunks[*index].data().to_vec();
+            self.chunks[*index] = Chunk::new(to.clone(), data);
+        }
+
+        Ok(indices)
+    }
+    /// Moves the chunk at `index` next to another chunk. The move is refused if it would add
+    /// ordering violations, unless `force` is set. Returns the chunk's new position.
+    pub fn move_chunk(
+        &mut self,
+        index: usize,
+        target: MoveTarget,
+        force: bool,
+    ) -> Result<usize, PngMeError> {
+        let (MoveTarget::Before(anchor) | MoveTarget::After(anchor)) = target;
+        if index >= self.chunks.len() || anchor >= self.chunks.len() {
+            return Err(PngMeError::IndexOutOfRange);
+        }
+        if index == anchor {
+            return Err(PngMeError::Invalid(
+                "cannot move a chunk relative to itself",
+            ));
+        }
+
+        let mut chunks = self.chunks.clone();
+        let chunk = chunks.remove(index);
+        let anchor = if anchor > index { anchor - 1 } else { anchor };
+        let destination = match target {
+            MoveTarget::Before(_) => anchor,
+            MoveTarget::After(_) => anchor + 1,
+        };
+        chunks.insert(destination, chunk);
+
+        if !force
+            && ordering::violations(chunks.iter().map(ChunkRef::from)).len()
+                > self.violations().len()
+        {
+            return Err(PngMeError::Invalid("move would break chunk ordering rules"));
+        }
+
+        self.chunks = chunks;
+        Ok(destination)
+    }
+    /// Sorts ancillary chunks into the positions the spec expects. Returns how many chunks moved.
+    pub fn reorder_spec(&mut self) -> usize {
+        let ordered = ordering::spec_order(self.chunks.clone());
+        let moved = ordered
+            .iter()
+            .zip(self.chunks())
+            .filter(|(new, old)| new.as_bytes() != old.as_bytes())
+            .count();
+
+        self.chunks = ordered;
+        moved
+    }
+    /// Removes byte-identical copies of ancillary chunks, keeping the first of each
+    pub fn dedup_ancillary(&mut self) -> DedupReport {
+        let mut seen = HashSet::new();
+        let mut report = DedupReport::default();
+        let mut kept = Vec::with_capacity(self.chunks.len());
+
+        for (index, chunk) in self.chunks.drain(..).enumerate() {
+            if chunk.chunk_type().is_critical() || seen.insert(chunk.as_bytes()) {
+                kept.push(chunk);
+                continue;
             }
+            report.bytes_saved += chunk.total_size();
+            report.removed.push((index, chunk.chunk_type().clone()));
         }
-        
-        match del_index {
-            Some(index) => Ok(self.chunks.remove(index)),
-            None => Err("Could not find chunk in data")
+
+        self.chunks = kept;
+        report
+    }
+    /// Removes every chunk except the image chunks and the types in `keep`, so nothing can be
+    /// hidden in what's left. With `only`, just the chunks of those types are removed instead.
+    /// Image chunks and APNG animation chunks stay either way.
+    pub fn scrub(&mut self, keep: &[ChunkType], only: Option<&[ChunkType]>) -> ScrubReport {
+        self.scrub_matching(keep, only, None)
+    }
+    /// Like [`Png::scrub`], only removing the chunks that also match `filter`
+    pub fn scrub_where(
+        &mut self,
+        keep: &[ChunkType],
+        only: Option<&[ChunkType]>,
+        filter: &Filter,
+    ) -> ScrubReport {
+        self.scrub_matching(keep, only, Some(filter))
+    }
+    fn scrub_matching(
+        &mut self,
+        keep: &[ChunkType],
+        only: Option<&[ChunkType]>,
+        filter: Option<&Filter>,
+    ) -> ScrubReport {
+        let mut report = ScrubReport::default();
+        let mut kept = Vec::with_capacity(self.chunks.len());
+        let offsets = self.chunk_offsets();
+
+        for (index, chunk) in self.chunks.drain(..).enumerate() {
+            let chunk_type = chunk.chunk_type();
+            let is_image = IMAGE_CHUNK_TYPES.contains(&chunk_type.to_string().as_str())
+                || ANIMATION_CHUNK_TYPES.contains(chunk_type);
+            let selected = match only {
+                Some(only) => only.contains(chunk_type),
+                None => !keep.contains(chunk_type),
+            } && filter.is_none_or(|filter| filter.matches(index, offsets[index], &chunk));
+            if is_image || !selected {
+                kept.push(chunk);
+                continue;
+            }
+            report.bytes_saved += chunk.total_size();
+            report.removed.push((index, chunk));
         }
 
+        self.chunks = kept;
+        report
+    }
+    /// Recomputes the CRC of every chunk from its type and data, returning the chunks that had a
+    /// wrong one
+    pub fn repair(&mut self) -> Vec<CrcRepair> {
+        let mut repairs = Vec::new();
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            let stored = chunk.crc();
+            if chunk.recompute_crc() {
+                repairs.push(CrcRepair {
+                    index,
+                    chunk_type: chunk.chunk_type().clone(),
+                    stored,
+                    computed: chunk.crc(),
+                });
+            }
+        }
+        repairs
     }
-    fn header(&self) -> &[u8; 8] {
-        &self.header
+    /// Returns every chunk that sits somewhere the PNG spec doesn't allow
+    pub fn violations(&self) -> Vec<Violation> {
+        ordering::violations(self.chunks().iter().map(ChunkRef::from))
     }
-    fn chunks(&self) -> &[Chunk] {
+    fn signature(&self) -> &[u8; 8] {
+        &self.signature
+    }
+    /// Parses the IHDR chunk, which the spec requires to come first
+    pub fn header(&self) -> Result<Ihdr, PngMeError> {
+        let ihdr = self
+            .chunk_by_type("IHDR")
+            .ok_or_else(|| PngMeError::ChunkNotFound(String::from("IHDR")))?;
+        Ihdr::try_from(ihdr)
+    }
+    pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
-    fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         for chunk in self.chunks().iter() {
             if chunk.chunk_type().bytes() == chunk_type.as_bytes() {
                 return Some(chunk)
             }
         }
 
-        return None;
+        None
     }
-    fn as_bytes(&self) -> Vec<u8> {
-        let flattened: Vec<u8> = self
+    /// Returns the positions, among all chunks, of the selected chunks of the given type
+    pub fn indices_of_type(&self, chunk_type: &str, occurrence: Occurrence) -> Vec<usize> {
+        let matches = self
             .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().bytes() == chunk_type.as_bytes())
+            .map(|(index, _)| index)
+            .collect();
+
+        occurrence.select(matches)
+    }
+    pub fn chunks_by_type(&self, chunk_type: &str, occurrence: Occurrence) -> Vec<&Chunk> {
+        self.indices_of_type(chunk_type, occurrence)
             .into_iter()
+            .map(|index| &self.chunks[index])
+            .collect()
+    }
+    /// Returns the positions, among all chunks, of the chunks at the given address
+    pub fn indices_at(&self, address: &ChunkAddress) -> Result<Vec<usize>, PngMeError> {
+        self.indices_selected(&Selection::Address(address.clone()))
+    }
+    /// Returns the positions, among all chunks, of the selected chunks. A type or filter with no
+    /// chunks is [`PngMeError::ChunkNotFound`], and an occurrence or address past the last chunk is
+    /// [`PngMeError::IndexOutOfRange`].
+    pub fn indices_selected(&self, selection: &Selection) -> Result<Vec<usize>, PngMeError> {
+        select(selection, self.chunks().iter().map(ChunkRef::from))
+    }
+    pub fn chunks_at(&self, address: &ChunkAddress) -> Result<Vec<&Chunk>, PngMeError> {
+        Ok(self
+            .indices_at(address)?
+            .into_iter()
+            .map(|index| &self.chunks[index])
+            .collect())
+    }
+    /// Returns the positions of the chunks matching a `--where` filter
+    pub fn indices_where(&self, filter: &Filter) -> Vec<usize> {
+        self.chunk_offsets()
+            .into_iter()
+            .zip(self.chunks())
+            .enumerate()
+            .filter(|(index, (offset, chunk))| filter.matches(*index, *offset, *chunk))
+            .map(|(index, _)| index)
+            .collect()
+    }
+    pub fn chunks_where(&self, filter: &Filter) -> Vec<&Chunk> {
+        self.indices_where(filter)
+            .into_iter()
+            .map(|index| &self.chunks[index])
+            .collect()
+    }
+    /// Returns the byte offset of each chunk from the start of the file, signature included
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        self.chunks()
+            .iter()
+            .scan(self.signature().len(), |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.total_size();
+                Some(start)
+            })
+            .collect()
+    }
+    /// Writes the image to `out`, such as stdout. Unlike [`Png::write_file`] a failed write can
+    /// leave partial output behind.
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(&self.as_bytes())?;
+        out.flush()
+    }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let flattened: Vec<u8> = self
+            .chunks()
+            .iter()
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        [self.header().to_vec(), flattened].concat()
+        [self.signature().to_vec(), flattened].concat()
     }
 }
 
+/// Resolves `selection` against every chunk of a file, in file order
+fn select<'a>(
+    selection: &Selection,
+    chunks: impl Iterator<Item = ChunkRef<'a>>,
+) -> Result<Vec<usize>, PngMeError> {
+    let chunks: Vec<ChunkRef> = chunks.collect();
+    match selection {
+        Selection::Type(chunk_type, occurrence) => {
+            let matches: Vec<usize> = (0..chunks.len())
+                .filter(|index| chunks[*index].chunk_type().bytes() == chunk_type.as_bytes())
+                .collect();
+            if matches.is_empty() {
+                return Err(PngMeError::ChunkNotFound(chunk_type.clone()));
+            }
+            match occurrence.select(matches) {
+                selected if selected.is_empty() => Err(PngMeError::IndexOutOfRange),
+                selected => Ok(selected),
+            }
+        }
+        Selection::Address(ChunkAddress::Index(index)) if *index < chunks.len() => {
+            Ok(vec![*index])
+        }
+        Selection::Address(ChunkAddress::Range(range)) if range.end <= chunks.len() => {
+            Ok(range.clone().collect())
+        }
+        Selection::Address(ChunkAddress::Offset(offset)) => {
+            let mut start = Png::STANDARD_HEADER.len();
+            for (index, chunk) in chunks.iter().enumerate() {
+                if (start..start + chunk.total_size()).contains(offset) {
+                    return Ok(vec![index]);
+                }
+                start += chunk.total_size();
+            }
+            Err(PngMeError::Invalid("no chunk found at byte offset"))
+        }
+        Selection::Address(_) => Err(PngMeError::IndexOutOfRange),
+        Selection::Where(filter) => {
+            let mut offset = Png::STANDARD_HEADER.len();
+            let mut matches = Vec::new();
+            for (index, chunk) in chunks.iter().enumerate() {
+                if filter.matches(index, offset, *chunk) {
+                    matches.push(index);
+                }
+                offset += chunk.total_size();
+            }
+            match matches.is_empty() {
+                true => Err(PngMeError::ChunkNotFound(String::from("matching"))),
+                false => Ok(matches),
+            }
+        }
+    }
+}
+
+/// Reads the sequence number, piece count and payload hash at the start of a piece
+fn piece_header(data: &[u8]) -> Option<(u32, u32, [u8; 32])> {
+    let header = data.get(..PIECE_HEADER_LEN)?.strip_prefix(PIECE_MAGIC)?;
+    let sequence = u32::from_be_bytes(header[0..4].try_into().ok()?);
+    let total = u32::from_be_bytes(header[4..8].try_into().ok()?);
+    Some((sequence, total, header[8..].try_into().ok()?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,13 +761,11 @@ mod tests {
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -128,7 +773,7 @@ mod tests {
         Png::from_chunks(chunks)
     }
 
-    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, &'static str> {
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, PngMeError> {
         let chunk_type = ChunkType::from_str(chunk_type)?;
         let data: Vec<u8> = data.bytes().collect();
 
@@ -228,22 +873,457 @@ mod tests {
         fn test_remove_chunk() {
             let mut png = testing_png();
             png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
-            png.remove_chunk("TeSt").unwrap();
+            png.remove_first_chunk("TeSt").unwrap();
             let chunk = png.chunk_by_type("TeSt");
             assert!(chunk.is_none());
         }
 
+        #[test]
+        fn test_remove_all_chunks() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+            png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+            let removed = png.remove_all_chunks("TeSt").unwrap();
+            assert_eq!(removed.len(), 2);
+            assert_eq!(png.chunks().len(), 3);
+            assert!(png.remove_all_chunks("TeSt").is_err());
+        }
+
+        #[test]
+        fn test_remove_chunk_removes_first_duplicate() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+            png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+            let removed = png.remove_first_chunk("TeSt").unwrap();
+            assert_eq!(&removed.data_as_string().unwrap(), "first");
+            assert_eq!(&png.chunk_by_type("TeSt").unwrap().data_as_string().unwrap(), "second");
+        }
+
+        #[test]
+        fn test_chunks_by_type_occurrence() {
+            let mut png = testing_png();
+            for message in ["zero", "one", "two"] {
+                png.append_chunk(chunk_from_strings("TeSt", message).unwrap());
+            }
+
+            let data = |occurrence| -> Vec<String> {
+                png.chunks_by_type("TeSt", occurrence)
+                    .iter()
+                    .map(|chunk| chunk.data_as_string().unwrap())
+                    .collect()
+            };
+            assert_eq!(data(Occurrence::First), vec!["zero"]);
+            assert_eq!(data(Occurrence::Last), vec!["two"]);
+            assert_eq!(data(Occurrence::Index(1)), vec!["one"]);
+            assert_eq!(data(Occurrence::All), vec!["zero", "one", "two"]);
+            assert_eq!(png.indices_of_type("TeSt", Occurrence::All), vec![3, 4, 5]);
+        }
+
+        #[test]
+        fn test_remove_all_chunks_of_type() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+            png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+            let removed = png.remove_chunks("TeSt", Occurrence::All).unwrap();
+            assert_eq!(removed.len(), 2);
+            assert_eq!(&removed[1].data_as_string().unwrap(), "second");
+            assert_eq!(png.chunks().len(), 3);
+            assert!(png.remove_chunks("TeSt", Occurrence::All).is_err());
+        }
+
+        #[test]
+        fn test_chunk_offsets() {
+            let png = testing_png();
+            let offsets = png.chunk_offsets();
+            assert_eq!(offsets[0], 8);
+            assert_eq!(offsets[1], 8 + png.chunks()[0].total_size());
+            assert_eq!(offsets.len(), 3);
+        }
+
+        #[test]
+        fn test_split_payload() {
+            let mut png = testing_png();
+            let chunk_type = ChunkType::from_str("ruSt").unwrap();
+            let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+            assert_eq!(png.insert_payload(&chunk_type, &payload, 300).unwrap(), 4);
+            assert_eq!(png.chunks().len(), 7);
+            assert_eq!(png.chunks()[3].length() as usize, PIECE_HEADER_LEN + 300);
+            assert_eq!(png.extract_payload("ruSt").unwrap(), payload);
+
+            // Pieces can come back in any order
+            let last = png.remove_chunks("ruSt", Occurrence::Last).unwrap().remove(0);
+            png.insert_chunk_at(0, last).unwrap();
+            assert_eq!(png.extract_payload("ruSt").unwrap(), payload);
+
+            png.remove_chunks("ruSt", Occurrence::Index(1)).unwrap();
+            let missing = png.extract_payload("ruSt").unwrap_err();
+            assert!(matches!(missing, PngMeError::Malformed(_)));
+            assert_eq!(missing.exit_code(), 65);
+            assert!(png.insert_payload(&chunk_type, &payload, 0).is_err());
+        }
+
+        #[test]
+        fn test_extract_unsplit_payload() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("TeSt", "single chunk").unwrap());
+            assert_eq!(png.extract_payload("TeSt").unwrap(), b"single chunk");
+            assert!(png.extract_payload("ruSt").is_err());
+
+            let chunk_type = ChunkType::from_str("ruSt").unwrap();
+            assert_eq!(png.insert_payload(&chunk_type, b"", 10).unwrap(), 1);
+            assert_eq!(png.extract_payload("ruSt").unwrap(), b"");
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn test_png_serde() {
+            let json = serde_json::to_string(&testing_png()).unwrap();
+            assert!(json.starts_with(r#"{"chunks":[{"chunk_type":"FrSt","data":"#));
+            let png: Png = serde_json::from_str(&json).unwrap();
+            assert_eq!(png.as_bytes(), testing_png().as_bytes());
+        }
+
+        #[test]
+        fn test_header() {
+            assert!(matches!(
+                testing_png().header(),
+                Err(PngMeError::ChunkNotFound(_))
+            ));
+
+            let ihdr = Ihdr::new(3, 2, 16, crate::ihdr::ColorType::Grayscale);
+            let mut png = testing_png();
+            png.insert_chunk_at(0, ihdr.to_chunk()).unwrap();
+            assert_eq!(png.header().unwrap(), ihdr);
+        }
+
+        #[test]
+        fn test_reader_writer_round_trip() {
+            let mut written = Vec::new();
+            testing_png().write_to(&mut written).unwrap();
+            assert_eq!(written, testing_png().as_bytes());
+
+            let png = Png::from_reader(written.as_slice()).unwrap();
+            assert_eq!(png.as_bytes(), written);
+            assert!(Png::from_reader(&written[1..]).is_err());
+        }
+
+        #[test]
+        fn test_repair() {
+            let mut bytes = testing_png().as_bytes();
+            let second = testing_png().chunk_offsets()[1];
+            let crc_at = second + testing_png().chunks()[1].total_size() - 1;
+            bytes[crc_at] ^= 1;
+            assert!(Png::try_from(bytes.as_ref()).is_err());
+
+            let mut png = Png::parse_ignoring_crcs(bytes.as_ref()).unwrap();
+            assert_eq!(png.as_bytes(), bytes);
+            let repairs = png.repair();
+            assert_eq!(repairs.len(), 1);
+            assert_eq!(repairs[0].index, 1);
+            assert_eq!(repairs[0].chunk_type.to_string(), "miDl");
+            assert_eq!(repairs[0].stored ^ repairs[0].computed, 1);
+            assert_eq!(png.as_bytes(), testing_png().as_bytes());
+            assert!(png.repair().is_empty());
+
+            assert!(Png::parse_ignoring_crcs(&bytes[1..]).is_err());
+        }
+
+        #[test]
+        fn test_chunks_at_address() {
+            let png = testing_png();
+            let second = png.chunk_offsets()[1];
+
+            let types = |address| -> Vec<String> {
+                png.chunks_at(&address)
+                    .unwrap()
+                    .iter()
+                    .map(|chunk| chunk.chunk_type().to_string())
+                    .collect()
+            };
+            assert_eq!(types(ChunkAddress::Index(2)), vec!["LASt"]);
+            assert_eq!(types(ChunkAddress::Range(0..2)), vec!["FrSt", "miDl"]);
+            assert_eq!(types(ChunkAddress::Offset(second)), vec!["miDl"]);
+            assert_eq!(types(ChunkAddress::Offset(second - 1)), vec!["FrSt"]);
+            assert!(png.chunks_at(&ChunkAddress::Index(3)).is_err());
+            assert!(png.chunks_at(&ChunkAddress::Range(1..4)).is_err());
+            assert!(png.chunks_at(&ChunkAddress::Offset(4)).is_err());
+        }
+
+        #[test]
+        fn test_remove_at_address() {
+            let mut png = testing_png();
+            let removed = png.remove_at(&ChunkAddress::Range(1..3)).unwrap();
+            assert_eq!(removed.len(), 2);
+            assert_eq!(&removed[0].chunk_type().to_string(), "miDl");
+            assert_eq!(png.chunks().len(), 1);
+        }
+
+        #[test]
+        fn test_indices_selected() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("miDl", "again").unwrap());
+            let by_type = |occurrence| Selection::Type(String::from("miDl"), occurrence);
+
+            assert_eq!(png.indices_selected(&by_type(Occurrence::All)).unwrap(), vec![1, 3]);
+            assert_eq!(png.indices_selected(&by_type(Occurrence::Last)).unwrap(), vec![3]);
+            assert!(matches!(
+                png.indices_selected(&by_type(Occurrence::Index(2))),
+                Err(PngMeError::IndexOutOfRange)
+            ));
+            assert!(matches!(
+                png.indices_selected(&Selection::Type(String::from("noNe"), Occurrence::First)),
+                Err(PngMeError::ChunkNotFound(_))
+            ));
+
+            let bytes = png.as_bytes();
+            let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+            let address = Selection::Address(ChunkAddress::Offset(png.chunk_offsets()[3]));
+            assert_eq!(png_ref.indices_selected(&address).unwrap(), vec![3]);
+            let filter = |expr: &str| Selection::Where(expr.parse().unwrap());
+            assert_eq!(png_ref.indices_selected(&filter("length > 15")).unwrap(), [0, 1, 2]);
+            assert_eq!(png_ref.indices_selected(&filter("offset == 8")).unwrap(), [0]);
+            assert!(matches!(
+                png_ref.indices_selected(&filter("type == \"noNe\"")),
+                Err(PngMeError::ChunkNotFound(_))
+            ));
+            assert_eq!(png.remove_selected(&by_type(Occurrence::All)).unwrap().len(), 2);
+        }
+
+        #[test]
+        fn test_chunks_where() {
+            let mut png = testing_png();
+            let filter: Filter = "critical && index > 0".parse().unwrap();
+            assert_eq!(png.indices_where(&filter), vec![2]);
+            assert_eq!(&png.chunks_where(&filter)[0].chunk_type().to_string(), "LASt");
+
+            let removed = png.remove_where(&"!critical".parse().unwrap());
+            assert_eq!(&removed[0].chunk_type().to_string(), "miDl");
+            assert_eq!(png.chunks().len(), 2);
+        }
+
+        #[test]
+        fn test_retag() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("ruSt", "payload").unwrap());
+            png.append_chunk(chunk_from_strings("ruSt", "more payload").unwrap());
+
+            let to = ChunkType::from_str("prVt").unwrap();
+            let retagged = png.retag("ruSt", &to).unwrap();
+            assert_eq!(retagged, vec![3, 4]);
+            assert!(png.chunk_by_type("ruSt").is_none());
+
+            let chunk = png.chunk_by_type("prVt").unwrap();
+            let expected = Chunk::new(to, b"payload".to_vec());
+            assert_eq!(chunk.crc(), expected.crc());
+            assert!(png.retag("ruSt", &ChunkType::from_str("prVt").unwrap()).is_err());
+        }
+
+        #[test]
+        fn test_move_chunk() {
+            let mut png = testing_png();
+            assert_eq!(png.move_chunk(2, MoveTarget::Before(0), false).unwrap(), 0);
+            assert_eq!(&png.chunks()[0].chunk_type().to_string(), "LASt");
+            assert_eq!(png.move_chunk(0, MoveTarget::After(2), false).unwrap(), 2);
+            assert_eq!(&png.chunks()[2].chunk_type().to_string(), "LASt");
+            assert!(png.move_chunk(0, MoveTarget::After(3), false).is_err());
+        }
+
+        #[test]
+        fn test_move_chunk_checks_ordering() {
+            let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+            let idat = png.indices_of_type("IDAT", Occurrence::First)[0];
+            let last = png.chunks().len() - 1;
+
+            assert!(png.move_chunk(last, MoveTarget::Before(idat), false).is_err());
+            assert!(png.violations().is_empty());
+            assert!(png.move_chunk(last, MoveTarget::Before(idat), true).is_ok());
+            assert_eq!(png.violations().len(), 1);
+        }
+
+        #[test]
+        fn test_reorder_spec() {
+            let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+            let last = png.chunks().len() - 1;
+            png.move_chunk(3, MoveTarget::Before(last), true).unwrap();
+            assert!(!png.violations().is_empty());
+
+            assert!(png.reorder_spec() > 0);
+            assert!(png.violations().is_empty());
+            assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        }
+
+        #[test]
+        fn test_dedup_ancillary() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("teXt", "copy").unwrap());
+            png.append_chunk(chunk_from_strings("teXt", "copy").unwrap());
+            png.append_chunk(chunk_from_strings("teXt", "different").unwrap());
+            png.append_chunk(chunk_from_strings("teXt", "copy").unwrap());
+            png.append_chunk(chunk_from_strings("LASt", "I am the last chunk").unwrap());
+
+            let report = png.dedup_ancillary();
+            let duplicate = chunk_from_strings("teXt", "copy").unwrap();
+            assert_eq!(report.removed.len(), 2);
+            assert_eq!(report.removed[0].0, 4);
+            assert_eq!(report.removed[1].0, 6);
+            assert_eq!(report.bytes_saved, 2 * duplicate.total_size());
+            // critical chunks are never merged, even when identical
+            assert_eq!(png.chunks_by_type("LASt", Occurrence::All).len(), 2);
+            assert_eq!(png.chunks().len(), 6);
+        }
+
+        #[test]
+        fn test_insert_chunk_ordered() {
+            let mut png = Png::new(
+                ["IHDR", "PLTE", "IDAT", "IDAT", "IEND"]
+                    .iter()
+                    .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                    .collect(),
+            );
+            let mut insert = |chunk_type| {
+                png.insert_chunk_ordered(chunk_from_strings(chunk_type, "").unwrap())
+            };
+            assert_eq!(insert("ruSt"), 4);
+            assert_eq!(insert("gAMA"), 1);
+            assert_eq!(insert("tRNS"), 3);
+            assert_eq!(insert("pHYs"), 4);
+            assert_eq!(insert("IDAT"), 7);
+            assert_eq!(insert("tEXt"), 9);
+
+            let types: Vec<String> = png
+                .chunks()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect();
+            assert_eq!(
+                types,
+                [
+                    "IHDR", "gAMA", "PLTE", "tRNS", "pHYs", "IDAT", "IDAT", "IDAT", "ruSt", "tEXt",
+                    "IEND"
+                ]
+            );
+            assert!(png.violations().is_empty());
+        }
+
+        #[test]
+        fn test_insert_chunk_at_position() {
+            let mut png = Png::new(
+                ["IHDR", "IDAT", "IDAT", "IEND"]
+                    .iter()
+                    .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                    .collect(),
+            );
+            let chunk = |chunk_type| chunk_from_strings(chunk_type, "").unwrap();
+            let mut insert = |chunk_type, position| {
+                png.insert_chunk_at_position(chunk(chunk_type), position)
+            };
+            assert_eq!(insert("ruSt", InsertPosition::BeforeIdat).unwrap(), 1);
+            assert_eq!(insert("ruSt", InsertPosition::AfterIdat).unwrap(), 4);
+            assert_eq!(insert("ruSt", InsertPosition::End).unwrap(), 5);
+            assert!(insert("pHYs", InsertPosition::End).is_err());
+            assert_eq!(png.chunks().len(), 7);
+
+            let mut png = testing_png();
+            assert!(matches!(
+                png.insert_chunk_at_position(chunk("ruSt"), InsertPosition::AfterIdat),
+                Err(PngMeError::ChunkNotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_scrub() {
+            let mut png = testing_png();
+            png.append_chunk(chunk_from_strings("tEXt", "Comment").unwrap());
+            png.append_chunk(chunk_from_strings("pHYs", "size").unwrap());
+            png.append_chunk(chunk_from_strings("ruSt", "hidden").unwrap());
+            let with_extras = png.clone();
+            let text = ChunkType::from_str("tEXt").unwrap();
+            let rust = ChunkType::from_str("ruSt").unwrap();
+
+            let report = png.scrub(&[text], None);
+            let removed: Vec<String> = report
+                .removed
+                .iter()
+                .map(|(_, chunk)| chunk.chunk_type().to_string())
+                .collect();
+            assert_eq!(removed, ["FrSt", "miDl", "LASt", "pHYs", "ruSt"]);
+            assert_eq!(report.removed[0].0, 0);
+            assert_eq!(
+                report.bytes_saved,
+                report.removed.iter().map(|(_, chunk)| chunk.total_size()).sum::<usize>()
+            );
+            assert_eq!(png.chunks().len(), 1);
+
+            let mut png = with_extras.clone();
+            let report = png.scrub(&[], Some(&[rust]));
+            assert_eq!(report.removed.len(), 1);
+            assert_eq!(png.chunks().len(), with_extras.chunks().len() - 1);
+
+            let mut png = with_extras.clone();
+            let report = png.scrub_where(&[], None, &"!public && length < 19".parse().unwrap());
+            let removed: Vec<usize> = report.removed.iter().map(|(index, _)| *index).collect();
+            assert_eq!(removed, [1, 5]);
+
+            let mut png = Png::new(
+                IMAGE_CHUNK_TYPES
+                    .iter()
+                    .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                    .collect(),
+            );
+            let image = ChunkType::from_str("IDAT").unwrap();
+            assert!(png.scrub(&[], None).removed.is_empty());
+            assert!(png.scrub(&[], Some(&[image])).removed.is_empty());
+
+            let mut png = crate::apng::tests::testing_apng();
+            assert!(png.scrub(&[], None).removed.is_empty());
+            assert!(png.scrub(&[], Some(&[ChunkType::fdAT])).removed.is_empty());
+        }
+
         #[test]
         fn test_png_from_image_file() {
             let png = Png::try_from(&PNG_FILE[..]);
             assert!(png.is_ok());
         }
 
+        #[test]
+        fn test_png_ref() {
+            let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+            assert_eq!(png.chunks().len(), Png::try_from(&PNG_FILE[..]).unwrap().chunks().len());
+            assert_eq!(png.to_png().as_bytes(), PNG_FILE.to_vec());
+
+            let idat = png.chunk_by_type("IDAT").unwrap().data().as_ptr_range();
+            assert!(
+                PNG_FILE.as_ptr_range().contains(&idat.start),
+                "chunk data must borrow from the file"
+            );
+            assert_eq!(png.chunks_by_type("IEND", Occurrence::All).len(), 1);
+        }
+
+        #[test]
+        fn test_chunk_refs() {
+            let png = Png::try_from(&PNG_FILE[..]).unwrap();
+            let mut chunks = ChunkRefs::new(&PNG_FILE[..]).unwrap();
+            for offset in png.chunk_offsets() {
+                assert_eq!(chunks.offset(), offset);
+                assert!(chunks.next().unwrap().is_ok());
+            }
+            assert!(chunks.next().is_none());
+
+            let mut bytes = PNG_FILE.to_vec();
+            let second = png.chunk_offsets()[1];
+            bytes[second + 8] ^= 1;
+            let results: Vec<_> = ChunkRefs::new(&bytes).unwrap().collect();
+            assert_eq!(results.len(), 2, "iteration must stop at the bad chunk");
+            assert!(results[1].is_err());
+            assert_eq!(ChunkRefs::ignoring_crcs(&bytes).unwrap().count(), png.chunks().len());
+            assert!(ChunkRefs::new(&bytes[1..]).is_err());
+        }
+
         #[test]
         fn test_as_bytes() {
             let png = Png::try_from(&PNG_FILE[..]).unwrap();
             let actual = png.as_bytes();
-            let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+            let expected: Vec<u8> = PNG_FILE.to_vec();
             assert_eq!(actual, expected);
         }
 
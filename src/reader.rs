@@ -0,0 +1,202 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use std::io::{self, Read};
+
+/// Largest data length the PNG spec allows in a single chunk, 2^31 - 1 bytes
+pub const MAX_CHUNK_LENGTH: u32 = i32::MAX as u32;
+
+/// Parses the chunks of a PNG one at a time from any reader, so only the chunk being read has
+/// to fit in memory. The signature is checked before the first chunk.
+///
+/// Iteration stops after IEND, at the end of the input, or after the first error.
+pub struct ChunkReader<R: Read> {
+    inner: R,
+    max_chunk_length: u32,
+    offset: u64,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(inner: R) -> ChunkReader<R> {
+        ChunkReader {
+            inner,
+            max_chunk_length: MAX_CHUNK_LENGTH,
+            offset: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Refuses chunks declaring more than `max` bytes of data instead of allocating for them
+    pub fn with_max_chunk_length(mut self, max: u32) -> ChunkReader<R> {
+        self.max_chunk_length = max;
+        self
+    }
+
+    /// Byte offset in the input where the next chunk starts
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Reads the next chunk, or returns `None` once IEND or the end of the input is reached
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>, PngMeError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let chunk = self.next_chunk();
+        match &chunk {
+            Ok(Some(chunk)) => self.finished = chunk.chunk_type().to_string() == "IEND",
+            _ => self.finished = true,
+        }
+        chunk
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Chunk>, PngMeError> {
+        if !self.started {
+            let mut header = [0u8; 8];
+            if self.fill(&mut header)? != header.len() || header != Png::STANDARD_HEADER {
+                return Err(PngMeError::InvalidSignature);
+            }
+            self.started = true;
+        }
+
+        let mut prefix = [0u8; 8];
+        match self.fill(&mut prefix)? {
+            0 => return Ok(None),
+            8 => (),
+            _ => return Err(PngMeError::TruncatedChunk),
+        }
+        let (length, chunk_type) = prefix.split_at(4);
+        let length = u32::from_be_bytes(length.try_into().unwrap());
+        if length > self.max_chunk_length {
+            return Err(PngMeError::ChunkTooLong {
+                length,
+                max: self.max_chunk_length,
+            });
+        }
+        let chunk_type: [u8; 4] = chunk_type.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(chunk_type)?;
+
+        // Grows with the data actually read, so a bogus length can't force a huge allocation
+        let mut data = Vec::new();
+        (&mut self.inner)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        self.offset += data.len() as u64;
+        if data.len() != length as usize {
+            return Err(PngMeError::TruncatedChunk);
+        }
+
+        let mut crc = [0u8; 4];
+        if self.fill(&mut crc)? != crc.len() {
+            return Err(PngMeError::TruncatedChunk);
+        }
+        let crc = u32::from_be_bytes(crc);
+
+        let chunk = Chunk::new(chunk_type, data);
+        match crc == chunk.crc() {
+            true => Ok(Some(chunk)),
+            false => Err(PngMeError::CrcMismatch {
+                expected: chunk.crc(),
+                actual: crc,
+            }),
+        }
+    }
+
+    /// Reads until `buf` is full or the input ends, returning how many bytes were read
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        self.offset += filled as u64;
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, PngMeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::new(
+            ["IHDR", "ruSt", "IEND"]
+                .iter()
+                .map(|chunk_type| {
+                    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), b"data".to_vec())
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_reads_every_chunk() {
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice());
+        assert_eq!(reader.offset(), 0);
+        let first = reader.read_chunk().unwrap().unwrap();
+        assert_eq!(first.chunk_type().to_string(), "IHDR");
+        assert_eq!(reader.offset(), 8 + first.total_size() as u64);
+
+        let rest: Vec<String> = reader
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(rest, ["ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_stops_after_iend() {
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend_from_slice(b"trailing garbage");
+        assert_eq!(ChunkReader::new(bytes.as_slice()).count(), 3);
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes = testing_png().as_bytes();
+        assert!(matches!(
+            ChunkReader::new(&bytes[1..]).next(),
+            Some(Err(PngMeError::InvalidSignature))
+        ));
+
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 2]);
+        assert!(matches!(
+            reader.nth(2),
+            Some(Err(PngMeError::TruncatedChunk))
+        ));
+        assert!(reader.next().is_none());
+
+        let mut reader = ChunkReader::new(bytes.as_slice()).with_max_chunk_length(3);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PngMeError::ChunkTooLong { length: 4, max: 3 }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let chunks: Vec<_> = ChunkReader::new(bytes.as_slice()).collect();
+        assert!(matches!(chunks[2], Err(PngMeError::CrcMismatch { .. })));
+    }
+}
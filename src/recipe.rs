@@ -0,0 +1,263 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::selection::{ChunkAddress, Occurrence};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, str::FromStr};
+
+/// A single step of a recipe. In TOML each step is an `[[operations]]` table whose `op` key names
+/// the operation, e.g. `op = "strip-types"`.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Operation {
+    /// Removes every chunk of the listed types
+    StripTypes { types: Vec<String> },
+    /// Writes a `tEXt` chunk, replacing any existing one with the same keyword
+    SetText { keyword: String, text: String },
+    /// Adds a chunk carrying `message`, placed where the spec expects its type
+    EncodePayload { chunk_type: String, message: String },
+    /// Recomputes the CRC of every chunk
+    FixCrcs,
+    /// Sorts ancillary chunks into the positions the spec expects
+    Reorder,
+}
+
+/// An ordered list of edits applied together to each file
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Recipe {
+    pub operations: Vec<Operation>,
+}
+
+impl Recipe {
+    /// Reads a recipe, as TOML when the file ends in `.toml` and as JSON otherwise
+    pub fn from_file(path: &Path) -> crate::MyResult<Recipe> {
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(toml::from_str(&contents)?),
+            _ => Ok(serde_json::from_str(&contents)?),
+        }
+    }
+
+    /// Whether the recipe recomputes CRCs, so files with damaged ones can still be read for it
+    pub fn fixes_crcs(&self) -> bool {
+        self.operations.contains(&Operation::FixCrcs)
+    }
+
+    /// Runs every operation in a single transaction. If any operation fails, or the result breaks
+    /// the chunk ordering rules, `png` is left untouched.
+    pub fn apply(&self, png: &mut Png) -> Result<(), PngMeError> {
+        let mut transaction = png.transaction();
+        for operation in self.operations.iter() {
+            apply_operation(&mut transaction, operation)?;
+        }
+
+        transaction.commit().map_err(|_| {
+            PngMeError::Invalid("recipe would leave chunks in an order the PNG spec doesn't allow")
+        })
+    }
+}
+
+fn apply_operation(png: &mut Png, operation: &Operation) -> Result<(), PngMeError> {
+    match operation {
+        Operation::StripTypes { types } => {
+            for chunk_type in types.iter() {
+                ChunkType::from_str(chunk_type)?;
+                // a type that isn't present is nothing to strip
+                let _ = png.remove_chunks(chunk_type, Occurrence::All);
+            }
+        }
+        Operation::SetText { keyword, text } => {
+            if keyword.is_empty() || keyword.len() > 79 || keyword.contains('\0') {
+                return Err(PngMeError::Invalid(
+                    "tEXt keywords must be 1-79 bytes without NUL",
+                ));
+            }
+            let data = [keyword.as_bytes(), &[0], text.as_bytes()].concat();
+            let chunk = Chunk::new(ChunkType::from_str("tEXt")?, data);
+
+            let existing = png
+                .indices_of_type("tEXt", Occurrence::All)
+                .into_iter()
+                .find(|index| {
+                    let data = png.chunks()[*index].data();
+                    data.split(|byte| *byte == 0).next() == Some(keyword.as_bytes())
+                });
+            match existing {
+                Some(index) => {
+                    png.remove_at(&ChunkAddress::Index(index))?;
+                    png.insert_chunk_at(index, chunk)?;
+                }
+                None => png.insert_before_iend(chunk)?,
+            }
+        }
+        Operation::EncodePayload {
+            chunk_type,
+            message,
+        } => {
+            let chunk = Chunk::new(
+                ChunkType::from_str(chunk_type)?,
+                message.as_bytes().to_vec(),
+            );
+            png.insert_chunk_ordered(chunk);
+        }
+        Operation::FixCrcs => {
+            let chunks: Vec<Chunk> = png
+                .chunks()
+                .iter()
+                .map(|chunk| Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()))
+                .collect();
+            *png = Png::new(chunks);
+        }
+        Operation::Reorder => {
+            png.reorder_spec();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Ihdr};
+
+    fn testing_png() -> Png {
+        let chunk = |chunk_type: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+        };
+        Png::new(vec![
+            Ihdr::new(1, 1, 8, ColorType::Rgb).to_chunk(),
+            chunk("tEXt", b"Comment\0old"),
+            chunk("tIME", &[7; 7]),
+            chunk("IDAT", &[1, 2, 3]),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let recipe: Recipe = toml::from_str(
+            r#"
+            [[operations]]
+            op = "strip-types"
+            types = ["tIME"]
+
+            [[operations]]
+            op = "set-text"
+            keyword = "Comment"
+            text = "new"
+
+            [[operations]]
+            op = "fix-crcs"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(recipe.operations.len(), 3);
+        assert_eq!(recipe.operations[2], Operation::FixCrcs);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let recipe: Recipe = serde_json::from_str(
+            r#"{"operations": [{"op": "encode-payload", "chunk_type": "ruSt", "message": "hi"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            recipe.operations[0],
+            Operation::EncodePayload {
+                chunk_type: String::from("ruSt"),
+                message: String::from("hi"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let recipe = Recipe {
+            operations: vec![
+                Operation::StripTypes {
+                    types: vec![String::from("tIME")],
+                },
+                Operation::SetText {
+                    keyword: String::from("Comment"),
+                    text: String::from("new"),
+                },
+                Operation::EncodePayload {
+                    chunk_type: String::from("ruSt"),
+                    message: String::from("hidden"),
+                },
+                Operation::Reorder,
+            ],
+        };
+
+        let mut png = testing_png();
+        recipe.apply(&mut png).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "tEXt", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), b"Comment\0new");
+    }
+
+    #[test]
+    fn test_payload_goes_where_its_type_belongs() {
+        let recipe = Recipe {
+            operations: vec![Operation::EncodePayload {
+                chunk_type: String::from("gAMA"),
+                message: String::from("1234"),
+            }],
+        };
+
+        let mut png = testing_png();
+        recipe.apply(&mut png).unwrap();
+        assert_eq!(
+            types(&png),
+            vec!["IHDR", "tEXt", "tIME", "gAMA", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_result_applies_nothing() {
+        let recipe = Recipe {
+            operations: vec![Operation::EncodePayload {
+                chunk_type: String::from("IHDR"),
+                message: String::from("late"),
+            }],
+        };
+
+        let mut png = testing_png();
+        assert!(recipe.apply(&mut png).is_err());
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_failed_operation_applies_nothing() {
+        let recipe = Recipe {
+            operations: vec![
+                Operation::StripTypes {
+                    types: vec![String::from("tEXt")],
+                },
+                Operation::EncodePayload {
+                    chunk_type: String::from("bad!"),
+                    message: String::from("hidden"),
+                },
+            ],
+        };
+        let mut png = testing_png();
+        assert!(recipe.apply(&mut png).is_err());
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+
+        let recipe = Recipe {
+            operations: vec![recipe.operations[0].clone()],
+        };
+        recipe.apply(&mut png).unwrap();
+        assert_eq!(types(&png).len(), 4);
+    }
+}
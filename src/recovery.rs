@@ -0,0 +1,284 @@
+use crate::capacity::MAX_CHUNK_LENGTH;
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use std::fmt::Display;
+
+/// How many times the length of the input the CRC checks of a recovery may read between them.
+/// A chunk is tried at every offset that could start one, so without a limit input with a
+/// plausible header every few bytes would take quadratic time. Once it's spent, what is left
+/// after the last chunk that was read is skipped.
+const CRC_BUDGET: usize = 4;
+
+/// Something [`Png::try_from_lenient`] had to work around, with the byte offset in the input
+/// where it starts
+#[derive(Debug, Clone)]
+pub enum ParseWarning {
+    /// The stored CRC doesn't match. The chunk is kept as read, so `repair` can fix it.
+    CrcMismatch {
+        offset: usize,
+        chunk_type: ChunkType,
+    },
+    /// A critical chunk of a type no decoder knows, which would make them reject the image. It's
+    /// taken out of the image and kept here.
+    Quarantined { offset: usize, chunk: Chunk },
+    /// Bytes that don't read as a chunk, such as a chunk with a wrong length, skipped up to the
+    /// next chunk with a valid CRC
+    Skipped { offset: usize, len: usize },
+    /// Bytes after IEND, which are dropped
+    TrailingData { offset: usize, len: usize },
+}
+
+impl ParseWarning {
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseWarning::CrcMismatch { offset, .. }
+            | ParseWarning::Quarantined { offset, .. }
+            | ParseWarning::Skipped { offset, .. }
+            | ParseWarning::TrailingData { offset, .. } => *offset,
+        }
+    }
+}
+
+/// A line in the style of `verify`, such as `byte 33: skipped 12 bytes that aren't a chunk`
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "byte {}: ", self.offset())?;
+        match self {
+            ParseWarning::CrcMismatch { chunk_type, .. } => {
+                write!(f, "{} chunk has the wrong CRC, kept as is", chunk_type)
+            }
+            ParseWarning::Quarantined { chunk, .. } => write!(
+                f,
+                "unknown critical chunk {} quarantined",
+                chunk.chunk_type()
+            ),
+            ParseWarning::Skipped { len, .. } => {
+                write!(f, "skipped {} bytes that aren't a chunk", len)
+            }
+            ParseWarning::TrailingData { len, .. } => {
+                write!(f, "dropped {} bytes after IEND", len)
+            }
+        }
+    }
+}
+
+impl Png {
+    /// Reads as much of a damaged image as it can instead of failing on the first problem.
+    /// Chunks with a wrong CRC are kept, unknown critical chunks are quarantined, and bytes that
+    /// don't read as a chunk are skipped until the next chunk with a valid CRC. Anything after
+    /// IEND is dropped. Every such problem is returned as a warning, in file order. Only a
+    /// missing signature is an error, no input makes it panic, and the time taken stays linear
+    /// in the length of the input.
+    pub fn try_from_lenient(value: &[u8]) -> Result<(Png, Vec<ParseWarning>), PngMeError> {
+        if value.get(..Png::STANDARD_HEADER.len()) != Some(&Png::STANDARD_HEADER[..]) {
+            return Err(PngMeError::InvalidSignature);
+        }
+        let mut chunks = Vec::new();
+        let mut warnings = Vec::new();
+        let mut offset = Png::STANDARD_HEADER.len();
+        let mut crc_budget = value.len().saturating_mul(CRC_BUDGET);
+        while offset < value.len() {
+            let rest = &value[offset..];
+            let chunk = match parse_aligned(rest, &mut crc_budget) {
+                Some(chunk) => chunk,
+                None => {
+                    let len = (1..rest.len())
+                        .find(|&skip| is_chunk_start(&rest[skip..], &mut crc_budget))
+                        .unwrap_or(rest.len());
+                    warnings.push(ParseWarning::Skipped { offset, len });
+                    offset += len;
+                    continue;
+                }
+            };
+
+            let chunk_type = chunk.chunk_type();
+            let is_iend = chunk_type == ChunkType::IEND;
+            let is_unknown_critical = chunk_type.is_critical() && chunk_type.standard().is_none();
+            if !chunk.has_valid_crc() {
+                warnings.push(ParseWarning::CrcMismatch { offset, chunk_type });
+            }
+            match is_unknown_critical {
+                true => warnings.push(ParseWarning::Quarantined {
+                    offset,
+                    chunk: chunk.to_chunk(),
+                }),
+                false => chunks.push(chunk.to_chunk()),
+            }
+            offset += chunk.total_size();
+
+            if is_iend && offset < value.len() {
+                warnings.push(ParseWarning::TrailingData {
+                    offset,
+                    len: value.len() - offset,
+                });
+                break;
+            }
+        }
+        Ok((Png::new(chunks), warnings))
+    }
+}
+
+/// Reads the chunk at the start of `value` if it looks like one: a plausible header, and either
+/// the end of the input or another chunk that fits in it right after it, or else a valid CRC. A
+/// chunk with a wrong length usually fails all three, since whatever follows is misaligned.
+fn parse_aligned<'a>(value: &'a [u8], crc_budget: &mut usize) -> Option<ChunkRef<'a>> {
+    let chunk = parse_plausible(value)?;
+    let next = &value[chunk.total_size()..];
+    match next.is_empty() || parse_plausible(next).is_some() || has_valid_crc(&chunk, crc_budget) {
+        true => Some(chunk),
+        false => None,
+    }
+}
+
+/// Reads the chunk at the start of `value` without checking its CRC, if its header is plausible
+/// and it fits in `value`
+fn parse_plausible(value: &[u8]) -> Option<ChunkRef<'_>> {
+    match has_plausible_header(value) {
+        true => ChunkRef::parse_lenient(value).ok(),
+        false => None,
+    }
+}
+
+/// Whether a chunk with a valid CRC starts at `value`, the test for picking up again after bytes
+/// that had to be skipped. Most offsets fail on the header alone, and only chunks that fit get
+/// their CRC computed.
+fn is_chunk_start(value: &[u8], crc_budget: &mut usize) -> bool {
+    parse_plausible(value).is_some_and(|chunk| has_valid_crc(&chunk, crc_budget))
+}
+
+/// Checks the CRC of a chunk if what is left of the budget covers its data, and counts it off.
+/// Without enough budget the CRC counts as wrong.
+fn has_valid_crc(chunk: &ChunkRef, crc_budget: &mut usize) -> bool {
+    match crc_budget.checked_sub(chunk.data().len()) {
+        Some(left) => {
+            *crc_budget = left;
+            chunk.has_valid_crc()
+        }
+        None => false,
+    }
+}
+
+/// Whether `value` starts with a length the spec allows and a type made of ASCII letters
+fn has_plausible_header(value: &[u8]) -> bool {
+    match (value.get(..4), value.get(4..8)) {
+        (Some(length), Some(chunk_type)) => {
+            u32::from_be_bytes(length.try_into().unwrap()) as u64 <= MAX_CHUNK_LENGTH
+                && chunk_type.iter().all(u8::is_ascii_alphabetic)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::new(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Comment\0hi"),
+            chunk("IDAT", b"pixels"),
+            chunk("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_intact() {
+        let png = testing_png();
+        let (recovered, warnings) = Png::try_from_lenient(&png.as_bytes()).unwrap();
+        assert_eq!(recovered.as_bytes(), png.as_bytes());
+        assert!(warnings.is_empty());
+        assert!(matches!(
+            Png::try_from_lenient(b"\x89PNG"),
+            Err(PngMeError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_recovery() {
+        let mut png = testing_png();
+        png.insert_chunk_at(2, chunk("ZZZZ", b"unknown")).unwrap();
+        let mut bytes = png.as_bytes();
+        let text_offset = 8 + 25;
+        // A length one too long for the tEXt chunk, then garbage after IEND
+        bytes[text_offset + 3] += 1;
+        bytes.extend_from_slice(b"junk");
+
+        let (recovered, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        let types: Vec<String> = recovered
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+        let lines: Vec<String> = warnings.iter().map(ParseWarning::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "byte 33: skipped 22 bytes that aren't a chunk",
+                "byte 55: unknown critical chunk ZZZZ quarantined",
+                "byte 104: dropped 4 bytes after IEND",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut bytes = testing_png().as_bytes();
+        let idat_crc = bytes.len() - 12 - 1;
+        bytes[idat_crc] ^= 1;
+        let (recovered, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        assert_eq!(recovered.chunks().len(), 4);
+        assert!(!recovered.chunks()[2].has_valid_crc());
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            &warnings[0],
+            ParseWarning::CrcMismatch { offset: 55, chunk_type } if *chunk_type == ChunkType::IDAT
+        ));
+    }
+
+    #[test]
+    fn test_crafted_resync() {
+        // A tEXt header every 8 bytes, each claiming the rest of the input but one byte, so none
+        // is aligned and every one of them fits. Checking every CRC would read 64 GiB.
+        let len = 1 << 20;
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        while bytes.len() + 8 + 13 <= len {
+            let length = (len - bytes.len() - 13) as u32;
+            bytes.extend_from_slice(&length.to_be_bytes());
+            bytes.extend_from_slice(b"tEXt");
+        }
+        bytes.resize(len, 0);
+        let (recovered, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        assert!(recovered.chunks().is_empty());
+        assert!(matches!(
+            warnings[..],
+            [ParseWarning::Skipped { offset: 8, len }] if len == bytes.len() - 8
+        ));
+    }
+
+    #[test]
+    fn test_never_panics() {
+        // Every truncation and every single-byte change of a small image, as a cheap stand-in for
+        // the fuzz target
+        let bytes = testing_png().as_bytes();
+        for len in 0..=bytes.len() {
+            let _ = Png::try_from_lenient(&bytes[..len]);
+        }
+        for index in 8..bytes.len() {
+            for value in [0, 1, 0x7f, 0x80, 0xff] {
+                let mut damaged = bytes.clone();
+                damaged[index] = value;
+                let (recovered, _) = Png::try_from_lenient(&damaged).unwrap();
+                assert!(recovered.as_bytes().len() <= damaged.len());
+            }
+        }
+    }
+}
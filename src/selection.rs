@@ -0,0 +1,176 @@
+use crate::filter::Filter;
+use std::ops::Range;
+
+/// Which of several chunks sharing a type an operation applies to. Indexes count from zero among
+/// the matching chunks only, not among all chunks in the file.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Occurrence {
+    #[default]
+    First,
+    Last,
+    Index(usize),
+    All,
+}
+
+impl Occurrence {
+    /// Builds an occurrence from the `--index`, `--all`, `--first` and `--last` flags, which are
+    /// mutually exclusive. With no flags the first match is selected.
+    pub fn from_flags(
+        index: Option<usize>,
+        all: bool,
+        first: bool,
+        last: bool,
+    ) -> Result<Occurrence, &'static str> {
+        let given = [index.is_some(), all, first, last]
+            .iter()
+            .filter(|flag| **flag)
+            .count();
+        if given > 1 {
+            return Err("only one of --index, --all, --first and --last may be given");
+        }
+
+        Ok(match (index, all, last) {
+            (Some(index), _, _) => Occurrence::Index(index),
+            (None, true, _) => Occurrence::All,
+            (None, false, true) => Occurrence::Last,
+            _ => Occurrence::First,
+        })
+    }
+
+    /// Narrows `matches`, which must be in file order, down to the selected occurrences
+    pub fn select<T>(&self, mut matches: Vec<T>) -> Vec<T> {
+        match self {
+            Occurrence::First => matches.into_iter().take(1).collect(),
+            Occurrence::Last => matches.pop().into_iter().collect(),
+            Occurrence::Index(index) => matches.into_iter().skip(*index).take(1).collect(),
+            Occurrence::All => matches,
+        }
+    }
+}
+
+/// Identifies chunks by where they sit in the file rather than by type, for files where duplicated
+/// or malformed types make type-based selection ambiguous.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ChunkAddress {
+    /// Position among all chunks, counting from zero
+    Index(usize),
+    /// Half-open range of positions, as in `5..9`
+    Range(Range<usize>),
+    /// Byte offset from the start of the file, anywhere within the chunk
+    Offset(usize),
+}
+
+impl ChunkAddress {
+    /// Parses an `--at-index` value: a single index, `start..end` or `start..=end`
+    pub fn parse_index(s: &str) -> Result<ChunkAddress, &'static str> {
+        let parse = |s: &str| s.trim().parse::<usize>().map_err(|_| "invalid chunk index");
+
+        if let Some((start, end)) = s.split_once("..=") {
+            let end = parse(end)?.checked_add(1).ok_or("invalid chunk index")?;
+            return Self::range(parse(start)?, end);
+        }
+        if let Some((start, end)) = s.split_once("..") {
+            return Self::range(parse(start)?, parse(end)?);
+        }
+
+        Ok(ChunkAddress::Index(parse(s)?))
+    }
+
+    /// Parses an `--at-offset` value, in decimal or as hex with a `0x` prefix
+    pub fn parse_offset(s: &str) -> Result<ChunkAddress, &'static str> {
+        let s = s.trim();
+        let offset = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => s.parse::<usize>(),
+        };
+
+        offset
+            .map(ChunkAddress::Offset)
+            .map_err(|_| "invalid byte offset")
+    }
+
+    fn range(start: usize, end: usize) -> Result<ChunkAddress, &'static str> {
+        if start >= end {
+            return Err("chunk index range is empty");
+        }
+        Ok(ChunkAddress::Range(start..end))
+    }
+}
+
+/// The chunks a command works on: some of the chunks of a type, chunks picked by position, or
+/// every chunk matching a `--where` filter
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Selection {
+    Type(String, Occurrence),
+    Address(ChunkAddress),
+    Where(Filter),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(
+            Occurrence::from_flags(None, false, false, false),
+            Ok(Occurrence::First)
+        );
+        assert_eq!(
+            Occurrence::from_flags(Some(2), false, false, false),
+            Ok(Occurrence::Index(2))
+        );
+        assert_eq!(
+            Occurrence::from_flags(None, true, false, false),
+            Ok(Occurrence::All)
+        );
+        assert_eq!(
+            Occurrence::from_flags(None, false, false, true),
+            Ok(Occurrence::Last)
+        );
+    }
+
+    #[test]
+    fn test_from_conflicting_flags() {
+        assert!(Occurrence::from_flags(Some(1), false, true, false).is_err());
+        assert!(Occurrence::from_flags(None, true, false, true).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let matches = vec![4, 7, 9];
+        assert_eq!(Occurrence::First.select(matches.clone()), vec![4]);
+        assert_eq!(Occurrence::Last.select(matches.clone()), vec![9]);
+        assert_eq!(Occurrence::Index(1).select(matches.clone()), vec![7]);
+        assert_eq!(Occurrence::All.select(matches.clone()), vec![4, 7, 9]);
+        assert!(Occurrence::Index(3).select(matches).is_empty());
+    }
+
+    #[test]
+    fn test_parse_index() {
+        assert_eq!(ChunkAddress::parse_index("7"), Ok(ChunkAddress::Index(7)));
+        assert_eq!(
+            ChunkAddress::parse_index("5..9"),
+            Ok(ChunkAddress::Range(5..9))
+        );
+        assert_eq!(
+            ChunkAddress::parse_index("5..=9"),
+            Ok(ChunkAddress::Range(5..10))
+        );
+        assert!(ChunkAddress::parse_index("9..5").is_err());
+        assert!(ChunkAddress::parse_index("five").is_err());
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(
+            ChunkAddress::parse_offset("0x1A3F"),
+            Ok(ChunkAddress::Offset(0x1A3F))
+        );
+        assert_eq!(
+            ChunkAddress::parse_offset("33"),
+            Ok(ChunkAddress::Offset(33))
+        );
+        assert!(ChunkAddress::parse_offset("0xZZ").is_err());
+    }
+}
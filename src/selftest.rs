@@ -0,0 +1,341 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compress::{self, Algorithm};
+use crate::crypto;
+use crate::lsb;
+use crate::pixels::ImageData;
+use crate::png::Png;
+use crate::shamir::{self, Threshold};
+use crate::text::{self, TextChunk, TextKind};
+use crate::transform::{self, Pipeline};
+use crate::watermark;
+use crate::MyResult;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Status {
+    Pass,
+    Fail,
+    /// The component isn't part of this build
+    Skipped,
+}
+
+/// Outcome of checking one component against its known answers
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Check {
+    pub component: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self.status {
+            Status::Pass => "PASS",
+            Status::Fail => "FAIL",
+            Status::Skipped => "SKIP",
+        };
+        write!(f, "{:<4}  {:<20} {}", status, self.component, self.detail)
+    }
+}
+
+/// Runs every known-answer and round-trip check. A build is fit for use when none of the
+/// returned checks failed.
+pub fn run() -> Vec<Check> {
+    vec![
+        check("crc32", crc32),
+        check("chunk crc", chunk_crc),
+        check("sha256", sha256),
+        check("ed25519", ed25519),
+        check("aead/kdf", aead_kdf),
+        check("chunk round trip", chunk_round_trip),
+        check("image codec", image_codec),
+        check("zlib", || compression(Algorithm::Zlib)),
+        check_if("zstd", cfg!(feature = "zstd"), || {
+            compression(Algorithm::Zstd)
+        }),
+        check("mode: chunk", mode_chunk),
+        check("mode: lsb", mode_lsb),
+        check("mode: text", mode_text),
+        check("mode: transform", mode_transform),
+        check("mode: watermark", mode_watermark),
+        check("mode: shamir", mode_shamir),
+    ]
+}
+
+pub fn passed(checks: &[Check]) -> bool {
+    checks.iter().all(|check| check.status != Status::Fail)
+}
+
+fn check(component: &'static str, test: fn() -> MyResult<String>) -> Check {
+    let (status, detail) = match test() {
+        Ok(detail) => (Status::Pass, detail),
+        Err(e) => (Status::Fail, e.to_string()),
+    };
+    Check {
+        component,
+        status,
+        detail,
+    }
+}
+
+/// Like [`check`], for a component only some builds include
+fn check_if(component: &'static str, included: bool, test: fn() -> MyResult<String>) -> Check {
+    match included {
+        true => check(component, test),
+        false => Check {
+            component,
+            status: Status::Skipped,
+            detail: String::from("not in this build"),
+        },
+    }
+}
+
+fn expect<T: PartialEq + std::fmt::Debug>(
+    what: &str,
+    actual: T,
+    expected: T,
+) -> Result<(), String> {
+    match actual == expected {
+        true => Ok(()),
+        false => Err(format!(
+            "{}: expected {:?}, got {:?}",
+            what, expected, actual
+        )),
+    }
+}
+
+/// The check value of the CRC-32 used by PNG
+fn crc32() -> MyResult<String> {
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(b"123456789");
+    expect("check value of \"123456789\"", crc, 0xcbf43926)?;
+    Ok(String::from("check value 0xcbf43926"))
+}
+
+/// Every PNG ends with the same IEND chunk, whose CRC is fixed by the spec
+fn chunk_crc() -> MyResult<String> {
+    let iend = Chunk::new(ChunkType::from_str("IEND")?, vec![]);
+    expect("IEND crc", iend.crc(), 0xae426082)?;
+    expect(
+        "IEND bytes",
+        iend.as_bytes(),
+        vec![0, 0, 0, 0, 73, 69, 78, 68, 0xae, 0x42, 0x60, 0x82],
+    )?;
+    Ok(String::from("IEND crc 0xae426082"))
+}
+
+/// FIPS 180-2 example "abc"
+fn sha256() -> MyResult<String> {
+    expect(
+        "digest of \"abc\"",
+        hex::encode(Sha256::digest(b"abc")).as_str(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+    )?;
+    Ok(String::from("FIPS 180-2 \"abc\""))
+}
+
+/// RFC 8032 section 7.1, test 1
+fn ed25519() -> MyResult<String> {
+    let secret: [u8; 32] =
+        hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")?
+            .try_into()
+            .map_err(|_| "secret key has the wrong length")?;
+    let key = SigningKey::from_bytes(&secret);
+
+    expect(
+        "public key",
+        hex::encode(key.verifying_key().to_bytes()),
+        String::from("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"),
+    )?;
+    expect(
+        "signature",
+        hex::encode(key.sign(b"").to_bytes()),
+        String::from(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+    )?;
+    Ok(String::from("RFC 8032 test 1"))
+}
+
+/// RFC 8439 section 2.8.2 for ChaCha20-Poly1305 and RFC 7914 section 11 for PBKDF2-HMAC-SHA256
+fn aead_kdf() -> MyResult<String> {
+    expect(
+        "derived key",
+        hex::encode(crypto::derive_key("passwd", b"salt", 1)),
+        String::from("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"),
+    )?;
+
+    let key: Vec<u8> = (0x80..=0x9f).collect();
+    let cipher = ChaCha20Poly1305::new(&Key::try_from(key.as_slice())?);
+    let nonce = Nonce::try_from(hex::decode("070000004041424344454647")?.as_slice())?;
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let aad = hex::decode("50515253c0c1c2c3c4c5c6c7")?;
+    let sealed = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| "encryption failed")?;
+
+    let (ciphertext, tag) = sealed.split_at(plaintext.len());
+    expect(
+        "ciphertext prefix",
+        hex::encode(&ciphertext[..16]),
+        String::from("d31a8d34648e60db7b86afbc53ef7ec2"),
+    )?;
+    expect(
+        "tag",
+        hex::encode(tag),
+        String::from("1ae10b594f09e26a7e902ecbd0600691"),
+    )?;
+    Ok(String::from("RFC 8439 2.8.2, RFC 7914 PBKDF2"))
+}
+
+fn chunk_round_trip() -> MyResult<String> {
+    let chunk = Chunk::new(ChunkType::from_str("ruSt")?, b"round trip".to_vec());
+    let parsed = Chunk::try_from(chunk.as_bytes().as_slice())?;
+    expect("parsed chunk", parsed.as_bytes(), chunk.as_bytes())?;
+    Ok(String::from("serialize and parse"))
+}
+
+/// A generated image whose rows exercise every byte value
+fn generated_image() -> MyResult<Png> {
+    let mut image = ImageData {
+        width: 32,
+        height: 16,
+        bit_depth: 8,
+        color_type: 6,
+        data: vec![],
+    };
+    image.data = (0..image.height as usize * image.stride())
+        .map(|i| (i * 7 % 256) as u8)
+        .collect();
+    Ok(image.to_png()?)
+}
+
+fn image_codec() -> MyResult<String> {
+    let png = generated_image()?;
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    let image = ImageData::decode(&parsed)?;
+    expect("decoded image", image.data, ImageData::decode(&png)?.data)?;
+    Ok(String::from("32x16 RGBA through zlib and back"))
+}
+
+fn compression(algorithm: Algorithm) -> MyResult<String> {
+    let message = b"compressible ".repeat(64);
+    let compressed = compress::compress(algorithm, &message)?;
+    expect("decompressed", compress::decompress(&compressed)?, message)?;
+    Ok(format!("{} round trip", algorithm))
+}
+
+fn mode_chunk() -> MyResult<String> {
+    let mut png = generated_image()?;
+    let message = b"hidden in a chunk";
+    png.insert_chunk_at(
+        1,
+        Chunk::new(ChunkType::from_str("ruSt")?, message.to_vec()),
+    )?;
+
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    let chunk = parsed
+        .chunk_by_type("ruSt")
+        .ok_or("payload chunk is missing")?;
+    expect("payload", chunk.data(), message.as_slice())?;
+    Ok(String::from("ancillary chunk payload"))
+}
+
+fn mode_lsb() -> MyResult<String> {
+    let mut png = generated_image()?;
+    let message = b"hidden in the pixels";
+    lsb::embed(&mut png, message)?;
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    expect("message", lsb::extract(&parsed)?, message.to_vec())?;
+    Ok(String::from("least-significant bits of IDAT"))
+}
+
+fn mode_text() -> MyResult<String> {
+    let mut png = generated_image()?;
+    let message = "hidden in plain sight";
+    let text_chunk = TextChunk::new(TextKind::Compressed, "Comment", message)?;
+    png.insert_chunk_ordered(text_chunk.to_chunk()?);
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    let found = text::find(&parsed, TextKind::Compressed, Some("Comment"))?;
+    expect("text", found.text(), message)?;
+    Ok(String::from("zTXt keyword and text"))
+}
+
+fn mode_transform() -> MyResult<String> {
+    let payload = b"layered payload";
+    let encoded = Pipeline::from_str("gzip,xor:0x5a,base64")?.encode(payload)?;
+    expect("decoded", transform::decode(&encoded)?, payload.to_vec())?;
+    Ok(String::from("gzip,xor,base64"))
+}
+
+fn mode_watermark() -> MyResult<String> {
+    let mut png = generated_image()?;
+    watermark::embed(&mut png, b"selftest")?;
+    let parsed = Png::try_from(png.as_bytes().as_slice())?;
+    match watermark::check(&parsed, b"selftest")?.is_intact() {
+        true => Ok(String::from("embed and verify")),
+        false => Err("freshly watermarked image failed verification".into()),
+    }
+}
+
+fn mode_shamir() -> MyResult<String> {
+    let secret = b"selftest secret";
+    let shares = shamir::split(secret, Threshold::from_str("2-of-3")?)?;
+    let carriers: Vec<Png> = shares
+        .iter()
+        .map(|share| Png::new(vec![share.to_chunk()]))
+        .collect();
+
+    let mut gathered = shamir::shares_in(&carriers[2])?;
+    gathered.extend(shamir::shares_in(&carriers[0])?);
+    expect(
+        "recovered secret",
+        shamir::combine(&gathered)?,
+        secret.to_vec(),
+    )?;
+    Ok(String::from("2-of-3 split and combine"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        let checks = run();
+        for check in &checks {
+            assert_ne!(check.status, Status::Fail, "{}", check);
+        }
+        assert!(passed(&checks));
+    }
+
+    #[test]
+    fn test_failure_is_reported() {
+        let failed = check("broken", || Err("wrong answer".into()));
+        assert_eq!(failed.status, Status::Fail);
+        assert!(!passed(&[failed]));
+        let skipped = check_if("missing", false, || Ok(String::new()));
+        assert_eq!(skipped.status, Status::Skipped);
+        assert!(passed(&[skipped]));
+        assert_eq!(
+            check("crc32", crc32).to_string(),
+            "PASS  crc32                check value 0xcbf43926"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::selection::Occurrence;
+use std::str::FromStr;
+
+/// Private, ancillary, safe-to-copy chunk type that carries one share
+pub const SHARE_CHUNK_TYPE: &str = "shAr";
+pub const SHARE_VERSION: u8 = 1;
+const SET_ID_LEN: usize = 8;
+
+/// How many shares a secret is split into and how many of them are needed to recover it
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Threshold {
+    pub required: u8,
+    pub shares: u8,
+}
+
+impl FromStr for Threshold {
+    type Err = &'static str;
+
+    /// Parses the `--threshold` form `2-of-3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (required, shares) = s
+            .split_once("-of-")
+            .ok_or("threshold must look like 2-of-3")?;
+        let required: u8 = required.trim().parse().map_err(|_| "invalid threshold")?;
+        let shares: u8 = shares.trim().parse().map_err(|_| "invalid share count")?;
+        if required == 0 || required > shares {
+            return Err("threshold must be between 1 and the number of shares");
+        }
+
+        Ok(Threshold { required, shares })
+    }
+}
+
+/// One point on the sharing polynomial of every secret byte. Shares from the same split carry
+/// the same random `set_id` so shares of different secrets aren't mixed up on recovery.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Share {
+    pub set_id: [u8; SET_ID_LEN],
+    pub required: u8,
+    pub index: u8,
+    pub data: Vec<u8>,
+}
+
+impl Share {
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = vec![SHARE_VERSION, self.required, self.index];
+        data.extend_from_slice(&self.set_id);
+        data.extend_from_slice(&self.data);
+
+        Chunk::new(ChunkType::from_str(SHARE_CHUNK_TYPE).unwrap(), data)
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Result<Share, &'static str> {
+        if chunk.chunk_type().bytes() != SHARE_CHUNK_TYPE.as_bytes() {
+            return Err("Chunk does not hold a key share");
+        }
+        let data = chunk.data();
+        if data.len() < 3 + SET_ID_LEN || data[0] != SHARE_VERSION {
+            return Err("Key share chunk is malformed");
+        }
+
+        let mut set_id = [0u8; SET_ID_LEN];
+        set_id.copy_from_slice(&data[3..3 + SET_ID_LEN]);
+        Ok(Share {
+            set_id,
+            required: data[1],
+            index: data[2],
+            data: data[3 + SET_ID_LEN..].to_vec(),
+        })
+    }
+}
+
+/// Splits `secret` so that any `threshold.required` of the returned shares recover it and fewer
+/// reveal nothing about it
+pub fn split(secret: &[u8], threshold: Threshold) -> Result<Vec<Share>, &'static str> {
+    if threshold.required == 0 || threshold.required > threshold.shares {
+        return Err("threshold must be between 1 and the number of shares");
+    }
+
+    let degree = threshold.required as usize - 1;
+    let mut set_id = [0u8; SET_ID_LEN];
+    let mut coefficients = vec![0u8; secret.len() * degree];
+    getrandom::fill(&mut set_id).map_err(|_| "Failed to gather randomness")?;
+    getrandom::fill(&mut coefficients).map_err(|_| "Failed to gather randomness")?;
+
+    let shares = (1..=threshold.shares)
+        .map(|x| {
+            let data = secret
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| {
+                    // evaluate byte + c1*x + c2*x^2 + ... with Horner's rule
+                    coefficients[i * degree..(i + 1) * degree]
+                        .iter()
+                        .rev()
+                        .chain(std::iter::once(byte))
+                        .fold(0, |acc, c| gf_mul(acc, x) ^ c)
+                })
+                .collect();
+
+            Share {
+                set_id,
+                required: threshold.required,
+                index: x,
+                data,
+            }
+        })
+        .collect();
+
+    Ok(shares)
+}
+
+/// Recovers the secret from at least the required number of shares of the same split
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, &'static str> {
+    let first = shares.first().ok_or("No key shares given")?;
+    if shares.iter().any(|share| {
+        share.set_id != first.set_id
+            || share.required != first.required
+            || share.data.len() != first.data.len()
+    }) {
+        return Err("Key shares come from different splits");
+    }
+
+    let mut indices: Vec<u8> = shares.iter().map(|share| share.index).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    if indices.contains(&0) || indices.len() != shares.len() {
+        return Err("Key shares have invalid or repeated indices");
+    }
+    if shares.len() < first.required as usize {
+        return Err("Not enough key shares to recover the secret");
+    }
+
+    let shares = &shares[..first.required as usize];
+    let secret = (0..first.data.len())
+        .map(|i| {
+            // Lagrange interpolation at x = 0; subtraction is xor in GF(256)
+            shares.iter().fold(0, |acc, share| {
+                let basis = shares
+                    .iter()
+                    .filter(|other| other.index != share.index)
+                    .fold(1, |basis, other| {
+                        gf_mul(basis, gf_div(other.index, other.index ^ share.index))
+                    });
+                acc ^ gf_mul(share.data[i], basis)
+            })
+        })
+        .collect();
+
+    Ok(secret)
+}
+
+/// Collects every key share chunk in `png`, so shares spread over several carrier files can be
+/// gathered and passed to [`combine`] together
+pub fn shares_in(png: &Png) -> Result<Vec<Share>, &'static str> {
+    png.chunks_by_type(SHARE_CHUNK_TYPE, Occurrence::All)
+        .into_iter()
+        .map(Share::from_chunk)
+        .collect()
+}
+
+/// Multiplication in GF(2^8) with the AES reduction polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b, as every non-zero element satisfies b^255 = 1
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(
+            Threshold::from_str("2-of-3"),
+            Ok(Threshold {
+                required: 2,
+                shares: 3
+            })
+        );
+        assert!(Threshold::from_str("4-of-3").is_err());
+        assert!(Threshold::from_str("0-of-3").is_err());
+        assert!(Threshold::from_str("2/3").is_err());
+    }
+
+    #[test]
+    fn test_gf_arithmetic() {
+        assert_eq!(gf_mul(0x53, 0xca), 0x01);
+        assert_eq!(gf_div(0x01, 0x53), 0xca);
+        assert_eq!(gf_mul(7, 0), 0);
+    }
+
+    #[test]
+    fn test_any_threshold_of_shares_recovers_secret() {
+        let secret = b"payload key material".to_vec();
+        let shares = split(&secret, Threshold::from_str("2-of-3").unwrap()).unwrap();
+        assert_eq!(shares.len(), 3);
+
+        for pair in [[0, 1], [0, 2], [2, 1]] {
+            let chosen = vec![shares[pair[0]].clone(), shares[pair[1]].clone()];
+            assert_eq!(combine(&chosen).unwrap(), secret);
+        }
+    }
+
+    #[test]
+    fn test_too_few_shares() {
+        let shares = split(b"secret", Threshold::from_str("3-of-5").unwrap()).unwrap();
+        assert!(combine(&shares[..2]).is_err());
+        assert_eq!(combine(&shares[1..4]).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_mixed_splits_are_rejected() {
+        let threshold = Threshold::from_str("2-of-2").unwrap();
+        let first = split(b"secret", threshold).unwrap();
+        let second = split(b"secret", threshold).unwrap();
+        assert!(combine(&[first[0].clone(), second[1].clone()]).is_err());
+        assert!(combine(&[first[0].clone(), first[0].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_share_chunk_round_trip() {
+        let shares = split(b"secret", Threshold::from_str("1-of-2").unwrap()).unwrap();
+        let chunk = shares[1].to_chunk();
+        assert_eq!(&chunk.chunk_type().to_string(), SHARE_CHUNK_TYPE);
+        assert!(!chunk.chunk_type().is_critical());
+        assert_eq!(Share::from_chunk(&chunk).unwrap(), shares[1]);
+    }
+
+    #[test]
+    fn test_shares_across_carrier_files() {
+        let shares = split(b"secret", Threshold::from_str("2-of-3").unwrap()).unwrap();
+        let first = Png::new(vec![shares[0].to_chunk()]);
+        let second = Png::new(vec![shares[2].to_chunk()]);
+
+        let mut gathered = shares_in(&first).unwrap();
+        assert!(combine(&gathered).is_err());
+        gathered.extend(shares_in(&second).unwrap());
+        assert_eq!(combine(&gathered).unwrap(), b"secret");
+    }
+}
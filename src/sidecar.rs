@@ -0,0 +1,187 @@
+use crate::png::Png;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the store written next to the files it describes. The evidence file itself is never
+/// opened for writing.
+pub const SIDECAR_FILE_NAME: &str = ".pngme.json";
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Annotation {
+    pub chunk: usize,
+    pub note: String,
+}
+
+/// Analyst notes for every file in a directory, keyed by the sha256 of the file contents so a note
+/// follows the exact bytes it was written about rather than a file name.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AnnotationStore {
+    #[serde(skip)]
+    path: PathBuf,
+    files: BTreeMap<String, Vec<Annotation>>,
+}
+
+impl AnnotationStore {
+    /// Returns the location of the store that holds notes for `file`
+    pub fn sidecar_path(file: &Path) -> PathBuf {
+        match file.parent() {
+            Some(dir) => dir.join(SIDECAR_FILE_NAME),
+            None => PathBuf::from(SIDECAR_FILE_NAME),
+        }
+    }
+
+    /// Loads the store for `file`, or an empty one if none has been written yet
+    pub fn open(file: &Path) -> crate::MyResult<AnnotationStore> {
+        let path = Self::sidecar_path(file);
+        let mut store: AnnotationStore = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AnnotationStore::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        store.path = path;
+
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn annotate(&mut self, file_bytes: &[u8], chunk: usize, note: &str) {
+        self.files
+            .entry(file_hash(file_bytes))
+            .or_default()
+            .push(Annotation {
+                chunk,
+                note: String::from(note),
+            });
+    }
+
+    pub fn annotations(&self, file_bytes: &[u8]) -> &[Annotation] {
+        match self.files.get(&file_hash(file_bytes)) {
+            Some(annotations) => annotations,
+            None => &[],
+        }
+    }
+
+    /// Returns the notes attached to a single chunk, in the order they were written
+    pub fn chunk_annotations(&self, file_bytes: &[u8], chunk: usize) -> Vec<&Annotation> {
+        self.annotations(file_bytes)
+            .iter()
+            .filter(|annotation| annotation.chunk == chunk)
+            .collect()
+    }
+
+    pub fn save(&self) -> crate::MyResult<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Attaches `note` to the chunk at `chunk` in `file` and persists it to the sidecar store
+pub fn annotate_file(file: &Path, chunk: usize, note: &str) -> crate::MyResult<()> {
+    let bytes = fs::read(file)?;
+    let png = Png::try_from(bytes.as_slice())?;
+    if chunk >= png.chunks().len() {
+        return Err("chunk index is out of range".into());
+    }
+
+    let mut store = AnnotationStore::open(file)?;
+    store.annotate(&bytes, chunk, note);
+    store.save()
+}
+
+/// Returns the lowercase hex sha256 digest used to key files in the store
+pub fn file_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn testing_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pngme-sidecar-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        let path = AnnotationStore::sidecar_path(Path::new("evidence/image.png"));
+        assert_eq!(path, Path::new("evidence").join(SIDECAR_FILE_NAME));
+    }
+
+    #[test]
+    fn test_file_hash() {
+        assert_eq!(
+            file_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_annotations_keyed_by_contents() {
+        let mut store = AnnotationStore::default();
+        store.annotate(b"first file", 3, "looks like attacker tool output");
+
+        assert_eq!(store.annotations(b"first file").len(), 1);
+        assert_eq!(
+            store.chunk_annotations(b"first file", 3)[0].note,
+            "looks like attacker tool output"
+        );
+        assert!(store.chunk_annotations(b"first file", 2).is_empty());
+        assert!(store.annotations(b"second file").is_empty());
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let dir = testing_dir("round-trip");
+        let file = dir.join("image.png");
+
+        let mut store = AnnotationStore::open(&file).unwrap();
+        store.annotate(b"contents", 1, "first note");
+        store.annotate(b"contents", 1, "second note");
+        store.save().unwrap();
+
+        let store = AnnotationStore::open(&file).unwrap();
+        let notes: Vec<&str> = store
+            .annotations(b"contents")
+            .iter()
+            .map(|annotation| annotation.note.as_str())
+            .collect();
+        assert_eq!(notes, vec!["first note", "second note"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_annotate_file_leaves_evidence_untouched() {
+        let dir = testing_dir("untouched");
+        let file = dir.join("image.png");
+        let png = Png::new(vec![crate::chunk::Chunk::new(
+            "ruSt".parse().unwrap(),
+            b"hidden".to_vec(),
+        )]);
+        fs::write(&file, png.as_bytes()).unwrap();
+
+        annotate_file(&file, 0, "suspicious").unwrap();
+        assert!(annotate_file(&file, 1, "out of range").is_err());
+
+        let bytes = fs::read(&file).unwrap();
+        assert_eq!(bytes, png.as_bytes());
+        let store = AnnotationStore::open(&file).unwrap();
+        assert_eq!(store.annotations(&bytes)[0].note, "suspicious");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
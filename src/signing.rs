@@ -0,0 +1,167 @@
+use crate::error::PngMeError;
+use crate::png::Png;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+
+/// Marks a signed payload. A flags byte follows, then the HMAC-SHA256 tag, then the payload.
+pub const SIGNED_MAGIC: &[u8; 4] = b"pmS1";
+/// Version of the signed payload format, the digit at the end of [`SIGNED_MAGIC`]
+pub const SIGNED_VERSION: u32 = 1;
+
+/// Flag set when the tag also covers the critical chunks of the image
+const COVERS_IMAGE: u8 = 1;
+const TAG_LEN: usize = 32;
+const HEADER_LEN: usize = SIGNED_MAGIC.len() + 1 + TAG_LEN;
+
+/// Frames `payload` with an HMAC-SHA256 tag under `key`. When `image` is given the tag also
+/// covers its critical chunks, so changes to the pixels or layout are caught as well as changes
+/// to the payload. The payload has to go in an ancillary chunk for that to hold.
+pub fn sign(key: &[u8], payload: &[u8], image: Option<&Png>) -> Result<Vec<u8>, PngMeError> {
+    let flags = match image {
+        Some(_) => COVERS_IMAGE,
+        None => 0,
+    };
+    let tag = mac(key, flags, payload, image)?.finalize().into_bytes();
+
+    let mut signed = SIGNED_MAGIC.to_vec();
+    signed.push(flags);
+    signed.extend_from_slice(&tag);
+    signed.extend_from_slice(payload);
+    Ok(signed)
+}
+
+/// Checks the tag of a payload made by [`sign`] and returns the payload. `image` is the file the
+/// payload was found in, and is only read when the tag covers it.
+pub fn verify(key: &[u8], signed: &[u8], image: &Png) -> Result<Vec<u8>, PngMeError> {
+    let (flags, tag, payload) = split(signed)?;
+    if flags & !COVERS_IMAGE != 0 {
+        return Err(PngMeError::Malformed(
+            "signed message has unknown flags set",
+        ));
+    }
+    let image = (flags & COVERS_IMAGE != 0).then_some(image);
+    mac(key, flags, payload, image)?
+        .verify_slice(tag)
+        .map_err(|_| PngMeError::SignatureMismatch)?;
+    Ok(payload.to_vec())
+}
+
+/// Returns the payload of a signed message without checking the tag
+pub fn strip(signed: &[u8]) -> Result<&[u8], PngMeError> {
+    Ok(split(signed)?.2)
+}
+
+/// Whether `payload` starts like the output of [`sign`]
+pub fn is_signed(payload: &[u8]) -> bool {
+    payload.starts_with(SIGNED_MAGIC)
+}
+
+fn split(signed: &[u8]) -> Result<(u8, &[u8], &[u8]), PngMeError> {
+    let rest = signed
+        .strip_prefix(SIGNED_MAGIC)
+        .ok_or(PngMeError::Malformed("message is not signed"))?;
+    if signed.len() < HEADER_LEN {
+        return Err(PngMeError::TruncatedChunk);
+    }
+    let (tag, payload) = rest[1..].split_at(TAG_LEN);
+    Ok((rest[0], tag, payload))
+}
+
+/// The tag covers the flags, so coverage of the image can't be stripped, and the payload length,
+/// so payload bytes can't be passed off as chunk bytes
+fn mac(
+    key: &[u8],
+    flags: u8,
+    payload: &[u8],
+    image: Option<&Png>,
+) -> Result<Hmac<Sha256>, PngMeError> {
+    if key.is_empty() {
+        return Err(PngMeError::Invalid("signing key can't be empty"));
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(SIGNED_MAGIC);
+    mac.update(&[flags]);
+    mac.update(&(payload.len() as u64).to_be_bytes());
+    mac.update(payload);
+    for chunk in image.iter().flat_map(|png| png.chunks()) {
+        if chunk.chunk_type().is_critical() {
+            mac.update(&chunk.as_bytes());
+        }
+    }
+    Ok(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    const KEY: &[u8] = b"0123456789abcdef";
+
+    fn testing_png() -> Png {
+        Png::new(
+            ["IHDR", "IDAT", "IEND"]
+                .iter()
+                .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![1]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let png = testing_png();
+        for image in [None, Some(&png)] {
+            let signed = sign(KEY, b"meet at dawn", image).unwrap();
+            assert!(is_signed(&signed));
+            assert_eq!(signed.len(), HEADER_LEN + b"meet at dawn".len());
+            assert_eq!(verify(KEY, &signed, &png).unwrap(), b"meet at dawn");
+            assert_eq!(strip(&signed).unwrap(), b"meet at dawn");
+        }
+    }
+
+    #[test]
+    fn test_detects_changes() {
+        let png = testing_png();
+        let mut signed = sign(KEY, b"meet at dawn", Some(&png)).unwrap();
+        assert!(matches!(
+            verify(b"another key", &signed, &png),
+            Err(PngMeError::SignatureMismatch)
+        ));
+
+        let mut changed = png.clone();
+        let idat = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![2]);
+        changed.remove_first_chunk("IDAT").unwrap();
+        changed.insert_chunk_at(1, idat).unwrap();
+        assert!(verify(KEY, &signed, &changed).is_err());
+        let text = Chunk::new(ChunkType::from_str("tEXt").unwrap(), vec![2]);
+        changed = png.clone();
+        changed.insert_before_iend(text).unwrap();
+        assert!(
+            verify(KEY, &signed, &changed).is_ok(),
+            "ancillary chunks aren't covered"
+        );
+
+        let last = signed.len() - 1;
+        signed[last] ^= 1;
+        assert!(verify(KEY, &signed, &png).is_err());
+        signed[last] ^= 1;
+        signed[4] = 0;
+        assert!(
+            verify(KEY, &signed, &png).is_err(),
+            "image coverage can't be dropped"
+        );
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        let png = testing_png();
+        assert!(verify(KEY, b"meet at dawn", &png).is_err());
+        assert!(matches!(
+            strip(b"pmS1\x00"),
+            Err(PngMeError::TruncatedChunk)
+        ));
+        assert!(sign(b"", b"meet at dawn", None).is_err());
+    }
+}
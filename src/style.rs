@@ -0,0 +1,161 @@
+use pngme::ChunkType;
+use std::{env, fmt, io::IsTerminal, str::FromStr};
+
+/// Set to any non-empty value to turn off color, see <https://no-color.org>
+pub const NO_COLOR: &str = "NO_COLOR";
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const REVERSE_YELLOW: &str = "\x1b[1;7;33m";
+
+/// The `--color` setting
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err("color must be one of auto, always or never"),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Decides whether stdout gets color. `always` and `never` win over everything else; `auto`
+    /// colors a terminal unless `NO_COLOR` is set.
+    pub fn enabled(self) -> bool {
+        let no_color = env::var_os(NO_COLOR).is_some_and(|value| !value.is_empty());
+        self.resolve(std::io::stdout().is_terminal(), no_color)
+    }
+
+    fn resolve(self, is_terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && !no_color,
+        }
+    }
+}
+
+/// Applies the output theme, or passes text through untouched when color is off
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Theme {
+    enabled: bool,
+}
+
+impl Theme {
+    pub fn new(choice: ColorChoice) -> Theme {
+        Theme {
+            enabled: choice.enabled(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn plain() -> Theme {
+        Theme { enabled: false }
+    }
+
+    /// Critical chunks in bold, public ancillary chunks in cyan and private chunks, the usual
+    /// place for hidden data, highlighted
+    pub fn chunk_type(&self, chunk_type: &ChunkType) -> Painted<String> {
+        let code = if chunk_type.is_critical() {
+            BOLD
+        } else if chunk_type.is_public() {
+            CYAN
+        } else {
+            REVERSE_YELLOW
+        };
+        self.paint(code, chunk_type.to_string())
+    }
+
+    pub fn error<T: fmt::Display>(&self, text: T) -> Painted<T> {
+        self.paint(RED, text)
+    }
+
+    pub fn success<T: fmt::Display>(&self, text: T) -> Painted<T> {
+        self.paint(GREEN, text)
+    }
+
+    fn paint<T: fmt::Display>(&self, code: &'static str, text: T) -> Painted<T> {
+        Painted {
+            code: self.enabled.then_some(code),
+            text,
+        }
+    }
+}
+
+/// Text wrapped in an escape sequence when displayed
+pub struct Painted<T> {
+    code: Option<&'static str>,
+    text: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}{}{}", code, self.text, RESET),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color_choice() {
+        assert_eq!(ColorChoice::from_str("always"), Ok(ColorChoice::Always));
+        assert_eq!(ColorChoice::from_str("never"), Ok(ColorChoice::Never));
+        assert_eq!(ColorChoice::from_str("auto"), Ok(ColorChoice::Auto));
+        assert!(ColorChoice::from_str("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert!(ColorChoice::Auto.resolve(true, false));
+        assert!(!ColorChoice::Auto.resolve(true, true));
+        assert!(!ColorChoice::Auto.resolve(false, false));
+        assert!(ColorChoice::Always.resolve(false, true));
+        assert!(!ColorChoice::Never.resolve(true, false));
+    }
+
+    #[test]
+    fn test_chunk_type_categories() {
+        let theme = Theme { enabled: true };
+        let painted = |s: &str| {
+            theme
+                .chunk_type(&ChunkType::from_str(s).unwrap())
+                .to_string()
+        };
+
+        assert_eq!(painted("IHDR"), "\x1b[1mIHDR\x1b[0m");
+        assert_eq!(painted("tEXt"), "\x1b[36mtEXt\x1b[0m");
+        assert_eq!(painted("ruSt"), "\x1b[1;7;33mruSt\x1b[0m");
+    }
+
+    #[test]
+    fn test_plain_theme() {
+        let theme = Theme::plain();
+        assert_eq!(theme.error("bad CRC").to_string(), "bad CRC");
+        assert_eq!(
+            theme
+                .chunk_type(&ChunkType::from_str("ruSt").unwrap())
+                .to_string(),
+            "ruSt"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMeError;
+use crate::png::Png;
+use crate::selection::Occurrence;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+/// Longest keyword the PNG spec allows, in bytes
+pub const MAX_KEYWORD_LEN: usize = 79;
+
+/// The textual chunk types from the PNG specification
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TextKind {
+    /// `tEXt`: Latin-1 text stored as is
+    Text,
+    /// `zTXt`: Latin-1 text compressed with zlib
+    Compressed,
+    /// `iTXt`: UTF-8 text with a language tag and a translated keyword
+    International,
+}
+
+impl TextKind {
+    pub fn chunk_type(self) -> ChunkType {
+        ChunkType::from_str(self.name()).unwrap()
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TextKind::Text => "tEXt",
+            TextKind::Compressed => "zTXt",
+            TextKind::International => "iTXt",
+        }
+    }
+}
+
+impl FromStr for TextKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tEXt" => Ok(TextKind::Text),
+            "zTXt" => Ok(TextKind::Compressed),
+            "iTXt" => Ok(TextKind::International),
+            _ => Err("text chunk must be one of tEXt, zTXt or iTXt"),
+        }
+    }
+}
+
+impl Display for TextKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A keyword and value stored in one of the spec's textual chunks, so a message looks like
+/// ordinary image metadata rather than a custom chunk
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TextChunk {
+    kind: TextKind,
+    keyword: String,
+    text: String,
+    language_tag: String,
+    translated_keyword: String,
+}
+
+impl TextChunk {
+    /// Checks the keyword against the spec and, for `tEXt` and `zTXt`, that the text fits in
+    /// Latin-1
+    pub fn new(kind: TextKind, keyword: &str, text: &str) -> Result<TextChunk, PngMeError> {
+        validate_keyword(keyword)?;
+        if kind != TextKind::International {
+            latin1_bytes(text)?;
+        }
+        Ok(TextChunk {
+            kind,
+            keyword: String::from(keyword),
+            text: String::from(text),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+        })
+    }
+
+    /// Sets the language of an `iTXt` chunk, such as `en` or `pt-BR`, and the keyword translated
+    /// into it
+    pub fn with_language(
+        mut self,
+        language_tag: &str,
+        translated_keyword: &str,
+    ) -> Result<TextChunk, PngMeError> {
+        if self.kind != TextKind::International {
+            return Err(PngMeError::Invalid("only iTXt chunks carry a language"));
+        }
+        if !language_tag
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        {
+            return Err(PngMeError::Invalid(
+                "language tag may only contain ASCII letters, digits and hyphens",
+            ));
+        }
+        if translated_keyword.contains('\0') {
+            return Err(PngMeError::Invalid("translated keyword can't contain NUL"));
+        }
+        self.language_tag = String::from(language_tag);
+        self.translated_keyword = String::from(translated_keyword);
+        Ok(self)
+    }
+
+    pub fn kind(&self) -> TextKind {
+        self.kind
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    /// Encodes the keyword and text into a chunk of the matching type
+    pub fn to_chunk(&self) -> Result<Chunk, PngMeError> {
+        let mut data = latin1_bytes(&self.keyword)?;
+        data.push(0);
+        match self.kind {
+            TextKind::Text => data.extend(latin1_bytes(&self.text)?),
+            TextKind::Compressed => {
+                data.push(0); // compression method, zlib is the only one defined
+                data.extend(compress(&latin1_bytes(&self.text)?)?);
+            }
+            TextKind::International => {
+                data.extend([0, 0]); // uncompressed, so the method is ignored
+                data.extend(self.language_tag.as_bytes());
+                data.push(0);
+                data.extend(self.translated_keyword.as_bytes());
+                data.push(0);
+                data.extend(self.text.as_bytes());
+            }
+        }
+        Ok(Chunk::new(self.kind.chunk_type(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngMeError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let kind =
+            TextKind::from_str(&chunk.chunk_type().to_string()).map_err(PngMeError::Invalid)?;
+        let (keyword, rest) = split_nul(chunk.data())?;
+        let keyword = latin1_string(keyword);
+        validate_keyword(&keyword)?;
+
+        let mut text_chunk = TextChunk {
+            kind,
+            keyword,
+            text: String::new(),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+        };
+        match kind {
+            TextKind::Text => text_chunk.text = latin1_string(rest),
+            TextKind::Compressed => {
+                let (&method, compressed) = rest.split_first().ok_or(PngMeError::TruncatedChunk)?;
+                text_chunk.text = latin1_string(&decompress(method, compressed)?);
+            }
+            TextKind::International => {
+                let [flag, method, rest @ ..] = rest else {
+                    return Err(PngMeError::TruncatedChunk);
+                };
+                let (language_tag, rest) = split_nul(rest)?;
+                let (translated_keyword, text) = split_nul(rest)?;
+                let text = match flag {
+                    0 => text.to_vec(),
+                    1 => decompress(*method, text)?,
+                    _ => {
+                        return Err(PngMeError::Malformed(
+                            "iTXt compression flag must be 0 or 1",
+                        ))
+                    }
+                };
+                text_chunk.language_tag = String::from(std::str::from_utf8(language_tag)?);
+                text_chunk.translated_keyword =
+                    String::from(std::str::from_utf8(translated_keyword)?);
+                text_chunk.text = String::from_utf8(text).map_err(|e| e.utf8_error())?;
+            }
+        }
+        Ok(text_chunk)
+    }
+}
+
+/// Finds the first text chunk of `kind`, or the first one with `keyword` when one is given.
+/// Chunks of that type which fail to parse are skipped.
+pub fn find(png: &Png, kind: TextKind, keyword: Option<&str>) -> Result<TextChunk, PngMeError> {
+    png.chunks_by_type(&kind.to_string(), Occurrence::All)
+        .into_iter()
+        .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+        .find(|text_chunk| keyword.is_none_or(|keyword| text_chunk.keyword() == keyword))
+        .ok_or_else(|| match keyword {
+            Some(keyword) => PngMeError::ChunkNotFound(format!("{} {:?}", kind, keyword)),
+            None => PngMeError::ChunkNotFound(kind.to_string()),
+        })
+}
+
+/// Keywords are 1 to 79 printable Latin-1 characters, without leading, trailing or consecutive
+/// spaces
+fn validate_keyword(keyword: &str) -> Result<(), PngMeError> {
+    let bytes = latin1_bytes(keyword)?;
+    if bytes.is_empty() || bytes.len() > MAX_KEYWORD_LEN {
+        return Err(PngMeError::Invalid("keyword must be 1 to 79 characters"));
+    }
+    if !bytes
+        .iter()
+        .all(|byte| matches!(byte, 32..=126 | 161..=255))
+    {
+        return Err(PngMeError::Invalid(
+            "keyword may only contain printable Latin-1 characters",
+        ));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(PngMeError::Invalid(
+            "keyword can't have leading, trailing or consecutive spaces",
+        ));
+    }
+    Ok(())
+}
+
+fn latin1_bytes(text: &str) -> Result<Vec<u8>, PngMeError> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| PngMeError::Invalid("text must be Latin-1, use iTXt")))
+        .collect()
+}
+
+fn latin1_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+fn split_nul(data: &[u8]) -> Result<(&[u8], &[u8]), PngMeError> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(PngMeError::TruncatedChunk)?;
+    Ok((&data[..end], &data[end + 1..]))
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn decompress(method: u8, data: &[u8]) -> Result<Vec<u8>, PngMeError> {
+    if method != 0 {
+        return Err(PngMeError::Malformed("unknown text compression method"));
+    }
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        for kind in [
+            TextKind::Text,
+            TextKind::Compressed,
+            TextKind::International,
+        ] {
+            let text_chunk = TextChunk::new(kind, "Comment", "caf\u{e9} au lait").unwrap();
+            let chunk = text_chunk.to_chunk().unwrap();
+            assert_eq!(chunk.chunk_type().to_string(), kind.to_string());
+            assert_eq!(TextChunk::try_from(&chunk).unwrap(), text_chunk);
+        }
+    }
+
+    #[test]
+    fn test_text_encoding() {
+        let chunk = TextChunk::new(TextKind::Text, "Comment", "caf\u{e9}")
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+
+        let chunk = TextChunk::new(TextKind::International, "Title", "\u{732b}")
+            .unwrap()
+            .with_language("ja", "\u{984c}")
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert_eq!(
+            chunk.data(),
+            [&b"Title\0\0\0ja\0"[..], "\u{984c}\0\u{732b}".as_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn test_compressed_itxt() {
+        let mut data = b"Title\0\x01\x00en\0\0".to_vec();
+        data.extend(compress("\u{732b} and more".as_bytes()).unwrap());
+        let chunk = Chunk::new(TextKind::International.chunk_type(), data);
+        let text_chunk = TextChunk::try_from(&chunk).unwrap();
+        assert_eq!(text_chunk.text(), "\u{732b} and more");
+        assert_eq!(text_chunk.language_tag(), "en");
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert!(TextChunk::new(TextKind::Text, "", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, &"k".repeat(80), "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, " Comment", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "Two  spaces", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "Tab\there", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "Comment", "\u{732b}").is_err());
+        assert!(TextChunk::new(TextKind::International, "Comment", "\u{732b}").is_ok());
+        assert!(TextChunk::new(TextKind::Text, "Comment", "text")
+            .unwrap()
+            .with_language("en", "")
+            .is_err());
+
+        let chunk = Chunk::new(TextKind::Text.chunk_type(), b"no separator".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+        assert!(TextKind::from_str("ruSt").is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let chunks = ["Software", "Comment"]
+            .iter()
+            .map(|keyword| {
+                TextChunk::new(TextKind::Text, keyword, keyword)
+                    .unwrap()
+                    .to_chunk()
+                    .unwrap()
+            })
+            .collect();
+        let png = Png::new(chunks);
+        assert_eq!(
+            find(&png, TextKind::Text, None).unwrap().keyword(),
+            "Software"
+        );
+        assert_eq!(
+            find(&png, TextKind::Text, Some("Comment")).unwrap().text(),
+            "Comment"
+        );
+        assert!(find(&png, TextKind::Text, Some("Author")).is_err());
+        assert!(find(&png, TextKind::Compressed, None).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::ordering::Violation;
+use crate::png::Png;
+use std::ops::{Deref, DerefMut};
+
+/// Edits staged against a copy of a [`Png`]. Every `Png` method is available on the transaction
+/// and only touches the copy; [`Transaction::commit`] writes the copy back unless the edits broke
+/// the chunk ordering rules. Dropping a transaction without committing discards the edits.
+pub struct Transaction<'a> {
+    target: &'a mut Png,
+    staged: Png,
+    /// Violations the image already had, which edits aren't blamed for
+    existing: Vec<Violation>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(target: &'a mut Png) -> Transaction<'a> {
+        let staged = target.clone();
+        let existing = target.violations();
+        Transaction {
+            target,
+            staged,
+            existing,
+        }
+    }
+
+    /// Replaces the original with the staged copy, or returns the ordering violations the edits
+    /// added and leaves the original untouched. Violations the image had to begin with are
+    /// matched by chunk type and rule, since edits may have moved the chunks involved.
+    pub fn commit(mut self) -> Result<(), Vec<Violation>> {
+        let violations: Vec<Violation> = self
+            .staged
+            .violations()
+            .into_iter()
+            .filter(|violation| {
+                let existing = self.existing.iter().position(|existing| {
+                    existing.chunk_type == violation.chunk_type && existing.rule == violation.rule
+                });
+                match existing {
+                    Some(position) => {
+                        self.existing.swap_remove(position);
+                        false
+                    }
+                    None => true,
+                }
+            })
+            .collect();
+        if !violations.is_empty() {
+            return Err(violations);
+        }
+
+        *self.target = self.staged;
+        Ok(())
+    }
+
+    pub fn rollback(self) {}
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Png;
+
+    fn deref(&self) -> &Png {
+        &self.staged
+    }
+}
+
+impl DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Png {
+        &mut self.staged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::Png;
+    use crate::selection::Occurrence;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![])
+    }
+
+    fn testing_png() -> Png {
+        Png::new(vec![chunk("IHDR"), chunk("IDAT"), chunk("IEND")])
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut png = testing_png();
+        let mut transaction = png.transaction();
+        transaction.insert_chunk_at(1, chunk("tEXt")).unwrap();
+        transaction.insert_chunk_at(3, chunk("ruSt")).unwrap();
+        assert_eq!(transaction.chunks().len(), 5);
+
+        transaction.commit().unwrap();
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_invalid_result_is_rolled_back() {
+        let mut png = testing_png();
+        let mut transaction = png.transaction();
+        transaction.append_chunk(chunk("ruSt"));
+        transaction.remove_chunks("IDAT", Occurrence::All).unwrap();
+
+        let violations = transaction.commit().unwrap_err();
+        assert_eq!(violations[0].rule, "IEND must be the last chunk");
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_existing_violations_are_allowed() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("ruSt"));
+        let mut transaction = png.transaction();
+        transaction.insert_chunk_at(1, chunk("tEXt")).unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(png.chunks().len(), 5);
+
+        let mut transaction = png.transaction();
+        transaction.insert_chunk_at(3, chunk("gAMA")).unwrap();
+        let violations = transaction.commit().unwrap_err();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].chunk_type.to_string(), "gAMA");
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut png = testing_png();
+        let mut transaction = png.transaction();
+        transaction.remove_chunks("IDAT", Occurrence::All).unwrap();
+        transaction.rollback();
+        assert_eq!(png.chunks().len(), 3);
+    }
+}
@@ -0,0 +1,260 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+/// Marks a payload that went through a transform chain. The chain itself follows as a length
+/// byte and the `--transform` spec, so decoding can undo it without being told.
+pub const TRANSFORM_MAGIC: &[u8; 4] = b"pmT1";
+/// Version of the transform header, the digit at the end of [`TRANSFORM_MAGIC`]
+pub const TRANSFORM_VERSION: u32 = 1;
+/// Most bytes a gzip step may expand to when it's undone. Unlike a compressed payload, a gzip
+/// step doesn't record its length, so this is what keeps a small payload that claims to be a huge
+/// one from being inflated without limit.
+pub const MAX_GUNZIP_LEN: u64 = 256 << 20;
+
+/// One reversible step applied to a payload
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Transform {
+    Gzip,
+    /// Xors the payload with the key repeated over its length
+    Xor(Vec<u8>),
+    Base64,
+}
+
+impl Transform {
+    pub fn apply(&self, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+        match self {
+            Transform::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(data)
+                    .map_err(|_| "failed to gzip payload")?;
+                encoder.finish().map_err(|_| "failed to gzip payload")
+            }
+            Transform::Xor(key) => Ok(xor(data, key)),
+            Transform::Base64 => Ok(STANDARD.encode(data).into_bytes()),
+        }
+    }
+
+    pub fn reverse(&self, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+        match self {
+            Transform::Gzip => gunzip(data, MAX_GUNZIP_LEN),
+            Transform::Xor(key) => Ok(xor(data, key)),
+            Transform::Base64 => STANDARD
+                .decode(data)
+                .map_err(|_| "payload is not valid base64"),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = &'static str;
+
+    /// Parses `gzip`, `base64` or `xor:KEY`, where the key is `0x` followed by hex bytes or
+    /// otherwise taken as text
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "gzip" => Ok(Transform::Gzip),
+            "base64" => Ok(Transform::Base64),
+            step => {
+                let key = step.strip_prefix("xor:").ok_or("unknown transform")?;
+                let key = match key.strip_prefix("0x") {
+                    Some(hex_key) => hex::decode(hex_key).map_err(|_| "invalid hex xor key")?,
+                    None => key.as_bytes().to_vec(),
+                };
+                if key.is_empty() {
+                    return Err("xor key can't be empty");
+                }
+                Ok(Transform::Xor(key))
+            }
+        }
+    }
+}
+
+impl Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transform::Gzip => write!(f, "gzip"),
+            Transform::Xor(key) => write!(f, "xor:0x{}", hex::encode(key)),
+            Transform::Base64 => write!(f, "base64"),
+        }
+    }
+}
+
+/// An ordered `--transform` chain such as `gzip,xor:0x5A,base64`. Encoding applies the steps left
+/// to right and decoding undoes them right to left.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<Transform>,
+}
+
+impl Pipeline {
+    pub fn steps(&self) -> &[Transform] {
+        &self.steps
+    }
+
+    /// Runs the chain over `payload` and prefixes the result with a header naming the chain
+    pub fn encode(&self, payload: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let spec = self.to_string();
+        let spec_len = u8::try_from(spec.len()).map_err(|_| "transform chain is too long")?;
+
+        let mut data = payload.to_vec();
+        for step in &self.steps {
+            data = step.apply(&data)?;
+        }
+
+        let mut encoded = TRANSFORM_MAGIC.to_vec();
+        encoded.push(spec_len);
+        encoded.extend_from_slice(spec.as_bytes());
+        encoded.extend_from_slice(&data);
+        Ok(encoded)
+    }
+
+    /// Undoes the chain, right to left, on data that has no header, such as a payload layered by
+    /// another tool
+    pub fn reverse(&self, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let mut data = data.to_vec();
+        for step in self.steps.iter().rev() {
+            data = step.reverse(&data)?;
+        }
+        Ok(data)
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Pipeline::default());
+        }
+        let steps = s
+            .split(',')
+            .map(Transform::from_str)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Pipeline { steps })
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let steps: Vec<String> = self.steps.iter().map(Transform::to_string).collect();
+        write!(f, "{}", steps.join(","))
+    }
+}
+
+/// Whether `data` starts with a transform header
+pub fn is_transformed(data: &[u8]) -> bool {
+    data.starts_with(TRANSFORM_MAGIC)
+}
+
+/// Undoes the transform chain recorded in the header of `data`. Data without a header was
+/// stored untransformed and is returned as is.
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let Some(rest) = data.strip_prefix(TRANSFORM_MAGIC) else {
+        return Ok(data.to_vec());
+    };
+    let (&spec_len, rest) = rest.split_first().ok_or("transform header is truncated")?;
+    if rest.len() < spec_len as usize {
+        return Err("transform header is truncated");
+    }
+    let (spec, payload) = rest.split_at(spec_len as usize);
+    let spec = std::str::from_utf8(spec).map_err(|_| "transform header is not valid UTF-8")?;
+    Pipeline::from_str(spec)?.reverse(payload)
+}
+
+/// Inflates `data`, reading one byte past `limit` to tell that it expands too far
+fn gunzip(data: &[u8], limit: u64) -> Result<Vec<u8>, &'static str> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decoded)
+        .map_err(|_| "payload is not valid gzip data")?;
+    match decoded.len() as u64 > limit {
+        true => Err("gzip data expands past the size limit"),
+        false => Ok(decoded),
+    }
+}
+
+fn xor(data: &[u8], key: &[u8]) -> Vec<u8> {
+    data.iter()
+        .zip(key.iter().cycle())
+        .map(|(byte, k)| byte ^ k)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pipeline() {
+        let pipeline = Pipeline::from_str("gzip, xor:0x5A,base64").unwrap();
+        assert_eq!(
+            pipeline.steps(),
+            &[
+                Transform::Gzip,
+                Transform::Xor(vec![0x5a]),
+                Transform::Base64
+            ]
+        );
+        assert_eq!(pipeline.to_string(), "gzip,xor:0x5a,base64");
+        assert_eq!(
+            Transform::from_str("xor:key"),
+            Ok(Transform::Xor(b"key".to_vec()))
+        );
+        assert!(Pipeline::from_str("gzip,rot13").is_err());
+        assert!(Transform::from_str("xor:0xZZ").is_err());
+        assert!(Transform::from_str("xor:").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = b"layered payload layered payload layered payload";
+        for spec in ["", "gzip", "xor:0x5A,base64", "gzip,xor:secret,base64,gzip"] {
+            let encoded = Pipeline::from_str(spec).unwrap().encode(payload).unwrap();
+            assert!(encoded.starts_with(TRANSFORM_MAGIC));
+            assert_eq!(decode(&encoded).unwrap(), payload, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_steps_apply_in_order() {
+        let encoded = Pipeline::from_str("xor:0x01,base64")
+            .unwrap()
+            .encode(b"abc")
+            .unwrap();
+        let header_len = TRANSFORM_MAGIC.len() + 1 + "xor:0x01,base64".len();
+        assert_eq!(&encoded[header_len..], STANDARD.encode(b"`cb").as_bytes());
+    }
+
+    #[test]
+    fn test_reverse_without_header() {
+        let pipeline = Pipeline::from_str("xor:0x01,base64").unwrap();
+        let layered = STANDARD.encode(b"`cb");
+        assert_eq!(pipeline.reverse(layered.as_bytes()).unwrap(), b"abc");
+        assert!(!is_transformed(layered.as_bytes()));
+        assert!(is_transformed(&pipeline.encode(b"abc").unwrap()));
+    }
+
+    #[test]
+    fn test_gzip_expansion_is_bounded() {
+        let bomb = Transform::Gzip.apply(&[0; 1001]).unwrap();
+        assert_eq!(gunzip(&bomb, 1001).unwrap().len(), 1001);
+        assert_eq!(
+            gunzip(&bomb, 1000),
+            Err("gzip data expands past the size limit")
+        );
+    }
+
+    #[test]
+    fn test_untransformed_data_passes_through() {
+        assert_eq!(decode(b"plain message").unwrap(), b"plain message");
+        assert!(decode(b"pmT1\x09gzip").is_err());
+    }
+}
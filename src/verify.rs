@@ -0,0 +1,291 @@
+use crate::chunk::ChunkRef;
+use crate::chunk_type::{ChunkType, StandardChunk};
+use crate::ihdr::Ihdr;
+use crate::ordering;
+use crate::png::Png;
+use crate::reader::MAX_CHUNK_LENGTH;
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::fmt::Display;
+
+/// One way a file departs from the PNG specification
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Problem {
+    /// Byte offset of the chunk or data the problem was found at
+    pub offset: usize,
+    /// Position of the chunk, when the problem belongs to one
+    pub index: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(
+                f,
+                "chunk {} at byte {}: {}",
+                index, self.offset, self.message
+            ),
+            None => write!(f, "byte {}: {}", self.offset, self.message),
+        }
+    }
+}
+
+/// Everything [`verify`] found in a file
+#[derive(PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    /// Chunks whose header and data could be read, whether or not they were valid
+    pub chunks: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn problem(&mut self, offset: usize, index: Option<usize>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            offset,
+            index,
+            message: message.into(),
+        });
+    }
+}
+
+/// Checks `bytes` against the structural rules of the PNG specification: the signature, chunk
+/// lengths, CRCs, chunk type names and reserved bits, how many of each standard chunk there are
+/// and the chunk order.
+/// Unlike parsing, it keeps going after a problem so every one of them is reported.
+pub fn verify(bytes: &[u8]) -> Report {
+    check(bytes, true)
+}
+
+/// Like [`verify`], leaving out the chunk order, for files put out of order on purpose
+pub fn verify_ignoring_order(bytes: &[u8]) -> Report {
+    check(bytes, false)
+}
+
+fn check(bytes: &[u8], check_order: bool) -> Report {
+    let mut report = Report::default();
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        report.problem(0, None, "missing the 8-byte PNG signature");
+    }
+
+    // Chunks with a readable type, and their position and offset, for the ordering rules
+    let mut chunks: Vec<(usize, usize, ChunkRef)> = Vec::new();
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+    let mut offset = Png::STANDARD_HEADER.len();
+    while offset < bytes.len() {
+        let index = report.chunks;
+        let Some(header) = bytes.get(offset..offset + 8) else {
+            report.problem(offset, Some(index), "file ends inside a chunk header");
+            break;
+        };
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let type_bytes: [u8; 4] = header[4..].try_into().unwrap();
+        if length > MAX_CHUNK_LENGTH {
+            report.problem(
+                offset,
+                Some(index),
+                format!("length {} is over the 2^31 - 1 limit", length),
+            );
+            break;
+        }
+        let data_end = offset + 8 + length as usize;
+        let Some(stored_crc) = bytes.get(data_end..data_end + 4) else {
+            report.problem(
+                offset,
+                Some(index),
+                "file ends inside the chunk data or CRC",
+            );
+            break;
+        };
+        let stored_crc = u32::from_be_bytes(stored_crc.try_into().unwrap());
+        report.chunks += 1;
+
+        let actual_crc = crc.checksum(&bytes[offset + 4..data_end]);
+        if stored_crc != actual_crc {
+            report.problem(
+                offset,
+                Some(index),
+                format!(
+                    "CRC is {:08x} but the chunk contents give {:08x}",
+                    stored_crc, actual_crc
+                ),
+            );
+        }
+
+        // The chunk is known to fit in `bytes` by now, so only its type can fail to parse
+        if let Ok(chunk) = ChunkRef::parse_lenient(&bytes[offset..data_end + 4]) {
+            if !chunk.chunk_type().is_reserved_bit_valid() {
+                report.problem(
+                    offset,
+                    Some(index),
+                    format!("{} has the reserved bit set", chunk.chunk_type()),
+                );
+            }
+            chunks.push((index, offset, chunk));
+        } else {
+            report.problem(
+                offset,
+                Some(index),
+                format!(
+                    "chunk type {:?} isn't four ASCII letters",
+                    String::from_utf8_lossy(&type_bytes)
+                ),
+            );
+        }
+        offset = data_end + 4;
+    }
+
+    for standard in StandardChunk::ALL {
+        let count = chunks
+            .iter()
+            .filter(|(_, _, chunk)| chunk.chunk_type().standard() == Some(standard))
+            .count();
+        let rule = match standard.is_required() {
+            true => "exactly one",
+            false => "at most one",
+        };
+        match count {
+            0 if standard.is_required() => {
+                report.problem(bytes.len(), None, format!("no {} chunk", standard))
+            }
+            0 | 1 => (),
+            _ if standard.allows_multiple() => (),
+            _ => report.problem(
+                bytes.len(),
+                None,
+                format!("{} {} chunks, the spec allows {}", count, standard, rule),
+            ),
+        }
+    }
+    for (index, offset, chunk) in &chunks {
+        if chunk.chunk_type() == ChunkType::IHDR {
+            if let Err(e) = Ihdr::try_from(*chunk) {
+                report.problem(*offset, Some(*index), format!("IHDR: {}", e));
+            }
+        }
+    }
+
+    if check_order {
+        for violation in ordering::violations(chunks.iter().map(|(_, _, chunk)| *chunk)) {
+            let (index, offset, _) = &chunks[violation.index];
+            report.problem(
+                *offset,
+                Some(*index),
+                format!("{}: {}", violation.chunk_type, violation.rule),
+            );
+        }
+    }
+    report.problems.sort_by_key(|problem| problem.offset);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::ihdr::ColorType;
+    use std::str::FromStr;
+
+    fn testing_bytes(types: &[&str]) -> Vec<u8> {
+        Png::new(
+            types
+                .iter()
+                .map(|chunk_type| match *chunk_type {
+                    "IHDR" => Ihdr::new(1, 1, 8, ColorType::Rgb).to_chunk(),
+                    _ => Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![1]),
+                })
+                .collect(),
+        )
+        .as_bytes()
+    }
+
+    fn messages(report: &Report) -> Vec<String> {
+        report
+            .problems
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let report = verify(&testing_bytes(&["IHDR", "tEXt", "IDAT", "IDAT", "IEND"]));
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert_eq!(report.chunks, 5);
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let mut bytes = testing_bytes(&["IHDR", "IDAT", "rust", "IDAT", "IEND", "IEND"]);
+        bytes[0] = 0;
+        bytes[8 + 8] ^= 1; // data byte of IHDR
+
+        assert_eq!(
+            messages(&verify(&bytes)),
+            vec![
+                "byte 0: missing the 8-byte PNG signature",
+                "chunk 0 at byte 8: CRC is 907753de but the chunk contents give 4de18a5b",
+                "chunk 2 at byte 46: rust has the reserved bit set",
+                "chunk 3 at byte 59: IDAT: IDAT chunks must be consecutive",
+                "chunk 4 at byte 72: IEND: IEND must be the last chunk",
+                "byte 98: 2 IEND chunks, the spec allows exactly one",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunk_counts() {
+        let bytes = testing_bytes(&[
+            "IHDR", "gAMA", "gAMA", "tEXt", "tEXt", "IDAT", "IDAT", "IEND",
+        ]);
+        assert_eq!(
+            messages(&verify(&bytes)),
+            ["byte 124: 2 gAMA chunks, the spec allows at most one"]
+        );
+    }
+
+    #[test]
+    fn test_structural_problems() {
+        let mut bytes = testing_bytes(&["IHDR", "ruSt"]);
+        bytes[8 + 25 + 4] = b'1';
+        let report = verify(&bytes);
+        assert_eq!(report.chunks, 2);
+        assert_eq!(
+            messages(&report)[1..],
+            [
+                "chunk 1 at byte 33: chunk type \"1uSt\" isn't four ASCII letters",
+                "byte 46: no IDAT chunk",
+                "byte 46: no IEND chunk",
+            ]
+        );
+
+        let bytes = testing_bytes(&["IHDR", "IDAT", "IEND"]);
+        let truncated = verify(&bytes[..bytes.len() - 2]);
+        assert_eq!(truncated.chunks, 2);
+        assert!(messages(&truncated)[0].ends_with("file ends inside the chunk data or CRC"));
+
+        let mut bytes = testing_bytes(&["IHDR"]);
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(messages(&verify(&bytes))[0].contains("over the 2^31 - 1 limit"));
+
+        let mut bytes = testing_bytes(&["IHDR", "IDAT", "IEND"]);
+        bytes[8..33].copy_from_slice(
+            &Ihdr {
+                bit_depth: 4,
+                ..Ihdr::new(1, 1, 8, ColorType::Rgb)
+            }
+            .to_chunk()
+            .as_bytes(),
+        );
+        assert_eq!(
+            messages(&verify(&bytes)),
+            ["chunk 0 at byte 8: IHDR: bit depth isn't allowed for the color type in IHDR"]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use crate::error::PngMeError;
+use crate::png::Png;
+use wasm_bindgen::prelude::*;
+
+/// Hides `message` in a new chunk of type `chunk_type` and returns the changed image. Invalid
+/// images and chunk types are thrown as JavaScript errors.
+#[wasm_bindgen]
+pub fn encode(bytes: &[u8], chunk_type: &str, message: &str) -> Result<Vec<u8>, JsError> {
+    let mut png = Png::try_from(bytes)?;
+    crate::encode_message(&mut png, chunk_type, message)?;
+    Ok(png.as_bytes())
+}
+
+/// Reads the message in the first chunk of type `chunk_type`, or `undefined` when the image has
+/// no such chunk
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8], chunk_type: &str) -> Result<Option<String>, JsError> {
+    let png = Png::try_from(bytes)?;
+    match crate::decode_message(&png, chunk_type) {
+        Ok(message) => Ok(Some(message)),
+        Err(PngMeError::ChunkNotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    // Only the paths that succeed can run natively, since errors become JavaScript values
+    #[test]
+    fn test_encode_decode() {
+        let image = Png::new(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+        .as_bytes();
+        let encoded = encode(&image, "ruSt", "hidden message").unwrap();
+        assert_eq!(
+            decode(&encoded, "ruSt").unwrap().as_deref(),
+            Some("hidden message")
+        );
+        assert_eq!(decode(&image, "ruSt").unwrap(), None);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::error::PngMeError;
+use crate::pixels::ImageData;
+use crate::png::Png;
+use sha2::{Digest, Sha256};
+
+/// Width and height, in pixels, of the regions that are checked independently
+pub const BLOCK_SIZE: u32 = 8;
+
+/// A rectangle of pixels whose contents no longer match the watermark embedded in it
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct WatermarkReport {
+    pub blocks: usize,
+    pub altered: Vec<Region>,
+}
+
+impl WatermarkReport {
+    pub fn is_intact(&self) -> bool {
+        self.altered.is_empty()
+    }
+}
+
+/// Embeds a fragile watermark in the least-significant bit of every sample. Each block of pixels
+/// carries a keyed hash of its own upper bits and position, so changing any bit of any pixel, or
+/// moving blocks around, breaks verification of the blocks involved.
+pub fn embed(png: &mut Png, key: &[u8]) -> Result<(), PngMeError> {
+    let mut image = decode(png)?;
+    for region in regions(&image) {
+        let positions = lsb_positions(&image, region);
+        let bits = block_bits(&image, region, &positions, key);
+        for (position, bit) in positions.iter().zip(bits) {
+            image.data[*position] = (image.data[*position] & !1) | bit;
+        }
+    }
+
+    image.encode_into(png)
+}
+
+/// Checks every block against the watermark and reports the ones that were altered
+pub fn check(png: &Png, key: &[u8]) -> Result<WatermarkReport, PngMeError> {
+    let image = decode(png)?;
+    let regions = regions(&image);
+
+    let altered = regions
+        .iter()
+        .filter(|region| {
+            let positions = lsb_positions(&image, **region);
+            let expected = block_bits(&image, **region, &positions, key);
+            positions
+                .iter()
+                .zip(expected)
+                .any(|(position, bit)| image.data[*position] & 1 != bit)
+        })
+        .copied()
+        .collect();
+
+    Ok(WatermarkReport {
+        blocks: regions.len(),
+        altered,
+    })
+}
+
+fn decode(png: &Png) -> Result<ImageData, PngMeError> {
+    let image = ImageData::decode(png)?;
+    if !image.has_lsb_plane() {
+        return Err(PngMeError::Invalid(
+            "fragile watermarks need an 8 or 16-bit image without a palette",
+        ));
+    }
+    Ok(image)
+}
+
+fn regions(image: &ImageData) -> Vec<Region> {
+    let mut regions = Vec::new();
+    for y in (0..image.height).step_by(BLOCK_SIZE as usize) {
+        for x in (0..image.width).step_by(BLOCK_SIZE as usize) {
+            regions.push(Region {
+                x,
+                y,
+                width: BLOCK_SIZE.min(image.width - x),
+                height: BLOCK_SIZE.min(image.height - y),
+            });
+        }
+    }
+    regions
+}
+
+/// Returns the index, in the image data, of the byte holding the least-significant bit of each
+/// sample in the region
+fn lsb_positions(image: &ImageData, region: Region) -> Vec<usize> {
+    let bytes_per_sample = image.bytes_per_sample();
+    let bytes_per_pixel = image.channels().unwrap_or(0) * bytes_per_sample;
+    let stride = image.stride();
+
+    let mut positions = Vec::new();
+    for y in region.y..region.y + region.height {
+        for x in region.x..region.x + region.width {
+            let pixel = y as usize * stride + x as usize * bytes_per_pixel;
+            for sample in (0..bytes_per_pixel).step_by(bytes_per_sample) {
+                positions.push(pixel + sample + bytes_per_sample - 1);
+            }
+        }
+    }
+    positions
+}
+
+/// Derives one watermark bit per sample from the key, the block's position and the block's
+/// pixels with every watermark bit cleared
+fn block_bits(image: &ImageData, region: Region, positions: &[usize], key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(image.width.to_be_bytes());
+    hasher.update(image.height.to_be_bytes());
+    hasher.update(region.x.to_be_bytes());
+    hasher.update(region.y.to_be_bytes());
+
+    let bytes_per_sample = image.bytes_per_sample();
+    for position in positions {
+        let sample = &image.data[position + 1 - bytes_per_sample..=*position];
+        hasher.update(&sample[..bytes_per_sample - 1]);
+        hasher.update([sample[bytes_per_sample - 1] & !1]);
+    }
+    let digest = hasher.finalize();
+
+    // stretch the digest into as many bits as the block has samples
+    let mut bits = Vec::with_capacity(positions.len());
+    let mut counter: u32 = 0;
+    while bits.len() < positions.len() {
+        let block = Sha256::new()
+            .chain_update(digest)
+            .chain_update(counter.to_be_bytes())
+            .finalize();
+        for byte in block.iter() {
+            for shift in 0..8 {
+                bits.push((byte >> shift) & 1);
+            }
+        }
+        counter += 1;
+    }
+    bits.truncate(positions.len());
+
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::tests::{resize_header, testing_image};
+
+    fn set_byte(png: &mut Png, index: usize, value: impl Fn(u8) -> u8) {
+        let mut image = ImageData::decode(png).unwrap();
+        image.data[index] = value(image.data[index]);
+        image.encode_into(png).unwrap();
+    }
+
+    #[test]
+    fn test_watermarked_image_is_intact() {
+        let mut png = testing_image(20, 12, 6, 8);
+        embed(&mut png, b"key").unwrap();
+
+        let report = check(&png, b"key").unwrap();
+        assert_eq!(report.blocks, 6);
+        assert!(report.is_intact());
+    }
+
+    #[test]
+    fn test_unwatermarked_image_fails() {
+        let png = testing_image(20, 12, 2, 8);
+        assert!(!check(&png, b"key").unwrap().is_intact());
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let mut png = testing_image(8, 8, 2, 8);
+        embed(&mut png, b"key").unwrap();
+        assert!(!check(&png, b"other key").unwrap().is_intact());
+    }
+
+    #[test]
+    fn test_reports_altered_region() {
+        let mut png = testing_image(20, 12, 2, 8);
+        embed(&mut png, b"key").unwrap();
+
+        // pixel (10, 9) sits in the block starting at (8, 8)
+        let stride = 20 * 3;
+        set_byte(&mut png, 9 * stride + 10 * 3, |byte| byte ^ 0x80);
+
+        let report = check(&png, b"key").unwrap();
+        assert_eq!(
+            report.altered,
+            vec![Region {
+                x: 8,
+                y: 8,
+                width: 8,
+                height: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lsb_change_is_detected() {
+        let mut png = testing_image(16, 16, 0, 16);
+        embed(&mut png, b"key").unwrap();
+        set_byte(&mut png, 1, |byte| byte ^ 1);
+
+        let report = check(&png, b"key").unwrap();
+        assert_eq!(report.altered.len(), 1);
+        assert_eq!((report.altered[0].x, report.altered[0].y), (0, 0));
+    }
+
+    #[test]
+    fn test_palette_image_is_rejected() {
+        let mut png = testing_image(4, 4, 3, 8);
+        assert!(embed(&mut png, b"key").is_err());
+    }
+
+    #[test]
+    fn test_oversized_header_is_rejected() {
+        let mut png = testing_image(8, 8, 6, 16);
+        embed(&mut png, b"key").unwrap();
+        resize_header(&mut png, 2147483647, 2147483647);
+        assert!(matches!(check(&png, b"key"), Err(PngMeError::Malformed(_))));
+        assert!(embed(&mut png, b"key").is_err());
+    }
+}